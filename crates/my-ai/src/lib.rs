@@ -6,6 +6,8 @@
 //! - Response caching with rocketcache
 //! - Newtonian agent orchestration
 //! - Streaming support
+//! - An interpreter backend executing AI expressions (`RuntimeBackend`)
+//! - Record/replay of provider calls through JSON cassettes
 //!
 //! # Security
 //!
@@ -15,9 +17,13 @@
 //! - Keys are cloned minimally to reduce exposure
 
 use async_trait::async_trait;
+use my_lang::ai::{AiBackend, AiRequest};
+use my_lang::cassette::{Cassette, CassetteMode, Interaction, RecordedMessage};
+use my_lang::AiKeyword;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::sync::Arc;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 use thiserror::Error;
 use tokio::sync::RwLock;
 use zeroize::{Zeroize, ZeroizeOnDrop};
@@ -79,6 +85,9 @@ pub enum AIError {
 
     #[error("cache error: {0}")]
     CacheError(String),
+
+    #[error("cassette error: {0}")]
+    CassetteError(String),
 }
 
 /// Completion request
@@ -143,6 +152,9 @@ pub trait AIProvider: Send + Sync {
 
     /// Check if model is available
     fn supports_model(&self, model: &str) -> bool;
+
+    /// Provider name, as written in `ai_model` declarations
+    fn name(&self) -> &str;
 }
 
 /// Anthropic provider
@@ -210,6 +222,10 @@ impl AIProvider for AnthropicProvider {
     fn supports_model(&self, model: &str) -> bool {
         model.starts_with("claude")
     }
+
+    fn name(&self) -> &str {
+        "anthropic"
+    }
 }
 
 /// OpenAI provider
@@ -297,6 +313,10 @@ impl AIProvider for OpenAIProvider {
     fn supports_model(&self, model: &str) -> bool {
         model.starts_with("gpt") || model.starts_with("o1")
     }
+
+    fn name(&self) -> &str {
+        "openai"
+    }
 }
 
 /// Ollama provider (local)
@@ -385,6 +405,10 @@ impl AIProvider for OllamaProvider {
     fn supports_model(&self, _model: &str) -> bool {
         true // Ollama can run any model
     }
+
+    fn name(&self) -> &str {
+        "ollama"
+    }
 }
 
 /// AI cache for response deduplication
@@ -396,6 +420,7 @@ pub struct AICache {
 #[derive(Debug, Clone)]
 struct CachedResponse {
     response: CompletionResponse,
+    #[allow(dead_code)]
     timestamp: std::time::Instant,
 }
 
@@ -439,11 +464,53 @@ impl Default for AICache {
     }
 }
 
+/// Cassette an [`AIRuntime`] records to or replays from
+struct RuntimeCassette {
+    mode: CassetteMode,
+    path: PathBuf,
+    cassette: Mutex<Cassette>,
+}
+
+impl RuntimeCassette {
+    fn interaction(operation: &str, model: Option<&str>, messages: &[Message], temperature: Option<f32>) -> Interaction {
+        Interaction {
+            operation: operation.to_string(),
+            model: model.map(str::to_string),
+            messages: messages
+                .iter()
+                .map(|m| RecordedMessage {
+                    role: match m.role {
+                        Role::System => "system",
+                        Role::User => "user",
+                        Role::Assistant => "assistant",
+                    }
+                    .to_string(),
+                    content: m.content.clone(),
+                })
+                .collect(),
+            temperature: temperature.map(f64::from),
+            result: serde_json::Value::Null,
+        }
+    }
+
+    fn replay(&self, request: &Interaction) -> Result<serde_json::Value, AIError> {
+        let mut cassette = self.cassette.lock().map_err(|e| AIError::CassetteError(e.to_string()))?;
+        cassette.replay(request).map_err(|e| AIError::CassetteError(e.to_string()))
+    }
+
+    fn record(&self, interaction: Interaction) -> Result<(), AIError> {
+        let mut cassette = self.cassette.lock().map_err(|e| AIError::CassetteError(e.to_string()))?;
+        cassette.record(interaction);
+        cassette.save(&self.path).map_err(|e| AIError::CassetteError(e.to_string()))
+    }
+}
+
 /// AI Runtime - main entry point
 pub struct AIRuntime {
     pub providers: Vec<Box<dyn AIProvider>>,
     cache: AICache,
     pub default_model: String,
+    cassette: Option<RuntimeCassette>,
 }
 
 impl AIRuntime {
@@ -452,7 +519,21 @@ impl AIRuntime {
             providers: Vec::new(),
             cache: AICache::new(),
             default_model: "claude-3-opus".to_string(),
+            cassette: None,
+        }
+    }
+
+    /// Record every completion and embedding to the cassette at `path`, or
+    /// serve them from it (see `my_lang::cassette`)
+    pub fn with_cassette(mut self, mode: CassetteMode, path: impl AsRef<Path>) -> Result<Self, AIError> {
+        let path = path.as_ref().to_path_buf();
+        let cassette = match mode {
+            CassetteMode::Record => Cassette::create(&path),
+            CassetteMode::Replay => Cassette::load(&path),
         }
+        .map_err(|e| AIError::CassetteError(e.to_string()))?;
+        self.cassette = Some(RuntimeCassette { mode, path, cassette: Mutex::new(cassette) });
+        Ok(self)
     }
 
     pub fn with_anthropic(mut self, api_key: String) -> Self {
@@ -490,23 +571,65 @@ impl AIRuntime {
             system: None,
         };
 
+        self.complete(request, None, true).await
+    }
+
+    /// Execute a completion request with the provider named `provider`, or
+    /// else the first one supporting the requested model
+    pub async fn complete(
+        &self,
+        request: CompletionRequest,
+        provider: Option<&str>,
+        use_cache: bool,
+    ) -> Result<String, AIError> {
+        let interaction = self.cassette.as_ref().map(|_| {
+            let mut messages = request.messages.clone();
+            if let Some(system) = &request.system {
+                messages.insert(0, Message { role: Role::System, content: system.clone() });
+            }
+            RuntimeCassette::interaction("complete", Some(&request.model), &messages, request.temperature)
+        });
+        if let (Some(cassette), Some(interaction)) = (&self.cassette, &interaction) {
+            if cassette.mode == CassetteMode::Replay {
+                return match cassette.replay(interaction)? {
+                    serde_json::Value::String(content) => Ok(content),
+                    other => Err(AIError::CassetteError(format!("recorded result {} is not a reply", other))),
+                };
+            }
+        }
+
         // Check cache
         let cache_key = AICache::cache_key(&request);
-        if let Some(cached) = self.cache.get(&cache_key).await {
-            return Ok(cached.content);
+        if use_cache {
+            if let Some(cached) = self.cache.get(&cache_key).await {
+                return Ok(cached.content);
+            }
         }
 
         // Find provider
         let provider = self
             .providers
             .iter()
-            .find(|p| p.supports_model(model))
-            .ok_or_else(|| AIError::ModelNotFound(model.to_string()))?;
+            .find(|p| match provider {
+                Some(name) => p.name() == name,
+                None => p.supports_model(&request.model),
+            })
+            .ok_or_else(|| match provider {
+                Some(name) => AIError::ProviderError(format!("provider '{}' is not configured", name)),
+                None => AIError::ModelNotFound(request.model.clone()),
+            })?;
 
         let response = provider.complete(request).await?;
 
         // Cache response
-        self.cache.set(cache_key, response.clone()).await;
+        if use_cache {
+            self.cache.set(cache_key, response.clone()).await;
+        }
+
+        if let (Some(cassette), Some(interaction)) = (&self.cassette, interaction) {
+            let result = serde_json::Value::from(response.content.clone());
+            cassette.record(Interaction { result, ..interaction })?;
+        }
 
         Ok(response.content)
     }
@@ -523,9 +646,25 @@ impl AIRuntime {
 
     /// Generate embeddings
     pub async fn embed(&self, text: &str) -> Result<Vec<f32>, AIError> {
+        let message = Message { role: Role::User, content: text.to_string() };
+        let interaction = RuntimeCassette::interaction("embed", None, &[message], None);
+        if let Some(cassette) = self.cassette.as_ref().filter(|c| c.mode == CassetteMode::Replay) {
+            let result = cassette.replay(&interaction)?;
+            return result
+                .as_array()
+                .and_then(|values| values.iter().map(|v| v.as_f64().map(|f| f as f32)).collect())
+                .ok_or_else(|| AIError::CassetteError(format!("recorded result {} is not an embedding", result)));
+        }
+
         for provider in &self.providers {
             match provider.embed(text).await {
-                Ok(response) => return Ok(response.embedding),
+                Ok(response) => {
+                    if let Some(cassette) = &self.cassette {
+                        let result = serde_json::Value::from(response.embedding.clone());
+                        cassette.record(Interaction { result, ..interaction })?;
+                    }
+                    return Ok(response.embedding);
+                }
                 Err(_) => continue,
             }
         }
@@ -564,6 +703,98 @@ pub fn runtime_from_env() -> AIRuntime {
     runtime
 }
 
+/// Interpreter backend executing AI expressions with an [`AIRuntime`]
+///
+/// The `provider`, `model`, `temperature` and `cache` attributes of the
+/// `ai_model` an expression names override the runtime's defaults.
+pub struct RuntimeBackend {
+    runtime: AIRuntime,
+    /// Only taken when the backend is dropped
+    executor: Option<tokio::runtime::Runtime>,
+}
+
+impl RuntimeBackend {
+    pub fn new(runtime: AIRuntime) -> Result<Self, AIError> {
+        let executor = tokio::runtime::Runtime::new().map_err(|e| AIError::ProviderError(e.to_string()))?;
+        Ok(RuntimeBackend { runtime, executor: Some(executor) })
+    }
+
+    /// Backend over the providers configured in the environment (see
+    /// [`runtime_from_env`]), of which there must be at least one
+    pub fn from_env() -> Result<Self, AIError> {
+        let runtime = runtime_from_env();
+        if runtime.providers.is_empty() {
+            return Err(AIError::ProviderError(
+                "no provider is configured; set ANTHROPIC_API_KEY, OPENAI_API_KEY or OLLAMA_HOST".to_string(),
+            ));
+        }
+        Self::new(runtime)
+    }
+
+    fn completion_request(&self, request: &AiRequest) -> CompletionRequest {
+        let model = request.model.as_ref();
+        let content = match request.operation {
+            AiKeyword::Verify | AiKeyword::Validate => format!("Answer only 'true' or 'false': {}", request.prompt),
+            _ => request.prompt.clone(),
+        };
+        CompletionRequest {
+            model: model
+                .and_then(|m| m.model.clone())
+                .unwrap_or_else(|| self.runtime.default_model.clone()),
+            messages: vec![Message { role: Role::User, content }],
+            temperature: model.and_then(|m| m.temperature).map(|t| t as f32),
+            max_tokens: None,
+            system: None,
+        }
+    }
+
+    /// Run `future` on the backend's executor. A thread already driving an
+    /// async runtime, such as the test runner's, may not block on another,
+    /// so callers on one block on a helper thread instead.
+    fn block_on<F>(&self, future: F) -> F::Output
+    where
+        F: std::future::Future + Send,
+        F::Output: Send,
+    {
+        let executor = self.executor.as_ref().expect("executor is present until drop");
+        if tokio::runtime::Handle::try_current().is_err() {
+            return executor.block_on(future);
+        }
+        std::thread::scope(|scope| {
+            scope
+                .spawn(|| executor.block_on(future))
+                .join()
+                .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+        })
+    }
+}
+
+impl Drop for RuntimeBackend {
+    fn drop(&mut self) {
+        // Unlike dropping it, shutting down in the background is also
+        // allowed from within another runtime
+        if let Some(executor) = self.executor.take() {
+            executor.shutdown_background();
+        }
+    }
+}
+
+impl AiBackend for RuntimeBackend {
+    fn complete(&mut self, request: &AiRequest) -> Result<String, String> {
+        let completion = self.completion_request(request);
+        let provider = request.model.as_ref().and_then(|m| m.provider.as_deref());
+        let use_cache = request.model.as_ref().is_none_or(|m| m.cache);
+        self.block_on(self.runtime.complete(completion, provider, use_cache))
+            .map_err(|e| e.to_string())
+    }
+
+    fn embed(&mut self, request: &AiRequest) -> Result<Vec<f64>, String> {
+        self.block_on(self.runtime.embed(&request.prompt))
+            .map(|embedding| embedding.into_iter().map(f64::from).collect())
+            .map_err(|e| e.to_string())
+    }
+}
+
 /// Newtonian agents module
 pub mod agents {
     use super::*;
@@ -664,4 +895,124 @@ mod tests {
         let key = AICache::cache_key(&request);
         assert!(!key.is_empty());
     }
+
+    /// Provider answering with its name and the request it received
+    struct EchoProvider(&'static str);
+
+    #[async_trait]
+    impl AIProvider for EchoProvider {
+        async fn complete(&self, request: CompletionRequest) -> Result<CompletionResponse, AIError> {
+            Ok(CompletionResponse {
+                content: format!(
+                    "{} {} {:?} {}",
+                    self.0, request.model, request.temperature, request.messages[0].content
+                ),
+                model: request.model,
+                usage: Usage::default(),
+            })
+        }
+
+        async fn embed(&self, _text: &str) -> Result<EmbeddingResponse, AIError> {
+            Err(AIError::ProviderError("no embeddings".to_string()))
+        }
+
+        fn supports_model(&self, model: &str) -> bool {
+            model.starts_with(self.0)
+        }
+
+        fn name(&self) -> &str {
+            self.0
+        }
+    }
+
+    #[test]
+    fn test_runtime_backend() {
+        use my_lang::ai::AiModel;
+
+        let mut runtime = AIRuntime::new().with_default_model("alpha-1".to_string());
+        runtime.providers.push(Box::new(EchoProvider("alpha")));
+        runtime.providers.push(Box::new(EchoProvider("beta")));
+        let mut backend = RuntimeBackend::new(runtime).unwrap();
+
+        let mut request = AiRequest { operation: AiKeyword::Query, prompt: "hi".to_string(), model: None, schema: None };
+        assert_eq!(backend.complete(&request).unwrap(), "alpha alpha-1 None hi");
+
+        request.operation = AiKeyword::Verify;
+        request.model = Some(AiModel {
+            name: "local".to_string(),
+            provider: Some("beta".to_string()),
+            model: Some("llama3".to_string()),
+            temperature: Some(0.5),
+            cache: false,
+        });
+        assert_eq!(
+            backend.complete(&request).unwrap(),
+            "beta llama3 Some(0.5) Answer only 'true' or 'false': hi"
+        );
+
+        request.model.as_mut().unwrap().provider = Some("gamma".to_string());
+        assert_eq!(
+            backend.complete(&request).unwrap_err(),
+            "provider error: provider 'gamma' is not configured"
+        );
+        assert!(backend.embed(&request).is_err());
+    }
+
+    #[test]
+    fn test_interpreter_uses_runtime_backend() {
+        let mut runtime = AIRuntime::new().with_default_model("alpha-1".to_string());
+        runtime.providers.push(Box::new(EchoProvider("alpha")));
+        let mut interpreter = my_lang::Interpreter::new();
+        interpreter.ai_backend = Box::new(RuntimeBackend::new(runtime).unwrap());
+
+        let program = my_lang::parse(r#"fn main() -> String { return ai! { "hi" }; }"#).unwrap();
+        let result = interpreter.run(&program).unwrap();
+        assert_eq!(result.to_string(), "alpha alpha-1 None hi");
+    }
+
+    #[tokio::test]
+    async fn test_runtime_backend_in_async_context() {
+        let mut runtime = AIRuntime::new().with_default_model("alpha-1".to_string());
+        runtime.providers.push(Box::new(EchoProvider("alpha")));
+        let mut backend = RuntimeBackend::new(runtime).unwrap();
+
+        let request = AiRequest { operation: AiKeyword::Query, prompt: "hi".to_string(), model: None, schema: None };
+        assert_eq!(backend.complete(&request).unwrap(), "alpha alpha-1 None hi");
+        drop(backend);
+    }
+
+    #[tokio::test]
+    async fn test_runtime_cassette() {
+        let path = std::env::temp_dir().join(format!("my-ai-cassette-{}.json", std::process::id()));
+        std::fs::write(&path, r#"{ "interactions": [
+            { "operation": "complete", "model": "alpha-1", "temperature": null,
+              "messages": [{ "role": "user", "content": "stale" }], "result": "old" }
+        ] }"#).unwrap();
+
+        let mut recording = AIRuntime::new()
+            .with_default_model("alpha-1".to_string())
+            .with_cassette(CassetteMode::Record, &path)
+            .unwrap();
+        recording.providers.push(Box::new(EchoProvider("alpha")));
+        assert_eq!(recording.query("hi", None).await.unwrap(), "alpha alpha-1 None hi");
+
+        // Recording replaced the stale cassette
+        let cassette = Cassette::load(&path).unwrap();
+        assert_eq!(cassette.interactions.len(), 1);
+        assert_eq!(cassette.interactions[0].operation, "complete");
+        assert_eq!(cassette.interactions[0].model.as_deref(), Some("alpha-1"));
+        assert_eq!(cassette.interactions[0].messages[0].role, "user");
+
+        // Replay needs no provider, and rejects requests it has no recording for
+        let replaying = AIRuntime::new()
+            .with_default_model("alpha-1".to_string())
+            .with_cassette(CassetteMode::Replay, &path)
+            .unwrap();
+        assert_eq!(replaying.query("hi", None).await.unwrap(), "alpha alpha-1 None hi");
+        assert_eq!(
+            replaying.query("bye", None).await.unwrap_err().to_string(),
+            "cassette error: no recorded interaction matches complete request to 'alpha-1': \"bye\""
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
 }
@@ -5,9 +5,16 @@
 use std::env;
 use std::fs;
 use std::io::{self, BufRead, Write};
+use std::path::Path;
 use std::process;
 
+use my_ai::RuntimeBackend;
+use my_lang::ai::{AiBackend, MockBackend};
+use my_lang::cassette::{CassetteMode, RecordingBackend, ReplayBackend};
+use my_lang::modules::{Loader, ModuleGraph};
+use my_lang::verify::Verdict;
 use my_lang::{Interpreter, Value};
+use my_pkg::PackageCache;
 
 fn main() {
     let args: Vec<String> = env::args().collect();
@@ -25,7 +32,22 @@ fn main() {
                 eprintln!("Error: run command requires a file argument");
                 process::exit(1);
             }
-            run_file(&args[2]);
+            let options = &args[3..];
+            let check_contracts = !options.iter().any(|a| a == "--no-contracts");
+            let mock = options.iter().any(|a| a == "--mock");
+            let cassette = [("--record", CassetteMode::Record), ("--replay", CassetteMode::Replay)]
+                .into_iter()
+                .find_map(|(flag, mode)| {
+                    let i = options.iter().position(|a| a == flag)?;
+                    match options.get(i + 1) {
+                        Some(path) => Some((mode, path.as_str())),
+                        None => {
+                            eprintln!("Error: {} requires a cassette file argument", flag);
+                            process::exit(1);
+                        }
+                    }
+                });
+            run_file(&args[2], check_contracts, mock, cassette);
         }
         "parse" => {
             if args.len() < 3 {
@@ -62,8 +84,15 @@ fn main() {
             }
             compile_file(&args[2]);
         }
+        "verify" => {
+            if args.len() < 3 {
+                eprintln!("Error: verify command requires a file argument");
+                process::exit(1);
+            }
+            verify_file(&args[2]);
+        }
         "repl" => {
-            run_repl();
+            run_repl(args[2..].iter().any(|a| a == "--mock"));
         }
         "help" | "--help" | "-h" => {
             print_usage();
@@ -74,7 +103,7 @@ fn main() {
         _ => {
             // Try to run as a file if it looks like a path
             if command.ends_with(".ml") || command.ends_with(".mylang") || args.len() == 2 {
-                run_file(command);
+                run_file(command, true, false, None);
             } else {
                 eprintln!("Unknown command: {}", command);
                 print_usage();
@@ -87,7 +116,7 @@ fn main() {
 fn print_usage() {
     eprintln!("My Language - A programming language with first-class AI integration");
     eprintln!();
-    eprintln!("Usage: my-lang <command> [file]");
+    eprintln!("Usage: my-lang <command> [file] [options]");
     eprintln!();
     eprintln!("Commands:");
     eprintln!("  run <file>        Run a source file with the interpreter");
@@ -97,25 +126,106 @@ fn print_usage() {
     eprintln!("  check <file>      Parse and validate syntax");
     eprintln!("  typecheck <file>  Parse and type-check a source file");
     eprintln!("  compile <file>    Full compilation (parse + typecheck)");
+    eprintln!("  verify <file>     Prove pre/post/invariant clauses statically");
     eprintln!("  help              Show this help message");
     eprintln!("  version           Show version information");
     eprintln!();
+    eprintln!("Options:");
+    eprintln!("  --no-contracts    Skip pre/post/invariant checks when running");
+    eprintln!("  --mock            Answer AI expressions offline instead of with a provider");
+    eprintln!("  --record <file>   Record provider replies to a fresh JSON cassette when running");
+    eprintln!("  --replay <file>   Serve AI calls from a recorded cassette when running");
+    eprintln!();
     eprintln!("Examples:");
     eprintln!("  my-lang run example.ml");
+    eprintln!("  my-lang run example.ml --no-contracts");
+    eprintln!("  my-lang run example.ml --replay example.cassette.json");
+    eprintln!("  my-lang run example.ml --mock");
     eprintln!("  my-lang repl");
     eprintln!("  my-lang typecheck example.ml");
+    eprintln!("  my-lang verify example.ml");
+    eprintln!();
+    eprintln!("AI expressions are answered by the providers configured with ANTHROPIC_API_KEY,");
+    eprintln!("OPENAI_API_KEY or OLLAMA_HOST; with none set, only --mock runs them.");
 }
 
-fn run_file(path: &str) {
-    let source = match fs::read_to_string(path) {
-        Ok(s) => s,
+/// The backend answering AI expressions: the offline mock if `mock` is set,
+/// else the providers configured in the environment, exiting if there are none
+fn ai_backend(mock: bool) -> Box<dyn AiBackend> {
+    if mock {
+        return Box::new(MockBackend::new());
+    }
+    match RuntimeBackend::from_env() {
+        Ok(backend) => Box::new(backend),
         Err(e) => {
-            eprintln!("Error reading file '{}': {}", path, e);
+            eprintln!("Error: {}", e);
+            process::exit(1);
+        }
+    }
+}
+
+/// Load a program with the modules it imports, exiting on failure. The
+/// dependencies of the nearest `my.toml` above the entry file are importable.
+fn load_program(path: &str) -> ModuleGraph {
+    let path = Path::new(path);
+    let absolute = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let project = absolute.parent().and_then(|dir| dir.ancestors().find(|d| d.join("my.toml").is_file()));
+    let loader = match project {
+        Some(dir) => match my_pkg::module_loader(dir, &PackageCache::default_cache()) {
+            Ok(loader) => loader,
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                process::exit(1);
+            }
+        },
+        None => Loader::new(),
+    };
+    match loader.load(path) {
+        Ok(graph) => graph,
+        Err(e) => {
+            eprintln!("Error: {}", e);
             process::exit(1);
         }
+    }
+}
+
+fn run_file(path: &str, check_contracts: bool, mock: bool, cassette: Option<(CassetteMode, &str)>) {
+    let graph = load_program(path);
+    let mut interpreter = Interpreter::new();
+    interpreter.check_contracts = check_contracts;
+    interpreter.ai_backend = match cassette {
+        // Record what a provider actually answers, unless the mock is asked for
+        Some((CassetteMode::Record, cassette_path)) => {
+            let live: Box<dyn AiBackend> = if mock {
+                Box::new(MockBackend::new())
+            } else {
+                match RuntimeBackend::from_env() {
+                    Ok(backend) => Box::new(backend),
+                    Err(e) => {
+                        eprintln!("Error: cannot record: {}", e);
+                        process::exit(1);
+                    }
+                }
+            };
+            match RecordingBackend::new(live, cassette_path) {
+                Ok(backend) => Box::new(backend),
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    process::exit(1);
+                }
+            }
+        }
+        Some((CassetteMode::Replay, cassette_path)) => match ReplayBackend::open(Path::new(cassette_path)) {
+            Ok(backend) => Box::new(backend),
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                process::exit(1);
+            }
+        },
+        None => ai_backend(mock),
     };
 
-    match my_lang::eval(&source) {
+    match interpreter.run_modules(&graph) {
         Ok(value) => {
             // Only print non-unit return values
             if !matches!(value, Value::Unit) {
@@ -123,7 +233,7 @@ fn run_file(path: &str) {
             }
         }
         Err(e) => {
-            eprintln!("{}", e);
+            eprintln!("Runtime error: {}", e);
             process::exit(1);
         }
     }
@@ -182,44 +292,36 @@ fn check_file(path: &str) {
         }
     };
 
-    match my_lang::parse(&source) {
-        Ok(program) => {
-            println!("OK: {} parsed successfully", path);
-            println!("    {} top-level items", program.items.len());
-        }
-        Err(e) => {
+    let (program, errors) = my_lang::parse_with_errors(&source);
+    if errors.is_empty() {
+        println!("OK: {} parsed successfully", path);
+        println!("    {} top-level items", program.items.len());
+    } else {
+        for e in &errors {
             eprintln!("FAIL: {}", e);
-            process::exit(1);
         }
+        process::exit(1);
     }
 }
 
 fn typecheck_file(path: &str) {
-    let source = match fs::read_to_string(path) {
-        Ok(s) => s,
-        Err(e) => {
-            eprintln!("Error reading file '{}': {}", path, e);
-            process::exit(1);
-        }
-    };
+    let graph = load_program(path);
 
-    let program = match my_lang::parse(&source) {
-        Ok(p) => p,
-        Err(e) => {
-            eprintln!("Parse error: {}", e);
-            process::exit(1);
-        }
-    };
-
-    match my_lang::check(&program) {
-        Ok(()) => {
+    match my_lang::check_modules(&graph) {
+        Ok(warnings) => {
+            for (file, warning) in &warnings {
+                eprintln!("warning: {}: {}", graph.module(*file).path.display(), warning);
+            }
             println!("OK: {} type-checked successfully", path);
-            println!("    {} top-level items", program.items.len());
+            println!("    {} top-level items", graph.entry().program.items.len());
+            if graph.modules.len() > 1 {
+                println!("    {} modules", graph.modules.len());
+            }
         }
         Err(errors) => {
             eprintln!("Type errors in {}:", path);
-            for error in &errors {
-                eprintln!("  - {}", error);
+            for (file, error) in &errors {
+                eprintln!("  - {}: {}", graph.module(*file).path.display(), error);
             }
             process::exit(1);
         }
@@ -227,27 +329,48 @@ fn typecheck_file(path: &str) {
 }
 
 fn compile_file(path: &str) {
-    let source = match fs::read_to_string(path) {
-        Ok(s) => s,
-        Err(e) => {
-            eprintln!("Error reading file '{}': {}", path, e);
-            process::exit(1);
-        }
-    };
+    let graph = load_program(path);
 
-    match my_lang::compile(&source) {
-        Ok(program) => {
+    match my_lang::check_modules(&graph) {
+        Ok(_) => {
             println!("OK: {} compiled successfully", path);
-            println!("    {} top-level items", program.items.len());
+            println!("    {} top-level items", graph.entry().program.items.len());
         }
-        Err(e) => {
-            eprintln!("Compilation failed: {}", e);
+        Err(errors) => {
+            for (file, error) in &errors {
+                eprintln!("Compilation failed: {}: {}", graph.module(*file).path.display(), error);
+            }
             process::exit(1);
         }
     }
 }
 
-fn run_repl() {
+fn verify_file(path: &str) {
+    let graph = load_program(path);
+
+    let reports = my_lang::verify::verify_modules(&graph);
+    let (mut proved, mut refuted) = (0, 0);
+    for report in &reports {
+        match report.verdict {
+            Verdict::Proved => proved += 1,
+            Verdict::Refuted(_) => refuted += 1,
+            Verdict::Unknown(_) => {}
+        }
+        println!("{}:{}", graph.module(report.span.file).path.display(), report);
+    }
+    println!(
+        "{}: {} proved, {} refuted, {} unknown",
+        path,
+        proved,
+        refuted,
+        reports.len() - proved - refuted
+    );
+    if refuted > 0 {
+        process::exit(1);
+    }
+}
+
+fn run_repl(mock: bool) {
     println!("My Language REPL v0.1.0");
     println!("Type 'help' for commands, 'exit' to quit");
     println!();
@@ -255,6 +378,7 @@ fn run_repl() {
     let stdin = io::stdin();
     let mut stdout = io::stdout();
     let mut interpreter = Interpreter::new();
+    interpreter.ai_backend = ai_backend(mock);
     let mut multiline_buffer = String::new();
     let mut in_multiline = false;
 
@@ -304,6 +428,7 @@ fn run_repl() {
             }
             "reset" | ":r" => {
                 interpreter = Interpreter::new();
+                interpreter.ai_backend = ai_backend(mock);
                 println!("Interpreter reset.");
                 continue;
             }
@@ -338,7 +463,9 @@ fn eval_repl_input(interpreter: &mut Interpreter, input: &str) {
                     let fn_value = Value::Function(std::rc::Rc::new(
                         my_lang::interpreter::FunctionValue {
                             name: func.name.name.clone(),
-                            params: func.params.iter().map(|p| p.name.name.clone()).collect(),
+                            params: func.params.clone(),
+                            return_type: func.return_type.clone(),
+                            contract: func.contract.clone(),
                             body: func.body.clone(),
                             closure: interpreter.env.clone(),
                         },
@@ -436,6 +563,15 @@ fn item_summary(item: &my_lang::TopLevel) -> String {
     match item {
         my_lang::TopLevel::Function(f) => format!("fn {}", f.name.name),
         my_lang::TopLevel::Struct(s) => format!("struct {}", s.name.name),
+        my_lang::TopLevel::Enum(e) => format!("enum {}", e.name.name),
+        my_lang::TopLevel::Trait(t) => format!("trait {}", t.name.name),
+        my_lang::TopLevel::Impl(i) => {
+            let self_ty = my_lang::types::ast_type_to_ty(&i.self_ty);
+            match &i.trait_name {
+                Some(t) => format!("impl {} for {}", t.name, self_ty),
+                None => format!("impl {}", self_ty),
+            }
+        }
         my_lang::TopLevel::Effect(e) => format!("effect {}", e.name.name),
         my_lang::TopLevel::AiModel(m) => format!("ai_model {}", m.name.name),
         my_lang::TopLevel::Prompt(p) => format!("prompt {}", p.name.name),
@@ -452,5 +588,6 @@ fn item_summary(item: &my_lang::TopLevel) -> String {
         my_lang::TopLevel::Comptime(_) => "comptime { ... }".to_string(),
         my_lang::TopLevel::Arena(a) => format!("arena {}", a.name.name),
         my_lang::TopLevel::Contract(c) => format!("contract {:?}", c),
+        my_lang::TopLevel::Error(_) => "<parse error>".to_string(),
     }
 }
@@ -4,7 +4,8 @@
 //! Provides consistent code formatting using a pretty-printing approach.
 //! Supports all My Language syntax including AI expressions and dialects.
 
-use my_lang::{parse, Program, TopLevel};
+use my_lang::cst::{AstNode, Item, SyntaxElement};
+use my_lang::{Program, TopLevel};
 use thiserror::Error;
 
 /// Formatter errors
@@ -130,10 +131,31 @@ impl Formatter {
         Formatter { config }
     }
 
-    /// Format source code
+    /// Format source code, keeping the comments between declarations
     pub fn format(&self, source: &str) -> Result<String, FormatError> {
-        let program = parse(source).map_err(|e| FormatError::ParseError(e.to_string()))?;
-        let doc = self.format_program(&program);
+        let tree = my_lang::cst::parse(source);
+        if let Some(error) = tree.errors.first() {
+            return Err(FormatError::ParseError(error.to_string()));
+        }
+
+        // The comments before each item, and those after the last one
+        let mut comments: Vec<Vec<String>> = Vec::new();
+        let mut pending = Vec::new();
+        for element in tree.syntax().children_with_tokens() {
+            match element {
+                SyntaxElement::Token(token) if token.kind().is_comment() => pending.push(token.text().to_string()),
+                SyntaxElement::Node(node) => {
+                    if let Some(item) = Item::cast(node) {
+                        pending.extend(item.leading_comments().iter().map(|c| c.text().to_string()));
+                        comments.push(std::mem::take(&mut pending));
+                    }
+                }
+                SyntaxElement::Token(_) => {}
+            }
+        }
+        comments.push(pending);
+
+        let doc = self.format_program(&tree.program, &comments);
         let mut result = doc.pretty(self.config.max_width);
 
         if self.config.final_newline && !result.ends_with('\n') {
@@ -143,20 +165,37 @@ impl Formatter {
         Ok(result)
     }
 
-    fn format_program(&self, program: &Program) -> Doc {
+    /// `comments` holds the comments before each item, then those at the end
+    fn format_program(&self, program: &Program, comments: &[Vec<String>]) -> Doc {
         let mut doc = Doc::Nil;
 
         for (i, item) in program.items.iter().enumerate() {
             if i > 0 {
                 doc = doc.concat(Doc::HardLine).concat(Doc::HardLine);
             }
+            for comment in comments.get(i).into_iter().flatten() {
+                doc = doc.concat(Doc::text(comment)).concat(Doc::HardLine);
+            }
             doc = doc.concat(self.format_top_level(item));
         }
 
+        let trailing = comments.get(program.items.len()).map(Vec::as_slice).unwrap_or_default();
+        for (i, comment) in trailing.iter().enumerate() {
+            if i > 0 || !program.items.is_empty() {
+                doc = doc.concat(Doc::HardLine);
+            }
+            doc = doc.concat(Doc::text(comment));
+        }
+
         doc
     }
 
     fn format_top_level(&self, item: &TopLevel) -> Doc {
+        let visibility = if item.is_public() { Doc::text("pub ") } else { Doc::Nil };
+        visibility.concat(self.format_declaration(item))
+    }
+
+    fn format_declaration(&self, item: &TopLevel) -> Doc {
         match item {
             TopLevel::Function(f) => {
                 Doc::text("fn ")
@@ -176,6 +215,11 @@ impl Formatter {
                     .concat(Doc::text(&s.name.name))
                     .concat(Doc::text(" { ... }"))
             }
+            TopLevel::Enum(e) => {
+                Doc::text("enum ")
+                    .concat(Doc::text(&e.name.name))
+                    .concat(Doc::text(" { ... }"))
+            }
             TopLevel::Effect(e) => {
                 Doc::text("effect ")
                     .concat(Doc::text(&e.name.name))
@@ -193,6 +237,20 @@ impl Formatter {
                     .concat(Doc::text(&p.template))
                     .concat(Doc::text("\" }"))
             }
+            TopLevel::Trait(t) => {
+                Doc::text("trait ")
+                    .concat(Doc::text(&t.name.name))
+                    .concat(Doc::text(" { ... }"))
+            }
+            TopLevel::Impl(i) => {
+                Doc::text("impl ")
+                    .concat(match &i.trait_name {
+                        Some(t) => Doc::text(&t.name).concat(Doc::text(" for ")),
+                        None => Doc::Nil,
+                    })
+                    .concat(Doc::text(my_lang::types::ast_type_to_ty(&i.self_ty).to_string()))
+                    .concat(Doc::text(" { ... }"))
+            }
             _ => Doc::text("// TODO: format this item"),
         }
     }
@@ -203,6 +261,9 @@ impl Formatter {
             if i > 0 {
                 doc = doc.concat(Doc::text(", "));
             }
+            if param.mutable {
+                doc = doc.concat(Doc::text("mut "));
+            }
             doc = doc
                 .concat(Doc::text(&param.name.name))
                 .concat(Doc::text(": "))
@@ -249,4 +310,13 @@ mod tests {
         let result = doc.pretty(80);
         assert!(result.contains("hello") && result.contains("world"));
     }
+
+    #[test]
+    fn test_format_keeps_comments() {
+        let source = "// Entry point\n/* runs first */\nfn main() { }\n\n// unrelated\n\nstruct Point { x: Int }\n";
+        let formatted = Formatter::new(FormatConfig::default()).format(source).unwrap();
+        assert_eq!(formatted, "// Entry point\n/* runs first */\nfn main() { ... }\n\n// unrelated\nstruct Point { ... }\n");
+        let formatted = Formatter::new(FormatConfig::default()).format("fn main() { }\n// the end\n").unwrap();
+        assert_eq!(formatted, "fn main() { ... }\n// the end\n");
+    }
 }
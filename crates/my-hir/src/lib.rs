@@ -14,10 +14,12 @@
 //! ```
 
 use my_lang::{
-    Program, TopLevel, FnDecl, StructDecl, EffectDecl, AiModelDecl,
-    Type, PrimitiveType, AiModelAttr, Block, Stmt, Expr, Literal,
-    BinaryOp, UnaryOp, Pattern, MatchArm, LambdaBody, AiExpr, AiKeyword,
+    Program, TopLevel, FnDecl, StructDecl, ImplDecl, TraitDecl, EnumDecl, VariantKind, EffectDecl, AiModelDecl,
+    Type, PrimitiveType, AiModelAttr, Block, Stmt, Expr, Literal, StringPart,
+    BinaryOp, UnaryOp, Pattern, MatchArm, LambdaBody, AiExpr, AiKeyword, HandlerClause,
 };
+use my_lang::modules::ModuleGraph;
+use std::collections::HashMap;
 use thiserror::Error;
 
 /// HIR lowering errors
@@ -31,6 +33,9 @@ pub enum HirError {
 
     #[error("invalid pattern: {0}")]
     InvalidPattern(String),
+
+    #[error("code at line {line}, column {column} failed to parse")]
+    Unparsed { line: usize, column: usize },
 }
 
 /// HIR Program representation
@@ -44,6 +49,7 @@ pub struct HirProgram {
 pub enum HirItem {
     Function(HirFunction),
     Struct(HirStruct),
+    Enum(HirEnum),
     Effect(HirEffect),
     AIModel(HirAIModel),
 }
@@ -52,6 +58,7 @@ pub enum HirItem {
 #[derive(Debug, Clone)]
 pub struct HirFunction {
     pub name: String,
+    pub type_params: Vec<String>,
     pub params: Vec<HirParam>,
     pub return_type: HirType,
     pub body: HirBlock,
@@ -74,6 +81,7 @@ pub enum HirType {
     AI(Box<HirType>),
     Effect(Box<HirType>, Vec<String>),
     Named(String),
+    Generic(String, Vec<HirType>),
     Unit,
 }
 
@@ -101,6 +109,21 @@ pub struct HirField {
     pub ty: HirType,
 }
 
+/// HIR enum definition
+#[derive(Debug, Clone)]
+pub struct HirEnum {
+    pub name: String,
+    pub type_params: Vec<String>,
+    pub variants: Vec<HirVariant>,
+}
+
+/// HIR enum variant; tuple payload fields are named by position
+#[derive(Debug, Clone)]
+pub struct HirVariant {
+    pub name: String,
+    pub fields: Vec<HirField>,
+}
+
 /// HIR effect declaration
 #[derive(Debug, Clone)]
 pub struct HirEffect {
@@ -142,6 +165,24 @@ pub enum HirStmt {
     },
     Expr(HirExpr),
     Return(Option<HirExpr>),
+    While {
+        label: Option<String>,
+        condition: HirExpr,
+        body: HirBlock,
+    },
+    For {
+        label: Option<String>,
+        binding: String,
+        iterable: HirExpr,
+        body: HirBlock,
+    },
+    Break {
+        label: Option<String>,
+        value: Option<HirExpr>,
+    },
+    Continue {
+        label: Option<String>,
+    },
 }
 
 /// HIR expression
@@ -152,14 +193,34 @@ pub enum HirExpr {
     Call(Box<HirExpr>, Vec<HirExpr>),
     Lambda(Vec<HirParam>, Box<HirExpr>),
     If(Box<HirExpr>, HirBlock, Option<HirBlock>),
+    Loop(Option<String>, HirBlock),
+    Assign(Box<HirExpr>, Box<HirExpr>),
     Match(Box<HirExpr>, Vec<HirArm>),
     Block(HirBlock),
     Field(Box<HirExpr>, String),
+    MethodCall(Box<HirExpr>, String, Vec<HirExpr>),
+    Index(Box<HirExpr>, Box<HirExpr>),
+    Slice(Box<HirExpr>, Option<Box<HirExpr>>, Option<Box<HirExpr>>),
     Array(Vec<HirExpr>),
+    Tuple(Vec<HirExpr>),
     Record(Vec<(String, HirExpr)>),
+    Construct(String, Vec<(String, HirExpr)>),
     BinOp(Box<HirExpr>, HirBinOp, Box<HirExpr>),
     UnOp(HirUnOp, Box<HirExpr>),
     AI(HirAIExpr),
+    /// Effect operation: effect, operation and arguments
+    Perform(String, String, Vec<HirExpr>),
+    /// Effect handler: body, handled effect and one clause per operation
+    Handle(HirBlock, String, Vec<HirHandlerClause>),
+    Resume(Box<HirExpr>),
+}
+
+/// HIR handler clause
+#[derive(Debug, Clone)]
+pub struct HirHandlerClause {
+    pub op: String,
+    pub params: Vec<String>,
+    pub body: HirExpr,
 }
 
 /// HIR literal
@@ -178,6 +239,12 @@ pub enum HirBinOp {
     Sub,
     Mul,
     Div,
+    Rem,
+    BitAnd,
+    BitOr,
+    BitXor,
+    Shl,
+    Shr,
     Eq,
     Ne,
     Lt,
@@ -212,6 +279,15 @@ pub enum HirPattern {
     Var(String),
     Literal(HirLiteral),
     Constructor(String, Vec<HirPattern>),
+    Struct(String, Vec<(String, HirPattern)>),
+    Record(Vec<(String, HirPattern)>),
+    Tuple(Vec<HirPattern>),
+    /// Prefix, the pattern bound to the elements matched by `..`, and suffix
+    Slice(Vec<HirPattern>, Option<Box<HirPattern>>, Vec<HirPattern>),
+    /// Start, end and whether the end is inclusive
+    Range(HirLiteral, HirLiteral, bool),
+    Or(Vec<HirPattern>),
+    Binding(String, Box<HirPattern>),
 }
 
 /// HIR AI expression
@@ -233,11 +309,39 @@ pub enum HirAIExpr {
     },
 }
 
-/// Lower AST to HIR
+/// Lower AST to HIR. A single program has no other modules to import from,
+/// so any `use` outside `std` is unresolved; see [`lower_modules`].
 pub fn lower(program: &Program) -> Result<HirProgram, HirError> {
+    for item in &program.items {
+        if let TopLevel::Import(import) = item {
+            let path: Vec<&str> = import.path.iter().map(|s| s.name.as_str()).collect();
+            if path.first() != Some(&"std") {
+                return Err(HirError::UnresolvedImport(path.join("::")));
+            }
+        }
+    }
+    lower_items(program)
+}
+
+/// Lower each module of a loaded program, indexed by file id. The loader has
+/// already resolved the imports of the modules.
+pub fn lower_modules(graph: &ModuleGraph) -> Result<Vec<HirProgram>, HirError> {
+    graph.modules.iter().map(|module| lower_items(&module.program)).collect()
+}
+
+fn lower_items(program: &Program) -> Result<HirProgram, HirError> {
     let mut items = Vec::new();
+    let traits: HashMap<&str, &TraitDecl> = program
+        .items
+        .iter()
+        .filter_map(|item| match item {
+            TopLevel::Trait(t) => Some((t.name.name.as_str(), t)),
+            _ => None,
+        })
+        .collect();
+    let derived = my_lang::stdlib::derived_impls(&program.items);
 
-    for item in &program.items {
+    for item in program.items.iter().chain(&derived) {
         match item {
             TopLevel::Function(f) => {
                 items.push(HirItem::Function(lower_function(f)?));
@@ -245,24 +349,45 @@ pub fn lower(program: &Program) -> Result<HirProgram, HirError> {
             TopLevel::Struct(s) => {
                 items.push(HirItem::Struct(lower_struct(s)?));
             }
+            TopLevel::Enum(e) => {
+                items.push(HirItem::Enum(lower_enum(e)?));
+            }
             TopLevel::Effect(e) => {
                 items.push(HirItem::Effect(lower_effect(e)?));
             }
             TopLevel::AiModel(m) => {
                 items.push(HirItem::AIModel(lower_ai_model(m)?));
             }
+            TopLevel::Impl(i) => {
+                let defaults = i
+                    .trait_name
+                    .as_ref()
+                    .and_then(|t| traits.get(t.name.as_str()).copied());
+                items.extend(lower_impl(i, defaults)?.into_iter().map(HirItem::Function));
+            }
+            TopLevel::Error(span) => {
+                return Err(HirError::Unparsed { line: span.line, column: span.column });
+            }
             _ => {
                 // TODO: Handle other top-level items
             }
         }
     }
 
+    let rows = my_lang::effects::infer(program);
+    for item in &mut items {
+        if let HirItem::Function(f) = item {
+            f.effects = rows.get(&f.name).map(|row| row.keys().cloned().collect()).unwrap_or_default();
+        }
+    }
+
     Ok(HirProgram { items })
 }
 
 fn lower_function(f: &FnDecl) -> Result<HirFunction, HirError> {
     Ok(HirFunction {
         name: f.name.name.clone(),
+        type_params: f.type_params.iter().map(|p| p.name.name.clone()).collect(),
         params: f
             .params
             .iter()
@@ -274,17 +399,84 @@ fn lower_function(f: &FnDecl) -> Result<HirFunction, HirError> {
         return_type: f
             .return_type
             .as_ref()
-            .map(|t| lower_type(t))
+            .map(lower_type)
             .unwrap_or(HirType::Unit),
         body: lower_block(&f.body)?,
-        effects: vec![], // TODO: Extract from contract or modifiers
+        effects: vec![], // Filled in from the program's effect rows
     })
 }
 
+/// Lower an impl block to free functions named `Type::method`, with `Self`
+/// replaced by the implementing type and the trait's default methods filled in
+fn lower_impl(i: &ImplDecl, tr: Option<&TraitDecl>) -> Result<Vec<HirFunction>, HirError> {
+    let Some(key) = my_lang::types::ast_type_to_ty(&i.self_ty).impl_key() else {
+        return Ok(vec![]);
+    };
+    let self_ty = lower_type(&i.self_ty);
+    let impl_params: Vec<String> = i.type_params.iter().map(|p| p.name.name.clone()).collect();
+
+    let mut functions = Vec::new();
+    for m in &i.methods {
+        let mut f = lower_function(m)?;
+        f.type_params = impl_params.iter().cloned().chain(f.type_params).collect();
+        functions.push(f);
+    }
+    for method in tr.map(|t| t.methods.as_slice()).unwrap_or_default() {
+        let Some(body) = &method.default else { continue };
+        if i.methods.iter().any(|m| m.name.name == method.name.name) {
+            continue;
+        }
+        functions.push(HirFunction {
+            name: method.name.name.clone(),
+            type_params: impl_params
+                .iter()
+                .cloned()
+                .chain(method.type_params.iter().map(|p| p.name.name.clone()))
+                .collect(),
+            params: method
+                .params
+                .iter()
+                .map(|p| HirParam {
+                    name: p.name.name.clone(),
+                    ty: lower_type(&p.ty),
+                })
+                .collect(),
+            return_type: method.return_type.as_ref().map(lower_type).unwrap_or(HirType::Unit),
+            body: lower_block(body)?,
+            effects: vec![],
+        });
+    }
+
+    for f in &mut functions {
+        f.name = format!("{}::{}", key, f.name);
+        for p in &mut f.params {
+            p.ty = substitute_self(&p.ty, &self_ty);
+        }
+        f.return_type = substitute_self(&f.return_type, &self_ty);
+    }
+    Ok(functions)
+}
+
+fn substitute_self(ty: &HirType, self_ty: &HirType) -> HirType {
+    let sub = |t: &HirType| Box::new(substitute_self(t, self_ty));
+    match ty {
+        HirType::Named(name) if name == "Self" => self_ty.clone(),
+        HirType::Function(p, r) => HirType::Function(sub(p), sub(r)),
+        HirType::Array(t) => HirType::Array(sub(t)),
+        HirType::AI(t) => HirType::AI(sub(t)),
+        HirType::Effect(t, effects) => HirType::Effect(sub(t), effects.clone()),
+        HirType::Generic(name, args) => HirType::Generic(
+            name.clone(),
+            args.iter().map(|a| substitute_self(a, self_ty)).collect(),
+        ),
+        _ => ty.clone(),
+    }
+}
+
 fn lower_struct(s: &StructDecl) -> Result<HirStruct, HirError> {
     Ok(HirStruct {
         name: s.name.name.clone(),
-        type_params: s.type_params.iter().map(|p| p.name.clone()).collect(),
+        type_params: s.type_params.iter().map(|p| p.name.name.clone()).collect(),
         fields: s
             .fields
             .iter()
@@ -296,16 +488,55 @@ fn lower_struct(s: &StructDecl) -> Result<HirStruct, HirError> {
     })
 }
 
+fn lower_enum(e: &EnumDecl) -> Result<HirEnum, HirError> {
+    Ok(HirEnum {
+        name: e.name.name.clone(),
+        type_params: e.type_params.iter().map(|p| p.name.name.clone()).collect(),
+        variants: e
+            .variants
+            .iter()
+            .map(|v| HirVariant {
+                name: v.name.name.clone(),
+                fields: match &v.kind {
+                    VariantKind::Unit => vec![],
+                    VariantKind::Tuple(types) => types
+                        .iter()
+                        .enumerate()
+                        .map(|(i, ty)| HirField {
+                            name: i.to_string(),
+                            ty: lower_type(ty),
+                        })
+                        .collect(),
+                    VariantKind::Struct(fields) => fields
+                        .iter()
+                        .map(|f| HirField {
+                            name: f.name.name.clone(),
+                            ty: lower_type(&f.ty),
+                        })
+                        .collect(),
+                },
+            })
+            .collect(),
+    })
+}
+
 fn lower_effect(e: &EffectDecl) -> Result<HirEffect, HirError> {
     Ok(HirEffect {
         name: e.name.name.clone(),
         operations: e
             .ops
             .iter()
-            .map(|op| HirOperation {
-                name: op.name.name.clone(),
-                params: vec![],
-                return_type: lower_type(&op.ty),
+            .map(|op| {
+                // `op name: A -> R`, or `op name: A` resuming with unit
+                let (param, return_type) = match &op.ty {
+                    Type::Function { param, result, .. } => (param.as_ref(), lower_type(result)),
+                    ty => (ty, HirType::Unit),
+                };
+                let params = match param {
+                    Type::Tuple { elements, .. } => elements.iter().map(lower_type).collect(),
+                    ty => vec![lower_type(ty)],
+                };
+                HirOperation { name: op.name.name.clone(), params, return_type }
             })
             .collect(),
     })
@@ -345,7 +576,13 @@ fn lower_type(ty: &Type) -> HirType {
             HirType::Function(Box::new(lower_type(param)), Box::new(lower_type(result)))
         }
         Type::Named(name) => HirType::Named(name.name.clone()),
-        Type::Effect { inner, .. } => HirType::Effect(Box::new(lower_type(inner)), vec![]),
+        Type::Generic { name, args, .. } => {
+            HirType::Generic(name.name.clone(), args.iter().map(lower_type).collect())
+        }
+        Type::Effect { inner, effects, .. } => HirType::Effect(
+            Box::new(lower_type(inner)),
+            effects.iter().map(|e| e.name.clone()).collect(),
+        ),
         _ => HirType::Unit,
     }
 }
@@ -362,6 +599,17 @@ fn lower_block(block: &Block) -> Result<HirBlock, HirError> {
             Stmt::Expr(expr) if is_last => {
                 final_expr = Some(Box::new(lower_expr(expr)?));
             }
+            Stmt::Let { pattern, ty, value, span, .. } if !matches!(pattern, Pattern::Ident(_)) => {
+                // Destructuring binds the value to a hidden local and each
+                // name in the pattern to a projection of it
+                let tmp = format!("$let{}_{}", span.line, span.column);
+                stmts.push(HirStmt::Let {
+                    name: tmp.clone(),
+                    ty: ty.as_ref().map(lower_type),
+                    value: lower_expr(value)?,
+                });
+                lower_let_pattern(pattern, HirExpr::Var(tmp), &mut stmts)?;
+            }
             _ => {
                 stmts.push(lower_stmt(stmt)?);
             }
@@ -376,11 +624,12 @@ fn lower_block(block: &Block) -> Result<HirBlock, HirError> {
 
 fn lower_stmt(stmt: &Stmt) -> Result<HirStmt, HirError> {
     match stmt {
-        Stmt::Let { name, ty, value, .. } => Ok(HirStmt::Let {
+        Stmt::Let { pattern: Pattern::Ident(name), ty, value, .. } => Ok(HirStmt::Let {
             name: name.name.clone(),
             ty: ty.as_ref().map(lower_type),
             value: lower_expr(value)?,
         }),
+        Stmt::Let { .. } => unreachable!("destructuring let is lowered by lower_block"),
         Stmt::Expr(expr) => Ok(HirStmt::Expr(lower_expr(expr)?)),
         Stmt::Return { value, .. } => Ok(HirStmt::Return(
             value.as_ref().map(lower_expr).transpose()?,
@@ -394,6 +643,24 @@ fn lower_stmt(stmt: &Stmt) -> Result<HirStmt, HirError> {
             );
             Ok(HirStmt::Expr(hir_if))
         }
+        Stmt::While { label, condition, body, .. } => Ok(HirStmt::While {
+            label: label.as_ref().map(|l| l.name.clone()),
+            condition: lower_expr(condition)?,
+            body: lower_block(body)?,
+        }),
+        Stmt::For { label, binding, iterable, body, .. } => Ok(HirStmt::For {
+            label: label.as_ref().map(|l| l.name.clone()),
+            binding: binding.name.clone(),
+            iterable: lower_expr(iterable)?,
+            body: lower_block(body)?,
+        }),
+        Stmt::Break { label, value, .. } => Ok(HirStmt::Break {
+            label: label.as_ref().map(|l| l.name.clone()),
+            value: value.as_ref().map(lower_expr).transpose()?,
+        }),
+        Stmt::Continue { label, .. } => Ok(HirStmt::Continue {
+            label: label.as_ref().map(|l| l.name.clone()),
+        }),
         Stmt::Go { block, .. } => {
             // Lower go blocks as async expressions (placeholder)
             Ok(HirStmt::Expr(HirExpr::Block(lower_block(block)?)))
@@ -414,12 +681,83 @@ fn lower_stmt(stmt: &Stmt) -> Result<HirStmt, HirError> {
             let hir_ai = lower_ai_keyword_expr(ai_stmt.keyword, &ai_stmt.body)?;
             Ok(HirStmt::Expr(hir_ai))
         }
+        Stmt::Error(span) => Err(HirError::Unparsed { line: span.line, column: span.column }),
+    }
+}
+
+/// Bind each name in an irrefutable `let` pattern to its projection of `path`
+fn lower_let_pattern(pattern: &Pattern, path: HirExpr, stmts: &mut Vec<HirStmt>) -> Result<(), HirError> {
+    let field = |name: String| HirExpr::Field(Box::new(path.clone()), name);
+    let int = |n: usize| HirExpr::Literal(HirLiteral::Int(n as i64));
+    match pattern {
+        Pattern::Ident(ident) => stmts.push(HirStmt::Let {
+            name: ident.name.clone(),
+            ty: None,
+            value: path,
+        }),
+        Pattern::Wildcard(_) | Pattern::Literal(_) | Pattern::Range { .. } => {}
+        // Positional fields are numbered, as for tuples
+        Pattern::Tuple(elements, _) | Pattern::Constructor { args: elements, .. } => {
+            for (i, element) in elements.iter().enumerate() {
+                lower_let_pattern(element, field(i.to_string()), stmts)?;
+            }
+        }
+        Pattern::Struct { fields, .. } | Pattern::Record { fields, .. } => {
+            for (name, element) in fields {
+                lower_let_pattern(element, field(name.name.clone()), stmts)?;
+            }
+        }
+        Pattern::Slice { prefix, rest, suffix, .. } => {
+            let len = HirExpr::Call(Box::new(HirExpr::Var("len".to_string())), vec![path.clone()]);
+            let from_end = |n: usize| HirExpr::BinOp(Box::new(len.clone()), HirBinOp::Sub, Box::new(int(n)));
+            let index = |i: HirExpr| HirExpr::Index(Box::new(path.clone()), Box::new(i));
+            for (i, element) in prefix.iter().enumerate() {
+                lower_let_pattern(element, index(int(i)), stmts)?;
+            }
+            if let Some(rest) = rest {
+                let middle = HirExpr::Slice(
+                    Box::new(path.clone()),
+                    Some(Box::new(int(prefix.len()))),
+                    Some(Box::new(from_end(suffix.len()))),
+                );
+                lower_let_pattern(rest, middle, stmts)?;
+            }
+            for (i, element) in suffix.iter().enumerate() {
+                lower_let_pattern(element, index(from_end(suffix.len() - i)), stmts)?;
+            }
+        }
+        Pattern::Or(alternatives, _) => {
+            // Every alternative binds the same names; an irrefutable
+            // or-pattern matches through its first one
+            if let Some(first) = alternatives.first() {
+                lower_let_pattern(first, path, stmts)?;
+            }
+        }
+        Pattern::Binding { name, pattern, .. } => {
+            stmts.push(HirStmt::Let {
+                name: name.name.clone(),
+                ty: None,
+                value: path,
+            });
+            lower_let_pattern(pattern, HirExpr::Var(name.name.clone()), stmts)?;
+        }
     }
+    Ok(())
 }
 
 fn lower_expr(expr: &Expr) -> Result<HirExpr, HirError> {
     match expr {
         Expr::Literal(lit) => Ok(HirExpr::Literal(lower_literal(lit))),
+        Expr::Interpolation { parts, .. } => {
+            // Sugar for concatenating the parts, displayed with `to_string`
+            let builtin = |name: &str, args| HirExpr::Call(Box::new(HirExpr::Var(name.to_string())), args);
+            let mut lowered = parts.iter().map(|part| match part {
+                StringPart::Text(text) => Ok(HirExpr::Literal(HirLiteral::String(text.clone()))),
+                StringPart::Expr(expr) => Ok(builtin("to_string", vec![lower_expr(expr)?])),
+            });
+            let first = lowered.next().unwrap_or(Ok(HirExpr::Literal(HirLiteral::String(String::new()))))?;
+            lowered.try_fold(first, |text, part| Ok(builtin("str_concat", vec![text, part?])))
+        }
         Expr::Ident(ident) => Ok(HirExpr::Var(ident.name.clone())),
         Expr::Call { callee, args, .. } => Ok(HirExpr::Call(
             Box::new(lower_expr(callee)?),
@@ -429,6 +767,30 @@ fn lower_expr(expr: &Expr) -> Result<HirExpr, HirError> {
             Box::new(lower_expr(object)?),
             field.name.clone(),
         )),
+        Expr::MethodCall { receiver, method, args, .. } => Ok(HirExpr::MethodCall(
+            Box::new(lower_expr(receiver)?),
+            method.name.clone(),
+            args.iter().map(lower_expr).collect::<Result<Vec<_>, _>>()?,
+        )),
+        Expr::Path { ty, name, .. } => Ok(HirExpr::Var(format!("{}::{}", ty.name, name.name))),
+        Expr::Index { object, index, .. } => Ok(HirExpr::Index(
+            Box::new(lower_expr(object)?),
+            Box::new(lower_expr(index)?),
+        )),
+        Expr::Slice { object, start, end, .. } => Ok(HirExpr::Slice(
+            Box::new(lower_expr(object)?),
+            start.as_deref().map(lower_expr).transpose()?.map(Box::new),
+            end.as_deref().map(lower_expr).transpose()?.map(Box::new),
+        )),
+        Expr::Assign { target, op, value, .. } => {
+            let target = lower_expr(target)?;
+            let mut value = lower_expr(value)?;
+            // `target op= value` is sugar for `target = target op value`
+            if let Some(op) = op {
+                value = HirExpr::BinOp(Box::new(target.clone()), lower_binop(*op), Box::new(value));
+            }
+            Ok(HirExpr::Assign(Box::new(target), Box::new(value)))
+        }
         Expr::Binary { left, op, right, .. } => Ok(HirExpr::BinOp(
             Box::new(lower_expr(left)?),
             lower_binop(*op),
@@ -443,11 +805,26 @@ fn lower_expr(expr: &Expr) -> Result<HirExpr, HirError> {
             lower_expr(operand)
         }
         Expr::Block(block) => Ok(HirExpr::Block(lower_block(block)?)),
+        Expr::Loop { label, body, .. } => Ok(HirExpr::Loop(
+            label.as_ref().map(|l| l.name.clone()),
+            lower_block(body)?,
+        )),
         Expr::Restrict { operand, .. } => {
             // Restrict expressions pass through (checked separately)
             lower_expr(operand)
         }
         Expr::Ai(ai_expr) => lower_ai_expr(ai_expr),
+        Expr::Perform { effect, op, args, .. } => Ok(HirExpr::Perform(
+            effect.name.clone(),
+            op.name.clone(),
+            args.iter().map(lower_expr).collect::<Result<Vec<_>, _>>()?,
+        )),
+        Expr::Handle { body, effect, clauses, .. } => Ok(HirExpr::Handle(
+            lower_block(body)?,
+            effect.name.clone(),
+            clauses.iter().map(lower_handler_clause).collect::<Result<Vec<_>, _>>()?,
+        )),
+        Expr::Resume { value, .. } => Ok(HirExpr::Resume(Box::new(lower_expr(value)?))),
         Expr::Lambda { params, body, .. } => {
             let hir_params: Vec<HirParam> = params
                 .iter()
@@ -471,12 +848,22 @@ fn lower_expr(expr: &Expr) -> Result<HirExpr, HirError> {
         Expr::Array { elements, .. } => Ok(HirExpr::Array(
             elements.iter().map(lower_expr).collect::<Result<Vec<_>, _>>()?,
         )),
+        Expr::Tuple { elements, .. } => Ok(HirExpr::Tuple(
+            elements.iter().map(lower_expr).collect::<Result<Vec<_>, _>>()?,
+        )),
         Expr::Record { fields, .. } => Ok(HirExpr::Record(
             fields
                 .iter()
                 .map(|f| Ok((f.name.name.clone(), lower_expr(&f.value)?)))
                 .collect::<Result<Vec<_>, HirError>>()?,
         )),
+        Expr::StructLit { name, fields, .. } => Ok(HirExpr::Construct(
+            name.name.clone(),
+            fields
+                .iter()
+                .map(|f| Ok((f.name.name.clone(), lower_expr(&f.value)?)))
+                .collect::<Result<Vec<_>, HirError>>()?,
+        )),
     }
 }
 
@@ -495,6 +882,12 @@ fn lower_binop(op: BinaryOp) -> HirBinOp {
         BinaryOp::Sub => HirBinOp::Sub,
         BinaryOp::Mul => HirBinOp::Mul,
         BinaryOp::Div => HirBinOp::Div,
+        BinaryOp::Rem => HirBinOp::Rem,
+        BinaryOp::BitAnd => HirBinOp::BitAnd,
+        BinaryOp::BitOr => HirBinOp::BitOr,
+        BinaryOp::BitXor => HirBinOp::BitXor,
+        BinaryOp::Shl => HirBinOp::Shl,
+        BinaryOp::Shr => HirBinOp::Shr,
         BinaryOp::Eq => HirBinOp::Eq,
         BinaryOp::Ne => HirBinOp::Ne,
         BinaryOp::Lt => HirBinOp::Lt,
@@ -503,7 +896,6 @@ fn lower_binop(op: BinaryOp) -> HirBinOp {
         BinaryOp::Ge => HirBinOp::Ge,
         BinaryOp::And => HirBinOp::And,
         BinaryOp::Or => HirBinOp::Or,
-        BinaryOp::Assign => HirBinOp::Add, // Assignments handled separately
     }
 }
 
@@ -516,10 +908,18 @@ fn lower_unop(op: UnaryOp) -> HirUnOp {
     }
 }
 
+fn lower_handler_clause(clause: &HandlerClause) -> Result<HirHandlerClause, HirError> {
+    Ok(HirHandlerClause {
+        op: clause.op.name.clone(),
+        params: clause.params.iter().map(|p| p.name.clone()).collect(),
+        body: lower_expr(&clause.body)?,
+    })
+}
+
 fn lower_match_arm(arm: &MatchArm) -> Result<HirArm, HirError> {
     Ok(HirArm {
         pattern: lower_pattern(&arm.pattern)?,
-        guard: None, // TODO: Add guard support if needed
+        guard: arm.guard.as_ref().map(lower_expr).transpose()?,
         body: lower_expr(&arm.body)?,
     })
 }
@@ -533,6 +933,37 @@ fn lower_pattern(pattern: &Pattern) -> Result<HirPattern, HirError> {
             name.name.clone(),
             args.iter().map(lower_pattern).collect::<Result<Vec<_>, _>>()?,
         )),
+        Pattern::Struct { name, fields, .. } => Ok(HirPattern::Struct(
+            name.name.clone(),
+            fields
+                .iter()
+                .map(|(field, pat)| Ok((field.name.clone(), lower_pattern(pat)?)))
+                .collect::<Result<Vec<_>, HirError>>()?,
+        )),
+        Pattern::Record { fields, .. } => Ok(HirPattern::Record(
+            fields
+                .iter()
+                .map(|(field, pat)| Ok((field.name.clone(), lower_pattern(pat)?)))
+                .collect::<Result<Vec<_>, HirError>>()?,
+        )),
+        Pattern::Tuple(elements, _) => Ok(HirPattern::Tuple(
+            elements.iter().map(lower_pattern).collect::<Result<Vec<_>, _>>()?,
+        )),
+        Pattern::Slice { prefix, rest, suffix, .. } => Ok(HirPattern::Slice(
+            prefix.iter().map(lower_pattern).collect::<Result<Vec<_>, _>>()?,
+            rest.as_deref().map(lower_pattern).transpose()?.map(Box::new),
+            suffix.iter().map(lower_pattern).collect::<Result<Vec<_>, _>>()?,
+        )),
+        Pattern::Range { start, end, inclusive, .. } => {
+            Ok(HirPattern::Range(lower_literal(start), lower_literal(end), *inclusive))
+        }
+        Pattern::Or(alternatives, _) => Ok(HirPattern::Or(
+            alternatives.iter().map(lower_pattern).collect::<Result<Vec<_>, _>>()?,
+        )),
+        Pattern::Binding { name, pattern, .. } => Ok(HirPattern::Binding(
+            name.name.clone(),
+            Box::new(lower_pattern(pattern)?),
+        )),
     }
 }
 
@@ -596,7 +1027,7 @@ fn lower_ai_expr(ai_expr: &AiExpr) -> Result<HirExpr, HirError> {
         AiExpr::PromptInvocation { name, args, .. } => {
             let hir_args: Vec<HirExpr> = args
                 .iter()
-                .map(lower_expr)
+                .map(|arg| lower_expr(&arg.value))
                 .collect::<Result<Vec<_>, _>>()?;
 
             Ok(HirExpr::AI(HirAIExpr::Generate {
@@ -654,4 +1085,97 @@ mod tests {
         let hir = lower(&program).unwrap();
         assert!(hir.items.is_empty());
     }
+
+    #[test]
+    fn test_lower_match_guard_and_patterns() {
+        let program = my_lang::parse(r#"
+            fn main() {
+                match xs {
+                    [x, ..rest] if x > 0 => x,
+                    n @ 1..=9 | n @ 20..30 => n,
+                    _ => 0,
+                };
+            }
+        "#).unwrap();
+        let hir = lower(&program).unwrap();
+        let HirItem::Function(f) = &hir.items[0] else {
+            panic!("Expected function");
+        };
+        let Some(HirExpr::Match(_, arms)) = f.body.expr.as_deref() else {
+            panic!("Expected match, got {:?}", f.body);
+        };
+        assert!(arms[0].guard.is_some());
+        assert!(matches!(&arms[0].pattern, HirPattern::Slice(prefix, Some(_), suffix)
+            if prefix.len() == 1 && suffix.is_empty()));
+        assert!(matches!(&arms[1].pattern, HirPattern::Or(alts)
+            if matches!(&alts[1], HirPattern::Binding(n, r) if n == "n" && matches!(r.as_ref(), HirPattern::Range(_, _, false)))));
+        assert!(arms[2].guard.is_none());
+    }
+
+    #[test]
+    fn test_lower_destructuring_let() {
+        let program = my_lang::parse(r#"
+            fn main() {
+                let (a, {b, ..}) = (1, {b: 2});
+                return a + b;
+            }
+        "#).unwrap();
+        let hir = lower(&program).unwrap();
+        let HirItem::Function(f) = &hir.items[0] else {
+            panic!("Expected function");
+        };
+        let names: Vec<&str> = f.body.stmts.iter().filter_map(|s| match s {
+            HirStmt::Let { name, .. } => Some(name.as_str()),
+            _ => None,
+        }).collect();
+        assert_eq!(names, ["$let3_17", "a", "b"]);
+        assert!(matches!(&f.body.stmts[0], HirStmt::Let { value: HirExpr::Tuple(elements), .. } if elements.len() == 2));
+        let HirStmt::Let { value: HirExpr::Field(tuple, b), .. } = &f.body.stmts[2] else {
+            panic!("Expected projection");
+        };
+        assert_eq!(b, "b");
+        assert!(matches!(tuple.as_ref(), HirExpr::Field(tmp, i) if i == "1" && matches!(tmp.as_ref(), HirExpr::Var(v) if v == "$let3_17")));
+    }
+
+    #[test]
+    fn test_lower_effects() {
+        let program = my_lang::parse(r#"
+            effect Store {
+                op load: String -> Int
+                op save: (String, Int) -> Bool
+            }
+            fn bump(key: String) {
+                perform Store.save(key, perform Store.load(key) + 1);
+            }
+            fn main() {
+                handle { bump("n"); } with Store {
+                    load(k) => resume(0),
+                    save(k, v) => resume(true),
+                };
+            }
+        "#).unwrap();
+        let hir = lower(&program).unwrap();
+        let HirItem::Effect(e) = &hir.items[0] else {
+            panic!("Expected effect");
+        };
+        assert_eq!(e.operations[1].params.len(), 2);
+        assert!(matches!(e.operations[1].return_type, HirType::Primitive(HirPrimitive::Bool)));
+        let [_, HirItem::Function(bump), HirItem::Function(main)] = &hir.items[..] else {
+            panic!("Expected functions");
+        };
+        assert_eq!(bump.effects, vec!["Store"]);
+        assert!(main.effects.is_empty());
+        assert!(matches!(main.body.expr.as_deref(), Some(HirExpr::Handle(_, effect, clauses)) if effect == "Store" && clauses.len() == 2));
+    }
+
+    #[test]
+    fn test_lower_unresolved_import() {
+        let program = my_lang::parse(r#"
+            use std::io;
+            use geometry::{area};
+            fn main() {}
+        "#).unwrap();
+        let err = lower(&program).unwrap_err();
+        assert!(matches!(&err, HirError::UnresolvedImport(path) if path == "geometry"), "{:?}", err);
+    }
 }
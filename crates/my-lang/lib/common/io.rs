@@ -41,7 +41,7 @@ pub fn read_all_lines() -> Vec<String> {
     io::stdin()
         .lock()
         .lines()
-        .filter_map(|l| l.ok())
+        .map_while(Result::ok)
         .collect()
 }
 
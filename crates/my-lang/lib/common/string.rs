@@ -135,7 +135,7 @@ pub fn pad_start(s: &str, len: usize, pad: char) -> String {
     if s.len() >= len {
         s.to_string()
     } else {
-        let padding: String = std::iter::repeat(pad).take(len - s.len()).collect();
+        let padding: String = std::iter::repeat_n(pad, len - s.len()).collect();
         format!("{}{}", padding, s)
     }
 }
@@ -145,7 +145,7 @@ pub fn pad_end(s: &str, len: usize, pad: char) -> String {
     if s.len() >= len {
         s.to_string()
     } else {
-        let padding: String = std::iter::repeat(pad).take(len - s.len()).collect();
+        let padding: String = std::iter::repeat_n(pad, len - s.len()).collect();
         format!("{}{}", s, padding)
     }
 }
@@ -197,7 +197,7 @@ pub fn parse_float(s: &str) -> Option<f64> {
 
 /// Format integer to string with radix
 pub fn int_to_string_radix(n: i64, radix: u32) -> Option<String> {
-    if radix < 2 || radix > 36 {
+    if !(2..=36).contains(&radix) {
         return None;
     }
 
@@ -206,7 +206,7 @@ pub fn int_to_string_radix(n: i64, radix: u32) -> Option<String> {
     }
 
     let mut result = String::new();
-    let mut num = n.abs() as u64;
+    let mut num = n.unsigned_abs();
 
     while num > 0 {
         let digit = (num % radix as u64) as u32;
@@ -297,7 +297,7 @@ mod tests {
     #[test]
     fn test_parse() {
         assert_eq!(parse_int("42"), Some(42));
-        assert_eq!(parse_float("3.14"), Some(3.14));
+        assert_eq!(parse_float("2.5"), Some(2.5));
     }
 
     #[test]
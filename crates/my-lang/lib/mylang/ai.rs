@@ -23,6 +23,7 @@ pub enum AiModelType {
 }
 
 impl AiModelType {
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(s: &str) -> Self {
         if s.starts_with("gpt-") || s.starts_with("o1") {
             AiModelType::OpenAI(s.to_string())
@@ -10,6 +10,9 @@ use std::collections::HashMap;
 // ============================================================================
 
 /// A prompt template with variable substitution
+///
+/// Variables are written `{{name}}`, or `{name}` as in `prompt` declarations.
+/// Braces around anything other than an identifier are left as they are.
 #[derive(Debug, Clone)]
 pub struct PromptTemplate {
     template: String,
@@ -47,52 +50,60 @@ impl PromptTemplate {
 
     /// Render the template with current variables
     pub fn render(&self) -> String {
-        let mut result = self.template.clone();
-
-        // Replace {{variable}} patterns
-        for (name, value) in &self.variables {
-            let pattern = format!("{{{{{}}}}}", name);
-            result = result.replace(&pattern, value);
-        }
-
-        // Replace remaining with defaults
-        for (name, value) in &self.default_values {
-            let pattern = format!("{{{{{}}}}}", name);
-            result = result.replace(&pattern, value);
+        let mut result = String::new();
+        let mut rest = 0;
+        for (start, end, name) in self.placeholders() {
+            let value = self.variables.get(&name).or_else(|| self.default_values.get(&name));
+            if let Some(value) = value {
+                result.push_str(&self.template[rest..start]);
+                result.push_str(value);
+                rest = end;
+            }
         }
-
+        result.push_str(&self.template[rest..]);
         result
     }
 
-    /// Get list of variables in template
+    /// Get list of variables in template, in order of first use
     pub fn variables(&self) -> Vec<String> {
-        let mut vars = Vec::new();
-        let mut chars = self.template.chars().peekable();
-
-        while let Some(c) = chars.next() {
-            if c == '{' && chars.peek() == Some(&'{') {
-                chars.next(); // consume second {
-                let mut var_name = String::new();
-                while let Some(&c) = chars.peek() {
-                    if c == '}' {
-                        chars.next();
-                        if chars.peek() == Some(&'}') {
-                            chars.next();
-                            if !var_name.is_empty() {
-                                vars.push(var_name);
-                            }
-                        }
-                        break;
-                    }
-                    var_name.push(c);
-                    chars.next();
-                }
+        let mut vars: Vec<String> = Vec::new();
+        for (_, _, name) in self.placeholders() {
+            if !vars.contains(&name) {
+                vars.push(name);
             }
         }
-
         vars
     }
 
+    /// Byte range and name of every `{{name}}` or `{name}` in the template
+    fn placeholders(&self) -> Vec<(usize, usize, String)> {
+        let bytes = self.template.as_bytes();
+        let mut found = Vec::new();
+        let mut i = 0;
+        while i < bytes.len() {
+            if bytes[i] != b'{' {
+                i += 1;
+                continue;
+            }
+            let double = bytes.get(i + 1) == Some(&b'{');
+            let open = if double { 2 } else { 1 };
+            let name_start = i + open;
+            let name_end = self.template[name_start..]
+                .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+                .map_or(bytes.len(), |n| name_start + n);
+            let close = if double { "}}" } else { "}" };
+            let is_ident = name_end > name_start && !bytes[name_start].is_ascii_digit();
+            if is_ident && self.template[name_end..].starts_with(close) {
+                let end = name_end + close.len();
+                found.push((i, end, self.template[name_start..name_end].to_string()));
+                i = end;
+            } else {
+                i += 1;
+            }
+        }
+        found
+    }
+
     /// Check if all required variables are set
     pub fn is_complete(&self) -> bool {
         for var in self.variables() {
@@ -136,6 +147,7 @@ impl PromptBuilder {
     }
 
     /// Add a part to the prompt
+    #[allow(clippy::should_implement_trait)]
     pub fn add(mut self, text: &str) -> Self {
         self.parts.push(text.to_string());
         self
@@ -367,4 +379,13 @@ mod tests {
         assert!(prompt.contains("Output: 4"));
         assert!(prompt.contains("Input: 5 + 5"));
     }
+
+    #[test]
+    fn test_single_brace_variables() {
+        let mut template = PromptTemplate::new("Translate {text} to {lang}: {text} {\"json\": 1} {{lang}}");
+        assert_eq!(template.variables(), vec!["text".to_string(), "lang".to_string()]);
+        template.set("text", "hola").set("lang", "English");
+        assert_eq!(template.render(), "Translate hola to English: hola {\"json\": 1} English");
+        assert_eq!(template.missing_variables(), Vec::<String>::new());
+    }
 }
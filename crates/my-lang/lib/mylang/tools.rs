@@ -45,6 +45,29 @@ impl ToolParamType {
             ToolParamType::Any => "any",
         }
     }
+
+    /// The schema keywords for this type, without braces: its `type`, and the
+    /// `items`, `properties` or `enum` of arrays, objects and enums
+    pub fn schema_keywords(&self) -> String {
+        let ty = format!("\"type\": \"{}\"", self.as_json_type());
+        match self {
+            ToolParamType::Array(element) => format!("{}, \"items\": {{{}}}", ty, element.schema_keywords()),
+            ToolParamType::Object(fields) if !fields.is_empty() => {
+                let mut names: Vec<&String> = fields.keys().collect();
+                names.sort();
+                let properties: Vec<String> = names
+                    .iter()
+                    .map(|name| format!("\"{}\": {{{}}}", name, fields[*name].schema_keywords()))
+                    .collect();
+                format!("{}, \"properties\": {{{}}}", ty, properties.join(", "))
+            }
+            ToolParamType::Enum(values) => {
+                let values: Vec<String> = values.iter().map(|v| format!("\"{}\"", v)).collect();
+                format!("{}, \"enum\": [{}]", ty, values.join(", "))
+            }
+            _ => ty,
+        }
+    }
 }
 
 impl ToolParameter {
@@ -116,10 +139,10 @@ impl ToolDef {
 
         for param in &self.parameters {
             let param_json = format!(
-                "\"{}\": {{\"type\": \"{}\", \"description\": \"{}\"}}",
+                "\"{}\": {{{}, \"description\": {}}}",
                 param.name,
-                param.param_type.as_json_type(),
-                param.description
+                param.param_type.schema_keywords(),
+                json_string(&param.description)
             );
             params.push(param_json);
 
@@ -129,15 +152,20 @@ impl ToolDef {
         }
 
         format!(
-            r#"{{"name": "{}", "description": "{}", "parameters": {{"type": "object", "properties": {{{}}}, "required": [{}]}}}}"#,
+            r#"{{"name": "{}", "description": {}, "parameters": {{"type": "object", "properties": {{{}}}, "required": [{}]}}}}"#,
             self.name,
-            self.description,
+            json_string(&self.description),
             params.join(", "),
             required.join(", ")
         )
     }
 }
 
+/// `text` as a quoted JSON string
+fn json_string(text: &str) -> String {
+    serde_json::Value::from(text).to_string()
+}
+
 // ============================================================================
 // Tool Call Types
 // ============================================================================
@@ -437,4 +465,18 @@ mod tests {
         assert!(registry.has("calculator"));
         assert!(registry.has("get_time"));
     }
+
+    #[test]
+    fn test_nested_json_schema() {
+        let tool = ToolDef::new("article", "An \"article\"")
+            .param(ToolParameter::new("tags", ToolParamType::Array(Box::new(ToolParamType::String))))
+            .param(ToolParameter::new("mood", ToolParamType::Enum(vec!["Calm".to_string(), "Tense".to_string()])).optional());
+
+        let schema: serde_json::Value = serde_json::from_str(&tool.to_json_schema()).unwrap();
+        assert_eq!(schema["description"], "An \"article\"");
+        let properties = &schema["parameters"]["properties"];
+        assert_eq!(properties["tags"]["items"], serde_json::json!({"type": "string"}));
+        assert_eq!(properties["mood"]["enum"], serde_json::json!(["Calm", "Tense"]));
+        assert_eq!(schema["parameters"]["required"], serde_json::json!(["tags"]));
+    }
 }
@@ -0,0 +1,133 @@
+//! AI backends for the interpreter
+//!
+//! The interpreter renders every AI expression into an [`AiRequest`] and hands
+//! it to the [`AiBackend`] it holds. The default [`MockBackend`] answers
+//! offline and deterministically, so programs and tests run without network
+//! access; `my_ai::RuntimeBackend` sends requests to real providers.
+
+use crate::ast::{AiKeyword, AiModelAttr, AiModelDecl};
+
+/// The attributes of an `ai_model` declaration
+#[derive(Debug, Clone, PartialEq)]
+pub struct AiModel {
+    pub name: String,
+    pub provider: Option<String>,
+    pub model: Option<String>,
+    pub temperature: Option<f64>,
+    /// Whether replies may be served from the backend's cache (the default)
+    pub cache: bool,
+}
+
+impl From<&AiModelDecl> for AiModel {
+    fn from(decl: &AiModelDecl) -> Self {
+        let mut model = AiModel {
+            name: decl.name.name.clone(),
+            provider: None,
+            model: None,
+            temperature: None,
+            cache: true,
+        };
+        for attr in &decl.attributes {
+            match attr {
+                AiModelAttr::Provider(p) => model.provider = Some(p.clone()),
+                AiModelAttr::Model(m) => model.model = Some(m.clone()),
+                AiModelAttr::Temperature(t) => model.temperature = Some(*t),
+                AiModelAttr::Cache(c) => model.cache = *c,
+            }
+        }
+        model
+    }
+}
+
+/// One AI operation, with its prompt rendered
+#[derive(Debug, Clone, PartialEq)]
+pub struct AiRequest {
+    /// `ai!` quick queries and prompt invocations are queries
+    pub operation: AiKeyword,
+    pub prompt: String,
+    /// The `ai_model` the expression names, if any
+    pub model: Option<AiModel>,
+    /// The JSON schema a typed `ai generate` reply must follow
+    pub schema: Option<String>,
+}
+
+/// Executes the AI operations of a running program
+pub trait AiBackend {
+    /// Answer the prompt of `request`
+    fn complete(&mut self, request: &AiRequest) -> Result<String, String>;
+
+    /// Embed the prompt of `request` as a vector
+    fn embed(&mut self, request: &AiRequest) -> Result<Vec<f64>, String>;
+}
+
+/// Deterministic offline backend: replies with the reply registered for the
+/// first pattern found in the prompt, and otherwise with one derived from the
+/// request alone, which for typed generation is a record fitting its schema
+#[derive(Debug, Clone, Default)]
+pub struct MockBackend {
+    replies: Vec<(String, String)>,
+}
+
+impl MockBackend {
+    pub fn new() -> Self {
+        MockBackend::default()
+    }
+
+    /// Reply with `reply` to prompts containing `pattern`
+    pub fn reply(mut self, pattern: &str, reply: &str) -> Self {
+        self.replies.push((pattern.to_string(), reply.to_string()));
+        self
+    }
+}
+
+/// A value fitting the JSON schema `schema`: the first value of an enum, the
+/// name of a string, zero or false, one element of an array and every
+/// property of an object
+fn example(schema: &serde_json::Value, name: &str) -> serde_json::Value {
+    use serde_json::{json, Value};
+    if let Some(first) = schema["enum"].as_array().and_then(|values| values.first()) {
+        return first.clone();
+    }
+    match schema["type"].as_str() {
+        Some("string") => json!(name),
+        Some("integer") => json!(0),
+        Some("number") => json!(0.0),
+        Some("boolean") => json!(false),
+        Some("array") => json!([example(&schema["items"], name)]),
+        Some("object") => {
+            let properties = schema["properties"].as_object().into_iter().flatten();
+            Value::Object(properties.map(|(field, schema)| (field.clone(), example(schema, field))).collect())
+        }
+        _ => Value::Null,
+    }
+}
+
+impl AiBackend for MockBackend {
+    fn complete(&mut self, request: &AiRequest) -> Result<String, String> {
+        if let Some((_, reply)) = self.replies.iter().find(|(pattern, _)| request.prompt.contains(pattern.as_str())) {
+            return Ok(reply.clone());
+        }
+        if let Some(schema) = &request.schema {
+            let schema: serde_json::Value = serde_json::from_str(schema).map_err(|e| e.to_string())?;
+            return Ok(example(&schema, "value").to_string());
+        }
+        Ok(match request.operation {
+            AiKeyword::Verify | AiKeyword::Validate => "true".to_string(),
+            op => format!("<{} response to: {}>", format!("{:?}", op).to_lowercase(), request.prompt),
+        })
+    }
+
+    fn embed(&mut self, request: &AiRequest) -> Result<Vec<f64>, String> {
+        // Fold the prompt's bytes into a fixed number of buckets, then normalize
+        const DIMENSIONS: usize = 8;
+        let mut vector = vec![0.0; DIMENSIONS];
+        for (i, byte) in request.prompt.bytes().enumerate() {
+            vector[i % DIMENSIONS] += f64::from(byte);
+        }
+        let norm = vector.iter().map(|x| x * x).sum::<f64>().sqrt();
+        if norm > 0.0 {
+            vector.iter_mut().for_each(|x| *x /= norm);
+        }
+        Ok(vector)
+    }
+}
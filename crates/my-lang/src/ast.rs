@@ -11,8 +11,11 @@ pub struct Program {
 /// Top-level declarations
 #[derive(Debug, Clone, PartialEq)]
 pub enum TopLevel {
-    Function(FnDecl),
+    Function(Box<FnDecl>),
     Struct(StructDecl),
+    Enum(EnumDecl),
+    Trait(TraitDecl),
+    Impl(ImplDecl),
     Effect(EffectDecl),
     Contract(ContractDecl),
     Import(ImportDecl),
@@ -20,6 +23,59 @@ pub enum TopLevel {
     Arena(ArenaDecl),
     AiModel(AiModelDecl),
     Prompt(PromptDecl),
+    /// A declaration that failed to parse, kept by `Parser::parse_with_errors`
+    Error(Span),
+}
+
+impl TopLevel {
+    /// The name of a declaration that `pub` can export
+    pub fn name(&self) -> Option<&Ident> {
+        match self {
+            TopLevel::Function(f) => Some(&f.name),
+            TopLevel::Struct(s) => Some(&s.name),
+            TopLevel::Enum(e) => Some(&e.name),
+            TopLevel::Trait(t) => Some(&t.name),
+            TopLevel::Effect(e) => Some(&e.name),
+            TopLevel::AiModel(m) => Some(&m.name),
+            TopLevel::Prompt(p) => Some(&p.name),
+            _ => None,
+        }
+    }
+
+    pub fn is_public(&self) -> bool {
+        self.public_flag().is_some_and(|public| *public)
+    }
+
+    /// Mark the declaration `pub`; fails for items that cannot be exported
+    pub fn set_public(&mut self) -> bool {
+        self.public_flag_mut().map(|public| *public = true).is_some()
+    }
+
+    fn public_flag(&self) -> Option<&bool> {
+        match self {
+            TopLevel::Function(f) => Some(&f.public),
+            TopLevel::Struct(s) => Some(&s.public),
+            TopLevel::Enum(e) => Some(&e.public),
+            TopLevel::Trait(t) => Some(&t.public),
+            TopLevel::Effect(e) => Some(&e.public),
+            TopLevel::AiModel(m) => Some(&m.public),
+            TopLevel::Prompt(p) => Some(&p.public),
+            _ => None,
+        }
+    }
+
+    fn public_flag_mut(&mut self) -> Option<&mut bool> {
+        match self {
+            TopLevel::Function(f) => Some(&mut f.public),
+            TopLevel::Struct(s) => Some(&mut s.public),
+            TopLevel::Enum(e) => Some(&mut e.public),
+            TopLevel::Trait(t) => Some(&mut t.public),
+            TopLevel::Effect(e) => Some(&mut e.public),
+            TopLevel::AiModel(m) => Some(&mut m.public),
+            TopLevel::Prompt(p) => Some(&mut p.public),
+            _ => None,
+        }
+    }
 }
 
 // ============================================
@@ -31,6 +87,8 @@ pub enum TopLevel {
 pub struct AiModelDecl {
     pub name: Ident,
     pub attributes: Vec<AiModelAttr>,
+    /// Exported from its module with `pub`
+    pub public: bool,
     pub span: Span,
 }
 
@@ -47,6 +105,10 @@ pub enum AiModelAttr {
 pub struct PromptDecl {
     pub name: Ident,
     pub template: String,
+    /// Names of the template's `{placeholder}`s, in order of first use
+    pub placeholders: Vec<String>,
+    /// Exported from its module with `pub`
+    pub public: bool,
     pub span: Span,
 }
 
@@ -99,17 +161,43 @@ pub enum AiExpr {
         query: String,
         span: Span,
     },
-    /// prompt_name!(args)
+    /// prompt_name!(args), filling placeholders in order or by name
     PromptInvocation {
         name: Ident,
-        args: Vec<Expr>,
+        args: Vec<PromptArg>,
         span: Span,
     },
 }
 
+/// Argument of a prompt invocation: `value`, or `placeholder: value`
+#[derive(Debug, Clone, PartialEq)]
+pub struct PromptArg {
+    pub name: Option<Ident>,
+    pub value: Expr,
+}
+
+impl AiExpr {
+    pub fn span(&self) -> Span {
+        match self {
+            AiExpr::Block { span, .. }
+            | AiExpr::Call { span, .. }
+            | AiExpr::Quick { span, .. }
+            | AiExpr::PromptInvocation { span, .. } => *span,
+        }
+    }
+
+    /// The operation performed; `ai!` and prompt invocations are queries
+    pub fn keyword(&self) -> AiKeyword {
+        match self {
+            AiExpr::Block { keyword, .. } | AiExpr::Call { keyword, .. } => *keyword,
+            AiExpr::Quick { .. } | AiExpr::PromptInvocation { .. } => AiKeyword::Query,
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum AiBodyItem {
-    Field { name: Ident, value: Expr },
+    Field { name: Ident, value: Box<Expr> },
     Literal(String),
 }
 
@@ -129,10 +217,11 @@ pub struct Block {
 pub enum Stmt {
     /// Expression statement: `expr;`
     Expr(Expr),
-    /// Let binding: `let [mut] name [: type] = expr;`
+    /// Let binding: `let [mut] pattern [: type] = expr;`. The pattern must
+    /// be irrefutable, e.g. a name or `(x, { y, .. })`.
     Let {
         mutable: bool,
-        name: Ident,
+        pattern: Pattern,
         ty: Option<Type>,
         value: Expr,
         span: Span,
@@ -144,6 +233,32 @@ pub enum Stmt {
         else_block: Option<Block>,
         span: Span,
     },
+    /// While loop: `['label:] while cond { }`
+    While {
+        label: Option<Ident>,
+        condition: Expr,
+        body: Block,
+        span: Span,
+    },
+    /// For loop: `['label:] for name in expr { }`
+    For {
+        label: Option<Ident>,
+        binding: Ident,
+        iterable: Expr,
+        body: Block,
+        span: Span,
+    },
+    /// Break statement: `break ['label] [expr];`
+    Break {
+        label: Option<Ident>,
+        value: Option<Expr>,
+        span: Span,
+    },
+    /// Continue statement: `continue ['label];`
+    Continue {
+        label: Option<Ident>,
+        span: Span,
+    },
     /// Go statement: `go { }`
     Go {
         block: Block,
@@ -172,6 +287,8 @@ pub enum Stmt {
     },
     /// AI statement
     Ai(AiStmt),
+    /// A statement that failed to parse, kept by `Parser::parse_with_errors`
+    Error(Span),
 }
 
 // ============================================
@@ -183,6 +300,11 @@ pub enum Stmt {
 pub enum Expr {
     /// Literal value
     Literal(Literal),
+    /// Interpolated string: `"Hello {name}, you are {age + 1}"`
+    Interpolation {
+        parts: Vec<StringPart>,
+        span: Span,
+    },
     /// Identifier
     Ident(Ident),
     /// Function/method call: `expr(args)`
@@ -197,6 +319,38 @@ pub enum Expr {
         field: Ident,
         span: Span,
     },
+    /// Method call: `expr.method(args)`
+    MethodCall {
+        receiver: Box<Expr>,
+        method: Ident,
+        args: Vec<Expr>,
+        span: Span,
+    },
+    /// Associated function of a type: `Point::new`
+    Path {
+        ty: Ident,
+        name: Ident,
+        span: Span,
+    },
+    /// Struct or struct-like variant construction: `Point { x: 1, y: 2 }`
+    StructLit {
+        name: Ident,
+        fields: Vec<RecordField>,
+        span: Span,
+    },
+    /// Index access: `expr[index]`
+    Index {
+        object: Box<Expr>,
+        index: Box<Expr>,
+        span: Span,
+    },
+    /// Range slice: `expr[start..end]`, either bound may be omitted
+    Slice {
+        object: Box<Expr>,
+        start: Option<Box<Expr>>,
+        end: Option<Box<Expr>>,
+        span: Span,
+    },
     /// Binary operation: `expr op expr`
     Binary {
         left: Box<Expr>,
@@ -204,15 +358,24 @@ pub enum Expr {
         right: Box<Expr>,
         span: Span,
     },
+    /// Assignment to a place: `target = value`, or `target op= value` with an
+    /// `op`, valued by the assigned value
+    Assign {
+        target: Box<Expr>,
+        op: Option<BinaryOp>,
+        value: Box<Expr>,
+        span: Span,
+    },
     /// Unary operation: `op expr`
     Unary {
         op: UnaryOp,
         operand: Box<Expr>,
         span: Span,
     },
-    /// Try expression: `try expr`
+    /// Try expression: `try expr [?]`
     Try {
         operand: Box<Expr>,
+        propagate: bool,
         span: Span,
     },
     /// Block expression
@@ -241,11 +404,67 @@ pub enum Expr {
         elements: Vec<Expr>,
         span: Span,
     },
+    /// Tuple literal: `(a, b)`, or `(a,)` for a single element
+    Tuple {
+        elements: Vec<Expr>,
+        span: Span,
+    },
     /// Record literal: `{ field: value, ... }`
     Record {
         fields: Vec<RecordField>,
         span: Span,
     },
+    /// Infinite loop: `['label:] loop { }`, valued by `break expr`
+    Loop {
+        label: Option<Ident>,
+        body: Block,
+        span: Span,
+    },
+    /// Effect operation: `perform Logger.log(msg)`, located by its idents
+    Perform {
+        effect: Ident,
+        op: Ident,
+        args: Vec<Expr>,
+    },
+    /// Effect handler: `handle { body } with Logger { log(m) => ... }`
+    Handle {
+        body: Box<Block>,
+        effect: Ident,
+        clauses: Vec<HandlerClause>,
+        span: Span,
+    },
+    /// Continue the suspended `perform` with a value, from a handler clause: `resume(value)`
+    Resume {
+        value: Box<Expr>,
+        span: Span,
+    },
+}
+
+/// A piece of an interpolated string
+#[derive(Debug, Clone, PartialEq)]
+pub enum StringPart {
+    /// Text with its escapes decoded
+    Text(String),
+    /// An embedded `{expr}`, displayed as by `to_string`
+    Expr(Expr),
+}
+
+impl StringPart {
+    pub fn expr(&self) -> Option<&Expr> {
+        match self {
+            StringPart::Text(_) => None,
+            StringPart::Expr(expr) => Some(expr),
+        }
+    }
+}
+
+/// A handler clause for one operation: `log(m) => expr`
+#[derive(Debug, Clone, PartialEq)]
+pub struct HandlerClause {
+    pub op: Ident,
+    pub params: Vec<Ident>,
+    pub body: Expr,
+    pub span: Span,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -257,6 +476,8 @@ pub enum LambdaBody {
 #[derive(Debug, Clone, PartialEq)]
 pub struct MatchArm {
     pub pattern: Pattern,
+    /// `if` guard evaluated after the pattern matches
+    pub guard: Option<Expr>,
     pub body: Expr,
     pub span: Span,
 }
@@ -271,6 +492,60 @@ pub enum Pattern {
         args: Vec<Pattern>,
         span: Span,
     },
+    /// Struct-like variant pattern: `Circle { radius }` or `Circle { radius: r }`
+    Struct {
+        name: Ident,
+        fields: Vec<(Ident, Pattern)>,
+        span: Span,
+    },
+    /// Record pattern: `{ name, age: a }`, or `{ name, .. }` to ignore the other fields
+    Record {
+        fields: Vec<(Ident, Pattern)>,
+        rest: bool,
+        span: Span,
+    },
+    /// Array pattern: `[first, second]` or `[first, ..rest, last]`.
+    /// `rest` is `Some` when there is a `..`, holding the pattern the middle
+    /// elements are bound to (a wildcard for a bare `..`).
+    Slice {
+        prefix: Vec<Pattern>,
+        rest: Option<Box<Pattern>>,
+        suffix: Vec<Pattern>,
+        span: Span,
+    },
+    /// Range pattern: `1..=9` or `0..10`
+    Range {
+        start: Literal,
+        end: Literal,
+        inclusive: bool,
+        span: Span,
+    },
+    /// Tuple pattern: `(a, b)`
+    Tuple(Vec<Pattern>, Span),
+    /// Alternatives: `A | B`
+    Or(Vec<Pattern>, Span),
+    /// Binding of the whole matched value: `x @ pat`
+    Binding {
+        name: Ident,
+        pattern: Box<Pattern>,
+        span: Span,
+    },
+}
+
+impl Pattern {
+    pub fn span(&self) -> Span {
+        match self {
+            Pattern::Literal(lit) => lit.span(),
+            Pattern::Ident(ident) => ident.span,
+            Pattern::Wildcard(span) | Pattern::Tuple(_, span) | Pattern::Or(_, span) => *span,
+            Pattern::Constructor { span, .. }
+            | Pattern::Struct { span, .. }
+            | Pattern::Record { span, .. }
+            | Pattern::Slice { span, .. }
+            | Pattern::Range { span, .. }
+            | Pattern::Binding { span, .. } => *span,
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -285,6 +560,12 @@ pub enum BinaryOp {
     Sub,
     Mul,
     Div,
+    Rem,
+    BitAnd,
+    BitOr,
+    BitXor,
+    Shl,
+    Shr,
     Eq,
     Ne,
     Lt,
@@ -293,7 +574,32 @@ pub enum BinaryOp {
     Ge,
     And,
     Or,
-    Assign,
+}
+
+impl BinaryOp {
+    /// The operator as written
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            BinaryOp::Add => "+",
+            BinaryOp::Sub => "-",
+            BinaryOp::Mul => "*",
+            BinaryOp::Div => "/",
+            BinaryOp::Rem => "%",
+            BinaryOp::BitAnd => "&",
+            BinaryOp::BitOr => "|",
+            BinaryOp::BitXor => "^",
+            BinaryOp::Shl => "<<",
+            BinaryOp::Shr => ">>",
+            BinaryOp::Eq => "==",
+            BinaryOp::Ne => "!=",
+            BinaryOp::Lt => "<",
+            BinaryOp::Gt => ">",
+            BinaryOp::Le => "<=",
+            BinaryOp::Ge => ">=",
+            BinaryOp::And => "&&",
+            BinaryOp::Or => "||",
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -315,15 +621,22 @@ pub enum Type {
     Primitive(PrimitiveType),
     /// Named type (identifier)
     Named(Ident),
+    /// Applied generic type: `Option<Int>`
+    Generic {
+        name: Ident,
+        args: Vec<Type>,
+        span: Span,
+    },
     /// Function type: `T -> U`
     Function {
         param: Box<Type>,
         result: Box<Type>,
         span: Span,
     },
-    /// Effect type: `Effect<T>`
+    /// Effect type: `Effect<T>`, or `Effect<T, Logger, ...>` naming the effects performed
     Effect {
         inner: Box<Type>,
+        effects: Vec<Ident>,
         span: Span,
     },
     /// AI effect type: `AI<T>`
@@ -358,6 +671,8 @@ pub enum Type {
         constraints: Vec<AiConstraint>,
         span: Span,
     },
+    /// Omitted annotation, left to inference: the type of `x` in `|x| => x + 1`
+    Infer(Span),
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -380,7 +695,7 @@ pub enum AiConstraint {
     Valid(String),
     Format(String),
     Infer,
-    Custom { name: Ident, value: Expr },
+    Custom { name: Ident, value: Box<Expr> },
 }
 
 // ============================================
@@ -392,10 +707,13 @@ pub enum AiConstraint {
 pub struct FnDecl {
     pub modifiers: Vec<FnModifier>,
     pub name: Ident,
+    pub type_params: Vec<TypeParam>,
     pub params: Vec<Param>,
     pub return_type: Option<Type>,
     pub contract: Option<Contract>,
     pub body: Block,
+    /// Exported from its module with `pub`
+    pub public: bool,
     pub span: Span,
 }
 
@@ -414,6 +732,23 @@ pub enum FnModifier {
 pub struct Param {
     pub name: Ident,
     pub ty: Type,
+    /// Declared `mut name: T`, or an `&mut self` receiver
+    pub mutable: bool,
+    pub span: Span,
+}
+
+impl Param {
+    /// Is this the `self` receiver of a method? Its type is `Self`.
+    pub fn is_self(&self) -> bool {
+        self.name.name == "self"
+    }
+}
+
+/// Type parameter with optional trait bounds: `T: Show + Eq`
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypeParam {
+    pub name: Ident,
+    pub bounds: Vec<Ident>,
     pub span: Span,
 }
 
@@ -422,8 +757,12 @@ pub struct Param {
 pub struct StructDecl {
     pub modifiers: Vec<StructModifier>,
     pub name: Ident,
-    pub type_params: Vec<Ident>,
+    pub type_params: Vec<TypeParam>,
     pub fields: Vec<StructField>,
+    /// `where invariant: ...` clauses
+    pub contract: Option<Contract>,
+    /// Exported from its module with `pub`
+    pub public: bool,
     pub span: Span,
 }
 
@@ -447,11 +786,74 @@ pub enum FieldModifier {
     AiEmbed,
 }
 
+/// Enum declaration: `enum Name<T> { A, B(T), C { field: T } }`
+#[derive(Debug, Clone, PartialEq)]
+pub struct EnumDecl {
+    pub modifiers: Vec<StructModifier>,
+    pub name: Ident,
+    pub type_params: Vec<TypeParam>,
+    pub variants: Vec<EnumVariant>,
+    /// Exported from its module with `pub`
+    pub public: bool,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EnumVariant {
+    pub name: Ident,
+    pub kind: VariantKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum VariantKind {
+    /// `None`
+    Unit,
+    /// `Some(T)`
+    Tuple(Vec<Type>),
+    /// `Circle { radius: Float }`
+    Struct(Vec<StructField>),
+}
+
+/// Trait declaration: `trait Show { fn show(self) -> String; }`
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraitDecl {
+    pub name: Ident,
+    pub methods: Vec<TraitMethod>,
+    /// Exported from its module with `pub`
+    pub public: bool,
+    pub span: Span,
+}
+
+/// Method signature in a trait, with an optional default body
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraitMethod {
+    pub name: Ident,
+    pub type_params: Vec<TypeParam>,
+    pub params: Vec<Param>,
+    pub return_type: Option<Type>,
+    pub default: Option<Block>,
+    pub span: Span,
+}
+
+/// Implementation block: `impl Point { .. }` or `impl<T> Show for Box<T> { .. }`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImplDecl {
+    pub type_params: Vec<TypeParam>,
+    /// The implemented trait; `None` for inherent impls
+    pub trait_name: Option<Ident>,
+    pub self_ty: Type,
+    pub methods: Vec<FnDecl>,
+    pub span: Span,
+}
+
 /// Effect declaration
 #[derive(Debug, Clone, PartialEq)]
 pub struct EffectDecl {
     pub name: Ident,
     pub ops: Vec<EffectOp>,
+    /// Exported from its module with `pub`
+    pub public: bool,
     pub span: Span,
 }
 
@@ -469,13 +871,30 @@ pub struct Contract {
     pub span: Span,
 }
 
+/// A clause of a contract, spanning from its keyword to the end of its condition
 #[derive(Debug, Clone, PartialEq)]
 pub enum ContractClause {
-    Pre(Expr),
-    Post(Expr),
-    Invariant(Expr),
-    AiCheck(String),
-    AiEnsure(String),
+    /// Checked on entry, with the parameters in scope
+    Pre(Expr, Span),
+    /// Checked on return, with the return value bound to `result`
+    Post(Expr, Span),
+    /// On a function, checked on entry and on return; on a struct, checked
+    /// whenever a value is constructed or mutated, with its fields in scope
+    Invariant(Expr, Span),
+    AiCheck(String, Span),
+    AiEnsure(String, Span),
+}
+
+impl ContractClause {
+    pub fn span(&self) -> Span {
+        match self {
+            ContractClause::Pre(_, span)
+            | ContractClause::Post(_, span)
+            | ContractClause::Invariant(_, span)
+            | ContractClause::AiCheck(_, span)
+            | ContractClause::AiEnsure(_, span) => *span,
+        }
+    }
 }
 
 /// Contract declaration (standalone)
@@ -0,0 +1,278 @@
+//! Record/replay cassettes for AI calls
+//!
+//! A cassette is a JSON file listing AI interactions: the request that was
+//! sent (operation, model, messages, temperature) and the result that came
+//! back. Recording starts a fresh cassette, replacing any earlier one at the
+//! same path, and saves every interaction to it as it happens; replaying
+//! serves results from the cassette instead of a provider, so tests can
+//! exercise AI-dependent logic offline. A replayed request that matches
+//! nothing in the cassette is an error rather than a call to a live backend.
+//!
+//! `RecordingBackend` and `ReplayBackend` do this for the interpreter;
+//! `my_ai::AIRuntime::with_cassette` does the same for provider completions.
+
+use crate::ai::{AiBackend, AiRequest};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum CassetteError {
+    #[error("cannot access cassette '{path}': {message}")]
+    Io { path: PathBuf, message: String },
+
+    #[error("malformed cassette '{path}': {message}")]
+    Malformed { path: PathBuf, message: String },
+
+    #[error("no recorded interaction matches {0}")]
+    Unmatched(String),
+}
+
+/// Whether AI calls are recorded to a cassette or replayed from it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CassetteMode {
+    Record,
+    Replay,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedMessage {
+    pub role: String,
+    pub content: String,
+}
+
+/// One request and the result it got
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Interaction {
+    /// An AI keyword such as `query` or `embed`, or `complete` for provider completions
+    pub operation: String,
+    pub model: Option<String>,
+    pub messages: Vec<RecordedMessage>,
+    pub temperature: Option<f64>,
+    /// The reply text, or an array of numbers for embeddings
+    #[serde(default)]
+    pub result: serde_json::Value,
+}
+
+impl Interaction {
+    /// The interaction for an interpreter request, without a result yet
+    pub fn for_request(request: &AiRequest) -> Self {
+        let model = request.model.as_ref();
+        Interaction {
+            operation: format!("{:?}", request.operation).to_lowercase(),
+            model: model.map(|m| m.model.clone().unwrap_or_else(|| m.name.clone())),
+            messages: vec![RecordedMessage { role: "user".to_string(), content: request.prompt.clone() }],
+            temperature: model.and_then(|m| m.temperature),
+            result: serde_json::Value::Null,
+        }
+    }
+
+    /// Whether both interactions are for the same request
+    fn same_request(&self, other: &Interaction) -> bool {
+        self.operation == other.operation
+            && self.model == other.model
+            && self.messages == other.messages
+            && self.temperature == other.temperature
+    }
+
+    /// Describe the request, for errors
+    pub fn describe(&self) -> String {
+        let prompt = self.messages.last().map_or("", |m| m.content.as_str());
+        match &self.model {
+            Some(model) => format!("{} request to '{}': {:?}", self.operation, model, prompt),
+            None => format!("{} request: {:?}", self.operation, prompt),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Cassette {
+    pub interactions: Vec<Interaction>,
+    /// Which interactions replay has served so far
+    #[serde(skip)]
+    served: Vec<bool>,
+}
+
+impl Cassette {
+    pub fn load(path: &Path) -> Result<Self, CassetteError> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| CassetteError::Io { path: path.to_path_buf(), message: e.to_string() })?;
+        serde_json::from_str(&text)
+            .map_err(|e| CassetteError::Malformed { path: path.to_path_buf(), message: e.to_string() })
+    }
+
+    /// Start an empty cassette at `path`, replacing any cassette there
+    pub fn create(path: &Path) -> Result<Self, CassetteError> {
+        let cassette = Cassette::default();
+        cassette.save(path)?;
+        Ok(cassette)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), CassetteError> {
+        let text = serde_json::to_string_pretty(self)
+            .map_err(|e| CassetteError::Malformed { path: path.to_path_buf(), message: e.to_string() })?;
+        std::fs::write(path, text).map_err(|e| CassetteError::Io { path: path.to_path_buf(), message: e.to_string() })
+    }
+
+    pub fn record(&mut self, interaction: Interaction) {
+        self.interactions.push(interaction);
+    }
+
+    /// The result recorded for `request`. Identical requests are served their
+    /// recordings in order; once those run out, the last one is served again.
+    pub fn replay(&mut self, request: &Interaction) -> Result<serde_json::Value, CassetteError> {
+        self.served.resize(self.interactions.len(), false);
+        let matches: Vec<usize> = (0..self.interactions.len())
+            .filter(|&i| self.interactions[i].same_request(request))
+            .collect();
+        let index = matches
+            .iter()
+            .find(|&&i| !self.served[i])
+            .or(matches.last())
+            .copied()
+            .ok_or_else(|| CassetteError::Unmatched(request.describe()))?;
+        self.served[index] = true;
+        Ok(self.interactions[index].result.clone())
+    }
+}
+
+/// Interpreter backend recording every interaction of another backend,
+/// saving the cassette after each one
+pub struct RecordingBackend {
+    inner: Box<dyn AiBackend>,
+    cassette: Cassette,
+    path: PathBuf,
+}
+
+impl RecordingBackend {
+    /// Record to a fresh cassette at `path`
+    pub fn new(inner: Box<dyn AiBackend>, path: impl Into<PathBuf>) -> Result<Self, CassetteError> {
+        let path = path.into();
+        let cassette = Cassette::create(&path)?;
+        Ok(RecordingBackend { inner, cassette, path })
+    }
+
+    fn record(&mut self, request: &AiRequest, result: serde_json::Value) -> Result<(), String> {
+        self.cassette.record(Interaction { result, ..Interaction::for_request(request) });
+        self.cassette.save(&self.path).map_err(|e| e.to_string())
+    }
+}
+
+impl AiBackend for RecordingBackend {
+    fn complete(&mut self, request: &AiRequest) -> Result<String, String> {
+        let reply = self.inner.complete(request)?;
+        self.record(request, serde_json::Value::from(reply.clone()))?;
+        Ok(reply)
+    }
+
+    fn embed(&mut self, request: &AiRequest) -> Result<Vec<f64>, String> {
+        let vector = self.inner.embed(request)?;
+        self.record(request, serde_json::Value::from(vector.clone()))?;
+        Ok(vector)
+    }
+}
+
+/// Interpreter backend serving results from a cassette
+pub struct ReplayBackend {
+    cassette: Cassette,
+}
+
+impl ReplayBackend {
+    pub fn new(cassette: Cassette) -> Self {
+        ReplayBackend { cassette }
+    }
+
+    pub fn open(path: &Path) -> Result<Self, CassetteError> {
+        Cassette::load(path).map(Self::new)
+    }
+
+    fn replay(&mut self, request: &AiRequest) -> Result<serde_json::Value, String> {
+        self.cassette.replay(&Interaction::for_request(request)).map_err(|e| e.to_string())
+    }
+}
+
+impl AiBackend for ReplayBackend {
+    fn complete(&mut self, request: &AiRequest) -> Result<String, String> {
+        match self.replay(request)? {
+            serde_json::Value::String(reply) => Ok(reply),
+            other => Err(format!("recorded result {} is not a reply", other)),
+        }
+    }
+
+    fn embed(&mut self, request: &AiRequest) -> Result<Vec<f64>, String> {
+        let result = self.replay(request)?;
+        result
+            .as_array()
+            .and_then(|values| values.iter().map(serde_json::Value::as_f64).collect())
+            .ok_or_else(|| format!("recorded result {} is not an embedding", result))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::MockBackend;
+    use crate::{parse, AiKeyword, Interpreter, Value};
+
+    fn run(source: &str, backend: Box<dyn AiBackend>) -> Result<Value, crate::RuntimeError> {
+        let mut interpreter = Interpreter::new();
+        interpreter.ai_backend = backend;
+        interpreter.run(&parse(source).unwrap())
+    }
+
+    #[test]
+    fn test_record_and_replay() {
+        let path = std::env::temp_dir().join(format!("my-lang-cassette-{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let source = r#"
+            ai_model fast { model: "llama3" temperature: 0.2 }
+            fn main() -> String {
+                let a = ai query { prompt: "capital of France?" model: fast };
+                let b = ai! { "roll a die" };
+                let c = ai! { "roll a die" };
+                return a + " " + b + c + " " + to_string(len(ai embed("x")));
+            }
+        "#;
+
+        let live = MockBackend::new().reply("France", "Paris").reply("die", "4");
+        let recorded = run(source, Box::new(RecordingBackend::new(Box::new(live), &path).unwrap()));
+        assert!(matches!(&recorded, Ok(Value::String(s)) if s == "Paris 44 8"), "{:?}", recorded);
+
+        let cassette = Cassette::load(&path).unwrap();
+        assert_eq!(cassette.interactions.len(), 4);
+        let first = &cassette.interactions[0];
+        assert_eq!((first.operation.as_str(), first.model.as_deref()), ("query", Some("llama3")));
+        assert_eq!((first.temperature, &first.result), (Some(0.2), &serde_json::json!("Paris")));
+        assert_eq!(first.messages[0].content, "capital of France?");
+
+        // Replay serves identical requests their recordings in order
+        let mut cassette = cassette;
+        cassette.interactions[2].result = serde_json::json!("6");
+        let replayed = run(source, Box::new(ReplayBackend::new(cassette.clone())));
+        assert!(matches!(&replayed, Ok(Value::String(s)) if s == "Paris 46 8"), "{:?}", replayed);
+
+        let changed = source.replace("France", "Spain");
+        let result = run(&changed, Box::new(ReplayBackend::new(cassette)));
+        assert!(matches!(&result, Err(crate::RuntimeError::AiBackend(m))
+            if m == "no recorded interaction matches query request to 'llama3': \"capital of Spain?\""), "{:?}", result);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_recording_replaces_cassette() {
+        let path = std::env::temp_dir().join(format!("my-lang-cassette-replace-{}.json", std::process::id()));
+        let source = r#"fn main() -> String { return ai! { "new" }; }"#;
+        let old = Interaction::for_request(&AiRequest { operation: AiKeyword::Query, prompt: "old".to_string(), model: None, schema: None });
+        Cassette { interactions: vec![old], ..Cassette::default() }.save(&path).unwrap();
+
+        // Starting to record empties the cassette even before the first call
+        let recording = RecordingBackend::new(Box::new(MockBackend::new()), &path).unwrap();
+        assert!(Cassette::load(&path).unwrap().interactions.is_empty());
+
+        run(source, Box::new(recording)).unwrap();
+        let cassette = Cassette::load(&path).unwrap();
+        assert_eq!(cassette.interactions.len(), 1);
+        assert_eq!(cassette.interactions[0].messages[0].content, "new");
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -3,9 +3,13 @@
 //! Performs name resolution, type checking, and validation of AI constructs.
 
 use crate::ast::*;
+use crate::effects::{self, EffectRow};
+use crate::exhaustiveness;
+use crate::modules::{Import, ModuleGraph};
 use crate::scope::*;
-use crate::token::Span;
+use crate::token::{FileId, Span};
 use crate::types::*;
+use std::collections::{HashMap, HashSet};
 use thiserror::Error;
 
 #[derive(Error, Debug, Clone)]
@@ -31,6 +35,14 @@ pub enum CheckError {
         column: usize,
     },
 
+    #[error("'{name}' is private to module '{module}' at line {line}, column {column}")]
+    PrivateItem {
+        name: String,
+        module: String,
+        line: usize,
+        column: usize,
+    },
+
     #[error("undefined AI model '{name}' at line {line}, column {column}")]
     UndefinedAiModel {
         name: String,
@@ -67,6 +79,12 @@ pub enum CheckError {
         column: usize,
     },
 
+    #[error("invalid assignment target at line {line}, column {column}: expected a variable, field or index")]
+    InvalidAssignmentTarget {
+        line: usize,
+        column: usize,
+    },
+
     #[error("wrong number of arguments: expected {expected}, found {found} at line {line}, column {column}")]
     WrongArgCount {
         expected: usize,
@@ -91,6 +109,89 @@ pub enum CheckError {
         column: usize,
     },
 
+    #[error("'{keyword}' outside of a loop at line {line}, column {column}")]
+    OutsideLoop {
+        keyword: String,
+        line: usize,
+        column: usize,
+    },
+
+    #[error("undefined loop label '{label}' at line {line}, column {column}")]
+    UndefinedLabel {
+        label: String,
+        line: usize,
+        column: usize,
+    },
+
+    #[error("cannot use '?' on {operand} in a function returning {return_type} at line {line}, column {column}")]
+    IncompatibleTry {
+        operand: String,
+        return_type: String,
+        line: usize,
+        column: usize,
+    },
+
+    #[error("infinite type: {var} occurs in {ty} at line {line}, column {column}")]
+    InfiniteType {
+        var: String,
+        ty: String,
+        line: usize,
+        column: usize,
+    },
+
+    #[error("non-exhaustive match: pattern '{witness}' not covered at line {line}, column {column}")]
+    NonExhaustiveMatch {
+        witness: String,
+        line: usize,
+        column: usize,
+    },
+
+    #[error("unreachable match arm at line {line}, column {column}")]
+    UnreachablePattern {
+        line: usize,
+        column: usize,
+    },
+
+    #[error("no method '{method}' on type '{ty}' at line {line}, column {column}")]
+    UndefinedMethod {
+        ty: String,
+        method: String,
+        line: usize,
+        column: usize,
+    },
+
+    #[error("the trait bound '{ty}: {bound}' is not satisfied at line {line}, column {column}")]
+    UnsatisfiedBound {
+        ty: String,
+        bound: String,
+        line: usize,
+        column: usize,
+    },
+
+    #[error("unhandled effect '{effect}' escapes '{function}' at line {line}, column {column}")]
+    UnhandledEffect {
+        effect: String,
+        function: String,
+        line: usize,
+        column: usize,
+    },
+
+    #[error("'{function}' performs effect '{effect}' at line {line}, column {column} but its return type does not declare it")]
+    MissingEffectAnnotation {
+        effect: String,
+        function: String,
+        line: usize,
+        column: usize,
+    },
+
+    #[error("{message} in invocation of prompt '{prompt}' at line {line}, column {column}")]
+    PromptArgument {
+        prompt: String,
+        message: String,
+        line: usize,
+        column: usize,
+    },
+
     #[error("{message} at line {line}, column {column}")]
     Other {
         message: String,
@@ -101,13 +202,84 @@ pub enum CheckError {
 
 pub type CheckResult<T> = Result<T, CheckError>;
 
+/// The type parameter `T` of generic stdlib signatures
+fn t() -> Ty {
+    Ty::Named("T".to_string())
+}
+
+/// `[T]`
+fn array_t() -> Ty {
+    Ty::Array(Box::new(t()))
+}
+
+/// Whether `expr` is a syntactic value, whose type a `let` may generalize
+/// without making mutable state polymorphic (the value restriction)
+fn is_syntactic_value(expr: &Expr) -> bool {
+    matches!(expr, Expr::Lambda { .. } | Expr::Ident(_) | Expr::Path { .. } | Expr::Literal(_))
+}
+
 /// The type checker and semantic analyzer
 pub struct Checker {
     symbols: SymbolTable,
     types: TypeEnv,
     errors: Vec<CheckError>,
+    /// Diagnostics that do not fail the check, such as unreachable match arms
+    warnings: Vec<CheckError>,
     /// Current function's return type (for checking return statements)
     current_return_type: Option<Ty>,
+    /// Whether the current function performs `AI`, so that an `AI<T>` it
+    /// returns as a plain `T` is left to `check_effect_rows` to report
+    performs_ai: bool,
+    /// Enclosing loops, innermost last (for checking break/continue)
+    loops: Vec<LoopContext>,
+    /// Solved inference variables
+    subst: Substitution,
+    /// Nesting depth of `let` values being checked (for let-generalization)
+    level: usize,
+    /// Whether a `return` has been seen in the current lambda body
+    returned: bool,
+    /// The type `Self` stands for in the impl or trait being checked
+    self_ty: Option<Ty>,
+    /// Trait bounds of the type parameters in scope, innermost last
+    bounds: Vec<(String, Vec<String>)>,
+    /// Trait bounds of each generic function's type parameters, in declaration order
+    fn_bounds: HashMap<String, Vec<Vec<String>>>,
+    /// Bounds required of inferred types, discharged at the end of each function
+    obligations: Vec<Obligation>,
+    /// Effect rows of the program's functions and methods
+    effect_rows: HashMap<String, EffectRow>,
+    /// Enclosing handler clauses, innermost last (for checking `resume`)
+    clauses: Vec<ClauseContext>,
+    /// Private functions of imported modules, as `alias::name`, with the
+    /// module each belongs to
+    private: HashMap<String, String>,
+}
+
+/// A handler clause enclosing the expression being checked
+#[derive(Clone)]
+struct ClauseContext {
+    /// Type the suspended `perform` is resumed with
+    resume_ty: Ty,
+    /// Type of the `handle` expression, which `resume` evaluates to
+    answer_ty: Ty,
+    /// Spans of the `resume`s in tail position, the only ones allowed
+    tails: Vec<Span>,
+}
+
+/// A type that must implement a trait, from instantiating a bounded type parameter
+struct Obligation {
+    ty: Ty,
+    trait_name: String,
+    span: Span,
+}
+
+/// A loop enclosing the statement being checked
+struct LoopContext {
+    label: Option<String>,
+    /// Only `loop` expressions may `break` with a value
+    is_loop_expr: bool,
+    /// Type of the first `break value` seen in a `loop` expression
+    break_ty: Option<Ty>,
 }
 
 impl Default for Checker {
@@ -122,12 +294,40 @@ impl Checker {
             symbols: SymbolTable::new(),
             types: TypeEnv::new(),
             errors: Vec::new(),
+            warnings: Vec::new(),
             current_return_type: None,
+            performs_ai: false,
+            loops: Vec::new(),
+            subst: Substitution::new(),
+            level: 0,
+            returned: false,
+            self_ty: None,
+            bounds: Vec::new(),
+            fn_bounds: HashMap::new(),
+            obligations: Vec::new(),
+            effect_rows: HashMap::new(),
+            clauses: Vec::new(),
+            private: HashMap::new(),
         };
         checker.register_stdlib();
+        let prelude = crate::stdlib::prelude();
+        checker.declare(prelude.iter());
         checker
     }
 
+    /// Collect the definitions of `items`, then register their impl blocks, which
+    /// refer to the types and traits collected first
+    fn declare<'a>(&mut self, items: impl Iterator<Item = &'a TopLevel> + Clone) {
+        for item in items.clone() {
+            self.collect_definitions(item);
+        }
+        for item in items {
+            if let TopLevel::Impl(i) = item {
+                self.register_impl(i);
+            }
+        }
+    }
+
     /// Register standard library functions in the symbol table
     fn register_stdlib(&mut self) {
         use crate::stdlib::stdlib_functions;
@@ -145,6 +345,14 @@ impl Checker {
         }
     }
 
+    /// Stdlib signature quantified over a single type parameter `T`
+    fn generic(params: Vec<Ty>, result: Ty) -> Ty {
+        Ty::Forall {
+            params: vec!["T".to_string()],
+            ty: Box::new(Ty::Function { params, result: Box::new(result) }),
+        }
+    }
+
     /// Get the type signature for a stdlib function
     fn stdlib_function_type(name: &str) -> Ty {
         match name {
@@ -205,45 +413,30 @@ impl Checker {
                 params: vec![Ty::Unknown, Ty::Unknown],
                 result: Box::new(Ty::Unknown),
             },
+            "wrapping_add" | "wrapping_sub" | "wrapping_mul" | "saturating_add" | "saturating_sub"
+            | "saturating_mul" => Ty::Function {
+                params: vec![Ty::Int, Ty::Int],
+                result: Box::new(Ty::Int),
+            },
             "sqrt" | "sin" | "cos" | "tan" | "log" | "log10" | "exp" => Ty::Function {
                 params: vec![Ty::Unknown],
                 result: Box::new(Ty::Float),
             },
             "PI" | "E" | "TAU" => Ty::Float,
 
-            // Array functions
-            "push" => Ty::Function {
-                params: vec![Ty::Array(Box::new(Ty::Unknown)), Ty::Unknown],
-                result: Box::new(Ty::Array(Box::new(Ty::Unknown))),
-            },
-            "pop" | "reverse" => Ty::Function {
-                params: vec![Ty::Unknown],
-                result: Box::new(Ty::Unknown),
-            },
-            "first" | "last" => Ty::Function {
-                params: vec![Ty::Array(Box::new(Ty::Unknown))],
-                result: Box::new(Ty::Unknown),
-            },
-            "get" => Ty::Function {
-                params: vec![Ty::Array(Box::new(Ty::Unknown)), Ty::Int],
+            // Array functions, generic over the element type `T`
+            "push" => Self::generic(vec![array_t(), t()], array_t()),
+            "pop" => Self::generic(vec![array_t()], array_t()),
+            "reverse" => Ty::Function {
+                params: vec![Ty::Unknown], // String or Array
                 result: Box::new(Ty::Unknown),
             },
-            "set" => Ty::Function {
-                params: vec![Ty::Array(Box::new(Ty::Unknown)), Ty::Int, Ty::Unknown],
-                result: Box::new(Ty::Array(Box::new(Ty::Unknown))),
-            },
-            "concat" => Ty::Function {
-                params: vec![Ty::Array(Box::new(Ty::Unknown)), Ty::Array(Box::new(Ty::Unknown))],
-                result: Box::new(Ty::Array(Box::new(Ty::Unknown))),
-            },
-            "slice" => Ty::Function {
-                params: vec![Ty::Array(Box::new(Ty::Unknown)), Ty::Int, Ty::Int],
-                result: Box::new(Ty::Array(Box::new(Ty::Unknown))),
-            },
-            "contains" => Ty::Function {
-                params: vec![Ty::Array(Box::new(Ty::Unknown)), Ty::Unknown],
-                result: Box::new(Ty::Bool),
-            },
+            "first" | "last" => Self::generic(vec![array_t()], t()),
+            "get" => Self::generic(vec![array_t(), Ty::Int], t()),
+            "set" => Self::generic(vec![array_t(), Ty::Int, t()], array_t()),
+            "concat" => Self::generic(vec![array_t(), array_t()], array_t()),
+            "slice" => Self::generic(vec![array_t(), Ty::Int, Ty::Int], array_t()),
+            "contains" => Self::generic(vec![array_t(), t()], Ty::Bool),
             "range" => Ty::Function {
                 params: vec![Ty::Int, Ty::Int],
                 result: Box::new(Ty::Array(Box::new(Ty::Int))),
@@ -264,11 +457,11 @@ impl Checker {
             },
             "to_int" => Ty::Function {
                 params: vec![Ty::Unknown],
-                result: Box::new(Ty::Int),
+                result: Box::new(Ty::Generic { name: "Result".to_string(), args: vec![Ty::Int, Ty::String] }),
             },
             "to_float" => Ty::Function {
                 params: vec![Ty::Unknown],
-                result: Box::new(Ty::Float),
+                result: Box::new(Ty::Generic { name: "Result".to_string(), args: vec![Ty::Float, Ty::String] }),
             },
             "to_bool" => Ty::Function {
                 params: vec![Ty::Unknown],
@@ -292,10 +485,7 @@ impl Checker {
                 params: vec![Ty::String],
                 result: Box::new(Ty::Unit),
             },
-            "identity" | "clone" => Ty::Function {
-                params: vec![Ty::Unknown],
-                result: Box::new(Ty::Unknown),
-            },
+            "identity" | "clone" => Self::generic(vec![t()], t()),
             "default" => Ty::Function {
                 params: vec![Ty::String],
                 result: Box::new(Ty::Unknown),
@@ -325,17 +515,46 @@ impl Checker {
         }
     }
 
+    /// The warnings found by the checks run so far
+    pub fn warnings(&self) -> &[CheckError] {
+        &self.warnings
+    }
+
+    /// Check the module `file` of a multi-file program. The declarations it
+    /// imports were checked with their own modules, so here they are only
+    /// declared.
+    pub fn check_module(&mut self, graph: &ModuleGraph, file: FileId) -> Result<(), Vec<CheckError>> {
+        let reported = self.errors.len();
+        self.declare(graph.imported_items(file).iter());
+        self.errors.truncate(reported);
+        for import in &graph.module(file).imports {
+            if let Import::Module { module, alias } = import {
+                let module = graph.module(*module);
+                for item in module.program.items.iter().filter(|i| !i.is_public()) {
+                    if let TopLevel::Function(f) = item {
+                        self.private.insert(format!("{}::{}", alias, f.name.name), module.name.clone());
+                    }
+                }
+            }
+        }
+        self.check_program(&graph.module(file).program)
+    }
+
     /// Check a complete program
     pub fn check_program(&mut self, program: &Program) -> Result<(), Vec<CheckError>> {
+        let derived = crate::stdlib::derived_impls(&program.items);
+        let items = program.items.iter().chain(&derived);
+        self.effect_rows = effects::infer(program);
+
         // First pass: collect all type definitions
-        for item in &program.items {
-            self.collect_definitions(item);
-        }
+        self.declare(items.clone());
 
         // Second pass: type check all items
-        for item in &program.items {
+        for item in items {
             self.check_top_level(item);
         }
+        self.discharge_obligations();
+        self.check_effect_rows(&program.items);
 
         if self.errors.is_empty() {
             Ok(())
@@ -356,7 +575,8 @@ impl Checker {
                 let def = StructDef {
                     name: s.name.name.clone(),
                     fields,
-                    type_params: s.type_params.iter().map(|p| p.name.clone()).collect(),
+                    type_params: s.type_params.iter().map(|p| p.name.name.clone()).collect(),
+                    ai_generate: s.modifiers.contains(&StructModifier::AiGenerate),
                     span: s.span,
                 };
 
@@ -378,6 +598,8 @@ impl Checker {
                 });
             }
 
+            TopLevel::Enum(e) => self.define_enum(e),
+
             TopLevel::Effect(e) => {
                 let operations: Vec<(String, Ty)> = e.ops
                     .iter()
@@ -390,7 +612,7 @@ impl Checker {
                     span: e.span,
                 };
 
-                if let Err(_) = self.types.define_effect(def) {
+                if self.types.define_effect(def).is_err() {
                     self.errors.push(CheckError::DuplicateDefinition {
                         name: e.name.name.clone(),
                         line: e.span.line,
@@ -419,7 +641,7 @@ impl Checker {
                     span: m.span,
                 };
 
-                if let Err(_) = self.types.define_ai_model(def) {
+                if self.types.define_ai_model(def).is_err() {
                     self.errors.push(CheckError::DuplicateDefinition {
                         name: m.name.name.clone(),
                         line: m.span.line,
@@ -440,10 +662,11 @@ impl Checker {
                 let def = PromptDef {
                     name: p.name.name.clone(),
                     template: p.template.clone(),
+                    placeholders: p.placeholders.clone(),
                     span: p.span,
                 };
 
-                if let Err(_) = self.types.define_prompt(def) {
+                if self.types.define_prompt(def).is_err() {
                     self.errors.push(CheckError::DuplicateDefinition {
                         name: p.name.name.clone(),
                         line: p.span.line,
@@ -464,29 +687,23 @@ impl Checker {
             }
 
             TopLevel::Function(f) => {
-                // Collect function signature
-                let param_types: Vec<Ty> = f.params
-                    .iter()
-                    .map(|p| ast_type_to_ty(&p.ty))
-                    .collect();
-
-                let return_type = f.return_type
-                    .as_ref()
-                    .map(ast_type_to_ty)
-                    .unwrap_or(Ty::Unit);
-
-                let fn_type = Ty::Function {
-                    params: param_types,
-                    result: Box::new(return_type),
-                };
+                let fn_type = Self::signature(&f.type_params, &f.params, f.return_type.as_ref());
+                let fn_type = self.with_effect_row(fn_type, &f.name.name);
+                if f.type_params.iter().any(|p| !p.bounds.is_empty()) {
+                    let bounds = f.type_params
+                        .iter()
+                        .map(|p| p.bounds.iter().map(|b| b.name.clone()).collect())
+                        .collect();
+                    self.fn_bounds.insert(f.name.name.clone(), bounds);
+                }
 
-                if let Err(_) = self.symbols.define(Symbol {
+                if self.symbols.define(Symbol {
                     name: f.name.name.clone(),
                     kind: SymbolKind::Function,
                     ty: fn_type,
                     span: f.span,
                     mutable: false,
-                }) {
+                }).is_err() {
                     self.errors.push(CheckError::DuplicateDefinition {
                         name: f.name.name.clone(),
                         line: f.span.line,
@@ -495,33 +712,394 @@ impl Checker {
                 }
             }
 
+            TopLevel::Trait(t) => self.define_trait(t),
+
             _ => {}
         }
     }
 
+    /// Function type of a signature, quantified over its type parameters
+    fn signature(type_params: &[TypeParam], params: &[Param], return_type: Option<&Type>) -> Ty {
+        let fn_type = Ty::Function {
+            params: params.iter().map(|p| ast_type_to_ty(&p.ty)).collect(),
+            result: Box::new(return_type.map(ast_type_to_ty).unwrap_or(Ty::Unit)),
+        };
+        if type_params.is_empty() {
+            fn_type
+        } else {
+            Ty::Forall {
+                params: type_params.iter().map(|p| p.name.name.clone()).collect(),
+                ty: Box::new(fn_type),
+            }
+        }
+    }
+
+    /// Record the inferred effect row of function `key` in the result of its
+    /// type, unless the signature declares an `Effect<T, ...>` result itself.
+    /// Built-in effects are left out: they are checked against the signature
+    /// by `check_effect_rows` instead.
+    fn with_effect_row(&self, ty: Ty, key: &str) -> Ty {
+        let row: Vec<String> = match self.effect_rows.get(key) {
+            Some(row) => row.keys().filter(|e| !effects::is_builtin(e)).cloned().collect(),
+            None => return ty,
+        };
+        if row.is_empty() {
+            return ty;
+        }
+        match ty {
+            Ty::Forall { params, ty } => Ty::Forall { params, ty: Box::new(self.with_effect_row(*ty, key)) },
+            Ty::Function { params, result } if !matches!(*result, Ty::Effect(..)) => Ty::Function {
+                params,
+                result: Box::new(Ty::Effect(result, row)),
+            },
+            ty => ty,
+        }
+    }
+
+    /// Effects must not escape `main`, nor a function declaring which effects it
+    /// performs. Built-in effects may escape `main`, but a function performing
+    /// `AI` must say so with an `AI<T>` or `Effect<T, ..., AI>` return type.
+    fn check_effect_rows(&mut self, items: &[TopLevel]) {
+        let mut functions: Vec<(String, &FnDecl)> = Vec::new();
+        for item in items {
+            match item {
+                TopLevel::Function(f) => functions.push((f.name.name.clone(), f)),
+                TopLevel::Impl(i) => {
+                    let Some(ty) = ast_type_to_ty(&i.self_ty).impl_key() else { continue };
+                    functions.extend(i.methods.iter().map(|m| (format!("{}::{}", ty, m.name.name), m)));
+                }
+                _ => {}
+            }
+        }
+        for (key, f) in functions {
+            let is_main = key == "main";
+            let row = self.effect_rows.get(&key).cloned().unwrap_or_default();
+            let declared: Option<Vec<&str>> = match &f.return_type {
+                _ if is_main => Some(vec![]),
+                Some(Type::Effect { effects, .. }) => Some(effects.iter().map(|e| e.name.as_str()).collect()),
+                _ => None,
+            };
+            for (effect, span) in row {
+                let error = if effects::is_builtin(&effect) {
+                    let annotated = match &f.return_type {
+                        Some(Type::Ai { .. }) => true,
+                        _ => declared.as_ref().is_some_and(|d| d.contains(&effect.as_str())),
+                    };
+                    if effect != "AI" || is_main || annotated {
+                        continue;
+                    }
+                    CheckError::MissingEffectAnnotation {
+                        effect,
+                        function: f.name.name.clone(),
+                        line: span.line,
+                        column: span.column,
+                    }
+                } else {
+                    match &declared {
+                        Some(d) if !d.contains(&effect.as_str()) => CheckError::UnhandledEffect {
+                            effect,
+                            function: f.name.name.clone(),
+                            line: span.line,
+                            column: span.column,
+                        },
+                        _ => continue,
+                    }
+                };
+                self.errors.push(error);
+            }
+        }
+    }
+
+    fn define_trait(&mut self, t: &TraitDecl) {
+        let def = TraitDef {
+            name: t.name.name.clone(),
+            methods: t.methods
+                .iter()
+                .map(|m| MethodDef {
+                    name: m.name.name.clone(),
+                    ty: Self::signature(&m.type_params, &m.params, m.return_type.as_ref()),
+                    has_self: m.params.first().is_some_and(Param::is_self),
+                    has_default: m.default.is_some(),
+                })
+                .collect(),
+            span: t.span,
+        };
+
+        if let Err(_msg) = self.types.define_trait(def) {
+            self.errors.push(CheckError::DuplicateDefinition {
+                name: t.name.name.clone(),
+                line: t.span.line,
+                column: t.span.column,
+            });
+        }
+
+        let _ = self.symbols.define(Symbol {
+            name: t.name.name.clone(),
+            kind: SymbolKind::Trait,
+            ty: Ty::Named(t.name.name.clone()),
+            span: t.span,
+            mutable: false,
+        });
+    }
+
+    /// Register the methods of an impl block, checking a trait impl against the trait
+    fn register_impl(&mut self, i: &ImplDecl) {
+        let self_ty = ast_type_to_ty(&i.self_ty);
+        let Some(key) = self_ty.impl_key() else {
+            self.errors.push(CheckError::Other {
+                message: format!("Cannot implement methods for type '{}'", self_ty),
+                line: i.span.line,
+                column: i.span.column,
+            });
+            return;
+        };
+        let with_self = |ty: &Ty| ty.substitute(&["Self".to_string()], std::slice::from_ref(&self_ty));
+
+        let mut methods: Vec<MethodDef> = i.methods
+            .iter()
+            .map(|m| MethodDef {
+                name: m.name.name.clone(),
+                ty: with_self(&Self::signature(&m.type_params, &m.params, m.return_type.as_ref())),
+                has_self: m.params.first().is_some_and(Param::is_self),
+                has_default: false,
+            })
+            .collect();
+
+        if let Some(trait_name) = &i.trait_name {
+            let Some(trait_def) = self.types.get_trait(&trait_name.name).cloned() else {
+                self.errors.push(CheckError::Other {
+                    message: format!("Undefined trait '{}'", trait_name.name),
+                    line: trait_name.span.line,
+                    column: trait_name.span.column,
+                });
+                return;
+            };
+
+            for (method, decl) in methods.iter().zip(&i.methods) {
+                match trait_def.methods.iter().find(|m| m.name == method.name) {
+                    Some(required) => {
+                        self.expect_ty(&with_self(&required.ty), &method.ty, decl.span);
+                    }
+                    None => self.errors.push(CheckError::Other {
+                        message: format!("Method '{}' is not a member of trait '{}'", method.name, trait_def.name),
+                        line: decl.span.line,
+                        column: decl.span.column,
+                    }),
+                }
+            }
+
+            // Unimplemented methods fall back to the trait's defaults
+            for required in &trait_def.methods {
+                if methods.iter().any(|m| m.name == required.name) {
+                    continue;
+                }
+                if required.has_default {
+                    methods.push(MethodDef { ty: with_self(&required.ty), ..required.clone() });
+                } else {
+                    self.errors.push(CheckError::Other {
+                        message: format!(
+                            "Missing method '{}' in impl of '{}' for '{}'",
+                            required.name, trait_def.name, self_ty
+                        ),
+                        line: i.span.line,
+                        column: i.span.column,
+                    });
+                }
+            }
+        }
+
+        let def = ImplDef {
+            trait_name: i.trait_name.as_ref().map(|t| t.name.clone()),
+            type_params: i.type_params
+                .iter()
+                .map(|p| (p.name.name.clone(), p.bounds.iter().map(|b| b.name.clone()).collect()))
+                .collect(),
+            self_ty,
+            methods,
+            span: i.span,
+        };
+        if let Err(message) = self.types.add_impl(&key, def) {
+            self.errors.push(CheckError::Other {
+                message,
+                line: i.span.line,
+                column: i.span.column,
+            });
+        }
+    }
+
+    /// Define an enum type, its unit variants as values and its tuple variants as constructors
+    fn define_enum(&mut self, e: &EnumDecl) {
+        let variants: Vec<VariantDef> = e.variants
+            .iter()
+            .map(|v| match &v.kind {
+                VariantKind::Unit => VariantDef {
+                    name: v.name.name.clone(),
+                    shape: VariantShape::Unit,
+                    fields: vec![],
+                },
+                VariantKind::Tuple(types) => VariantDef {
+                    name: v.name.name.clone(),
+                    shape: VariantShape::Tuple,
+                    fields: types
+                        .iter()
+                        .enumerate()
+                        .map(|(i, t)| (i.to_string(), ast_type_to_ty(t)))
+                        .collect(),
+                },
+                VariantKind::Struct(fields) => VariantDef {
+                    name: v.name.name.clone(),
+                    shape: VariantShape::Struct,
+                    fields: fields
+                        .iter()
+                        .map(|f| (f.name.name.clone(), ast_type_to_ty(&f.ty)))
+                        .collect(),
+                },
+            })
+            .collect();
+
+        let def = EnumDef {
+            name: e.name.name.clone(),
+            variants,
+            type_params: e.type_params.iter().map(|p| p.name.name.clone()).collect(),
+            span: e.span,
+        };
+
+        // A variant may clash with another enum's variant or an earlier one of its own
+        let mut seen = HashSet::new();
+        if let Some(v) = e.variants.iter().find(|v| {
+            !seen.insert(v.name.name.as_str()) || self.types.get_variant(&v.name.name).is_some()
+        }) {
+            self.errors.push(CheckError::DuplicateDefinition {
+                name: v.name.name.clone(),
+                line: v.name.span.line,
+                column: v.name.span.column,
+            });
+            return;
+        }
+
+        if self.types.define_enum(def.clone()).is_err() {
+            self.errors.push(CheckError::DuplicateDefinition {
+                name: e.name.name.clone(),
+                line: e.span.line,
+                column: e.span.column,
+            });
+            return;
+        }
+
+        let _ = self.symbols.define(Symbol {
+            name: e.name.name.clone(),
+            kind: SymbolKind::Enum,
+            ty: def.self_ty(),
+            span: e.span,
+            mutable: false,
+        });
+
+        // Unit variants are values, tuple variants are constructor functions.
+        // Type parameters are left open here and inferred at each use.
+        let enum_ty = def.self_ty().substitute(&def.type_params, &[]);
+        for (variant, decl) in def.variants.iter().zip(&e.variants) {
+            let ty = match variant.shape {
+                VariantShape::Unit => enum_ty.clone(),
+                VariantShape::Tuple => Ty::Function {
+                    params: variant.fields
+                        .iter()
+                        .map(|(_, t)| t.substitute(&def.type_params, &[]))
+                        .collect(),
+                    result: Box::new(enum_ty.clone()),
+                },
+                VariantShape::Struct => continue,
+            };
+            let _ = self.symbols.define(Symbol {
+                name: variant.name.clone(),
+                kind: SymbolKind::Variant,
+                ty,
+                span: decl.span,
+                mutable: false,
+            });
+        }
+    }
+
     /// Second pass: type check top-level items
     fn check_top_level(&mut self, item: &TopLevel) {
         match item {
             TopLevel::Function(f) => self.check_function(f),
             TopLevel::Struct(s) => self.check_struct(s),
+            TopLevel::Enum(e) => self.check_enum(e),
             TopLevel::Comptime(c) => self.check_comptime(&c.block),
+            TopLevel::Trait(t) => self.check_trait(t),
+            TopLevel::Impl(i) => self.check_impl(i),
             _ => {} // Already handled in first pass
         }
     }
 
+    /// Check the default method bodies of a trait, where `Self` is some type implementing it
+    fn check_trait(&mut self, t: &TraitDecl) {
+        self.self_ty = Some(Ty::Named("Self".to_string()));
+        self.bounds.push(("Self".to_string(), vec![t.name.name.clone()]));
+        for method in &t.methods {
+            for param in &method.type_params {
+                self.check_bounds_exist(param);
+            }
+            if let Some(body) = &method.default {
+                self.check_function(&FnDecl {
+                    modifiers: vec![],
+                    name: method.name.clone(),
+                    type_params: method.type_params.clone(),
+                    params: method.params.clone(),
+                    return_type: method.return_type.clone(),
+                    contract: None,
+                    body: body.clone(),
+                    public: false,
+                    span: method.span,
+                });
+            }
+        }
+        self.bounds.pop();
+        self.self_ty = None;
+    }
+
+    fn check_impl(&mut self, i: &ImplDecl) {
+        self.enter_type_params(&i.type_params);
+        self.check_type_exists(&i.self_ty);
+        self.self_ty = Some(ast_type_to_ty(&i.self_ty));
+        for method in &i.methods {
+            self.check_function(method);
+        }
+        self.self_ty = None;
+        self.exit_type_params(&i.type_params);
+    }
+
+    /// Convert a type annotation, resolving `Self` inside impls and traits
+    fn lower_type(&self, ty: &Type) -> Ty {
+        let ty = ast_type_to_ty(ty);
+        match &self.self_ty {
+            Some(self_ty) => ty.substitute(&["Self".to_string()], std::slice::from_ref(self_ty)),
+            None => ty,
+        }
+    }
+
     fn check_function(&mut self, f: &FnDecl) {
+        self.enter_type_params(&f.type_params);
         self.symbols.enter_scope();
+        let outer_obligations = std::mem::take(&mut self.obligations);
 
         // Add parameters to scope
         for param in &f.params {
-            let ty = ast_type_to_ty(&param.ty);
-            if let Err(_) = self.symbols.define(Symbol {
+            if param.is_self() && self.self_ty.is_none() {
+                self.errors.push(CheckError::Other {
+                    message: "'self' parameter outside of an impl or trait".to_string(),
+                    line: param.span.line,
+                    column: param.span.column,
+                });
+            }
+            let ty = self.lower_type(&param.ty);
+            if self.symbols.define(Symbol {
                 name: param.name.name.clone(),
                 kind: SymbolKind::Parameter,
                 ty,
                 span: param.span,
-                mutable: false,
-            }) {
+                mutable: param.mutable,
+            }).is_err() {
                 self.errors.push(CheckError::DuplicateDefinition {
                     name: param.name.name.clone(),
                     line: param.span.line,
@@ -531,66 +1109,361 @@ impl Checker {
         }
 
         // Set return type context
-        self.current_return_type = f.return_type.as_ref().map(ast_type_to_ty);
+        self.current_return_type = f.return_type.as_ref().map(|t| self.lower_type(t));
+        let key = match self.self_ty.as_ref().and_then(Ty::impl_key) {
+            Some(ty) => format!("{}::{}", ty, f.name.name),
+            None => f.name.name.clone(),
+        };
+        self.performs_ai = self.effect_rows.get(&key).is_some_and(|row| row.contains_key("AI"));
+
+        if let Some(contract) = &f.contract {
+            let result_ty = match self.current_return_type.clone() {
+                Some(Ty::Effect(inner, _)) => *inner,
+                ty => ty.unwrap_or(Ty::Unit),
+            };
+            self.check_contract(contract, Some(&result_ty));
+        }
 
-        // Check function body
+        // Check function body; loops never extend across function boundaries
+        let outer_loops = std::mem::take(&mut self.loops);
         self.check_block(&f.body);
+        self.loops = outer_loops;
+
+        // Bounds are checked while this function's own type parameters are in scope
+        self.discharge_obligations();
+        self.obligations = outer_obligations;
 
         self.current_return_type = None;
+        self.performs_ai = false;
         self.symbols.exit_scope();
+        self.exit_type_params(&f.type_params);
     }
 
     fn check_struct(&mut self, s: &StructDecl) {
         // Check that field types are valid
+        self.enter_type_params(&s.type_params);
         for field in &s.fields {
             self.check_type_exists(&field.ty);
         }
-    }
 
-    fn check_comptime(&mut self, block: &Block) {
-        self.symbols.enter_scope();
-        self.check_block(block);
-        self.symbols.exit_scope();
+        // Invariants see the value as `self` and each of its fields by name
+        if let (Some(contract), Some(def)) = (&s.contract, self.types.get_struct(&s.name.name).cloned()) {
+            self.symbols.enter_scope();
+            let self_ty = if def.type_params.is_empty() {
+                Ty::Named(def.name.clone())
+            } else {
+                Ty::Generic {
+                    name: def.name.clone(),
+                    args: def.type_params.iter().map(|p| Ty::Named(p.clone())).collect(),
+                }
+            };
+            for (name, ty) in std::iter::once(("self".to_string(), self_ty)).chain(def.fields) {
+                let _ = self.symbols.define(Symbol {
+                    name,
+                    kind: SymbolKind::Variable,
+                    ty,
+                    span: contract.span,
+                    mutable: false,
+                });
+            }
+            self.check_contract(contract, None);
+            self.symbols.exit_scope();
+        }
+        self.exit_type_params(&s.type_params);
+        self.check_derives(&s.modifiers);
     }
 
-    fn check_block(&mut self, block: &Block) {
-        for stmt in &block.stmts {
-            self.check_stmt(stmt);
+    /// Check that contract conditions are `Bool`. `post` clauses see the return
+    /// value as `result`; without a `result_ty` the contract belongs to a struct,
+    /// which only takes `invariant` clauses.
+    fn check_contract(&mut self, contract: &Contract, result_ty: Option<&Ty>) {
+        for clause in &contract.clauses {
+            let (condition, span) = match clause {
+                ContractClause::Invariant(condition, span) => (condition, *span),
+                ContractClause::Pre(condition, span) | ContractClause::Post(condition, span) if result_ty.is_some() => {
+                    (condition, *span)
+                }
+                ContractClause::Pre(_, span) | ContractClause::Post(_, span) => {
+                    self.errors.push(CheckError::Other {
+                        message: "Only 'invariant' clauses apply to structs".to_string(),
+                        line: span.line,
+                        column: span.column,
+                    });
+                    continue;
+                }
+                ContractClause::AiCheck(..) | ContractClause::AiEnsure(..) => continue,
+            };
+
+            self.symbols.enter_scope();
+            if let (ContractClause::Post(..), Some(ty)) = (clause, result_ty) {
+                let _ = self.symbols.define(Symbol {
+                    name: "result".to_string(),
+                    kind: SymbolKind::Variable,
+                    ty: ty.clone(),
+                    span,
+                    mutable: false,
+                });
+            }
+            let ty = self.check_expr(condition);
+            self.expect_bool(&ty, span);
+            self.symbols.exit_scope();
         }
     }
 
-    fn check_stmt(&mut self, stmt: &Stmt) {
-        match stmt {
-            Stmt::Expr(expr) => {
-                self.check_expr(expr);
+    /// Only the traits in `DERIVABLE_TRAITS` can be derived
+    fn check_derives(&mut self, modifiers: &[StructModifier]) {
+        for modifier in modifiers {
+            if let StructModifier::Derive(traits) = modifier {
+                for t in traits {
+                    if !crate::stdlib::DERIVABLE_TRAITS.contains(&t.name.as_str()) {
+                        self.errors.push(CheckError::Other {
+                            message: format!(
+                                "Cannot derive '{}'; derivable traits are {}",
+                                t.name,
+                                crate::stdlib::DERIVABLE_TRAITS.join(", ")
+                            ),
+                            line: t.span.line,
+                            column: t.span.column,
+                        });
+                    }
+                }
             }
+        }
+    }
 
-            Stmt::Let { mutable, name, ty, value, span } => {
-                let value_ty = self.check_expr(value);
-
-                let declared_ty = ty.as_ref().map(ast_type_to_ty);
-
-                let final_ty = if let Some(decl) = &declared_ty {
-                    if !decl.is_assignable_from(&value_ty) && !value_ty.is_error_or_unknown() {
-                        self.errors.push(CheckError::TypeMismatch {
-                            expected: decl.to_string(),
-                            found: value_ty.to_string(),
-                            line: span.line,
-                            column: span.column,
-                        });
+    fn check_enum(&mut self, e: &EnumDecl) {
+        self.check_derives(&e.modifiers);
+        self.enter_type_params(&e.type_params);
+        for variant in &e.variants {
+            match &variant.kind {
+                VariantKind::Unit => {}
+                VariantKind::Tuple(types) => {
+                    for ty in types {
+                        self.check_type_exists(ty);
                     }
+                }
+                VariantKind::Struct(fields) => {
+                    for field in fields {
+                        self.check_type_exists(&field.ty);
+                    }
+                }
+            }
+        }
+        self.exit_type_params(&e.type_params);
+    }
+
+    /// Open a scope in which the given type parameters and their bounds are defined
+    fn enter_type_params(&mut self, params: &[TypeParam]) {
+        self.symbols.enter_scope();
+        for param in params {
+            self.check_bounds_exist(param);
+            let _ = self.symbols.define(Symbol {
+                name: param.name.name.clone(),
+                kind: SymbolKind::TypeParam,
+                ty: Ty::Named(param.name.name.clone()),
+                span: param.span,
+                mutable: false,
+            });
+            let bounds = param.bounds.iter().map(|b| b.name.clone()).collect();
+            self.bounds.push((param.name.name.clone(), bounds));
+        }
+    }
+
+    /// Close the scope opened by `enter_type_params`
+    fn exit_type_params(&mut self, params: &[TypeParam]) {
+        self.bounds.truncate(self.bounds.len() - params.len());
+        self.symbols.exit_scope();
+    }
+
+    fn check_bounds_exist(&mut self, param: &TypeParam) {
+        for bound in &param.bounds {
+            if self.types.get_trait(&bound.name).is_none() {
+                self.errors.push(CheckError::Other {
+                    message: format!("Undefined trait '{}'", bound.name),
+                    line: bound.span.line,
+                    column: bound.span.column,
+                });
+            }
+        }
+    }
+
+    /// Bounds of the type parameter `name` if one is in scope
+    fn bounds_of(&self, name: &str) -> Option<&[String]> {
+        self.bounds.iter().rev().find(|(n, _)| n == name).map(|(_, b)| b.as_slice())
+    }
+
+    /// Does `ty` implement `trait_name`? Types still being inferred are given the benefit
+    /// of the doubt.
+    fn implements(&mut self, ty: &Ty, trait_name: &str) -> bool {
+        let ty = self.subst.resolve(ty);
+        if ty.is_error_or_unknown() || matches!(ty, Ty::Var(_)) {
+            return true;
+        }
+        if let Ty::Named(name) = &ty {
+            if let Some(bounds) = self.bounds_of(name) {
+                return bounds.iter().any(|b| b == trait_name);
+            }
+        }
+        let Some(imp) = ty.impl_key().and_then(|k| self.types.get_impl(&k, trait_name)).cloned() else {
+            return false;
+        };
+
+        // A generic impl applies if its type matches and its own bounds hold
+        let names: Vec<String> = imp.type_params.iter().map(|(n, _)| n.clone()).collect();
+        let args: Vec<Ty> = names.iter().map(|_| self.fresh_var()).collect();
+        if self.subst.unify(&imp.self_ty.substitute(&names, &args), &ty).is_err() {
+            return false;
+        }
+        imp.type_params
+            .iter()
+            .zip(&args)
+            .all(|((_, bounds), arg)| bounds.iter().all(|b| self.implements(arg, b)))
+    }
+
+    /// Report the obligations that do not hold
+    fn discharge_obligations(&mut self) {
+        for ob in std::mem::take(&mut self.obligations) {
+            if !self.implements(&ob.ty, &ob.trait_name) {
+                self.errors.push(CheckError::UnsatisfiedBound {
+                    ty: self.subst.resolve(&ob.ty).to_string(),
+                    bound: ob.trait_name,
+                    line: ob.span.line,
+                    column: ob.span.column,
+                });
+            }
+        }
+    }
+
+    /// Replace the type parameters `params` with fresh variables, requiring each to
+    /// satisfy its bounds
+    fn instantiate_bounded(&mut self, params: &[(String, Vec<String>)], ty: &Ty, span: Span) -> Ty {
+        let names: Vec<String> = params.iter().map(|(n, _)| n.clone()).collect();
+        let args: Vec<Ty> = names.iter().map(|_| self.fresh_var()).collect();
+        for ((_, bounds), arg) in params.iter().zip(&args) {
+            for bound in bounds {
+                self.obligations.push(Obligation {
+                    ty: arg.clone(),
+                    trait_name: bound.clone(),
+                    span,
+                });
+            }
+        }
+        ty.substitute(&names, &args)
+    }
+
+    /// Instantiate a method's own type parameters, if it is generic
+    fn instantiate_method(&mut self, ty: &Ty) -> Ty {
+        match ty {
+            Ty::Forall { params, ty } => self.subst.instantiate(params, ty, self.level),
+            ty => ty.clone(),
+        }
+    }
+
+    /// Find the method `name` of `recv`, instantiated for this use. Type parameters
+    /// offer the methods of their bounds; other types those of their impls.
+    fn resolve_method(&mut self, recv: &Ty, name: &str, span: Span) -> Option<MethodDef> {
+        let recv = self.subst.resolve(recv);
+        if let Ty::Named(param) = &recv {
+            if let Some(bounds) = self.bounds_of(param).map(<[String]>::to_vec) {
+                let found = bounds.iter().find_map(|b| {
+                    self.types.get_trait(b)?.methods.iter().find(|m| m.name == name).cloned()
+                });
+                if let Some(method) = found {
+                    let ty = method.ty.substitute(&["Self".to_string()], std::slice::from_ref(&recv));
+                    return Some(MethodDef { ty: self.instantiate_method(&ty), ..method });
+                }
+            }
+        }
+
+        let found = recv
+            .impl_key()
+            .and_then(|k| self.types.find_method(&k, name))
+            .map(|(imp, m)| (imp.type_params.clone(), m.clone()));
+        match found {
+            Some((type_params, method)) => {
+                let ty = self.instantiate_bounded(&type_params, &method.ty, span);
+                Some(MethodDef { ty: self.instantiate_method(&ty), ..method })
+            }
+            None => {
+                self.errors.push(CheckError::UndefinedMethod {
+                    ty: recv.to_string(),
+                    method: name.to_string(),
+                    line: span.line,
+                    column: span.column,
+                });
+                None
+            }
+        }
+    }
+
+    fn check_comptime(&mut self, block: &Block) {
+        self.symbols.enter_scope();
+        self.check_block(block);
+        self.symbols.exit_scope();
+    }
+
+    fn check_block(&mut self, block: &Block) {
+        for stmt in &block.stmts {
+            self.check_stmt(stmt);
+        }
+    }
+
+    fn check_stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Expr(expr) => {
+                self.check_expr(expr);
+            }
+
+            Stmt::Let { mutable, pattern, ty, value, span } => {
+                let declared_ty = ty.as_ref().map(|t| self.lower_type(t));
+                self.level += 1;
+                let value_ty = self.check_expr(value);
+                self.level -= 1;
+                let value_ty = declared_ty
+                    .as_ref()
+                    .and_then(|decl| self.generated_struct(decl, value))
+                    .unwrap_or(value_ty);
+
+                let final_ty = if let Some(decl) = &declared_ty {
+                    self.expect_ty(decl, &value_ty, *span);
                     decl.clone()
+                } else if !*mutable && is_syntactic_value(value) && matches!(pattern, Pattern::Ident(_)) {
+                    self.subst.generalize(&value_ty, self.level)
                 } else {
+                    self.subst.pin(&value_ty, self.level);
                     value_ty
                 };
 
-                if let Err(_) = self.symbols.define(Symbol {
+                let name = match pattern {
+                    Pattern::Ident(name) if self.pattern_bindings(pattern).len() == 1 => name,
+                    _ => {
+                        // Destructuring: the pattern must match every value of the type
+                        self.check_pattern(pattern, &final_ty);
+                        if *mutable {
+                            for name in self.pattern_bindings(pattern) {
+                                if let Some(symbol) = self.symbols.lookup_current_mut(&name) {
+                                    symbol.mutable = true;
+                                }
+                            }
+                        }
+                        if let Some(witness) = exhaustiveness::check_let(&self.types, pattern) {
+                            self.errors.push(CheckError::Other {
+                                message: format!("Refutable pattern in 'let': '{}' not covered", witness),
+                                line: span.line,
+                                column: span.column,
+                            });
+                        }
+                        return;
+                    }
+                };
+
+                if self.symbols.define(Symbol {
                     name: name.name.clone(),
                     kind: SymbolKind::Variable,
                     ty: final_ty,
                     span: *span,
                     mutable: *mutable,
-                }) {
+                }).is_err() {
                     self.errors.push(CheckError::DuplicateDefinition {
                         name: name.name.clone(),
                         line: span.line,
@@ -601,13 +1474,7 @@ impl Checker {
 
             Stmt::If { condition, then_block, else_block, span } => {
                 let cond_ty = self.check_expr(condition);
-                if cond_ty != Ty::Bool && !cond_ty.is_error_or_unknown() {
-                    self.errors.push(CheckError::NonBoolCondition {
-                        found: cond_ty.to_string(),
-                        line: span.line,
-                        column: span.column,
-                    });
-                }
+                self.expect_bool(&cond_ty, *span);
 
                 self.symbols.enter_scope();
                 self.check_block(then_block);
@@ -620,6 +1487,72 @@ impl Checker {
                 }
             }
 
+            Stmt::While { label, condition, body, span } => {
+                let cond_ty = self.check_expr(condition);
+                self.expect_bool(&cond_ty, *span);
+
+                self.check_loop_body(label.as_ref(), false, body);
+            }
+
+            Stmt::For { label, binding, iterable, body, span } => {
+                let iter_ty = self.check_expr(iterable);
+                let elem_ty = match iter_ty {
+                    Ty::Array(elem) => *elem,
+                    Ty::String => Ty::String,
+                    Ty::Var(_) => {
+                        let elem = self.fresh_var();
+                        self.expect_ty(&iter_ty, &Ty::Array(Box::new(elem.clone())), *span);
+                        elem
+                    }
+                    ty if ty.is_error_or_unknown() => Ty::Unknown,
+                    ty => {
+                        self.errors.push(CheckError::TypeMismatch {
+                            expected: "array or string".to_string(),
+                            found: ty.to_string(),
+                            line: span.line,
+                            column: span.column,
+                        });
+                        Ty::Error
+                    }
+                };
+
+                self.symbols.enter_scope();
+                let _ = self.symbols.define(Symbol {
+                    name: binding.name.clone(),
+                    kind: SymbolKind::Variable,
+                    ty: elem_ty,
+                    span: binding.span,
+                    mutable: false,
+                });
+                self.check_loop_body(label.as_ref(), false, body);
+                self.symbols.exit_scope();
+            }
+
+            Stmt::Break { label, value, span } => {
+                let value_ty = value.as_ref().map(|v| self.check_expr(v));
+
+                if let Some(index) = self.resolve_loop("break", label.as_ref(), *span) {
+                    if let Some(value_ty) = value_ty {
+                        let ctx = &mut self.loops[index];
+                        if !ctx.is_loop_expr {
+                            self.errors.push(CheckError::Other {
+                                message: "'break' with a value is only allowed inside 'loop'".to_string(),
+                                line: span.line,
+                                column: span.column,
+                            });
+                        } else if let Some(expected) = ctx.break_ty.clone() {
+                            self.expect_ty(&expected, &value_ty, *span);
+                        } else {
+                            ctx.break_ty = Some(value_ty);
+                        }
+                    }
+                }
+            }
+
+            Stmt::Continue { label, span } => {
+                self.resolve_loop("continue", label.as_ref(), *span);
+            }
+
             Stmt::Go { block, .. } => {
                 self.symbols.enter_scope();
                 self.check_block(block);
@@ -629,18 +1562,23 @@ impl Checker {
             Stmt::Return { value, span } => {
                 let return_ty = value
                     .as_ref()
-                    .map(|v| self.check_expr(v))
+                    .map(|v| {
+                        let ty = self.check_expr(v);
+                        let expected = self.current_return_type.clone();
+                        expected.and_then(|e| self.generated_struct(&e, v)).unwrap_or(ty)
+                    })
                     .unwrap_or(Ty::Unit);
 
-                if let Some(expected) = &self.current_return_type {
-                    if !expected.is_assignable_from(&return_ty) && !return_ty.is_error_or_unknown() {
-                        self.errors.push(CheckError::TypeMismatch {
-                            expected: expected.to_string(),
-                            found: return_ty.to_string(),
-                            line: span.line,
-                            column: span.column,
-                        });
-                    }
+                self.returned = true;
+                if let Some(expected) = self.current_return_type.clone() {
+                    // A reply returned from a function not declaring `AI` is
+                    // reported once, as a missing effect annotation
+                    let return_ty = match (self.subst.resolve(&expected), self.subst.resolve(&return_ty)) {
+                        (Ty::AI(_) | Ty::Effect(..), _) => return_ty,
+                        (_, Ty::AI(inner)) if self.performs_ai => *inner,
+                        _ => return_ty,
+                    };
+                    self.expect_ty(&expected, &return_ty, *span);
                 }
             }
 
@@ -648,8 +1586,11 @@ impl Checker {
                 self.check_expr(value);
             }
 
-            Stmt::Try { value, .. } => {
-                self.check_expr(value);
+            Stmt::Try { value, propagate, span } => {
+                let ty = self.check_expr(value);
+                if *propagate {
+                    self.check_try(&ty, *span);
+                }
             }
 
             Stmt::Comptime { block, .. } => {
@@ -661,16 +1602,45 @@ impl Checker {
             Stmt::Ai(ai_stmt) => {
                 self.check_ai_stmt(ai_stmt);
             }
+
+            // Reported by the parser
+            Stmt::Error(_) => {}
         }
     }
 
+    /// Type of `expr`, with every inference variable solved so far substituted
     fn check_expr(&mut self, expr: &Expr) -> Ty {
+        let ty = self.infer_expr(expr);
+        self.subst.resolve(&ty)
+    }
+
+    fn infer_expr(&mut self, expr: &Expr) -> Ty {
         match expr {
             Expr::Literal(lit) => self.check_literal(lit),
 
+            // Any value can be displayed
+            Expr::Interpolation { parts, .. } => {
+                for expr in parts.iter().filter_map(StringPart::expr) {
+                    self.check_expr(expr);
+                }
+                Ty::String
+            }
+
             Expr::Ident(ident) => {
                 if let Some(symbol) = self.symbols.lookup(&ident.name) {
-                    symbol.ty.clone()
+                    // Each use of a polymorphic value gets its own instance
+                    let is_function = symbol.kind == SymbolKind::Function;
+                    match symbol.ty.clone() {
+                        Ty::Forall { params, ty } => {
+                            let bounds = self.fn_bounds.get(&ident.name).filter(|_| is_function);
+                            let params: Vec<(String, Vec<String>)> = match bounds {
+                                Some(bounds) => params.into_iter().zip(bounds.iter().cloned()).collect(),
+                                None => params.into_iter().map(|p| (p, vec![])).collect(),
+                            };
+                            self.instantiate_bounded(&params, &ty, ident.span)
+                        }
+                        ty => ty,
+                    }
                 } else {
                     self.errors.push(CheckError::UndefinedVariable {
                         name: ident.name.clone(),
@@ -683,35 +1653,63 @@ impl Checker {
 
             Expr::Call { callee, args, span } => {
                 let callee_ty = self.check_expr(callee);
-                let arg_types: Vec<Ty> = args.iter().map(|a| self.check_expr(a)).collect();
-
-                match callee_ty {
-                    Ty::Function { params, result } => {
-                        if params.len() != arg_types.len() {
+                let mut arg_types: Vec<Ty> = args.iter().map(|a| self.check_expr(a)).collect();
+                self.generated_args(&callee_ty, args, &mut arg_types);
+
+                // Variant constructors infer the enum's type arguments from their payload
+                if let Expr::Ident(ident) = callee.as_ref() {
+                    let is_variant = self.symbols
+                        .lookup(&ident.name)
+                        .map(|s| s.kind == SymbolKind::Variant)
+                        .unwrap_or(false);
+                    if let (true, Some((enum_def, variant))) = (is_variant, self.types.get_variant(&ident.name)) {
+                        let (enum_def, variant) = (enum_def.clone(), variant.clone());
+                        if arg_types.len() != variant.fields.len() {
                             self.errors.push(CheckError::WrongArgCount {
-                                expected: params.len(),
+                                expected: variant.fields.len(),
                                 found: arg_types.len(),
                                 line: span.line,
                                 column: span.column,
                             });
-                        } else {
-                            for (_i, (param, arg)) in params.iter().zip(arg_types.iter()).enumerate() {
-                                if !param.is_assignable_from(arg) && !arg.is_error_or_unknown() {
-                                    self.errors.push(CheckError::TypeMismatch {
-                                        expected: param.to_string(),
-                                        found: arg.to_string(),
-                                        line: span.line,
-                                        column: span.column,
-                                    });
-                                }
-                            }
+                            return Ty::Error;
                         }
-                        *result
+                        let field_types: Vec<Ty> = variant.fields.iter().map(|(_, t)| t.clone()).collect();
+                        return self.instantiate(&enum_def.type_params, &enum_def.self_ty(), &field_types, &arg_types, *span);
+                    }
+                }
+
+                self.check_call(callee_ty, arg_types, *span)
+            }
+
+            Expr::MethodCall { receiver, method, args, span } => {
+                let recv_ty = self.check_expr(receiver);
+                let mut arg_types: Vec<Ty> = args.iter().map(|a| self.check_expr(a)).collect();
+
+                // A function stored in a field is called like any other function
+                if let Some(field_ty) = self.function_field(&recv_ty, &method.name) {
+                    self.generated_args(&field_ty, args, &mut arg_types);
+                    return self.check_call(field_ty, arg_types, *span);
+                }
+                if recv_ty.is_error_or_unknown() || matches!(recv_ty, Ty::Var(_)) {
+                    return Ty::Unknown;
+                }
+
+                let Some(found) = self.resolve_method(&recv_ty, &method.name, method.span) else {
+                    return Ty::Error;
+                };
+                match found.ty {
+                    Ty::Function { params, result } if found.has_self => {
+                        self.expect_ty(&params[0], &recv_ty, *span);
+                        let rest = Ty::Function { params: params[1..].to_vec(), result };
+                        self.generated_args(&rest, args, &mut arg_types);
+                        self.check_call(rest, arg_types, *span)
                     }
-                    Ty::Error | Ty::Unknown => Ty::Error,
                     _ => {
                         self.errors.push(CheckError::Other {
-                            message: format!("Cannot call non-function type '{}'", callee_ty),
+                            message: format!(
+                                "'{}' is an associated function of '{}', not a method; call it as '{}::{}'",
+                                method.name, recv_ty, recv_ty.impl_key().unwrap_or_default(), method.name
+                            ),
                             line: span.line,
                             column: span.column,
                         });
@@ -720,46 +1718,149 @@ impl Checker {
                 }
             }
 
+            // Functions of modules imported with `use`, declared as `module::function`
+            Expr::Path { ty, name, span } if self.symbols.lookup(&format!("{}::{}", ty.name, name.name)).is_some() => {
+                self.check_expr(&Expr::Ident(Ident { name: format!("{}::{}", ty.name, name.name), span: *span }))
+            }
+
+            // Functions an imported module does not export
+            Expr::Path { ty, name, span } if self.private.contains_key(&format!("{}::{}", ty.name, name.name)) => {
+                let path = format!("{}::{}", ty.name, name.name);
+                self.errors.push(CheckError::PrivateItem {
+                    module: self.private[&path].clone(),
+                    name: path,
+                    line: span.line,
+                    column: span.column,
+                });
+                Ty::Error
+            }
+
+            // Associated functions, and methods called with the receiver as first argument
+            Expr::Path { ty, name, span } => self
+                .resolve_method(&Ty::Named(ty.name.clone()), &name.name, *span)
+                .map(|m| m.ty)
+                .unwrap_or(Ty::Error),
+
             Expr::Field { object, field, span } => {
                 let obj_ty = self.check_expr(object);
+                self.check_field_access(&obj_ty, &field.name, *span)
+            }
 
-                match &obj_ty {
-                    Ty::Named(name) => {
-                        if let Some(struct_def) = self.types.get_struct(name) {
-                            if let Some((_, field_ty)) = struct_def.fields.iter()
-                                .find(|(n, _)| n == &field.name)
-                            {
-                                field_ty.clone()
-                            } else {
-                                self.errors.push(CheckError::Other {
-                                    message: format!("No field '{}' on type '{}'", field.name, name),
-                                    line: span.line,
-                                    column: span.column,
-                                });
-                                Ty::Error
-                            }
-                        } else {
-                            Ty::Unknown
+            Expr::StructLit { name, fields, span } => {
+                let field_types: Vec<(String, Ty)> = fields
+                    .iter()
+                    .map(|f| (f.name.name.clone(), self.check_expr(&f.value)))
+                    .collect();
+
+                // Either a struct or a struct-like enum variant
+                let (type_params, result_ty, decl_fields) = if let Some(s) = self.types.get_struct(&name.name) {
+                    let result_ty = if s.type_params.is_empty() {
+                        Ty::Named(s.name.clone())
+                    } else {
+                        Ty::Generic {
+                            name: s.name.clone(),
+                            args: s.type_params.iter().map(|p| Ty::Named(p.clone())).collect(),
                         }
+                    };
+                    (s.type_params.clone(), result_ty, s.fields.clone())
+                } else if let Some((e, v)) = self.types.get_variant(&name.name) {
+                    if v.shape != VariantShape::Struct {
+                        self.errors.push(CheckError::Other {
+                            message: format!("Variant '{}' does not have named fields", name.name),
+                            line: span.line,
+                            column: span.column,
+                        });
+                        return Ty::Error;
                     }
-                    Ty::Record(fields) => {
-                        if let Some((_, field_ty)) = fields.iter()
-                            .find(|(n, _)| n == &field.name)
-                        {
-                            field_ty.clone()
-                        } else {
-                            self.errors.push(CheckError::Other {
-                                message: format!("No field '{}' in record", field.name),
-                                line: span.line,
-                                column: span.column,
-                            });
-                            Ty::Error
+                    (e.type_params.clone(), e.self_ty(), v.fields.clone())
+                } else {
+                    self.errors.push(CheckError::UndefinedType {
+                        name: name.name.clone(),
+                        line: name.span.line,
+                        column: name.span.column,
+                    });
+                    return Ty::Error;
+                };
+
+                for (field, _) in &field_types {
+                    if !decl_fields.iter().any(|(n, _)| n == field) {
+                        self.errors.push(CheckError::Other {
+                            message: format!("No field '{}' on type '{}'", field, name.name),
+                            line: span.line,
+                            column: span.column,
+                        });
+                    }
+                }
+
+                // Line the literal's fields up with the declaration order
+                let mut expected = Vec::new();
+                let mut actual = Vec::new();
+                for (field, ty) in &decl_fields {
+                    match field_types.iter().find(|(n, _)| n == field) {
+                        Some((_, value_ty)) => {
+                            expected.push(ty.clone());
+                            actual.push(value_ty.clone());
                         }
+                        None => self.errors.push(CheckError::Other {
+                            message: format!("Missing field '{}' in '{}'", field, name.name),
+                            line: span.line,
+                            column: span.column,
+                        }),
+                    }
+                }
+
+                self.instantiate(&type_params, &result_ty, &expected, &actual, *span)
+            }
+
+            Expr::Index { object, index, span } => {
+                let obj_ty = self.check_expr(object);
+                let index_ty = self.check_expr(index);
+
+                match (&obj_ty, index.as_ref()) {
+                    // `rec["key"]` is field access with a literal key
+                    (Ty::Record(_) | Ty::Named(_), Expr::Literal(Literal::String(key, _))) => {
+                        self.check_field_access(&obj_ty, key, *span)
+                    }
+                    (Ty::Array(elem), _) => {
+                        self.expect_index_type(&index_ty, *span);
+                        elem.as_ref().clone()
+                    }
+                    (Ty::String, _) => {
+                        self.expect_index_type(&index_ty, *span);
+                        Ty::String
+                    }
+                    (Ty::Var(_), _) => {
+                        self.expect_index_type(&index_ty, *span);
+                        let elem = self.fresh_var();
+                        self.expect_ty(&obj_ty, &Ty::Array(Box::new(elem.clone())), *span);
+                        elem
+                    }
+                    (Ty::Record(_), _) if index_ty == Ty::String || index_ty.is_error_or_unknown() => Ty::Unknown,
+                    (ty, _) if ty.is_error_or_unknown() => Ty::Error,
+                    _ => {
+                        self.errors.push(CheckError::Other {
+                            message: format!("Cannot index into type '{}'", obj_ty),
+                            line: span.line,
+                            column: span.column,
+                        });
+                        Ty::Error
                     }
-                    Ty::Error | Ty::Unknown => Ty::Error,
+                }
+            }
+
+            Expr::Slice { object, start, end, span } => {
+                let obj_ty = self.check_expr(object);
+                for bound in [start, end].into_iter().flatten() {
+                    let bound_ty = self.check_expr(bound);
+                    self.expect_index_type(&bound_ty, *span);
+                }
+
+                match &obj_ty {
+                    Ty::Array(_) | Ty::String | Ty::Var(_) => obj_ty,
+                    ty if ty.is_error_or_unknown() => Ty::Error,
                     _ => {
                         self.errors.push(CheckError::Other {
-                            message: format!("Cannot access field on type '{}'", obj_ty),
+                            message: format!("Cannot slice type '{}'", obj_ty),
                             line: span.line,
                             column: span.column,
                         });
@@ -775,13 +1876,34 @@ impl Checker {
                 self.check_binary_op(*op, &left_ty, &right_ty, *span)
             }
 
+            Expr::Assign { target, op, value, span } => {
+                self.check_assign_target(target, *span);
+                let target_ty = self.check_expr(target);
+                let mut value_ty = self.check_expr(value);
+                // `target op= value` assigns `target op value`
+                if let Some(op) = op {
+                    value_ty = self.check_binary_op(*op, &target_ty, &value_ty, *span);
+                }
+                if target_ty.is_error_or_unknown() || value_ty.is_error_or_unknown() {
+                    return Ty::Error;
+                }
+                // Assignment is valued by the assigned value
+                self.expect_ty(&target_ty, &value_ty, *span);
+                target_ty
+            }
+
             Expr::Unary { op, operand, span } => {
                 let operand_ty = self.check_expr(operand);
                 self.check_unary_op(*op, &operand_ty, *span)
             }
 
-            Expr::Try { operand, .. } => {
-                self.check_expr(operand)
+            Expr::Try { operand, propagate, span } => {
+                let ty = self.check_expr(operand);
+                if *propagate {
+                    self.check_try(&ty, *span)
+                } else {
+                    ty
+                }
             }
 
             Expr::Block(block) => {
@@ -795,15 +1917,55 @@ impl Checker {
                 self.check_expr(operand)
             }
 
+            Expr::Perform { effect, op, args } => {
+                let arg_types: Vec<Ty> = args.iter().map(|a| self.check_expr(a)).collect();
+                let Some(def) = self.lookup_effect(effect) else { return Ty::Error };
+                let Some(op_ty) = self.effect_operation(&def, op) else { return Ty::Error };
+                let (params, result) = operation_signature(&op_ty);
+                self.check_call(Ty::Function { params, result: Box::new(result) }, arg_types, op.span)
+            }
+
+            Expr::Handle { body, effect, clauses, span } => self.check_handle(body, effect, clauses, *span),
+
+            Expr::Resume { value, span } => {
+                let value_ty = self.check_expr(value);
+                let Some(clause) = self.clauses.last().cloned() else {
+                    self.errors.push(CheckError::Other {
+                        message: "'resume' outside of a handler clause".to_string(),
+                        line: span.line,
+                        column: span.column,
+                    });
+                    return Ty::Error;
+                };
+                // Resumptions are one-shot: nothing may run after the rest of the body returns
+                if !clause.tails.contains(span) {
+                    self.errors.push(CheckError::Other {
+                        message: "'resume' must be the last thing a handler clause does".to_string(),
+                        line: span.line,
+                        column: span.column,
+                    });
+                }
+                self.expect_ty(&clause.resume_ty, &value_ty, *span);
+                clause.answer_ty
+            }
+
+            Expr::Loop { label, body, .. } => {
+                self.check_loop_body(label.as_ref(), true, body).unwrap_or(Ty::Unit)
+            }
+
             Expr::Ai(ai_expr) => {
                 self.check_ai_expr(ai_expr)
             }
 
-            Expr::Lambda { params, body, span: _ } => {
+            Expr::Lambda { params, body, span } => {
                 self.symbols.enter_scope();
 
                 let param_types: Vec<Ty> = params.iter().map(|p| {
-                    let ty = ast_type_to_ty(&p.ty);
+                    // Unannotated parameters are inferred from their uses in the body
+                    let ty = match &p.ty {
+                        Type::Infer(_) => self.fresh_var(),
+                        ty => self.lower_type(ty),
+                    };
                     let _ = self.symbols.define(Symbol {
                         name: p.name.name.clone(),
                         kind: SymbolKind::Parameter,
@@ -814,13 +1976,31 @@ impl Checker {
                     ty
                 }).collect();
 
-                let result_ty = match body {
-                    LambdaBody::Expr(e) => self.check_expr(e),
-                    LambdaBody::Block(b) => {
-                        self.check_block(b);
-                        Ty::Unit
-                    }
+                // `return` inside the body leaves the lambda, so it agrees with the body's value
+                let result_ty = self.fresh_var();
+                let outer_loops = std::mem::take(&mut self.loops);
+                let outer_return = self.current_return_type.replace(result_ty.clone());
+                let outer_returned = std::mem::replace(&mut self.returned, false);
+                // `=> { ... }` is a block body too, however it was parsed
+                let block = match body {
+                    LambdaBody::Block(b) => Some(b),
+                    LambdaBody::Expr(e) => match e.as_ref() {
+                        Expr::Block(b) => Some(b),
+                        _ => None,
+                    },
                 };
+                if let Some(b) = block {
+                    self.check_block(b);
+                    if !self.returned {
+                        self.expect_ty(&result_ty, &Ty::Unit, *span);
+                    }
+                } else if let LambdaBody::Expr(e) = body {
+                    let body_ty = self.check_expr(e);
+                    self.expect_ty(&result_ty, &body_ty, *span);
+                }
+                self.loops = outer_loops;
+                self.current_return_type = outer_return;
+                self.returned = outer_returned;
 
                 self.symbols.exit_scope();
 
@@ -830,7 +2010,7 @@ impl Checker {
                 }
             }
 
-            Expr::Match { scrutinee, arms, span: _ } => {
+            Expr::Match { scrutinee, arms, span } => {
                 let scrutinee_ty = self.check_expr(scrutinee);
 
                 let mut result_ty: Option<Ty> = None;
@@ -840,18 +2020,15 @@ impl Checker {
 
                     // Check pattern and introduce bindings
                     self.check_pattern(&arm.pattern, &scrutinee_ty);
+                    if let Some(guard) = &arm.guard {
+                        let guard_ty = self.check_expr(guard);
+                        self.expect_bool(&guard_ty, arm.span);
+                    }
 
                     let arm_ty = self.check_expr(&arm.body);
 
-                    if let Some(ref expected) = result_ty {
-                        if !expected.is_assignable_from(&arm_ty) && !arm_ty.is_error_or_unknown() {
-                            self.errors.push(CheckError::TypeMismatch {
-                                expected: expected.to_string(),
-                                found: arm_ty.to_string(),
-                                line: arm.span.line,
-                                column: arm.span.column,
-                            });
-                        }
+                    if let Some(expected) = result_ty.clone() {
+                        self.expect_ty(&expected, &arm_ty, arm.span);
                     } else {
                         result_ty = Some(arm_ty);
                     }
@@ -859,29 +2036,32 @@ impl Checker {
                     self.symbols.exit_scope();
                 }
 
-                result_ty.unwrap_or(Ty::Unit)
+                self.check_exhaustive(&scrutinee_ty, arms, *span);
+                // A match without arms never produces a value, so it fits any type
+                result_ty.unwrap_or_else(|| self.fresh_var())
             }
 
             Expr::Array { elements, span } => {
                 if elements.is_empty() {
-                    Ty::Array(Box::new(Ty::Unknown))
+                    Ty::Array(Box::new(self.fresh_var()))
                 } else {
                     let first_ty = self.check_expr(&elements[0]);
                     for elem in elements.iter().skip(1) {
                         let elem_ty = self.check_expr(elem);
-                        if !first_ty.is_assignable_from(&elem_ty) && !elem_ty.is_error_or_unknown() {
-                            self.errors.push(CheckError::TypeMismatch {
-                                expected: first_ty.to_string(),
-                                found: elem_ty.to_string(),
-                                line: span.line,
-                                column: span.column,
-                            });
-                        }
+                        self.expect_ty(&first_ty, &elem_ty, *span);
                     }
                     Ty::Array(Box::new(first_ty))
                 }
             }
 
+            Expr::Tuple { elements, .. } => {
+                if elements.is_empty() {
+                    Ty::Unit
+                } else {
+                    Ty::Tuple(elements.iter().map(|e| self.check_expr(e)).collect())
+                }
+            }
+
             Expr::Record { fields, span: _ } => {
                 let field_types: Vec<(String, Ty)> = fields
                     .iter()
@@ -892,6 +2072,232 @@ impl Checker {
         }
     }
 
+    /// Type of a block used for its value: that of its final expression statement
+    fn check_block_value(&mut self, block: &Block) -> Ty {
+        self.symbols.enter_scope();
+        let ty = match block.stmts.split_last() {
+            Some((Stmt::Expr(last), rest)) => {
+                rest.iter().for_each(|stmt| self.check_stmt(stmt));
+                self.check_expr(last)
+            }
+            _ => {
+                self.check_block(block);
+                Ty::Unit
+            }
+        };
+        self.symbols.exit_scope();
+        ty
+    }
+
+    /// Positional arguments fill a prompt's placeholders in order, named ones
+    /// the placeholder they name; every placeholder must be filled exactly once
+    fn check_prompt_args(&mut self, def: &PromptDef, name: &Ident, args: &[PromptArg]) {
+        let error = |message: String, span: Span| CheckError::PromptArgument {
+            prompt: def.name.clone(),
+            message,
+            line: span.line,
+            column: span.column,
+        };
+        let mut errors = Vec::new();
+        let mut filled: Vec<&str> = Vec::new();
+        let mut named = false;
+        for (i, arg) in args.iter().enumerate() {
+            match &arg.name {
+                None if named => errors.push(error("positional argument after named arguments".to_string(), name.span)),
+                None => match def.placeholders.get(i) {
+                    Some(placeholder) => filled.push(placeholder),
+                    None => errors.push(error(
+                        format!("argument {} has no placeholder to fill (the template has {})", i + 1, def.placeholders.len()),
+                        name.span,
+                    )),
+                },
+                Some(arg_name) => {
+                    named = true;
+                    if !def.placeholders.contains(&arg_name.name) {
+                        errors.push(error(format!("no placeholder named '{}'", arg_name.name), arg_name.span));
+                    } else if filled.contains(&arg_name.name.as_str()) {
+                        errors.push(error(format!("placeholder '{}' is filled more than once", arg_name.name), arg_name.span));
+                    } else {
+                        filled.push(&arg_name.name);
+                    }
+                }
+            }
+        }
+        for placeholder in &def.placeholders {
+            if !filled.contains(&placeholder.as_str()) {
+                errors.push(error(format!("missing argument for placeholder '{}'", placeholder), name.span));
+            }
+        }
+        self.errors.extend(errors);
+    }
+
+    fn lookup_effect(&mut self, effect: &Ident) -> Option<EffectDef> {
+        let def = self.types.get_effect(&effect.name).cloned();
+        if def.is_none() {
+            self.errors.push(CheckError::Other {
+                message: format!("undefined effect '{}'", effect.name),
+                line: effect.span.line,
+                column: effect.span.column,
+            });
+        }
+        def
+    }
+
+    fn effect_operation(&mut self, def: &EffectDef, op: &Ident) -> Option<Ty> {
+        let ty = def.operations.iter().find(|(name, _)| *name == op.name).map(|(_, ty)| ty.clone());
+        if ty.is_none() {
+            self.errors.push(CheckError::Other {
+                message: format!("effect '{}' has no operation '{}'", def.name, op.name),
+                line: op.span.line,
+                column: op.span.column,
+            });
+        }
+        ty
+    }
+
+    /// Check `handle { body } with effect { clauses }`, valued by the body or by
+    /// a clause that does not resume
+    fn check_handle(&mut self, body: &Block, effect: &Ident, clauses: &[HandlerClause], span: Span) -> Ty {
+        let body_ty = self.check_block_value(body);
+        let Some(def) = self.lookup_effect(effect) else { return Ty::Error };
+
+        for (i, clause) in clauses.iter().enumerate() {
+            let Some(op_ty) = self.effect_operation(&def, &clause.op) else { continue };
+            if clauses[..i].iter().any(|c| c.op.name == clause.op.name) {
+                self.errors.push(CheckError::Other {
+                    message: format!("operation '{}' is handled more than once", clause.op.name),
+                    line: clause.span.line,
+                    column: clause.span.column,
+                });
+            }
+            let (params, resume_ty) = operation_signature(&op_ty);
+            if params.len() != clause.params.len() {
+                self.errors.push(CheckError::WrongArgCount {
+                    expected: params.len(),
+                    found: clause.params.len(),
+                    line: clause.span.line,
+                    column: clause.span.column,
+                });
+            }
+
+            self.symbols.enter_scope();
+            for (i, param) in clause.params.iter().enumerate() {
+                let _ = self.symbols.define(Symbol {
+                    name: param.name.clone(),
+                    kind: SymbolKind::Parameter,
+                    ty: params.get(i).cloned().unwrap_or(Ty::Error),
+                    span: param.span,
+                    mutable: false,
+                });
+            }
+            let mut tails = Vec::new();
+            Self::tail_resumes(&clause.body, &mut tails);
+            self.clauses.push(ClauseContext { resume_ty, answer_ty: body_ty.clone(), tails });
+            let clause_ty = match &clause.body {
+                Expr::Block(block) => self.check_block_value(block),
+                body => self.check_expr(body),
+            };
+            self.clauses.pop();
+            self.symbols.exit_scope();
+            self.expect_ty(&body_ty, &clause_ty, clause.span);
+        }
+
+        for (op, _) in &def.operations {
+            if !clauses.iter().any(|c| c.op.name == *op) {
+                self.errors.push(CheckError::Other {
+                    message: format!("handler for '{}' does not handle operation '{}'", def.name, op),
+                    line: span.line,
+                    column: span.column,
+                });
+            }
+        }
+        body_ty
+    }
+
+    /// Spans of the `resume`s whose value is the value of `expr`
+    fn tail_resumes(expr: &Expr, tails: &mut Vec<Span>) {
+        match expr {
+            Expr::Resume { span, .. } => tails.push(*span),
+            Expr::Block(block) => Self::block_tail_resumes(block, tails),
+            Expr::Match { arms, .. } => arms.iter().for_each(|arm| Self::tail_resumes(&arm.body, tails)),
+            _ => {}
+        }
+    }
+
+    fn block_tail_resumes(block: &Block, tails: &mut Vec<Span>) {
+        match block.stmts.last() {
+            Some(Stmt::Expr(e)) => Self::tail_resumes(e, tails),
+            Some(Stmt::If { then_block, else_block, .. }) => {
+                Self::block_tail_resumes(then_block, tails);
+                if let Some(block) = else_block {
+                    Self::block_tail_resumes(block, tails);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Check a call of a value of type `callee_ty` with arguments of the given types
+    fn check_call(&mut self, callee_ty: Ty, arg_types: Vec<Ty>, span: Span) -> Ty {
+        match callee_ty {
+            Ty::Function { params, result } => {
+                if params.len() != arg_types.len() {
+                    self.errors.push(CheckError::WrongArgCount {
+                        expected: params.len(),
+                        found: arg_types.len(),
+                        line: span.line,
+                        column: span.column,
+                    });
+                } else {
+                    for (param, arg) in params.iter().zip(arg_types.iter()) {
+                        self.expect_ty(param, arg, span);
+                    }
+                }
+                // The call performs the effects and yields the plain value
+                match *result {
+                    Ty::Effect(inner, _) => *inner,
+                    result => result,
+                }
+            }
+            // Calling a value of unknown type determines it to be a function
+            Ty::Var(_) => {
+                let result = self.fresh_var();
+                let fn_ty = Ty::Function { params: arg_types, result: Box::new(result.clone()) };
+                self.expect_ty(&callee_ty, &fn_ty, span);
+                result
+            }
+            Ty::Error | Ty::Unknown => Ty::Error,
+            _ => {
+                self.errors.push(CheckError::Other {
+                    message: format!("Cannot call non-function type '{}'", callee_ty),
+                    line: span.line,
+                    column: span.column,
+                });
+                Ty::Error
+            }
+        }
+    }
+
+    /// Type of the field `name` of a record or struct, if there is such a field holding a function
+    fn function_field(&self, recv: &Ty, name: &str) -> Option<Ty> {
+        let field_ty = match recv {
+            Ty::Record(fields) => fields.iter().find(|(n, _)| n == name).map(|(_, t)| t.clone()),
+            Ty::Named(s) | Ty::Generic { name: s, .. } => {
+                let args = match recv {
+                    Ty::Generic { args, .. } => args.as_slice(),
+                    _ => &[],
+                };
+                let def = self.types.get_struct(s)?;
+                def.fields
+                    .iter()
+                    .find(|(n, _)| n == name)
+                    .map(|(_, t)| t.substitute(&def.type_params, args))
+            }
+            _ => None,
+        }?;
+        matches!(self.subst.resolve(&field_ty), Ty::Function { .. } | Ty::Var(_) | Ty::Unknown).then_some(field_ty)
+    }
+
     fn check_literal(&self, lit: &Literal) -> Ty {
         match lit {
             Literal::Int(_, _) => Ty::Int,
@@ -901,6 +2307,261 @@ impl Checker {
         }
     }
 
+    /// Infer the type arguments of a generic constructor from its arguments, check the
+    /// arguments against the instantiated parameter types and return the instantiated result
+    fn instantiate(&mut self, type_params: &[String], result: &Ty, params: &[Ty], args: &[Ty], span: Span) -> Ty {
+        let mut bindings: Vec<Option<Ty>> = vec![None; type_params.len()];
+        for (param, arg) in params.iter().zip(args) {
+            Self::bind_type_params(param, arg, type_params, &mut bindings);
+        }
+        let resolved: Vec<Ty> = bindings.into_iter().map(|b| b.unwrap_or(Ty::Unknown)).collect();
+
+        for (param, arg) in params.iter().zip(args) {
+            let param = param.substitute(type_params, &resolved);
+            self.expect_ty(&param, arg, span);
+        }
+
+        result.substitute(type_params, &resolved)
+    }
+
+    /// Match `pattern` against `actual`, recording the first binding found for each type parameter
+    fn bind_type_params(pattern: &Ty, actual: &Ty, type_params: &[String], bindings: &mut [Option<Ty>]) {
+        match (pattern, actual) {
+            (_, actual) if actual.is_error_or_unknown() => {}
+            (Ty::Named(name), _) => {
+                if let Some(i) = type_params.iter().position(|p| p == name) {
+                    if bindings[i].is_none() {
+                        bindings[i] = Some(actual.clone());
+                    }
+                }
+            }
+            (Ty::Array(p), Ty::Array(a)) => Self::bind_type_params(p, a, type_params, bindings),
+            (Ty::AI(p), Ty::AI(a)) | (Ty::Effect(p, _), Ty::Effect(a, _)) => {
+                Self::bind_type_params(p, a, type_params, bindings)
+            }
+            (Ty::Generic { name: n1, args: p }, Ty::Generic { name: n2, args: a }) if n1 == n2 => {
+                for (p, a) in p.iter().zip(a) {
+                    Self::bind_type_params(p, a, type_params, bindings);
+                }
+            }
+            (Ty::Tuple(p), Ty::Tuple(a)) => {
+                for (p, a) in p.iter().zip(a) {
+                    Self::bind_type_params(p, a, type_params, bindings);
+                }
+            }
+            (Ty::Function { params: p1, result: r1 }, Ty::Function { params: p2, result: r2 }) => {
+                for (p, a) in p1.iter().zip(p2) {
+                    Self::bind_type_params(p, a, type_params, bindings);
+                }
+                Self::bind_type_params(r1, r2, type_params, bindings);
+            }
+            _ => {}
+        }
+    }
+
+    /// A fresh inference variable at the current `let` level
+    fn fresh_var(&mut self) -> Ty {
+        self.subst.fresh(self.level)
+    }
+
+    /// Unify `actual` with `expected`, reporting a mismatch at `span` if they disagree
+    fn expect_ty(&mut self, expected: &Ty, actual: &Ty, span: Span) -> bool {
+        match self.subst.unify(expected, actual) {
+            Ok(()) => true,
+            Err(UnifyError::Mismatch) => {
+                self.errors.push(CheckError::TypeMismatch {
+                    expected: self.subst.resolve(expected).to_string(),
+                    found: self.subst.resolve(actual).to_string(),
+                    line: span.line,
+                    column: span.column,
+                });
+                false
+            }
+            Err(UnifyError::Occurs(var, ty)) => {
+                self.errors.push(CheckError::InfiniteType {
+                    var: Ty::Var(var).to_string(),
+                    ty: ty.to_string(),
+                    line: span.line,
+                    column: span.column,
+                });
+                false
+            }
+        }
+    }
+
+    /// Conditions must be `Bool`
+    fn expect_bool(&mut self, ty: &Ty, span: Span) {
+        if self.subst.unify(&Ty::Bool, ty).is_err() {
+            self.errors.push(CheckError::NonBoolCondition {
+                found: self.subst.resolve(ty).to_string(),
+                line: span.line,
+                column: span.column,
+            });
+        }
+    }
+
+    /// Type of `try operand?`: the success payload, checking that the failure case can be
+    /// returned from the enclosing function
+    fn check_try(&mut self, operand_ty: &Ty, span: Span) -> Ty {
+        let (name, args): (&str, &[Ty]) = match operand_ty {
+            Ty::Generic { name, args } if name == "Result" || name == "Option" => (name, args),
+            Ty::Named(name) if name == "Result" || name == "Option" => (name, &[]),
+            ty if ty.is_error_or_unknown() || matches!(ty, Ty::Var(_)) => return Ty::Unknown,
+            ty => {
+                self.errors.push(CheckError::TypeMismatch {
+                    expected: "Result or Option".to_string(),
+                    found: ty.to_string(),
+                    line: span.line,
+                    column: span.column,
+                });
+                return Ty::Error;
+            }
+        };
+        let payload = args.first().cloned().unwrap_or(Ty::Unknown);
+
+        let return_ty = self.current_return_type.clone().map(|t| self.subst.resolve(&t)).unwrap_or(Ty::Unit);
+        let compatible = match &return_ty {
+            // A lambda's result is fixed by its body, not by the `?` inside it
+            ty if ty.is_error_or_unknown() || matches!(ty, Ty::Var(_)) => true,
+            Ty::Generic { name: ret, args: ret_args } if ret == name => {
+                // The error types must agree; `Option` has no error type to compare
+                match (args.get(1), ret_args.get(1)) {
+                    (Some(err), Some(ret_err)) => ret_err.is_assignable_from(err),
+                    _ => true,
+                }
+            }
+            Ty::Named(ret) => ret == name,
+            _ => false,
+        };
+        if !compatible {
+            self.errors.push(CheckError::IncompatibleTry {
+                operand: operand_ty.to_string(),
+                return_type: return_ty.to_string(),
+                line: span.line,
+                column: span.column,
+            });
+        }
+
+        payload
+    }
+
+    /// Type of `field` accessed on a value of type `obj_ty`
+    fn check_field_access(&mut self, obj_ty: &Ty, field: &str, span: Span) -> Ty {
+        let obj_ty = &self.subst.resolve(obj_ty);
+        match obj_ty {
+            Ty::Named(name) | Ty::Generic { name, .. } => {
+                let args = match obj_ty {
+                    Ty::Generic { args, .. } => args.as_slice(),
+                    _ => &[],
+                };
+                if let Some(struct_def) = self.types.get_struct(name) {
+                    if let Some((_, field_ty)) = struct_def.fields.iter()
+                        .find(|(n, _)| n == field)
+                    {
+                        field_ty.substitute(&struct_def.type_params, args)
+                    } else {
+                        self.errors.push(CheckError::Other {
+                            message: format!("No field '{}' on type '{}'", field, name),
+                            line: span.line,
+                            column: span.column,
+                        });
+                        Ty::Error
+                    }
+                } else {
+                    Ty::Unknown
+                }
+            }
+            Ty::Record(fields) => {
+                if let Some((_, field_ty)) = fields.iter()
+                    .find(|(n, _)| n == field)
+                {
+                    field_ty.clone()
+                } else {
+                    self.errors.push(CheckError::Other {
+                        message: format!("No field '{}' in record", field),
+                        line: span.line,
+                        column: span.column,
+                    });
+                    Ty::Error
+                }
+            }
+            Ty::Tuple(elements) => match field.parse::<usize>().ok().and_then(|i| elements.get(i)) {
+                Some(ty) => ty.clone(),
+                None => {
+                    self.errors.push(CheckError::Other {
+                        message: format!("No field '{}' on tuple '{}'", field, obj_ty),
+                        line: span.line,
+                        column: span.column,
+                    });
+                    Ty::Error
+                }
+            },
+            Ty::Error | Ty::Unknown => Ty::Error,
+            // Records are structural, so an uninferred object's fields cannot be looked up yet
+            Ty::Var(_) => Ty::Unknown,
+            _ => {
+                self.errors.push(CheckError::Other {
+                    message: format!("Cannot access field on type '{}'", obj_ty),
+                    line: span.line,
+                    column: span.column,
+                });
+                Ty::Error
+            }
+        }
+    }
+
+    /// Indices and slice bounds must be `Int`
+    fn expect_index_type(&mut self, ty: &Ty, span: Span) {
+        self.expect_ty(&Ty::Int, ty, span);
+    }
+
+    /// Check a loop body in its own scope, returning the type of any `break value`
+    fn check_loop_body(&mut self, label: Option<&Ident>, is_loop_expr: bool, body: &Block) -> Option<Ty> {
+        self.loops.push(LoopContext {
+            label: label.map(|l| l.name.clone()),
+            is_loop_expr,
+            break_ty: None,
+        });
+
+        self.symbols.enter_scope();
+        self.check_block(body);
+        self.symbols.exit_scope();
+
+        self.loops.pop().and_then(|ctx| ctx.break_ty)
+    }
+
+    /// Find the loop targeted by a `break`/`continue`, reporting an error if there is none
+    fn resolve_loop(&mut self, keyword: &str, label: Option<&Ident>, span: Span) -> Option<usize> {
+        match label {
+            Some(label) => {
+                let found = self
+                    .loops
+                    .iter()
+                    .rposition(|ctx| ctx.label.as_deref() == Some(label.name.as_str()));
+                if found.is_none() {
+                    self.errors.push(CheckError::UndefinedLabel {
+                        label: label.name.clone(),
+                        line: label.span.line,
+                        column: label.span.column,
+                    });
+                }
+                found
+            }
+            None => {
+                if self.loops.is_empty() {
+                    self.errors.push(CheckError::OutsideLoop {
+                        keyword: keyword.to_string(),
+                        line: span.line,
+                        column: span.column,
+                    });
+                    None
+                } else {
+                    Some(self.loops.len() - 1)
+                }
+            }
+        }
+    }
+
     fn check_binary_op(&mut self, op: BinaryOp, left: &Ty, right: &Ty, span: Span) -> Ty {
         use BinaryOp::*;
 
@@ -909,8 +2570,28 @@ impl Checker {
             return Ty::Error;
         }
 
+        // An operand still being inferred takes its type from the other side
+        match op {
+            And | Or => {
+                let _ = self.subst.unify(&Ty::Bool, left);
+                let _ = self.subst.unify(&Ty::Bool, right);
+            }
+            _ if matches!(left, Ty::Var(_)) || matches!(right, Ty::Var(_)) => {
+                let _ = self.subst.unify(left, right);
+            }
+            _ => {}
+        }
+        let left = &self.subst.resolve(left);
+        let right = &self.subst.resolve(right);
+        if let Ty::Var(_) = left {
+            return match op {
+                Add | Sub | Mul | Div | Rem | BitAnd | BitOr | BitXor | Shl | Shr => left.clone(),
+                _ => Ty::Bool,
+            };
+        }
+
         match op {
-            Add | Sub | Mul | Div => {
+            Add | Sub | Mul | Div | Rem => {
                 if left.is_numeric() && right.is_numeric() {
                     if left == right {
                         left.clone()
@@ -931,8 +2612,39 @@ impl Checker {
                 }
             }
 
+            // Bitwise on integers, and on booleans without short-circuiting
+            BitAnd | BitOr | BitXor => {
+                if left == right && matches!(left, Ty::Int | Ty::Bool) {
+                    left.clone()
+                } else {
+                    self.errors.push(CheckError::InvalidBinaryOp {
+                        left: left.to_string(),
+                        op: format!("{:?}", op),
+                        right: right.to_string(),
+                        line: span.line,
+                        column: span.column,
+                    });
+                    Ty::Error
+                }
+            }
+
+            Shl | Shr => {
+                if left == &Ty::Int && right == &Ty::Int {
+                    Ty::Int
+                } else {
+                    self.errors.push(CheckError::InvalidBinaryOp {
+                        left: left.to_string(),
+                        op: format!("{:?}", op),
+                        right: right.to_string(),
+                        line: span.line,
+                        column: span.column,
+                    });
+                    Ty::Error
+                }
+            }
+
             Eq | Ne => {
-                if left == right || left.is_assignable_from(right) {
+                if self.subst.unify(left, right).is_ok() {
                     Ty::Bool
                 } else {
                     self.errors.push(CheckError::InvalidBinaryOp {
@@ -975,21 +2687,41 @@ impl Checker {
                     Ty::Error
                 }
             }
+        }
+    }
 
-            Assign => {
-                // Assignment returns the assigned value
-                if left.is_assignable_from(right) {
-                    left.clone()
-                } else {
-                    self.errors.push(CheckError::TypeMismatch {
-                        expected: left.to_string(),
-                        found: right.to_string(),
+    /// Check that an assignment target is a place rooted at a variable that
+    /// may be written: a `mut` binding, or a `&mut` reference when assigning
+    /// through a field or index
+    fn check_assign_target(&mut self, target: &Expr, span: Span) {
+        let mut place = target;
+        let root = loop {
+            match place {
+                Expr::Ident(ident) => break ident,
+                Expr::Field { object, .. } | Expr::Index { object, .. } => place = object,
+                _ => {
+                    self.errors.push(CheckError::InvalidAssignmentTarget {
                         line: span.line,
                         column: span.column,
                     });
-                    Ty::Error
+                    return;
                 }
             }
+        };
+
+        // Undefined names are reported when the target is checked
+        let Some(symbol) = self.symbols.lookup(&root.name) else {
+            return;
+        };
+        let through_ref = !matches!(target, Expr::Ident(_))
+            && matches!(self.subst.resolve(&symbol.ty), Ty::Ref { mutable: true, .. });
+        let writable = matches!(symbol.kind, SymbolKind::Variable | SymbolKind::Parameter) && symbol.mutable;
+        if !writable && !through_ref {
+            self.errors.push(CheckError::ImmutableAssignment {
+                name: root.name.clone(),
+                line: span.line,
+                column: span.column,
+            });
         }
     }
 
@@ -1002,7 +2734,7 @@ impl Checker {
 
         match op {
             Neg => {
-                if operand.is_numeric() {
+                if operand.is_numeric() || matches!(operand, Ty::Var(_)) {
                     operand.clone()
                 } else {
                     self.errors.push(CheckError::Other {
@@ -1015,7 +2747,7 @@ impl Checker {
             }
 
             Not => {
-                if operand == &Ty::Bool {
+                if self.subst.unify(&Ty::Bool, operand).is_ok() {
                     Ty::Bool
                 } else {
                     self.errors.push(CheckError::Other {
@@ -1039,25 +2771,51 @@ impl Checker {
         }
     }
 
+    /// Warn of arms that can never match and report values no arm covers
+    fn check_exhaustive(&mut self, scrutinee: &Ty, arms: &[MatchArm], span: Span) {
+        let report = exhaustiveness::check_match(&self.types, &self.subst.resolve(scrutinee), arms);
+        for i in report.unreachable {
+            self.warnings.push(CheckError::UnreachablePattern {
+                line: arms[i].span.line,
+                column: arms[i].span.column,
+            });
+        }
+        if let Some(witness) = report.missing {
+            self.errors.push(CheckError::NonExhaustiveMatch {
+                witness,
+                line: span.line,
+                column: span.column,
+            });
+        }
+    }
+
     fn check_pattern(&mut self, pattern: &Pattern, expected: &Ty) {
         match pattern {
-            Pattern::Literal(_) => {
-                // Literal patterns: check type compatibility
+            Pattern::Literal(lit) => {
+                let lit_ty = self.check_literal(lit);
+                self.expect_ty(expected, &lit_ty, lit.span());
             }
             Pattern::Ident(ident) => {
-                // Bind the identifier to the expected type
-                let _ = self.symbols.define(Symbol {
-                    name: ident.name.clone(),
-                    kind: SymbolKind::Variable,
-                    ty: expected.clone(),
-                    span: ident.span,
-                    mutable: false,
-                });
+                // A bare unit variant name matches that variant rather than binding
+                if let Some((_, variant)) = self.types.get_variant(&ident.name) {
+                    if variant.shape == VariantShape::Unit {
+                        self.check_variant_pattern(&ident.name, &[], expected, ident.span);
+                        return;
+                    }
+                }
+
+                self.bind_pattern_var(ident, expected);
             }
             Pattern::Wildcard(_) => {
                 // Wildcard matches anything
             }
-            Pattern::Constructor { name, args, span: _ } => {
+            Pattern::Constructor { name, args, span } => {
+                if self.types.get_variant(&name.name).is_some() {
+                    let fields: Vec<(Option<&str>, &Pattern)> = args.iter().map(|a| (None, a)).collect();
+                    self.check_variant_pattern(&name.name, &fields, expected, *span);
+                    return;
+                }
+
                 // Check constructor pattern
                 // Clone the field types to avoid borrow issues
                 let field_types: Vec<Ty> = self.types
@@ -1071,20 +2829,328 @@ impl Checker {
                     }
                 }
             }
-        }
-    }
-
-    fn check_ai_stmt(&mut self, stmt: &AiStmt) {
-        match &stmt.body {
-            AiStmtBody::Block(block) => {
-                self.symbols.enter_scope();
-                self.check_block(block);
-                self.symbols.exit_scope();
+            Pattern::Struct { name, fields, span } => {
+                let fields: Vec<(Option<&str>, &Pattern)> = fields
+                    .iter()
+                    .map(|(f, p)| (Some(f.name.as_str()), p))
+                    .collect();
+                self.check_variant_pattern(&name.name, &fields, expected, *span);
             }
-            AiStmtBody::Expr(expr) => {
-                self.check_expr(expr);
+            Pattern::Record { fields, rest, span } => {
+                self.check_record_pattern(fields, *rest, expected, *span);
             }
-        }
+            Pattern::Slice { prefix, rest, suffix, span } => {
+                let elem = match self.subst.resolve(expected) {
+                    Ty::Array(elem) => *elem,
+                    ty => {
+                        let elem = self.fresh_var();
+                        self.expect_ty(&ty, &Ty::Array(Box::new(elem.clone())), *span);
+                        elem
+                    }
+                };
+                for pattern in prefix.iter().chain(suffix) {
+                    self.check_pattern(pattern, &elem);
+                }
+                if let Some(rest) = rest {
+                    self.check_pattern(rest, &Ty::Array(Box::new(elem)));
+                }
+            }
+            Pattern::Range { start, end, inclusive, span } => {
+                let start_ty = self.check_literal(start);
+                if !matches!(start_ty, Ty::Int | Ty::Float) {
+                    self.errors.push(CheckError::Other {
+                        message: format!("Range patterns must be Int or Float, found {}", start_ty),
+                        line: span.line,
+                        column: span.column,
+                    });
+                }
+                self.expect_ty(expected, &start_ty, *span);
+                self.expect_ty(&start_ty, &self.check_literal(end), end.span());
+
+                let empty = match (start, end) {
+                    (Literal::Int(a, _), Literal::Int(b, _)) => if *inclusive { a > b } else { a >= b },
+                    (Literal::Float(a, _), Literal::Float(b, _)) => if *inclusive { a > b } else { a >= b },
+                    _ => false,
+                };
+                if empty {
+                    self.errors.push(CheckError::Other {
+                        message: "Range pattern matches no values".to_string(),
+                        line: span.line,
+                        column: span.column,
+                    });
+                }
+            }
+            Pattern::Tuple(elements, span) => {
+                let tys = match self.subst.resolve(expected) {
+                    Ty::Tuple(tys) if tys.len() == elements.len() => tys,
+                    Ty::Unit if elements.is_empty() => vec![],
+                    ty => {
+                        let tys: Vec<Ty> = elements.iter().map(|_| self.fresh_var()).collect();
+                        let tuple = if tys.is_empty() { Ty::Unit } else { Ty::Tuple(tys.clone()) };
+                        self.expect_ty(&ty, &tuple, *span);
+                        tys
+                    }
+                };
+                for (pattern, ty) in elements.iter().zip(&tys) {
+                    self.check_pattern(pattern, ty);
+                }
+            }
+            Pattern::Or(alternatives, span) => {
+                let Some((first, others)) = alternatives.split_first() else {
+                    return;
+                };
+                self.check_pattern(first, expected);
+                let names = self.pattern_bindings(first);
+
+                // Every alternative must bind the same names at the same types
+                for alternative in others {
+                    self.symbols.enter_scope();
+                    self.check_pattern(alternative, expected);
+                    let alt_names = self.pattern_bindings(alternative);
+                    let bound: Vec<(String, Option<Ty>)> = alt_names
+                        .iter()
+                        .map(|n| (n.clone(), self.symbols.lookup_current(n).map(|s| s.ty.clone())))
+                        .collect();
+                    self.symbols.exit_scope();
+
+                    for name in names.iter().filter(|n| !alt_names.contains(n)).chain(alt_names.iter().filter(|n| !names.contains(n))) {
+                        self.errors.push(CheckError::Other {
+                            message: format!("Variable '{}' is not bound in every alternative", name),
+                            line: span.line,
+                            column: span.column,
+                        });
+                    }
+                    for (name, alt_ty) in bound {
+                        let first_ty = self.symbols.lookup(&name).map(|s| s.ty.clone());
+                        if let (Some(first_ty), Some(alt_ty), true) = (first_ty, alt_ty, names.contains(&name)) {
+                            self.expect_ty(&first_ty, &alt_ty, alternative.span());
+                        }
+                    }
+                }
+            }
+            Pattern::Binding { name, pattern, .. } => {
+                self.bind_pattern_var(name, expected);
+                self.check_pattern(pattern, expected);
+            }
+        }
+    }
+
+    fn bind_pattern_var(&mut self, ident: &Ident, ty: &Ty) {
+        let _ = self.symbols.define(Symbol {
+            name: ident.name.clone(),
+            kind: SymbolKind::Variable,
+            ty: ty.clone(),
+            span: ident.span,
+            mutable: false,
+        });
+    }
+
+    /// Names a pattern binds, in order
+    fn pattern_bindings(&self, pattern: &Pattern) -> Vec<String> {
+        match pattern {
+            Pattern::Ident(ident) => match self.types.get_variant(&ident.name) {
+                Some((_, variant)) if variant.shape == VariantShape::Unit => vec![],
+                _ => vec![ident.name.clone()],
+            },
+            Pattern::Literal(_) | Pattern::Wildcard(_) | Pattern::Range { .. } => vec![],
+            Pattern::Constructor { args: elements, .. } | Pattern::Tuple(elements, _) => {
+                elements.iter().flat_map(|p| self.pattern_bindings(p)).collect()
+            }
+            Pattern::Struct { fields, .. } | Pattern::Record { fields, .. } => {
+                fields.iter().flat_map(|(_, p)| self.pattern_bindings(p)).collect()
+            }
+            Pattern::Slice { prefix, rest, suffix, .. } => prefix
+                .iter()
+                .chain(rest.as_deref())
+                .chain(suffix)
+                .flat_map(|p| self.pattern_bindings(p))
+                .collect(),
+            Pattern::Or(alternatives, _) => {
+                alternatives.first().map(|p| self.pattern_bindings(p)).unwrap_or_default()
+            }
+            Pattern::Binding { name, pattern, .. } => {
+                std::iter::once(name.name.clone()).chain(self.pattern_bindings(pattern)).collect()
+            }
+        }
+    }
+
+    /// Check a record pattern against a record or struct scrutinee. Without a
+    /// trailing `..` every field of the scrutinee must be mentioned.
+    fn check_record_pattern(&mut self, fields: &[(Ident, Pattern)], rest: bool, expected: &Ty, span: Span) {
+        let expected = self.subst.resolve(expected);
+        let declared: Option<Vec<(String, Ty)>> = match &expected {
+            Ty::Record(declared) => Some(declared.clone()),
+            Ty::Named(name) | Ty::Generic { name, .. } => self.types.get_struct(name).map(|s| {
+                let args = match &expected {
+                    Ty::Generic { args, .. } => args.clone(),
+                    _ => vec![],
+                };
+                s.fields.iter().map(|(n, t)| (n.clone(), t.substitute(&s.type_params, &args))).collect()
+            }),
+            _ => None,
+        };
+
+        let Some(declared) = declared else {
+            // A closed pattern fixes the record type; an open one leaves it unknown
+            let tys: Vec<Ty> = fields.iter().map(|_| if rest { Ty::Unknown } else { self.fresh_var() }).collect();
+            if !rest || !matches!(expected, Ty::Var(_)) {
+                let record = Ty::Record(fields.iter().map(|(f, _)| f.name.clone()).zip(tys.iter().cloned()).collect());
+                self.expect_ty(&expected, &record, span);
+            }
+            for ((_, pattern), ty) in fields.iter().zip(&tys) {
+                self.check_pattern(pattern, ty);
+            }
+            return;
+        };
+
+        for (field, pattern) in fields {
+            match declared.iter().find(|(n, _)| n == &field.name) {
+                Some((_, ty)) => self.check_pattern(pattern, ty),
+                None => {
+                    self.errors.push(CheckError::Other {
+                        message: format!("No field '{}' on type '{}'", field.name, expected),
+                        line: field.span.line,
+                        column: field.span.column,
+                    });
+                    self.check_pattern(pattern, &Ty::Unknown);
+                }
+            }
+        }
+        if !rest {
+            for (name, _) in declared.iter().filter(|(n, _)| !fields.iter().any(|(f, _)| &f.name == n)) {
+                self.errors.push(CheckError::Other {
+                    message: format!("Pattern does not mention field '{}'; add '..' to ignore it", name),
+                    line: span.line,
+                    column: span.column,
+                });
+            }
+        }
+    }
+
+    /// Check a variant pattern against the scrutinee type. Fields are positional
+    /// (`None`) for tuple variants and named for struct-like variants.
+    fn check_variant_pattern(&mut self, name: &str, fields: &[(Option<&str>, &Pattern)], expected: &Ty, span: Span) {
+        let Some((enum_def, variant)) = self.types.get_variant(name) else {
+            self.errors.push(CheckError::UndefinedType {
+                name: name.to_string(),
+                line: span.line,
+                column: span.column,
+            });
+            return;
+        };
+        let (enum_def, variant) = (enum_def.clone(), variant.clone());
+
+        // The scrutinee must be this enum; its type arguments instantiate the payload
+        let expected = &self.subst.resolve(expected);
+        let args: Vec<Ty> = match expected {
+            Ty::Var(_) => {
+                let args: Vec<Ty> = enum_def.type_params.iter().map(|_| self.fresh_var()).collect();
+                let enum_ty = enum_def.self_ty().substitute(&enum_def.type_params, &args);
+                self.expect_ty(expected, &enum_ty, span);
+                args
+            }
+            Ty::Generic { name, args } if name == &enum_def.name => args.clone(),
+            Ty::Named(name) if name == &enum_def.name => vec![],
+            ty if ty.is_error_or_unknown() => vec![],
+            ty => {
+                self.errors.push(CheckError::TypeMismatch {
+                    expected: ty.to_string(),
+                    found: enum_def.name.clone(),
+                    line: span.line,
+                    column: span.column,
+                });
+                vec![]
+            }
+        };
+        let field_ty = |ty: &Ty| ty.substitute(&enum_def.type_params, &args);
+
+        let named = fields.iter().any(|(n, _)| n.is_some());
+        if named != (variant.shape == VariantShape::Struct) {
+            self.errors.push(CheckError::Other {
+                message: if named {
+                    format!("Variant '{}' has no named fields", name)
+                } else {
+                    format!("Variant '{}' has named fields; use '{} {{ .. }}'", name, name)
+                },
+                line: span.line,
+                column: span.column,
+            });
+        } else if !named && fields.len() != variant.fields.len() {
+            self.errors.push(CheckError::WrongArgCount {
+                expected: variant.fields.len(),
+                found: fields.len(),
+                line: span.line,
+                column: span.column,
+            });
+        }
+
+        for (i, (field_name, pattern)) in fields.iter().enumerate() {
+            let decl = match field_name {
+                Some(n) => variant.fields.iter().find(|(f, _)| f == n),
+                None => variant.fields.get(i),
+            };
+            match decl {
+                Some((_, ty)) => self.check_pattern(pattern, &field_ty(ty)),
+                None => {
+                    if let Some(n) = field_name {
+                        self.errors.push(CheckError::Other {
+                            message: format!("No field '{}' on variant '{}'", n, name),
+                            line: span.line,
+                            column: span.column,
+                        });
+                    }
+                    self.check_pattern(pattern, &Ty::Unknown);
+                }
+            }
+        }
+    }
+
+    fn check_ai_stmt(&mut self, stmt: &AiStmt) {
+        match &stmt.body {
+            AiStmtBody::Block(block) => {
+                self.symbols.enter_scope();
+                self.check_block(block);
+                self.symbols.exit_scope();
+            }
+            AiStmtBody::Expr(expr) => {
+                self.check_expr(expr);
+            }
+        }
+    }
+
+    /// The struct `ai generate` is asked for where a value of type `expected`
+    /// is: a `let` annotation, a `return` or a call argument. The value is then
+    /// a record of the struct rather than text, and the struct must be marked
+    /// `#[ai_generate]`.
+    fn generated_struct(&mut self, expected: &Ty, value: &Expr) -> Option<Ty> {
+        let inner = match expected {
+            Ty::AI(inner) | Ty::Effect(inner, _) => inner,
+            ty => ty,
+        };
+        let (Ty::Named(name), Expr::Ai(ai_expr)) = (inner, value) else { return None };
+        if ai_expr.keyword() != AiKeyword::Generate {
+            return None;
+        }
+        if !self.types.get_struct(name)?.ai_generate {
+            let span = ai_expr.span();
+            self.errors.push(CheckError::Other {
+                message: format!("struct '{}' must be marked #[ai_generate] to be produced by 'ai generate'", name),
+                line: span.line,
+                column: span.column,
+            });
+        }
+        Some(expected.clone())
+    }
+
+    /// Retype the `ai generate` arguments of a call to `callee` whose
+    /// parameters expect a struct
+    fn generated_args(&mut self, callee: &Ty, args: &[Expr], arg_types: &mut [Ty]) {
+        let Ty::Function { params, .. } = callee else { return };
+        for ((arg, param), ty) in args.iter().zip(params).zip(arg_types) {
+            if let Some(generated) = self.generated_struct(param, arg) {
+                *ty = generated;
+            }
+        }
     }
 
     fn check_ai_expr(&mut self, expr: &AiExpr) -> Ty {
@@ -1096,7 +3162,7 @@ impl Checker {
                         AiBodyItem::Field { name, value } => {
                             // Check for model reference
                             if name.name == "model" {
-                                if let Expr::Ident(ident) = value {
+                                if let Expr::Ident(ident) = value.as_ref() {
                                     if self.types.get_ai_model(&ident.name).is_none() {
                                         self.errors.push(CheckError::UndefinedAiModel {
                                             name: ident.name.clone(),
@@ -1143,16 +3209,17 @@ impl Checker {
 
             AiExpr::PromptInvocation { name, args, span: _ } => {
                 // Check that the prompt exists
-                if self.types.get_prompt(&name.name).is_none() {
-                    self.errors.push(CheckError::UndefinedPrompt {
+                match self.types.get_prompt(&name.name).cloned() {
+                    Some(def) => self.check_prompt_args(&def, name, args),
+                    None => self.errors.push(CheckError::UndefinedPrompt {
                         name: name.name.clone(),
                         line: name.span.line,
                         column: name.span.column,
-                    });
+                    }),
                 }
 
                 for arg in args {
-                    self.check_expr(arg);
+                    self.check_expr(&arg.value);
                 }
 
                 Ty::AI(Box::new(Ty::String))
@@ -1174,10 +3241,41 @@ impl Checker {
                     });
                 }
             }
+            Type::Generic { name, args, span } => {
+                let arity = self.types
+                    .get_enum(&name.name)
+                    .map(|e| e.type_params.len())
+                    .or_else(|| self.types.get_struct(&name.name).map(|s| s.type_params.len()));
+                match arity {
+                    Some(n) if n != args.len() => {
+                        self.errors.push(CheckError::Other {
+                            message: format!("Type '{}' expects {} type argument(s), found {}", name.name, n, args.len()),
+                            line: span.line,
+                            column: span.column,
+                        });
+                    }
+                    Some(_) => {}
+                    None => {
+                        self.errors.push(CheckError::UndefinedType {
+                            name: name.name.clone(),
+                            line: name.span.line,
+                            column: name.span.column,
+                        });
+                    }
+                }
+                for arg in args {
+                    self.check_type_exists(arg);
+                }
+            }
             Type::Array { element, .. } => self.check_type_exists(element),
             Type::Reference { inner, .. } => self.check_type_exists(inner),
             Type::Ai { inner, .. } => self.check_type_exists(inner),
-            Type::Effect { inner, .. } => self.check_type_exists(inner),
+            Type::Effect { inner, effects: row, .. } => {
+                self.check_type_exists(inner);
+                for effect in row.iter().filter(|e| !effects::is_builtin(&e.name)) {
+                    self.lookup_effect(effect);
+                }
+            }
             Type::Function { param, result, .. } => {
                 self.check_type_exists(param);
                 self.check_type_exists(result);
@@ -1195,11 +3293,33 @@ impl Checker {
             Type::Constrained { base, .. } => {
                 self.check_type_exists(base);
             }
-            Type::Primitive(_) => {}
+            Type::Primitive(_) | Type::Infer(_) => {}
         }
     }
 }
 
+/// Diagnostics of a multi-file program, each with the file it is in
+pub type ModuleDiagnostics = Vec<(FileId, CheckError)>;
+
+/// Type check every module of a multi-file program, pairing each error with
+/// the file it is in. A program without errors checks to its warnings.
+pub fn check_modules(graph: &ModuleGraph) -> Result<ModuleDiagnostics, ModuleDiagnostics> {
+    let mut errors = Vec::new();
+    let mut warnings = Vec::new();
+    for &file in &graph.order {
+        let mut checker = Checker::new();
+        if let Err(found) = checker.check_module(graph, file) {
+            errors.extend(found.into_iter().map(|e| (file, e)));
+        }
+        warnings.extend(checker.warnings.into_iter().map(|w| (file, w)));
+    }
+    if errors.is_empty() {
+        Ok(warnings)
+    } else {
+        Err(errors)
+    }
+}
+
 /// Public function to check a program
 pub fn check(program: &Program) -> Result<(), Vec<CheckError>> {
     let mut checker = Checker::new();
@@ -1216,6 +3336,14 @@ mod tests {
         check(&program)
     }
 
+    fn check_warnings(source: &str) -> Vec<CheckError> {
+        let program = parse(source).expect("Parse failed");
+        let mut checker = Checker::new();
+        let result = checker.check_program(&program);
+        assert!(result.is_ok(), "{:?}", result);
+        checker.warnings().to_vec()
+    }
+
     #[test]
     fn test_basic_function() {
         let result = check_source("fn main() { let x: Int = 42; }");
@@ -1322,4 +3450,1001 @@ mod tests {
         let errors = result.unwrap_err();
         assert!(errors.iter().any(|e| matches!(e, CheckError::NonBoolCondition { .. })));
     }
+
+    #[test]
+    fn test_loops() {
+        let result = check_source(r#"
+            fn main() {
+                let mut total = 0;
+                'outer: for x in [1, 2, 3] {
+                    while total < 10 {
+                        if x == 2 { continue 'outer; }
+                        total = total + x;
+                    }
+                }
+                let n: Int = loop { break 5; };
+            }
+        "#);
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_break_outside_loop() {
+        let result = check_source(r#"
+            fn main() {
+                break;
+            }
+        "#);
+        let errors = result.unwrap_err();
+        assert!(errors.iter().any(|e| matches!(e, CheckError::OutsideLoop { .. })));
+    }
+
+    #[test]
+    fn test_undefined_label() {
+        let result = check_source(r#"
+            fn main() {
+                while true { break 'missing; }
+            }
+        "#);
+        let errors = result.unwrap_err();
+        assert!(errors.iter().any(|e| matches!(e, CheckError::UndefinedLabel { .. })));
+    }
+
+    #[test]
+    fn test_for_over_non_iterable() {
+        let result = check_source(r#"
+            fn main() {
+                for x in 42 { }
+            }
+        "#);
+        let errors = result.unwrap_err();
+        assert!(errors.iter().any(|e| matches!(e, CheckError::TypeMismatch { .. })));
+    }
+
+    #[test]
+    fn test_index_and_slice_types() {
+        let result = check_source(r#"
+            fn main() {
+                let xs = [1, 2, 3];
+                let x: Int = xs[0];
+                let ys: [Int] = xs[1..];
+                let c: String = "abc"[1];
+                let rec = { name: "a" };
+                let n: String = rec["name"];
+            }
+        "#);
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_non_int_index() {
+        let result = check_source(r#"
+            fn main() {
+                let xs = [1, 2, 3];
+                let x = xs["a"];
+            }
+        "#);
+        let errors = result.unwrap_err();
+        assert!(errors.iter().any(|e| matches!(e, CheckError::TypeMismatch { .. })));
+    }
+
+    #[test]
+    fn test_enum_construction_and_match() {
+        let result = check_source(r#"
+            enum Shape<T> {
+                Empty,
+                Rect(T, T),
+                Circle { radius: T },
+            }
+
+            fn area(s: Shape<Float>) -> Float {
+                return match s {
+                    Empty => 0.0,
+                    Rect(w, h) => w * h,
+                    Circle { radius } => radius * radius,
+                };
+            }
+
+            fn main() {
+                let a: Shape<Float> = Rect(1.0, 2.0);
+                let b: Shape<Float> = Circle { radius: 3.0 };
+                let c: Float = area(Empty);
+            }
+        "#);
+        assert!(result.is_ok(), "{:?}", result);
+    }
+
+    #[test]
+    fn test_enum_errors() {
+        let result = check_source(r#"
+            enum Shape<T> {
+                Rect(T, T),
+            }
+
+            fn main() {
+                let a: Shape<Int> = Rect(1, "two");
+                let b = Rect(1);
+                let c = match a {
+                    Rect(x) => x,
+                };
+                let d = match 5 {
+                    Rect(x, y) => x,
+                };
+            }
+        "#);
+        let errors = result.unwrap_err();
+        assert!(errors.iter().any(|e| matches!(e, CheckError::TypeMismatch { found, .. } if found == "String")));
+        assert_eq!(errors.iter().filter(|e| matches!(e, CheckError::WrongArgCount { .. })).count(), 2);
+        assert!(errors.iter().any(|e| matches!(e, CheckError::TypeMismatch { found, .. } if found == "Shape")));
+    }
+
+    #[test]
+    fn test_duplicate_variant() {
+        let result = check_source(r#"
+enum A { X, Y }
+enum B { Z, Y }
+enum C { W, W }
+"#);
+        let messages: Vec<String> = result.unwrap_err().iter().map(|e| e.to_string()).collect();
+        assert_eq!(messages, vec![
+            "duplicate definition of 'Y' at line 3, column 13",
+            "duplicate definition of 'W' at line 4, column 13",
+        ]);
+    }
+
+    #[test]
+    fn test_try_return_type_compatibility() {
+        let result = check_source(r#"
+            fn parse(s: String) -> Result<Int, String> {
+                let n: Int = try to_int(s)?;
+                return Ok(n);
+            }
+
+            fn lookup() -> Option<Int> {
+                let n = try to_int("1")?;
+                return Some(n);
+            }
+
+            fn main() {
+                let n = try parse("1")?;
+                let m = try 5?;
+            }
+        "#);
+        let errors = result.unwrap_err();
+        let incompatible: Vec<_> = errors
+            .iter()
+            .filter_map(|e| match e {
+                CheckError::IncompatibleTry { return_type, .. } => Some(return_type.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(incompatible, vec!["Option<Int>", "()"]);
+        assert!(errors.iter().any(|e| matches!(e, CheckError::TypeMismatch { expected, found, .. }
+            if expected == "Result or Option" && found == "Int")));
+    }
+
+    #[test]
+    fn test_prelude_types() {
+        let result = check_source(r#"
+            fn safe_div(a: Int, b: Int) -> Result<Int, String> {
+                if b == 0 { return Err("division by zero"); }
+                return Ok(a / b);
+            }
+
+            fn main() {
+                let x: Option<Int> = Some(1);
+                let y: Option<Int> = None;
+                let z = match safe_div(4, 2) { Ok(n) => n, Err(e) => 0 };
+            }
+        "#);
+        assert!(result.is_ok(), "{:?}", result);
+    }
+
+    #[test]
+    fn test_generic_functions() {
+        let result = check_source(r#"
+            fn id<T>(x: T) -> T { return x; }
+            fn pair_first<A, B>(a: A, b: B) -> A { return a; }
+
+            fn main() {
+                let n: Int = id(5);
+                let s: String = pair_first("a", 1);
+                let f: Int = first([1, 2, 3]);
+                let xs: [String] = push(["a"], "b");
+            }
+        "#);
+        assert!(result.is_ok(), "{:?}", result);
+
+        let result = check_source(r#"
+            fn id<T>(x: T) -> T { return x; }
+            fn add<T>(a: T, b: T) -> T { return a + b; }
+
+            fn main() {
+                let n: Int = id("five");
+                let m = id(1, 2);
+                let xs: [Int] = push([1], "two");
+            }
+        "#);
+        let errors = result.unwrap_err();
+        assert!(errors.iter().any(|e| matches!(e, CheckError::InvalidBinaryOp { left, .. } if left == "T")));
+        assert!(errors.iter().any(|e| matches!(e, CheckError::WrongArgCount { expected: 1, found: 2, .. })));
+        // `id("five")` instantiates to String; `push([1], "two")` fixes T = Int from the array
+        let mismatches = errors
+            .iter()
+            .filter(|e| matches!(e, CheckError::TypeMismatch { expected, found, .. } if expected == "Int" && found == "String"))
+            .count();
+        assert_eq!(mismatches, 2);
+    }
+
+    #[test]
+    fn test_generic_struct_instantiation() {
+        let result = check_source(r#"
+            struct Box<T> { value: T }
+
+            fn main() {
+                let b: Box<Int> = Box { value: 1 };
+                let n: Int = b.value;
+                let c: Box<String> = Box { value: 2 };
+            }
+        "#);
+        let errors = result.unwrap_err();
+        assert_eq!(errors.len(), 1, "{:?}", errors);
+        assert!(matches!(&errors[0], CheckError::TypeMismatch { expected, found, .. }
+            if expected == "Box<String>" && found == "Box<Int>"));
+    }
+
+    #[test]
+    fn test_inferred_lambda_types() {
+        let result = check_source(r#"
+            fn main() {
+                let inc = |x| => x + 1;
+                let n: Int = inc(41);
+                let s: String = inc(1);
+                let twice = |f, x| => f(f(x));
+                let m: Int = twice(inc, 0);
+                inc("one");
+            }
+        "#);
+        let errors = result.unwrap_err();
+        assert_eq!(errors.len(), 2, "{:?}", errors);
+        assert!(matches!(&errors[0], CheckError::TypeMismatch { expected, found, .. }
+            if expected == "String" && found == "Int"));
+        assert!(matches!(&errors[1], CheckError::TypeMismatch { expected, found, .. }
+            if expected == "Int" && found == "String"));
+    }
+
+    #[test]
+    fn test_let_polymorphism() {
+        let result = check_source(r#"
+            fn main() {
+                let id = |x| => x;
+                let n: Int = id(1);
+                let s: String = id("one");
+                let xs = [];
+                let ys: [Int] = push(xs, 1);
+                let zs: [String] = push(xs, "two");
+            }
+        "#);
+        // `id` is generalized, but the empty array's element type is fixed by its first use
+        let errors = result.unwrap_err();
+        assert!(errors.iter().all(|e| matches!(e, CheckError::TypeMismatch { line: 8, .. })), "{:?}", errors);
+        assert!(errors.iter().any(|e| matches!(e, CheckError::TypeMismatch { expected, found, .. }
+            if expected == "Int" && found == "String")));
+    }
+
+    #[test]
+    fn test_generalize_syntactic_values() {
+        let result = check_source(r#"
+            fn pick<T>(xs: [T]) -> T { return xs[0]; }
+
+            fn main() {
+                let f = pick;
+                let n: Int = f([1]);
+                let s: String = f(["one"]);
+                let id = |x| => x;
+                let g = id;
+                let b: Bool = g(true);
+                let c: Float = g(1.5);
+            }
+        "#);
+        assert!(result.is_ok(), "{:?}", result);
+    }
+
+    #[test]
+    fn test_lambda_block_body_returns() {
+        let result = check_source(r#"
+            fn main() {
+                let inc = |x: Int| => { return x + 1; };
+                let n: Int = inc(1);
+            }
+        "#);
+        assert!(result.is_ok(), "{:?}", result);
+    }
+
+    #[test]
+    fn test_infinite_type() {
+        let result = check_source(r#"
+            fn main() {
+                let omega = |f| => f(f);
+            }
+        "#);
+        let errors = result.unwrap_err();
+        assert!(matches!(&errors[0], CheckError::InfiniteType { .. }), "{:?}", errors);
+    }
+
+    #[test]
+    fn test_method_resolution() {
+        let result = check_source(r#"
+            trait Shape {
+                fn area(self) -> Int;
+                fn describe(self) -> String { return "area " + to_string(self.area()); }
+            }
+
+            struct Square { side: Int }
+
+            impl Square {
+                fn new(side: Int) -> Self { return Square { side: side }; }
+            }
+
+            impl Shape for Square {
+                fn area(self) -> Int { return self.side * self.side; }
+            }
+
+            fn total<T: Shape>(shapes: [T]) -> Int {
+                let mut sum = 0;
+                for s in shapes { sum = sum + s.area(); }
+                return sum;
+            }
+
+            fn main() {
+                let sq = Square::new(3);
+                let a: Int = sq.area();
+                let d: String = sq.describe();
+                let t: Int = total([sq]);
+                let s: String = 42.debug();
+            }
+        "#);
+        assert!(result.is_ok(), "{:?}", result);
+    }
+
+    #[test]
+    fn test_undefined_method() {
+        let result = check_source(r#"
+            struct Point { x: Int }
+            fn main() {
+                let p = Point { x: 1 };
+                p.norm();
+            }
+        "#);
+        let errors = result.unwrap_err();
+        assert!(matches!(&errors[0], CheckError::UndefinedMethod { ty, method, line: 5, .. }
+            if ty == "Point" && method == "norm"), "{:?}", errors);
+    }
+
+    #[test]
+    fn test_method_argument_mismatch() {
+        let result = check_source(r#"
+            struct Counter { n: Int }
+            impl Counter {
+                fn add(self, by: Int) -> Int { return self.n + by; }
+            }
+            fn main() {
+                let c = Counter { n: 1 };
+                let n: Int = c.add("two");
+            }
+        "#);
+        let errors = result.unwrap_err();
+        assert!(matches!(&errors[0], CheckError::TypeMismatch { expected, found, .. }
+            if expected == "Int" && found == "String"), "{:?}", errors);
+    }
+
+    #[test]
+    fn test_unsatisfied_trait_bound() {
+        let result = check_source(r#"
+            trait Shape { fn area(self) -> Int; }
+            struct Point { x: Int }
+            fn total<T: Shape>(shapes: [T]) -> Int { return 0; }
+            fn main() {
+                let n = total([Point { x: 1 }]);
+            }
+        "#);
+        let errors = result.unwrap_err();
+        assert!(matches!(&errors[0], CheckError::UnsatisfiedBound { ty, bound, line: 6, .. }
+            if ty == "Point" && bound == "Shape"), "{:?}", errors);
+    }
+
+    #[test]
+    fn test_incomplete_impl() {
+        let result = check_source(r#"
+            trait Shape {
+                fn area(self) -> Int;
+                fn perimeter(self) -> Int;
+            }
+            struct Point { x: Int }
+            impl Shape for Point {
+                fn area(self) -> Int { return 0; }
+                fn volume(self) -> Int { return 0; }
+            }
+        "#);
+        let errors = result.unwrap_err();
+        let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        assert!(messages.iter().any(|m| m.contains("perimeter")), "{:?}", messages);
+        assert!(messages.iter().any(|m| m.contains("volume")), "{:?}", messages);
+    }
+
+    #[test]
+    fn test_derive() {
+        let result = check_source(r#"
+            #[derive(Debug, Clone)]
+            struct Point { x: Int }
+
+            #[derive(Hash)]
+            struct Key { k: Int }
+
+            fn main() {
+                let p = Point { x: 1 };
+                let q: Point = p.clone();
+                let s: String = q.debug();
+            }
+        "#);
+        let errors = result.unwrap_err();
+        assert_eq!(errors.len(), 1, "{:?}", errors);
+        assert!(errors[0].to_string().contains("Cannot derive 'Hash'"), "{:?}", errors);
+    }
+
+    #[test]
+    fn test_exhaustive_matches() {
+        let result = check_source(r#"
+            enum Shape { Circle(Int), Rect { w: Int, h: Int }, Empty }
+
+            fn area(s: Shape) -> Int {
+                return match s {
+                    Circle(r) => r * r,
+                    Rect { w, h } => w * h,
+                    Empty => 0,
+                };
+            }
+
+            fn both(a: Bool, b: Option<Bool>) -> Int {
+                let x = match a { true => 1, false => 0 };
+                return match b {
+                    Some(true) => x,
+                    Some(false) => 0,
+                    None => 0,
+                };
+            }
+
+            fn digits(n: Int) -> String {
+                return match n { 0 => "zero", 1 => "one", _ => "many" };
+            }
+        "#);
+        assert!(result.is_ok(), "{:?}", result);
+    }
+
+    #[test]
+    fn test_non_exhaustive_match_witness() {
+        let cases = [
+            ("enum Shape { Circle(Int), Rect { w: Int, h: Int }, Empty }
+              fn f(s: Shape) -> Int { return match s { Circle(r) => r, Empty => 0 }; }",
+             "Rect { w: _, h: _ }"),
+            ("fn f(b: Option<Bool>) -> Int { return match b { Some(true) => 1, None => 0 }; }",
+             "Some(false)"),
+            ("fn f(r: Result<Option<Int>, String>) -> Int { return match r { Ok(Some(n)) => n, Err(e) => 0 }; }",
+             "Ok(None)"),
+            ("fn f(b: Bool) -> Int { return match b { true => 1 }; }", "false"),
+            ("fn f(n: Int) -> Int { return match n { 0 => 1, 1 => 2 }; }", "2"),
+            ("fn f(xs: [Int]) -> Int { return match xs { [] => 0, [x, ..rest] if x > 0 => x }; }", "[_]"),
+            ("fn f(n: Int) -> Int { return match n { 0..=9 | 20..=29 => 1, -5..0 => 2 }; }", "10"),
+            ("fn f(p: {ok: Bool, n: Int}) -> Int { return match p { {ok: true, ..} => 1 }; }", "{ ok: false, .. }"),
+        ];
+        for (source, expected) in cases {
+            let errors = check_source(source).unwrap_err();
+            assert!(matches!(&errors[..], [CheckError::NonExhaustiveMatch { witness, .. }] if witness == expected),
+                "{}: {:?}", expected, errors);
+        }
+    }
+
+    #[test]
+    fn test_empty_match_on_empty_enum() {
+        let result = check_source(r#"
+            enum Void {}
+            fn absurd(v: Void) -> Int { let n: Int = match v {}; return n; }
+            fn name(v: Void) -> String { return match v {}; }
+        "#);
+        assert!(result.is_ok(), "{:?}", result);
+
+        let errors = check_source("fn f(b: Bool) -> Int { return match b {}; }").unwrap_err();
+        assert!(matches!(&errors[..], [CheckError::NonExhaustiveMatch { .. }]), "{:?}", errors);
+    }
+
+    #[test]
+    fn test_unreachable_arms() {
+        let warnings = check_warnings(r#"
+            fn f(o: Option<Int>) -> Int {
+                return match o {
+                    Some(n) => n,
+                    _ => 0,
+                    None => 1,
+                };
+            }
+
+            fn g(b: Bool) -> Int {
+                return match b {
+                    true => 1,
+                    false => 0,
+                    true => 2,
+                };
+            }
+        "#);
+        let lines: Vec<usize> = warnings
+            .iter()
+            .filter_map(|w| match w {
+                CheckError::UnreachablePattern { line, .. } => Some(*line),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(lines, vec![6, 14], "{:?}", warnings);
+        assert_eq!(warnings.len(), 2);
+    }
+
+    #[test]
+    fn test_rich_patterns() {
+        let result = check_source(r#"
+            fn classify(n: Int) -> String {
+                return match n {
+                    0 => "zero",
+                    1..=9 => "digit",
+                    x if x < 0 => "negative",
+                    _ => "large",
+                };
+            }
+
+            fn head(xs: [Int]) -> Int {
+                return match xs {
+                    [] => 0,
+                    [x] | [x, _] => x,
+                    [first, ..rest] => first + len(rest),
+                };
+            }
+
+            fn greet(p: {name: String, age: Int}) -> String {
+                return match p {
+                    {name, age: 0..=17} => "hi " + name,
+                    whole @ {name, ..} => "hello " + name,
+                };
+            }
+        "#);
+        assert!(result.is_ok(), "{:?}", result);
+    }
+
+    #[test]
+    fn test_rich_pattern_errors() {
+        let result = check_source(r#"
+            fn f(o: Option<Int>, p: {name: String, age: Int}, xs: [Int]) {
+                let a = match o { Some(x) | None => 1 };
+                let b = match p { {name} => name };
+                let c = match xs { [x, ..] if x => 1, _ => 0 };
+                let d = match 5 { 9..=1 => 1, _ => 0 };
+                let e = match xs { ["one", ..] => 1, _ => 0 };
+            }
+        "#);
+        let errors = result.unwrap_err();
+        let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        assert!(messages.iter().any(|m| m.contains("'x' is not bound in every alternative")), "{:?}", messages);
+        assert!(messages.iter().any(|m| m.contains("does not mention field 'age'")), "{:?}", messages);
+        assert!(errors.iter().any(|e| matches!(e, CheckError::NonBoolCondition { line: 5, .. })), "{:?}", errors);
+        assert!(messages.iter().any(|m| m.contains("matches no values")), "{:?}", messages);
+        assert!(errors.iter().any(|e| matches!(e, CheckError::TypeMismatch { expected, found, .. }
+            if expected == "Int" && found == "String")), "{:?}", errors);
+    }
+
+    #[test]
+    fn test_guards_and_or_patterns_in_exhaustiveness() {
+        let warnings = check_warnings(r#"
+            fn f(o: Option<Int>) -> Int {
+                return match o {
+                    Some(n) if n > 0 => n,
+                    Some(0) | None => 0,
+                    Some(n) => 0 - n,
+                };
+            }
+
+            fn g(n: Int) -> Int {
+                return match n {
+                    0..=10 => 1,
+                    5 | 10 => 2,
+                    _ => 3,
+                };
+            }
+        "#);
+        assert!(matches!(&warnings[..], [CheckError::UnreachablePattern { line: 13, .. }]), "{:?}", warnings);
+    }
+
+    #[test]
+    fn test_tuples_and_destructuring_let() {
+        let result = check_source(r#"
+            fn min_max(xs: [Int]) -> (Int, Int) {
+                return (xs[0], xs[len(xs) - 1]);
+            }
+
+            fn main() -> Int {
+                let (lo, hi) = min_max([1, 2, 3]);
+                let (name, {age, ..}) = ("Ada", {age: 36, city: "London"});
+                let pair = (lo, name);
+                let s: String = pair.1;
+                return lo + hi + age + pair.0;
+            }
+        "#);
+        assert!(result.is_ok(), "{:?}", result);
+    }
+
+    #[test]
+    fn test_destructuring_let_errors() {
+        let result = check_source(r#"
+            fn main() {
+                let (a, b) = (1, 2, 3);
+                let t = (1, "x");
+                let c = t.2;
+                let s: String = t.0;
+                let Some(x) = Some(1);
+                let [first, ..] = [1, 2];
+            }
+        "#);
+        let errors = result.unwrap_err();
+        let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        assert!(errors.iter().any(|e| matches!(e, CheckError::TypeMismatch { line: 3, .. })), "{:?}", errors);
+        assert!(messages.iter().any(|m| m.contains("No field '2' on tuple")), "{:?}", messages);
+        assert!(errors.iter().any(|e| matches!(e, CheckError::TypeMismatch { line: 6, .. })), "{:?}", errors);
+        assert!(messages.iter().any(|m| m.contains("Refutable pattern in 'let': 'None' not covered")), "{:?}", messages);
+        assert!(messages.iter().any(|m| m.contains("Refutable pattern in 'let': '[]' not covered")), "{:?}", messages);
+    }
+
+    #[test]
+    fn test_mutable_assignment() {
+        let result = check_source(r#"
+            struct Counter { count: Int, history: [Int] }
+
+            impl Counter {
+                fn bump(&mut self, mut by: Int) {
+                    by = by + 1;
+                    self.count = self.count + by;
+                    self.history[0] = by;
+                }
+            }
+
+            fn main() -> Int {
+                let mut total = 0;
+                let mut point = {x: 1, y: 2};
+                let mut (a, b) = (1, 2);
+                total = total + 1;
+                point.x = 3;
+                a = b = 5;
+                return total + point.x + a + b;
+            }
+        "#);
+        assert!(result.is_ok(), "{:?}", result);
+    }
+
+    #[test]
+    fn test_immutable_assignment() {
+        let result = check_source(r#"
+            struct Counter { count: Int }
+
+            impl Counter {
+                fn bump(&self, by: Int) {
+                    self.count = by;
+                    by = 0;
+                }
+            }
+
+            fn main() {
+                let n = 1;
+                n = 2;
+                let point = {x: 1};
+                point.x = 3;
+                let (a, b) = (1, 2);
+                b = a;
+                main = main;
+                1 = 2;
+                point.x = "three";
+            }
+        "#);
+        let errors = result.unwrap_err();
+        let immutable: Vec<(&str, usize)> = errors.iter().filter_map(|e| match e {
+            CheckError::ImmutableAssignment { name, line, .. } => Some((name.as_str(), *line)),
+            _ => None,
+        }).collect();
+        assert_eq!(immutable, [("self", 6), ("by", 7), ("n", 13), ("point", 15), ("b", 17), ("main", 18), ("point", 20)]);
+        assert!(errors.iter().any(|e| matches!(e, CheckError::InvalidAssignmentTarget { line: 19, .. })), "{:?}", errors);
+        assert!(errors.iter().any(|e| matches!(e, CheckError::TypeMismatch { line: 20, .. })), "{:?}", errors);
+    }
+
+    #[test]
+    fn test_contract_conditions() {
+        let result = check_source(r#"
+            struct Account where invariant: balance >= 0 && self.owner != "" {
+                owner: String,
+                balance: Int
+            }
+
+            fn withdraw(a: Account, amount: Int) -> Int
+                where pre: amount > 0 && amount <= a.balance, post: result >= 0, invariant: a.balance >= 0
+            {
+                return a.balance - amount;
+            }
+        "#);
+        assert!(result.is_ok(), "{:?}", result);
+
+        let result = check_source(r#"
+            struct Account where pre: balance > 0, invariant: missing {
+                balance: Int
+            }
+
+            fn name(n: Int) -> String
+                where pre: n, post: result > 0
+            {
+                return "x";
+            }
+        "#);
+        let errors = result.unwrap_err();
+        let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        assert!(messages.iter().any(|m| m.contains("Only 'invariant' clauses apply to structs")), "{:?}", messages);
+        assert!(errors.iter().any(|e| matches!(e, CheckError::UndefinedVariable { name, .. } if name == "missing")), "{:?}", errors);
+        assert!(errors.iter().any(|e| matches!(e, CheckError::NonBoolCondition { found, line: 7, .. } if found == "Int")), "{:?}", errors);
+        assert!(errors.iter().any(|e| matches!(e, CheckError::InvalidBinaryOp { left, line: 7, .. } if left == "String")), "{:?}", errors);
+    }
+
+    #[test]
+    fn test_effect_handlers() {
+        let source = r#"
+            effect Logger {
+                op log: String
+            }
+            effect State {
+                op get: () -> Int
+            }
+
+            fn greet(name: String) -> Effect<Int, Logger> {
+                perform Logger.log("hello " + name);
+                return 1;
+            }
+
+            fn count() -> Int {
+                return greet("ada") + perform State.get();
+            }
+
+            fn main() {
+                let n: Int = handle { count(); } with State {
+                    get() => resume(2),
+                };
+            }
+        "#;
+        let errors = check_source(source).unwrap_err();
+        let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(messages, vec!["unhandled effect 'Logger' escapes 'main' at line 19, column 39"]);
+
+        let handled = source.replace("{ count(); }", "{ handle { count(); } with Logger { log(m) => resume(()) }; }");
+        assert!(check_source(&handled).is_ok(), "{:?}", check_source(&handled));
+
+        // A declared row must cover what the function performs
+        let result = check_source(&source.replace("-> Effect<Int, Logger>", "-> Effect<Int, State>"));
+        assert!(matches!(&result.unwrap_err()[0], CheckError::UnhandledEffect { effect, function, line: 10, .. }
+            if effect == "Logger" && function == "greet"));
+
+        let result = check_source(&source.replace("resume(2)", "resume(\"two\")"));
+        assert!(result.unwrap_err().iter().any(|e| matches!(e, CheckError::TypeMismatch { line: 20, .. })));
+    }
+
+    #[test]
+    fn test_handler_errors() {
+        let errors = check_source(r#"
+            effect Logger {
+                op log: String
+                op flush: () -> Bool
+            }
+
+            fn main() {
+                resume(1);
+                handle { perform Logger.log(1); perform Logger.warn("x"); } with Logger {
+                    log(m) => { resume(()); println(m); },
+                    log(a, b) => resume(()),
+                };
+                handle { 0; } with Console { print(s) => 0 };
+            }
+        "#).unwrap_err();
+        let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(messages, vec![
+            "'resume' outside of a handler clause at line 8, column 17",
+            "type mismatch: expected String, found Int at line 9, column 41",
+            "effect 'Logger' has no operation 'warn' at line 9, column 64",
+            "'resume' must be the last thing a handler clause does at line 10, column 33",
+            "operation 'log' is handled more than once at line 11, column 21",
+            "wrong number of arguments: expected 1, found 2 at line 11, column 21",
+            "handler for 'Logger' does not handle operation 'flush' at line 9, column 17",
+            "undefined effect 'Console' at line 13, column 36",
+        ]);
+    }
+
+    #[test]
+    fn test_ai_effect_annotations() {
+        let result = check_source(r#"
+            effect Logger { op log: String }
+
+            fn ask(q: String) -> AI<String> { return ai! { "What is 2 + 2?" }; }
+            fn ask_and_log(q: String) -> Effect<String, Logger, AI> {
+                perform Logger.log(q);
+                return ask(q);
+            }
+            fn roll() -> Int { println("rolling"); return random_int(1, 6); }
+            fn main() {
+                let answer = ai! { "hello" };
+                println(roll());
+            }
+        "#);
+        assert!(result.is_ok(), "{:?}", result);
+
+        let errors = check_source(r#"
+            struct Bot { name: String }
+            impl Bot {
+                fn reply(self, q: String) -> Int { return ask(q); }
+            }
+
+            fn ask(q: String) -> Int { let answer = ai! { "What is 2 + 2?" }; return 4; }
+            fn relay(q: String) -> Effect<Int, IO> { println(q); return ask(q); }
+            fn main() { println(relay("hi")); }
+        "#).unwrap_err();
+        let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(messages, vec![
+            "'reply' performs effect 'AI' at line 4, column 59 but its return type does not declare it",
+            "'ask' performs effect 'AI' at line 7, column 53 but its return type does not declare it",
+            "'relay' performs effect 'AI' at line 8, column 73 but its return type does not declare it",
+        ]);
+    }
+
+    #[test]
+    fn test_returned_reply_reports_only_missing_annotation() {
+        let errors = check_source(r#"
+            fn f() -> String { return ai query { prompt: "x" }; }
+            fn g() -> String { let s = ai query { prompt: "x" }; return s; }
+            fn h(s: AI<String>) -> String { return s; }
+            fn main() { }
+        "#).unwrap_err();
+        let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(messages, vec![
+            "type mismatch: expected String, found AI<String> at line 4, column 45",
+            "'f' performs effect 'AI' at line 2, column 39 but its return type does not declare it",
+            "'g' performs effect 'AI' at line 3, column 40 but its return type does not declare it",
+        ]);
+    }
+
+    #[test]
+    fn test_prompt_arguments() {
+        let result = check_source(r#"
+            prompt summarize { "Summarize the following text in 3 sentences: {text}" }
+            prompt translate { "Translate from {source} to {target}: {text}" }
+
+            fn main() {
+                let body = "long text";
+                let a = summarize!(body);
+                let b = summarize!(text: body);
+                let c = translate!("Spanish", target: "English", text: body);
+            }
+        "#);
+        assert!(result.is_ok(), "{:?}", result);
+
+        let errors = check_source(r#"
+            prompt summarize { "Summarize the following text in 3 sentences: {text}" }
+            prompt translate { "Translate from {source} to {target}: {text}" }
+
+            fn main() {
+                let body = "long text";
+                let a = summarize!(body, body, body);
+                let b = summarize!(txt: body);
+                let c = translate!(source: "es", "en", text: body, text: body);
+            }
+        "#).unwrap_err();
+        let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(messages, vec![
+            "argument 2 has no placeholder to fill (the template has 1) in invocation of prompt 'summarize' at line 7, column 25",
+            "argument 3 has no placeholder to fill (the template has 1) in invocation of prompt 'summarize' at line 7, column 25",
+            "no placeholder named 'txt' in invocation of prompt 'summarize' at line 8, column 36",
+            "missing argument for placeholder 'text' in invocation of prompt 'summarize' at line 8, column 25",
+            "positional argument after named arguments in invocation of prompt 'translate' at line 9, column 25",
+            "placeholder 'text' is filled more than once in invocation of prompt 'translate' at line 9, column 68",
+            "missing argument for placeholder 'target' in invocation of prompt 'translate' at line 9, column 25",
+        ]);
+    }
+
+    #[test]
+    fn test_ai_generate_struct() {
+        let result = check_source(r#"
+            #[ai_generate]
+            struct Article { title: String, words: Int }
+
+            fn draft() -> AI<Article> {
+                return ai generate { prompt: "Write a draft" };
+            }
+
+            fn words(a: Article) -> Int { return a.words; }
+
+            fn main() {
+                let a: Article = ai generate { prompt: "Write an article" };
+                let n: Int = a.words + 1;
+                let t: String = a.title;
+                let d: AI<Article> = draft();
+                let m: Int = words(ai generate { prompt: "Write another" }) + 1;
+            }
+        "#);
+        assert!(result.is_ok(), "{:?}", result);
+
+        let errors = check_source(r#"
+            #[ai_generate]
+            struct Article { title: String, words: Int }
+            struct Note { text: String }
+
+            fn main() {
+                let a: Note = ai generate { prompt: "Write a note" };
+                let b: Article = ai query { prompt: "Write an article" };
+            }
+        "#).unwrap_err();
+        let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(messages, vec![
+            "struct 'Note' must be marked #[ai_generate] to be produced by 'ai generate' at line 7, column 31",
+            "type mismatch: expected Article, found AI<String> at line 8, column 17",
+        ]);
+    }
+
+    #[test]
+    fn test_string_interpolation() {
+        let result = check_source(r#"
+            fn describe(name: String, age: Int) -> String {
+                let line: String = "{name} is {age + 1} next year";
+                return line;
+            }
+        "#);
+        assert!(result.is_ok(), "{:?}", result);
+
+        let errors = check_source(r#"
+            fn main() {
+                let n: Int = "count: {1 + 2}";
+                let s = "hello {nobody}";
+            }
+        "#).unwrap_err();
+        let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(messages, vec![
+            "type mismatch: expected Int, found String at line 3, column 17",
+            "undefined variable 'nobody' at line 4, column 33",
+        ]);
+    }
+
+    #[test]
+    fn test_numeric_operators() {
+        let result = check_source(r#"
+            fn main() {
+                let mut n = 0xFF % 7 << 2 | 1;
+                n += 1;
+                n ^= n >> 1;
+                let b: Bool = true & false;
+                let r: Float = 7.5 % 2;
+            }
+        "#);
+        assert!(result.is_ok(), "{:?}", result);
+
+        let errors = check_source(r#"
+            fn main() {
+                let mut n = 1;
+                let f = 1.5 & 2.5;
+                let s = 1 << 2.0;
+                n += 0.5;
+                let w: Float = wrapping_add(1, 2);
+            }
+        "#).unwrap_err();
+        let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(messages, vec![
+            "invalid binary operation: Float BitAnd Float at line 4, column 29",
+            "invalid binary operation: Int Shl Float at line 5, column 27",
+            "type mismatch: expected Int, found Float at line 6, column 19",
+            "type mismatch: expected Float, found Int at line 7, column 17",
+        ]);
+    }
 }
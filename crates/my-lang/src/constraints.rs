@@ -0,0 +1,127 @@
+//! Runtime validation of constrained types and `#[ai_validate]` fields
+//!
+//! `T where ai_format: "...", ai_check: "..."` constrains the values bound
+//! to `T`, and `#[ai_validate("...")]` constrains a struct field. The
+//! interpreter checks a value when it is bound by `let`, passed as an
+//! argument or assigned to a binding declared with a constrained type, and
+//! checks struct fields when the struct is constructed or a field assigned.
+//!
+//! `ai_format` is checked locally: `"email"` and `"json"` name formats, and
+//! anything else is a regular expression the whole value must match.
+//! `ai_check`, `ai_valid` and `#[ai_validate]` ask the AI backend, which
+//! answers `true` or `false`; verdicts are cached for the run.
+
+use crate::ai::AiRequest;
+use crate::ast::{AiConstraint, AiKeyword};
+use regex::Regex;
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use thiserror::Error;
+
+/// A value that does not satisfy a constraint
+#[derive(Debug, Clone, PartialEq, Error)]
+#[error("'{path}' does not satisfy {constraint}: {message}")]
+pub struct ValidationError {
+    /// The variable, parameter or field holding the value, such as
+    /// `user.email` or `tags[2]`
+    pub path: String,
+    /// The constraint as written, such as `ai_format: "email"`
+    pub constraint: String,
+    pub message: String,
+}
+
+/// A constraint the runtime can check
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Rule<'a> {
+    /// `ai_format: "..."`
+    Format(&'a str),
+    /// `ai_check: "..."`, `ai_valid: "..."` or `#[ai_validate("...")]`
+    Ai { keyword: &'static str, requirement: &'a str },
+}
+
+impl<'a> Rule<'a> {
+    /// The rule of a `where` constraint; `ai_infer` and custom constraints
+    /// have nothing to check
+    pub fn of(constraint: &'a AiConstraint) -> Option<Self> {
+        match constraint {
+            AiConstraint::Format(format) => Some(Rule::Format(format)),
+            AiConstraint::Check(requirement) => Some(Rule::Ai { keyword: "ai_check", requirement }),
+            AiConstraint::Valid(requirement) => Some(Rule::Ai { keyword: "ai_valid", requirement }),
+            AiConstraint::Infer | AiConstraint::Custom { .. } => None,
+        }
+    }
+
+    pub fn ai_validate(requirement: &'a str) -> Self {
+        Rule::Ai { keyword: "ai_validate", requirement }
+    }
+
+    /// The rule as written in the source
+    pub fn describe(&self) -> String {
+        match self {
+            Rule::Format(format) => format!("ai_format: {:?}", format),
+            Rule::Ai { keyword: "ai_validate", requirement } => format!("#[ai_validate({:?})]", requirement),
+            Rule::Ai { keyword, requirement } => format!("{}: {:?}", keyword, requirement),
+        }
+    }
+}
+
+/// The request asking the AI backend whether `text` satisfies the
+/// requirement of an AI rule
+pub fn validation_request(keyword: &str, requirement: &str, text: &str) -> AiRequest {
+    let prompt = match keyword {
+        "ai_valid" => format!("Is the following value a valid {}?\n\nValue: {}", requirement, text),
+        _ => format!("Does the following value satisfy this requirement: {}?\n\nValue: {}", requirement, text),
+    };
+    AiRequest { operation: AiKeyword::Validate, prompt, model: None, schema: None }
+}
+
+/// Compiled `ai_format` patterns, since a constrained binding in a loop is
+/// checked on every iteration
+static PATTERNS: OnceLock<Mutex<HashMap<String, Regex>>> = OnceLock::new();
+
+/// Check `text` against an `ai_format`, saying what is wrong if it fails
+pub fn check_format(format: &str, text: &str) -> Result<(), String> {
+    match format {
+        "email" => {
+            let (local, domain) = text.split_once('@').unwrap_or_default();
+            let valid = !local.is_empty()
+                && !text.chars().any(char::is_whitespace)
+                && !domain.contains('@')
+                && domain.split('.').count() > 1
+                && domain.split('.').all(|label| !label.is_empty());
+            if valid { Ok(()) } else { Err(format!("{:?} is not an email address", text)) }
+        }
+        "json" => serde_json::from_str::<serde_json::Value>(text)
+            .map(|_| ())
+            .map_err(|e| format!("{:?} is not valid JSON ({})", text, e)),
+        pattern => {
+            let mut patterns = PATTERNS.get_or_init(Default::default).lock().unwrap_or_else(|e| e.into_inner());
+            let regex = match patterns.get(pattern) {
+                Some(regex) => regex,
+                None => {
+                    let regex = Regex::new(&format!("^(?:{})$", pattern)).map_err(|e| format!("invalid pattern: {}", e))?;
+                    patterns.entry(pattern.to_string()).or_insert(regex)
+                }
+            };
+            if regex.is_match(text) { Ok(()) } else { Err(format!("{:?} does not match", text)) }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_formats() {
+        assert!(check_format("email", "ada@example.org").is_ok());
+        for address in ["ada", "ada@example", "@example.org", "ada@@example.org", "ada @example.org", "ada@example."] {
+            assert_eq!(check_format("email", address), Err(format!("{:?} is not an email address", address)));
+        }
+        assert!(check_format("json", r#"{"a": [1, 2]}"#).is_ok());
+        assert!(check_format("json", "{").unwrap_err().starts_with("\"{\" is not valid JSON"));
+        assert!(check_format(r"\d{4}-\d{2}-\d{2}", "2024-05-01").is_ok());
+        assert_eq!(check_format(r"\d{4}-\d{2}-\d{2}", "on 2024-05-01"), Err("\"on 2024-05-01\" does not match".to_string()));
+        assert!(check_format("(", "x").unwrap_err().starts_with("invalid pattern"));
+    }
+}
@@ -0,0 +1,747 @@
+//! Lossless concrete syntax tree
+//!
+//! The AST keeps what a program means; the concrete syntax tree keeps every
+//! byte of its source, whitespace and comments included, so that tools can
+//! attach comments to declarations and print source back unchanged.
+//!
+//! As in rowan, the tree has two layers. The green tree is immutable and
+//! knows no positions: nodes hold their kind, children and width, and tokens
+//! their text, so equal subtrees can be shared. [`SyntaxNode`] and
+//! [`SyntaxToken`] make up the red tree over it, built on the way down, with
+//! byte ranges and parent links. Typed views such as [`SourceFile`] and
+//! [`FnItem`] wrap red nodes of one kind.
+//!
+//! [`parse`] runs the parser, which records the token range of each
+//! declaration, statement and block, and hangs the lexer's trivia tokens
+//! around those nodes. Comments on the lines right before a declaration or
+//! statement belong to its node; other trivia belongs to the enclosing node.
+
+use crate::ast::{Program, TopLevel};
+use crate::lexer::Lexer;
+use crate::parser::{ParseError, Parser};
+use crate::token::{Token, TokenKind};
+use std::fmt;
+use std::iter::Peekable;
+use std::ops::Range;
+use std::rc::Rc;
+use std::sync::Arc;
+
+/// Kinds of syntax nodes and tokens
+#[derive(Debug, Clone, PartialEq)]
+pub enum SyntaxKind {
+    /// A token, trivia included
+    Token(TokenKind),
+    /// A whole file
+    SourceFile,
+    Function,
+    Struct,
+    Enum,
+    Trait,
+    Impl,
+    Effect,
+    Contract,
+    Import,
+    Comptime,
+    Arena,
+    AiModel,
+    Prompt,
+    /// A method of an `impl` or a trait
+    Method,
+    /// A field of a struct
+    Field,
+    Block,
+    Stmt,
+    /// A declaration or statement that failed to parse
+    Error,
+}
+
+impl SyntaxKind {
+    pub fn of_item(item: &TopLevel) -> Self {
+        match item {
+            TopLevel::Function(_) => SyntaxKind::Function,
+            TopLevel::Struct(_) => SyntaxKind::Struct,
+            TopLevel::Enum(_) => SyntaxKind::Enum,
+            TopLevel::Trait(_) => SyntaxKind::Trait,
+            TopLevel::Impl(_) => SyntaxKind::Impl,
+            TopLevel::Effect(_) => SyntaxKind::Effect,
+            TopLevel::Contract(_) => SyntaxKind::Contract,
+            TopLevel::Import(_) => SyntaxKind::Import,
+            TopLevel::Comptime(_) => SyntaxKind::Comptime,
+            TopLevel::Arena(_) => SyntaxKind::Arena,
+            TopLevel::AiModel(_) => SyntaxKind::AiModel,
+            TopLevel::Prompt(_) => SyntaxKind::Prompt,
+            TopLevel::Error(_) => SyntaxKind::Error,
+        }
+    }
+
+    /// Top-level declarations, including those that failed to parse
+    pub fn is_item(&self) -> bool {
+        !matches!(
+            self,
+            SyntaxKind::Token(_) | SyntaxKind::SourceFile | SyntaxKind::Method | SyntaxKind::Field | SyntaxKind::Block | SyntaxKind::Stmt
+        )
+    }
+
+    /// Nodes that take the comments right before them
+    fn takes_comments(&self) -> bool {
+        !matches!(self, SyntaxKind::Token(_) | SyntaxKind::SourceFile | SyntaxKind::Block)
+    }
+}
+
+// ============================================
+// Green Tree
+// ============================================
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GreenToken {
+    kind: TokenKind,
+    text: String,
+}
+
+impl GreenToken {
+    pub fn new(kind: TokenKind, text: impl Into<String>) -> Self {
+        GreenToken { kind, text: text.into() }
+    }
+
+    pub fn kind(&self) -> &TokenKind {
+        &self.kind
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum GreenElement {
+    Node(Arc<GreenNode>),
+    Token(Arc<GreenToken>),
+}
+
+impl GreenElement {
+    pub fn width(&self) -> usize {
+        match self {
+            GreenElement::Node(node) => node.width,
+            GreenElement::Token(token) => token.text.len(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GreenNode {
+    kind: SyntaxKind,
+    width: usize,
+    children: Vec<GreenElement>,
+}
+
+impl GreenNode {
+    pub fn new(kind: SyntaxKind, children: Vec<GreenElement>) -> Self {
+        let width = children.iter().map(GreenElement::width).sum();
+        GreenNode { kind, width, children }
+    }
+
+    pub fn kind(&self) -> &SyntaxKind {
+        &self.kind
+    }
+
+    /// Length of the node's text in bytes
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn children(&self) -> &[GreenElement] {
+        &self.children
+    }
+}
+
+impl fmt::Display for GreenNode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for child in &self.children {
+            match child {
+                GreenElement::Node(node) => write!(f, "{}", node)?,
+                GreenElement::Token(token) => f.write_str(&token.text)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+// ============================================
+// Red Tree
+// ============================================
+
+/// A node of the green tree at a position in the file
+#[derive(Clone)]
+pub struct SyntaxNode(Rc<NodeData>);
+
+struct NodeData {
+    green: Arc<GreenNode>,
+    parent: Option<SyntaxNode>,
+    offset: usize,
+}
+
+impl SyntaxNode {
+    pub fn new_root(green: Arc<GreenNode>) -> Self {
+        SyntaxNode(Rc::new(NodeData { green, parent: None, offset: 0 }))
+    }
+
+    pub fn kind(&self) -> &SyntaxKind {
+        &self.0.green.kind
+    }
+
+    pub fn green(&self) -> &Arc<GreenNode> {
+        &self.0.green
+    }
+
+    /// Byte range of the node in the file
+    pub fn text_range(&self) -> Range<usize> {
+        self.0.offset..self.0.offset + self.0.green.width
+    }
+
+    pub fn text(&self) -> String {
+        self.0.green.to_string()
+    }
+
+    pub fn parent(&self) -> Option<&SyntaxNode> {
+        self.0.parent.as_ref()
+    }
+
+    pub fn children_with_tokens(&self) -> Vec<SyntaxElement> {
+        let mut offset = self.0.offset;
+        let mut elements = Vec::with_capacity(self.0.green.children.len());
+        for child in &self.0.green.children {
+            elements.push(match child {
+                GreenElement::Node(green) => SyntaxElement::Node(SyntaxNode(Rc::new(NodeData {
+                    green: green.clone(),
+                    parent: Some(self.clone()),
+                    offset,
+                }))),
+                GreenElement::Token(green) => {
+                    SyntaxElement::Token(SyntaxToken { green: green.clone(), parent: self.clone(), offset })
+                }
+            });
+            offset += child.width();
+        }
+        elements
+    }
+
+    pub fn children(&self) -> impl Iterator<Item = SyntaxNode> {
+        self.children_with_tokens().into_iter().filter_map(|element| match element {
+            SyntaxElement::Node(node) => Some(node),
+            SyntaxElement::Token(_) => None,
+        })
+    }
+
+    /// The tokens directly in this node
+    pub fn tokens(&self) -> impl Iterator<Item = SyntaxToken> {
+        self.children_with_tokens().into_iter().filter_map(|element| match element {
+            SyntaxElement::Token(token) => Some(token),
+            SyntaxElement::Node(_) => None,
+        })
+    }
+
+    /// This node and every node under it, in source order
+    pub fn descendants(&self) -> Vec<SyntaxNode> {
+        let mut nodes = vec![self.clone()];
+        for child in self.children() {
+            nodes.extend(child.descendants());
+        }
+        nodes
+    }
+
+    /// Every token under this node, in source order
+    pub fn descendant_tokens(&self) -> Vec<SyntaxToken> {
+        let mut tokens = Vec::new();
+        for element in self.children_with_tokens() {
+            match element {
+                SyntaxElement::Node(node) => tokens.extend(node.descendant_tokens()),
+                SyntaxElement::Token(token) => tokens.push(token),
+            }
+        }
+        tokens
+    }
+
+    /// The token containing the byte at `offset`
+    pub fn token_at_offset(&self, offset: usize) -> Option<SyntaxToken> {
+        for element in self.children_with_tokens() {
+            match element {
+                SyntaxElement::Node(node) if node.text_range().contains(&offset) => return node.token_at_offset(offset),
+                SyntaxElement::Token(token) if token.text_range().contains(&offset) => return Some(token),
+                _ => {}
+            }
+        }
+        None
+    }
+
+    /// Comments before the first token of the node's own code
+    pub fn leading_comments(&self) -> Vec<SyntaxToken> {
+        self.children_with_tokens()
+            .into_iter()
+            .map_while(|element| match element {
+                SyntaxElement::Token(token) if token.kind().is_trivia() => Some(token),
+                _ => None,
+            })
+            .filter(|token| token.kind().is_comment())
+            .collect()
+    }
+
+    /// The tokens directly in this node that are not trivia, with attributes
+    /// and `pub` skipped
+    fn code_tokens(&self) -> Vec<SyntaxToken> {
+        let mut tokens = Vec::new();
+        let mut in_attribute = false;
+        for token in self.tokens().filter(|t| !t.kind().is_trivia()) {
+            match token.kind() {
+                TokenKind::HashBracket => in_attribute = true,
+                TokenKind::RBracket if in_attribute => in_attribute = false,
+                TokenKind::Pub => {}
+                _ if !in_attribute => tokens.push(token),
+                _ => {}
+            }
+        }
+        tokens
+    }
+}
+
+impl PartialEq for SyntaxNode {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0.green, &other.0.green) && self.0.offset == other.0.offset
+    }
+}
+
+impl fmt::Debug for SyntaxNode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}@{:?}", self.kind(), self.text_range())
+    }
+}
+
+impl fmt::Display for SyntaxNode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0.green)
+    }
+}
+
+/// A token of the green tree at a position in the file
+#[derive(Clone, PartialEq)]
+pub struct SyntaxToken {
+    green: Arc<GreenToken>,
+    parent: SyntaxNode,
+    offset: usize,
+}
+
+impl SyntaxToken {
+    pub fn kind(&self) -> &TokenKind {
+        &self.green.kind
+    }
+
+    pub fn text(&self) -> &str {
+        &self.green.text
+    }
+
+    /// Byte range of the token in the file
+    pub fn text_range(&self) -> Range<usize> {
+        self.offset..self.offset + self.green.text.len()
+    }
+
+    pub fn parent(&self) -> &SyntaxNode {
+        &self.parent
+    }
+}
+
+impl fmt::Debug for SyntaxToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}@{:?} {:?}", self.kind(), self.text_range(), self.text())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SyntaxElement {
+    Node(SyntaxNode),
+    Token(SyntaxToken),
+}
+
+// ============================================
+// Typed Nodes
+// ============================================
+
+/// A typed view of syntax nodes of some kinds
+pub trait AstNode: Sized {
+    fn can_cast(kind: &SyntaxKind) -> bool;
+
+    fn syntax(&self) -> &SyntaxNode;
+
+    fn cast(node: SyntaxNode) -> Option<Self>;
+}
+
+macro_rules! ast_node {
+    ($(#[$doc:meta])* $name:ident, $kinds:pat) => {
+        $(#[$doc])*
+        #[derive(Debug, Clone, PartialEq)]
+        pub struct $name(SyntaxNode);
+
+        impl AstNode for $name {
+            fn can_cast(kind: &SyntaxKind) -> bool {
+                matches!(kind, $kinds)
+            }
+
+            fn syntax(&self) -> &SyntaxNode {
+                &self.0
+            }
+
+            fn cast(node: SyntaxNode) -> Option<Self> {
+                Self::can_cast(node.kind()).then(|| $name(node))
+            }
+        }
+    };
+}
+
+ast_node!(
+    /// A whole file
+    SourceFile,
+    SyntaxKind::SourceFile
+);
+ast_node!(
+    /// Any top-level declaration
+    Item,
+    SyntaxKind::Function
+        | SyntaxKind::Struct
+        | SyntaxKind::Enum
+        | SyntaxKind::Trait
+        | SyntaxKind::Impl
+        | SyntaxKind::Effect
+        | SyntaxKind::Contract
+        | SyntaxKind::Import
+        | SyntaxKind::Comptime
+        | SyntaxKind::Arena
+        | SyntaxKind::AiModel
+        | SyntaxKind::Prompt
+        | SyntaxKind::Error
+);
+ast_node!(
+    /// A function, or a method of an `impl` or a trait
+    FnItem,
+    SyntaxKind::Function | SyntaxKind::Method
+);
+ast_node!(StructItem, SyntaxKind::Struct);
+ast_node!(ImplItem, SyntaxKind::Impl);
+ast_node!(FieldNode, SyntaxKind::Field);
+ast_node!(BlockNode, SyntaxKind::Block);
+ast_node!(StmtNode, SyntaxKind::Stmt | SyntaxKind::Error);
+
+/// The typed children of `node`
+fn children<N: AstNode>(node: &SyntaxNode) -> impl Iterator<Item = N> {
+    node.children().filter_map(N::cast)
+}
+
+/// The identifier after the keyword introducing a declaration
+fn declared_name(node: &SyntaxNode) -> Option<SyntaxToken> {
+    let tokens = node.code_tokens();
+    let keyword = tokens.iter().position(|t| {
+        matches!(
+            t.kind(),
+            TokenKind::Fn
+                | TokenKind::Struct
+                | TokenKind::Enum
+                | TokenKind::Trait
+                | TokenKind::Effect
+                | TokenKind::AiModel
+                | TokenKind::Prompt
+        )
+    })?;
+    tokens.into_iter().nth(keyword + 1).filter(|t| *t.kind() == TokenKind::Ident)
+}
+
+impl SourceFile {
+    pub fn items(&self) -> impl Iterator<Item = Item> {
+        children(&self.0)
+    }
+}
+
+impl Item {
+    pub fn kind(&self) -> &SyntaxKind {
+        self.0.kind()
+    }
+
+    pub fn name(&self) -> Option<SyntaxToken> {
+        declared_name(&self.0)
+    }
+
+    pub fn is_public(&self) -> bool {
+        self.0.tokens().any(|t| *t.kind() == TokenKind::Pub)
+    }
+
+    /// Comments on the lines right before the declaration
+    pub fn leading_comments(&self) -> Vec<SyntaxToken> {
+        self.0.leading_comments()
+    }
+}
+
+impl FnItem {
+    pub fn name(&self) -> Option<SyntaxToken> {
+        declared_name(&self.0)
+    }
+
+    pub fn body(&self) -> Option<BlockNode> {
+        children(&self.0).next()
+    }
+
+    pub fn leading_comments(&self) -> Vec<SyntaxToken> {
+        self.0.leading_comments()
+    }
+}
+
+impl StructItem {
+    pub fn name(&self) -> Option<SyntaxToken> {
+        declared_name(&self.0)
+    }
+
+    pub fn fields(&self) -> impl Iterator<Item = FieldNode> {
+        children(&self.0)
+    }
+}
+
+impl ImplItem {
+    pub fn methods(&self) -> impl Iterator<Item = FnItem> {
+        children(&self.0)
+    }
+}
+
+impl FieldNode {
+    pub fn name(&self) -> Option<SyntaxToken> {
+        self.0.code_tokens().into_iter().next().filter(|t| *t.kind() == TokenKind::Ident)
+    }
+
+    pub fn leading_comments(&self) -> Vec<SyntaxToken> {
+        self.0.leading_comments()
+    }
+}
+
+impl BlockNode {
+    pub fn statements(&self) -> impl Iterator<Item = StmtNode> {
+        children(&self.0)
+    }
+}
+
+impl StmtNode {
+    pub fn leading_comments(&self) -> Vec<SyntaxToken> {
+        self.0.leading_comments()
+    }
+
+    /// The blocks of the statement, such as the branches of an `if`
+    pub fn blocks(&self) -> Vec<BlockNode> {
+        self.0.descendants().into_iter().skip(1).filter_map(BlockNode::cast).collect()
+    }
+}
+
+// ============================================
+// Parsing
+// ============================================
+
+/// A parsed file: its syntax tree, with the AST and errors of the same parse
+#[derive(Debug, Clone)]
+pub struct SyntaxTree {
+    green: Arc<GreenNode>,
+    pub program: Program,
+    pub errors: Vec<ParseError>,
+}
+
+impl SyntaxTree {
+    pub fn syntax(&self) -> SyntaxNode {
+        SyntaxNode::new_root(self.green.clone())
+    }
+
+    pub fn source_file(&self) -> SourceFile {
+        SourceFile(self.syntax())
+    }
+}
+
+/// Parse `source` into a lossless syntax tree, recovering from errors as
+/// `Parser::parse_with_errors` does
+pub fn parse(source: &str) -> SyntaxTree {
+    let mut tokens = Lexer::new(source).with_trivia().tokenize();
+    let eof = tokens.pop();
+
+    // The parser sees the tokens that are not trivia
+    let significant: Vec<usize> = (0..tokens.len()).filter(|&i| !tokens[i].kind.is_trivia()).collect();
+    let mut parser = Parser::new(significant.iter().map(|&i| tokens[i].clone()).chain(eof).collect());
+    let (program, errors) = parser.parse_with_errors();
+
+    // Token ranges of the nodes, outermost first among nodes starting together
+    let mut nodes: Vec<(usize, SyntaxKind, Range<usize>)> = parser
+        .take_nodes()
+        .into_iter()
+        .enumerate()
+        .filter(|(_, (_, range))| !range.is_empty() && range.end <= significant.len())
+        .map(|(index, (kind, range))| {
+            let start = significant[range.start];
+            let start = if kind.takes_comments() { comments_before(&tokens, start) } else { start };
+            (index, kind, start..significant[range.end - 1] + 1)
+        })
+        .collect();
+    nodes.sort_by(|a, b| a.2.start.cmp(&b.2.start).then(b.2.end.cmp(&a.2.end)).then(b.0.cmp(&a.0)));
+
+    let mut builder = Builder {
+        source,
+        tokens: &tokens,
+        nodes: nodes.into_iter().map(|(_, kind, range)| (kind, range)).peekable(),
+    };
+    let green = builder.node(SyntaxKind::SourceFile, 0..tokens.len());
+    SyntaxTree { green, program, errors }
+}
+
+/// Where a node starting at token `start` begins once it takes the comments
+/// on the lines right before it: comments that start their line, with no
+/// blank line between them and the node
+fn comments_before(tokens: &[Token], start: usize) -> usize {
+    let mut first = start;
+    let mut i = start;
+    while i > 0 {
+        let token = &tokens[i - 1];
+        match token.kind {
+            TokenKind::Whitespace if token.literal.matches('\n').count() < 2 => i -= 1,
+            TokenKind::LineComment | TokenKind::BlockComment
+                if i == 1 || (tokens[i - 2].kind == TokenKind::Whitespace && tokens[i - 2].literal.contains('\n')) =>
+            {
+                i -= 1;
+                first = i;
+            }
+            _ => break,
+        }
+    }
+    first
+}
+
+struct Builder<'a, I: Iterator<Item = (SyntaxKind, Range<usize>)>> {
+    source: &'a str,
+    tokens: &'a [Token],
+    /// Nodes by start, outermost first
+    nodes: Peekable<I>,
+}
+
+impl<I: Iterator<Item = (SyntaxKind, Range<usize>)>> Builder<'_, I> {
+    /// The node of `kind` over the tokens in `range`
+    fn node(&mut self, kind: SyntaxKind, range: Range<usize>) -> Arc<GreenNode> {
+        let mut children = Vec::new();
+        let mut i = range.start;
+        while i < range.end {
+            match self.nodes.peek() {
+                // A node crossing the boundary of one built already
+                Some((_, next)) if next.start < i || (next.start == i && next.end > range.end) => {
+                    self.nodes.next();
+                }
+                Some((_, next)) if next.start == i => {
+                    let Some((kind, next)) = self.nodes.next() else { break };
+                    i = next.end;
+                    children.push(GreenElement::Node(self.node(kind, next)));
+                }
+                _ => {
+                    let token = &self.tokens[i];
+                    let text = &self.source[token.span.start..token.span.end];
+                    children.push(GreenElement::Token(Arc::new(GreenToken::new(token.kind.clone(), text))));
+                    i += 1;
+                }
+            }
+        }
+        Arc::new(GreenNode::new(kind, children))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = r#"// Geometry helpers
+use std::math;
+
+/* A point */
+#[derive(Debug)]
+pub struct Point {
+    // Horizontal
+    x: Int,
+    y: Int, // trailing
+}
+
+impl Point {
+    /// Squared length
+    fn norm(self) -> Int { return self.x * self.x + self.y * self.y; }
+}
+
+// Entry point
+
+fn main() {
+    // The origin
+    let p = Point { x: 0, y: 0 };
+    if p.x == 0 {
+        println("héllo (* not a comment *)");
+    }
+}
+"#;
+
+    #[test]
+    fn test_round_trip() {
+        let tree = parse(SOURCE);
+        assert!(tree.errors.is_empty(), "{:?}", tree.errors);
+        assert_eq!(tree.syntax().to_string(), SOURCE);
+        assert_eq!(tree.syntax().text_range(), 0..SOURCE.len());
+        for token in tree.syntax().descendant_tokens() {
+            assert_eq!(&SOURCE[token.text_range()], token.text());
+        }
+        for source in [include_str!("../../../examples/hello.ml"), include_str!("../../../examples/demo.ml"), "", "  \n", "fn f( { let = ; }}"] {
+            assert_eq!(parse(source).syntax().to_string(), source);
+        }
+    }
+
+    #[test]
+    fn test_typed_nodes_and_comments() {
+        let tree = parse(SOURCE);
+        let items: Vec<Item> = tree.source_file().items().collect();
+        let kinds: Vec<&SyntaxKind> = items.iter().map(Item::kind).collect();
+        assert_eq!(kinds, vec![&SyntaxKind::Import, &SyntaxKind::Struct, &SyntaxKind::Impl, &SyntaxKind::Function]);
+        assert_eq!(items.len(), tree.program.items.len());
+
+        // The first comment starts the file and the last is cut off by a blank line
+        let comments = |item: &Item| item.leading_comments().iter().map(|c| c.text().to_string()).collect::<Vec<_>>();
+        assert_eq!(comments(&items[0]), vec!["// Geometry helpers"]);
+        assert_eq!(comments(&items[1]), vec!["/* A point */"]);
+        assert!(comments(&items[3]).is_empty());
+        assert!(items[1].syntax().text().starts_with("/* A point */\n#[derive(Debug)]\npub struct"));
+        assert!(items[1].is_public() && !items[3].is_public());
+
+        let point = StructItem::cast(items[1].syntax().clone()).unwrap();
+        assert_eq!(point.name().unwrap().text(), "Point");
+        let fields: Vec<FieldNode> = point.fields().collect();
+        assert_eq!(fields.iter().map(|f| f.name().unwrap().text().to_string()).collect::<Vec<_>>(), vec!["x", "y"]);
+        assert_eq!(fields[0].leading_comments()[0].text(), "// Horizontal");
+        // A trailing comment stays with the enclosing node
+        assert!(fields[1].leading_comments().is_empty());
+        assert_eq!(fields[1].syntax().parent().map(|p| p.kind()), Some(&SyntaxKind::Struct));
+
+        let method = ImplItem::cast(items[2].syntax().clone()).unwrap().methods().next().unwrap();
+        assert_eq!(method.name().unwrap().text(), "norm");
+        assert_eq!(method.leading_comments()[0].text(), "/// Squared length");
+
+        let main = FnItem::cast(items[3].syntax().clone()).unwrap();
+        let stmts: Vec<StmtNode> = main.body().unwrap().statements().collect();
+        assert_eq!(stmts.len(), 2);
+        assert_eq!(stmts[0].leading_comments()[0].text(), "// The origin");
+        assert_eq!(stmts[1].blocks().len(), 1);
+
+        let offset = SOURCE.find("héllo").unwrap();
+        let token = tree.syntax().token_at_offset(offset).unwrap();
+        assert_eq!(*token.kind(), TokenKind::StringLit);
+        assert_eq!(token.parent().kind(), &SyntaxKind::Stmt);
+    }
+
+    #[test]
+    fn test_error_nodes() {
+        let source = "fn a() {\n    let x = ;\n    let y = 1;\n}\nstruct { }\nfn b() { }\n";
+        let tree = parse(source);
+        assert_eq!(tree.errors.len(), 2);
+        assert_eq!(tree.syntax().to_string(), source);
+        let kinds: Vec<SyntaxKind> = tree.source_file().items().map(|i| i.kind().clone()).collect();
+        assert_eq!(kinds, vec![SyntaxKind::Function, SyntaxKind::Error, SyntaxKind::Function]);
+        let a = FnItem::cast(tree.source_file().items().next().unwrap().syntax().clone()).unwrap();
+        let stmts: Vec<SyntaxKind> = a.body().unwrap().statements().map(|s| s.syntax().kind().clone()).collect();
+        assert_eq!(stmts, vec![SyntaxKind::Error, SyntaxKind::Stmt]);
+    }
+}
@@ -0,0 +1,286 @@
+//! Effect-row inference
+//!
+//! The row of a function is the set of effects its body may perform without
+//! handling them. A `perform` adds its effect to the row, a call adds the row
+//! of its callee, and `handle { body } with E { .. }` removes `E` from the row
+//! of its body. Handler clauses run outside their handler, so whatever they
+//! perform is kept, even `E` itself. Rows of recursive functions are found as
+//! a least fixpoint, starting from empty rows.
+//!
+//! Besides the effects a program declares, rows hold the built-in effects of
+//! the runtime: `AI` for AI expressions and statements, and `IO`, `Random` and
+//! `Time` for the stdlib natives that have side effects. These cannot be
+//! handled; they are performed by the runtime itself.
+//!
+//! The analysis is syntactic and runs before type checking, so it is
+//! conservative where types would be needed: a lambda's effects are charged to
+//! the function that creates it, and a method call performs the effects of
+//! every method of that name.
+
+use crate::ast::*;
+use crate::token::Span;
+use crate::types::ast_type_to_ty;
+use std::collections::{BTreeMap, HashMap};
+
+/// Effects a function may perform, each with the span of the first
+/// `perform` or call that performs it
+pub type EffectRow = BTreeMap<String, Span>;
+
+/// Effects performed by the runtime rather than declared with `effect`
+pub const BUILTIN_EFFECTS: &[&str] = &["AI", "IO", "Random", "Time"];
+
+pub fn is_builtin(effect: &str) -> bool {
+    BUILTIN_EFFECTS.contains(&effect)
+}
+
+/// Infer the effect row of every function and method in the program.
+/// Methods are keyed `Type::method`.
+pub fn infer(program: &Program) -> HashMap<String, EffectRow> {
+    let mut functions: Vec<(String, &FnDecl)> = Vec::new();
+    let mut methods: HashMap<String, Vec<String>> = HashMap::new();
+    for item in &program.items {
+        match item {
+            TopLevel::Function(f) => functions.push((f.name.name.clone(), f)),
+            TopLevel::Impl(i) => {
+                let Some(ty) = ast_type_to_ty(&i.self_ty).impl_key() else { continue };
+                for m in &i.methods {
+                    let key = format!("{}::{}", ty, m.name.name);
+                    methods.entry(m.name.name.clone()).or_default().push(key.clone());
+                    functions.push((key, m));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let mut rows: HashMap<String, EffectRow> =
+        functions.iter().map(|(key, _)| (key.clone(), EffectRow::new())).collect();
+    loop {
+        let mut changed = false;
+        for (key, f) in &functions {
+            let mut scan = Scan { rows: &rows, methods: &methods, row: EffectRow::new() };
+            scan.function(f);
+            let row = scan.row;
+            if row.len() != rows[key].len() {
+                rows.insert(key.clone(), row);
+                changed = true;
+            }
+        }
+        if !changed {
+            return rows;
+        }
+    }
+}
+
+/// Collects the row of one function body, given the current rows of all functions
+struct Scan<'a> {
+    rows: &'a HashMap<String, EffectRow>,
+    methods: &'a HashMap<String, Vec<String>>,
+    row: EffectRow,
+}
+
+impl Scan<'_> {
+    fn function(&mut self, f: &FnDecl) {
+        for clause in f.contract.iter().flat_map(|c| &c.clauses) {
+            if let ContractClause::Pre(e, _) | ContractClause::Post(e, _) | ContractClause::Invariant(e, _) = clause {
+                self.expr(e);
+            }
+        }
+        self.block(&f.body);
+    }
+
+    fn add(&mut self, effect: &str, span: Span) {
+        self.row.entry(effect.to_string()).or_insert(span);
+    }
+
+    /// Add the row of the function keyed `key`, attributing it to the call at `span`
+    fn call(&mut self, key: &str, span: Span) {
+        if let Some(row) = self.rows.get(key) {
+            for effect in row.keys() {
+                self.row.entry(effect.clone()).or_insert(span);
+            }
+        } else if let Some(effect) = crate::stdlib::native_effect(key) {
+            self.add(effect, span);
+        }
+    }
+
+    fn block(&mut self, block: &Block) {
+        for stmt in &block.stmts {
+            self.stmt(stmt);
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Expr(e) | Stmt::Let { value: e, .. } | Stmt::Await { value: e, .. } | Stmt::Try { value: e, .. } => {
+                self.expr(e)
+            }
+            Stmt::If { condition, then_block, else_block, .. } => {
+                self.expr(condition);
+                self.block(then_block);
+                if let Some(block) = else_block {
+                    self.block(block);
+                }
+            }
+            Stmt::While { condition: e, body, .. } | Stmt::For { iterable: e, body, .. } => {
+                self.expr(e);
+                self.block(body);
+            }
+            Stmt::Break { value, .. } | Stmt::Return { value, .. } => {
+                if let Some(value) = value {
+                    self.expr(value);
+                }
+            }
+            Stmt::Go { block, .. } | Stmt::Comptime { block, .. } => self.block(block),
+            Stmt::Continue { .. } | Stmt::Error(_) => {}
+            Stmt::Ai(ai) => {
+                self.add("AI", ai.span);
+                match &ai.body {
+                    AiStmtBody::Block(block) => self.block(block),
+                    AiStmtBody::Expr(e) => self.expr(e),
+                }
+            }
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Literal(_) | Expr::Ident(_) | Expr::Path { .. } => {}
+            Expr::Interpolation { parts, .. } => parts.iter().filter_map(StringPart::expr).for_each(|e| self.expr(e)),
+            Expr::Perform { effect, args, .. } => {
+                self.add(&effect.name, effect.span);
+                args.iter().for_each(|arg| self.expr(arg));
+            }
+            Expr::Handle { body, effect, clauses, .. } => {
+                let mut inner = Scan { rows: self.rows, methods: self.methods, row: EffectRow::new() };
+                inner.block(body);
+                inner.row.remove(&effect.name);
+                for (name, span) in inner.row {
+                    self.add(&name, span);
+                }
+                clauses.iter().for_each(|clause| self.expr(&clause.body));
+            }
+            Expr::Resume { value, .. } => self.expr(value),
+            Expr::Call { callee, args, .. } => {
+                match &**callee {
+                    Expr::Ident(name) => self.call(&name.name, name.span),
+                    Expr::Path { ty, name, span } => self.call(&format!("{}::{}", ty.name, name.name), *span),
+                    _ => {}
+                }
+                self.expr(callee);
+                args.iter().for_each(|arg| self.expr(arg));
+            }
+            Expr::MethodCall { receiver, method, args, .. } => {
+                for key in self.methods.get(&method.name).into_iter().flatten() {
+                    self.call(key, method.span);
+                }
+                self.expr(receiver);
+                args.iter().for_each(|arg| self.expr(arg));
+            }
+            Expr::Field { object: e, .. }
+            | Expr::Restrict { operand: e, .. }
+            | Expr::Unary { operand: e, .. }
+            | Expr::Try { operand: e, .. } => self.expr(e),
+            Expr::Index { object: a, index: b, .. }
+            | Expr::Binary { left: a, right: b, .. }
+            | Expr::Assign { target: a, value: b, .. } => {
+                self.expr(a);
+                self.expr(b);
+            }
+            Expr::Slice { object, start, end, .. } => {
+                self.expr(object);
+                for bound in [start, end].into_iter().flatten() {
+                    self.expr(bound);
+                }
+            }
+            Expr::StructLit { fields, .. } | Expr::Record { fields, .. } => {
+                fields.iter().for_each(|field| self.expr(&field.value));
+            }
+            Expr::Array { elements, .. } | Expr::Tuple { elements, .. } => {
+                elements.iter().for_each(|e| self.expr(e));
+            }
+            Expr::Block(block) | Expr::Loop { body: block, .. } => self.block(block),
+            Expr::Match { scrutinee, arms, .. } => {
+                self.expr(scrutinee);
+                for arm in arms {
+                    if let Some(guard) = &arm.guard {
+                        self.expr(guard);
+                    }
+                    self.expr(&arm.body);
+                }
+            }
+            Expr::Lambda { body, .. } => match body {
+                LambdaBody::Expr(e) => self.expr(e),
+                LambdaBody::Block(block) => self.block(block),
+            },
+            Expr::Ai(ai) => {
+                self.add("AI", ai.span());
+                match ai {
+                    AiExpr::Block { body, .. } => {
+                        for item in body {
+                            if let AiBodyItem::Field { value, .. } = item {
+                                self.expr(value);
+                            }
+                        }
+                    }
+                    AiExpr::Call { args, .. } => args.iter().for_each(|arg| self.expr(arg)),
+                    AiExpr::PromptInvocation { args, .. } => args.iter().for_each(|arg| self.expr(&arg.value)),
+                    AiExpr::Quick { .. } => {}
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+
+    fn rows(source: &str) -> HashMap<String, Vec<String>> {
+        infer(&parse(source).unwrap())
+            .into_iter()
+            .map(|(name, row)| (name, row.into_keys().collect()))
+            .collect()
+    }
+
+    #[test]
+    fn test_rows_through_calls_and_handlers() {
+        let rows = rows(r#"
+            effect Logger { op log: String }
+            effect State { op get: () -> Int }
+
+            fn log_twice(msg: String) {
+                perform Logger.log(msg);
+                perform Logger.log(msg);
+            }
+            fn count(n: Int) -> Int {
+                if n > 0 { log_twice("tick"); count(n - 1); }
+                return perform State.get();
+            }
+            fn quiet() -> Int {
+                return handle { count(3); } with Logger { log(m) => resume(()), };
+            }
+            fn pure(x: Int) -> Int { return x + 1; }
+        "#);
+        assert_eq!(rows["log_twice"], vec!["Logger"]);
+        assert_eq!(rows["count"], vec!["Logger", "State"]);
+        assert_eq!(rows["quiet"], vec!["State"]);
+        assert!(rows["pure"].is_empty());
+    }
+
+    #[test]
+    fn test_builtin_effects() {
+        let rows = rows(r#"
+            fn ask() -> AI<String> { return ai! { "hello" }; }
+            fn roll() -> Int { return random_int(1, 6); }
+            fn report() { println(roll()); ask(); sleep(10); }
+            fn println(s: String) { }
+            fn shadowed() { println("quiet"); }
+        "#);
+        assert_eq!(rows["ask"], vec!["AI"]);
+        assert_eq!(rows["roll"], vec!["Random"]);
+        assert_eq!(rows["report"], vec!["AI", "Random", "Time"]);
+        assert!(rows["shadowed"].is_empty());
+    }
+}
@@ -0,0 +1,476 @@
+//! Exhaustiveness and redundancy checking for `match`
+//!
+//! Implements the usefulness algorithm from Maranget, "Warnings for pattern
+//! matching" (2007). A pattern vector is *useful* with respect to a matrix of
+//! earlier rows if some value matches it but none of the rows. A match is
+//! exhaustive when a wildcard is not useful after all of its arms, and an arm
+//! is unreachable when it is not useful after the arms above it.
+//!
+//! Usefulness is computed constructively: when a vector is useful, the search
+//! returns a witness, a pattern describing a value that only it matches.
+//!
+//! Integer ranges are split at the boundaries of the ranges they are tested
+//! against, and array patterns with a `..` are expanded to the array lengths
+//! that can tell the arms apart, following the approach rustc takes.
+
+use crate::ast::{Literal, MatchArm, Pattern};
+use crate::scope::{TypeEnv, VariantShape};
+use crate::types::Ty;
+
+/// The head of a deconstructed pattern
+#[derive(Debug, Clone, PartialEq)]
+enum Ctor {
+    Bool(bool),
+    /// Inclusive integer range; a literal is a range of one value
+    Int(i64, i64),
+    /// Compared by bit pattern so that `Ctor` can be `PartialEq`
+    Float(u64),
+    Str(String),
+    Variant(String),
+    Struct(String),
+    /// A tuple of this many elements
+    Tuple(usize),
+    /// A record, with one field for each name in `Cx::record_fields`
+    Record,
+    /// Arrays of exactly this length
+    Array(usize),
+    /// Arrays of at least this length
+    ArrayFrom(usize),
+    /// Values the analysis does not model, such as float ranges: it covers
+    /// nothing and is covered only by wildcards
+    Opaque,
+}
+
+/// A pattern reduced to constructors applied to sub-patterns
+#[derive(Debug, Clone)]
+enum Pat {
+    Wild,
+    Ctor(Ctor, Vec<Pat>),
+    Or(Vec<Pat>),
+    /// Array pattern with a `..`: the prefix and suffix around it
+    VarArray(Vec<Pat>, Vec<Pat>),
+}
+
+/// The outcome of checking the arms of one `match`
+#[derive(Debug, Default, PartialEq)]
+pub struct MatchReport {
+    /// A value no arm covers, rendered as a pattern
+    pub missing: Option<String>,
+    /// Indices of arms that can never match
+    pub unreachable: Vec<usize>,
+}
+
+/// Check the arms of a `match` on a value of type `scrutinee`, in order.
+/// Guarded arms can be unreachable but never count towards covering the
+/// scrutinee. A `match` without arms covers only an enum without variants.
+pub fn check_match(types: &TypeEnv, scrutinee: &Ty, arms: &[MatchArm]) -> MatchReport {
+    let uninhabited = match scrutinee {
+        Ty::Named(name) | Ty::Generic { name, .. } => types.get_enum(name).is_some_and(|e| e.variants.is_empty()),
+        _ => false,
+    };
+    if arms.is_empty() && uninhabited {
+        return MatchReport::default();
+    }
+
+    let cx = Cx::new(types, arms.iter().map(|arm| &arm.pattern));
+
+    let mut report = MatchReport::default();
+    let mut rows: Vec<Vec<Pat>> = Vec::new();
+    for (i, arm) in arms.iter().enumerate() {
+        let row = vec![cx.lower(&arm.pattern)];
+        if cx.is_useful(&rows, &row).is_none() {
+            report.unreachable.push(i);
+        }
+        if arm.guard.is_none() {
+            rows.push(row);
+        }
+    }
+    report.missing = cx.is_useful(&rows, &[Pat::Wild]).map(|w| cx.render(&w[0]));
+    report
+}
+
+/// Check that the pattern of a `let` is irrefutable, returning a value it
+/// does not match
+pub fn check_let(types: &TypeEnv, pattern: &Pattern) -> Option<String> {
+    let cx = Cx::new(types, std::iter::once(pattern));
+    let rows = vec![vec![cx.lower(pattern)]];
+    cx.is_useful(&rows, &[Pat::Wild]).map(|w| cx.render(&w[0]))
+}
+
+struct Cx<'a> {
+    types: &'a TypeEnv,
+    /// Every field name used in a record pattern of this match
+    record_fields: Vec<String>,
+    /// The longest array pattern, counting only the fixed elements
+    max_array_len: usize,
+}
+
+impl<'a> Cx<'a> {
+    fn new(types: &'a TypeEnv, patterns: impl Iterator<Item = &'a Pattern>) -> Self {
+        let mut cx = Cx {
+            types,
+            record_fields: Vec::new(),
+            max_array_len: 0,
+        };
+        patterns.for_each(|p| cx.survey(p));
+        cx.record_fields.sort();
+        cx.record_fields.dedup();
+        cx
+    }
+
+    /// Collect the record fields and array lengths the arms mention
+    fn survey(&mut self, pattern: &Pattern) {
+        match pattern {
+            Pattern::Literal(_) | Pattern::Ident(_) | Pattern::Wildcard(_) | Pattern::Range { .. } => {}
+            Pattern::Constructor { args: elements, .. } | Pattern::Tuple(elements, _) => {
+                elements.iter().for_each(|p| self.survey(p))
+            }
+            Pattern::Struct { fields, .. } => fields.iter().for_each(|(_, p)| self.survey(p)),
+            Pattern::Record { fields, .. } => {
+                for (field, p) in fields {
+                    self.record_fields.push(field.name.clone());
+                    self.survey(p);
+                }
+            }
+            Pattern::Slice { prefix, rest, suffix, .. } => {
+                self.max_array_len = self.max_array_len.max(prefix.len() + suffix.len());
+                prefix.iter().chain(rest.as_deref()).chain(suffix).for_each(|p| self.survey(p));
+            }
+            Pattern::Or(alternatives, _) => alternatives.iter().for_each(|p| self.survey(p)),
+            Pattern::Binding { pattern, .. } => self.survey(pattern),
+        }
+    }
+
+    /// Deconstruct a surface pattern. Names that resolve to nothing are treated
+    /// as wildcards; the type checker reports them separately.
+    fn lower(&self, pattern: &Pattern) -> Pat {
+        match pattern {
+            Pattern::Literal(lit) => Pat::Ctor(
+                match lit {
+                    Literal::Bool(b, _) => Ctor::Bool(*b),
+                    Literal::Int(n, _) => Ctor::Int(*n, *n),
+                    Literal::Float(f, _) => Ctor::Float(f.to_bits()),
+                    Literal::String(s, _) => Ctor::Str(s.clone()),
+                },
+                vec![],
+            ),
+            Pattern::Ident(ident) => match self.types.get_variant(&ident.name) {
+                Some((_, variant)) if variant.shape == VariantShape::Unit => {
+                    Pat::Ctor(Ctor::Variant(ident.name.clone()), vec![])
+                }
+                _ => Pat::Wild,
+            },
+            Pattern::Wildcard(_) => Pat::Wild,
+            Pattern::Constructor { name, args, .. } => {
+                let ctor = if self.types.get_variant(&name.name).is_some() {
+                    Ctor::Variant(name.name.clone())
+                } else if self.types.get_struct(&name.name).is_some() {
+                    Ctor::Struct(name.name.clone())
+                } else {
+                    return Pat::Wild;
+                };
+                let mut fields: Vec<Pat> = args.iter().map(|a| self.lower(a)).collect();
+                fields.resize(self.arity(&ctor), Pat::Wild);
+                Pat::Ctor(ctor, fields)
+            }
+            Pattern::Struct { name, fields, .. } => {
+                let (ctor, names): (Ctor, Vec<&String>) = if let Some((_, v)) = self.types.get_variant(&name.name) {
+                    (Ctor::Variant(name.name.clone()), v.fields.iter().map(|(f, _)| f).collect())
+                } else if let Some(s) = self.types.get_struct(&name.name) {
+                    (Ctor::Struct(name.name.clone()), s.fields.iter().map(|(f, _)| f).collect())
+                } else {
+                    return Pat::Wild;
+                };
+                Pat::Ctor(ctor, self.lower_fields(&names, fields))
+            }
+            Pattern::Tuple(elements, _) => {
+                Pat::Ctor(Ctor::Tuple(elements.len()), elements.iter().map(|p| self.lower(p)).collect())
+            }
+            Pattern::Record { fields, .. } => {
+                let names: Vec<&String> = self.record_fields.iter().collect();
+                Pat::Ctor(Ctor::Record, self.lower_fields(&names, fields))
+            }
+            Pattern::Slice { prefix, rest, suffix, .. } => {
+                let prefix: Vec<Pat> = prefix.iter().map(|p| self.lower(p)).collect();
+                match rest {
+                    Some(_) => Pat::VarArray(prefix, suffix.iter().map(|p| self.lower(p)).collect()),
+                    None => Pat::Ctor(Ctor::Array(prefix.len()), prefix),
+                }
+            }
+            Pattern::Range { start, end, inclusive, .. } => {
+                let ctor = match (start, end) {
+                    (Literal::Int(lo, _), Literal::Int(hi, _)) => {
+                        let hi = if *inclusive { Some(*hi) } else { hi.checked_sub(1) };
+                        match hi {
+                            Some(hi) if *lo <= hi => Ctor::Int(*lo, hi),
+                            _ => Ctor::Opaque,
+                        }
+                    }
+                    _ => Ctor::Opaque,
+                };
+                Pat::Ctor(ctor, vec![])
+            }
+            Pattern::Or(alternatives, _) => Pat::Or(alternatives.iter().map(|p| self.lower(p)).collect()),
+            Pattern::Binding { pattern, .. } => self.lower(pattern),
+        }
+    }
+
+    /// Place named field patterns at the positions of `names`; the rest are wildcards
+    fn lower_fields(&self, names: &[&String], fields: &[(crate::ast::Ident, Pattern)]) -> Vec<Pat> {
+        let mut lowered = vec![Pat::Wild; names.len()];
+        for (field, pattern) in fields {
+            if let Some(i) = names.iter().position(|n| **n == field.name) {
+                lowered[i] = self.lower(pattern);
+            }
+        }
+        lowered
+    }
+
+    fn arity(&self, ctor: &Ctor) -> usize {
+        match ctor {
+            Ctor::Variant(name) => self.types.get_variant(name).map_or(0, |(_, v)| v.fields.len()),
+            Ctor::Struct(name) => self.types.get_struct(name).map_or(0, |s| s.fields.len()),
+            Ctor::Record => self.record_fields.len(),
+            Ctor::Tuple(n) | Ctor::Array(n) | Ctor::ArrayFrom(n) => *n,
+            _ => 0,
+        }
+    }
+
+    /// Every constructor of the type the column's patterns belong to, or
+    /// `None` when there are too many to enumerate (numbers and strings)
+    fn all_ctors(&self, head: &Pat) -> Option<Vec<Ctor>> {
+        let ctor = match head {
+            Pat::Ctor(ctor, _) => ctor,
+            Pat::VarArray(..) => &Ctor::Array(0),
+            Pat::Wild | Pat::Or(_) => return None,
+        };
+        match ctor {
+            Ctor::Bool(_) => Some(vec![Ctor::Bool(true), Ctor::Bool(false)]),
+            Ctor::Variant(name) => {
+                let (enum_def, _) = self.types.get_variant(name)?;
+                Some(enum_def.variants.iter().map(|v| Ctor::Variant(v.name.clone())).collect())
+            }
+            Ctor::Struct(name) => Some(vec![Ctor::Struct(name.clone())]),
+            Ctor::Tuple(n) => Some(vec![Ctor::Tuple(*n)]),
+            Ctor::Record => Some(vec![Ctor::Record]),
+            // Lengths beyond the longest pattern cannot tell the arms apart
+            Ctor::Array(_) | Ctor::ArrayFrom(_) => Some(
+                (0..=self.max_array_len)
+                    .map(Ctor::Array)
+                    .chain(std::iter::once(Ctor::ArrayFrom(self.max_array_len + 1)))
+                    .collect(),
+            ),
+            Ctor::Int(..) | Ctor::Float(_) | Ctor::Str(_) | Ctor::Opaque => None,
+        }
+    }
+
+    /// Whether a row headed by `head` matches every value built with `ctor`
+    fn covers(&self, head: &Pat, ctor: &Ctor) -> bool {
+        match (head, ctor) {
+            (Pat::Wild, _) => true,
+            (Pat::Ctor(Ctor::Opaque, _), _) | (_, Ctor::Opaque) => false,
+            (Pat::Ctor(Ctor::Int(lo, hi), _), Ctor::Int(a, b)) => lo <= a && b <= hi,
+            (Pat::Ctor(c, _), _) => c == ctor,
+            (Pat::VarArray(prefix, suffix), Ctor::Array(n) | Ctor::ArrayFrom(n)) => prefix.len() + suffix.len() <= *n,
+            (Pat::VarArray(..), _) => false,
+            (Pat::Or(alternatives), _) => alternatives.iter().any(|p| self.covers(p, ctor)),
+        }
+    }
+
+    /// Return a witness if some value matches `v` but no row of `matrix`
+    fn is_useful(&self, matrix: &[Vec<Pat>], v: &[Pat]) -> Option<Vec<Pat>> {
+        let Some((head, rest)) = v.split_first() else {
+            return matrix.is_empty().then(Vec::new);
+        };
+        let matrix = expand_or(matrix);
+
+        match head {
+            Pat::Or(alternatives) => alternatives.iter().find_map(|alt| {
+                let v: Vec<Pat> = std::iter::once(alt.clone()).chain(rest.iter().cloned()).collect();
+                self.is_useful(&matrix, &v)
+            }),
+            Pat::Ctor(Ctor::Int(lo, hi), _) => {
+                // Each piece is either inside or outside every range in the column
+                split_range(*lo, *hi, &matrix).into_iter().find_map(|(a, b)| {
+                    self.specialize_useful(&matrix, &Ctor::Int(a, b), head, rest)
+                })
+            }
+            // Only wildcards are known to cover an opaque value
+            Pat::Ctor(Ctor::Opaque, _) => {
+                let mut witness = self.is_useful(&default_matrix(&matrix), rest)?;
+                witness.insert(0, Pat::Wild);
+                Some(witness)
+            }
+            Pat::Ctor(ctor, _) => self.specialize_useful(&matrix, ctor, head, rest),
+            Pat::VarArray(..) => self
+                .all_ctors(head)
+                .unwrap_or_default()
+                .iter()
+                .filter(|ctor| self.covers(head, ctor))
+                .find_map(|ctor| self.specialize_useful(&matrix, ctor, head, rest)),
+            Pat::Wild => {
+                let heads: Vec<&Pat> = matrix.iter().map(|row| &row[0]).filter(|p| !matches!(p, Pat::Wild)).collect();
+                let all = heads.first().and_then(|h| self.all_ctors(h));
+                let missing: Vec<&Ctor> = all
+                    .iter()
+                    .flatten()
+                    .filter(|c| !heads.iter().any(|h| self.covers(h, c)))
+                    .collect();
+
+                // With every constructor present, try each of them in turn
+                if let (Some(all), true) = (&all, missing.is_empty()) {
+                    return all.iter().find_map(|ctor| self.specialize_useful(&matrix, ctor, head, rest));
+                }
+
+                // Otherwise only the rows headed by a wildcard can cover the
+                // constructors that are absent from the column
+                let mut witness = self.is_useful(&default_matrix(&matrix), rest)?;
+                let head = match missing.first() {
+                    Some(ctor) => Pat::Ctor((*ctor).clone(), vec![Pat::Wild; self.arity(ctor)]),
+                    None => uncovered_int(&heads).map_or(Pat::Wild, |n| Pat::Ctor(Ctor::Int(n, n), vec![])),
+                };
+                witness.insert(0, head);
+                Some(witness)
+            }
+        }
+    }
+
+    /// Usefulness of `head :: rest` restricted to the values built with `ctor`
+    fn specialize_useful(&self, matrix: &[Vec<Pat>], ctor: &Ctor, head: &Pat, rest: &[Pat]) -> Option<Vec<Pat>> {
+        let specialized: Vec<Vec<Pat>> = matrix.iter().filter_map(|row| self.specialize_row(row, ctor)).collect();
+        let v = self.specialize_row(&[head.clone()].into_iter().chain(rest.iter().cloned()).collect::<Vec<_>>(), ctor)?;
+        self.is_useful(&specialized, &v).map(|w| self.rebuild(ctor, w))
+    }
+
+    /// Replace the head of a row with the fields of `ctor`, if it can match `ctor`
+    fn specialize_row(&self, row: &[Pat], ctor: &Ctor) -> Option<Vec<Pat>> {
+        let arity = self.arity(ctor);
+        let fields = match &row[0] {
+            Pat::Wild => vec![Pat::Wild; arity],
+            Pat::VarArray(prefix, suffix) if self.covers(&row[0], ctor) => {
+                let middle = vec![Pat::Wild; arity - prefix.len() - suffix.len()];
+                prefix.iter().cloned().chain(middle).chain(suffix.iter().cloned()).collect()
+            }
+            Pat::Ctor(Ctor::Int(..), _) if self.covers(&row[0], ctor) => vec![],
+            Pat::Ctor(c, args) if c == ctor && *c != Ctor::Opaque && !matches!(c, Ctor::Int(..)) => args.clone(),
+            _ => return None,
+        };
+        Some(fields.into_iter().chain(row[1..].iter().cloned()).collect())
+    }
+
+    /// Undo `specialize` on a witness: fold the leading fields back into `ctor`
+    fn rebuild(&self, ctor: &Ctor, mut witness: Vec<Pat>) -> Vec<Pat> {
+        let rest = witness.split_off(self.arity(ctor).min(witness.len()));
+        let mut rebuilt = vec![Pat::Ctor(ctor.clone(), witness)];
+        rebuilt.extend(rest);
+        rebuilt
+    }
+
+    fn render(&self, pat: &Pat) -> String {
+        let (ctor, args) = match pat {
+            Pat::Ctor(ctor, args) => (ctor, args),
+            Pat::Or(alternatives) => return alternatives.first().map_or("_".to_string(), |p| self.render(p)),
+            Pat::Wild | Pat::VarArray(..) => return "_".to_string(),
+        };
+        let args: Vec<String> = args.iter().map(|a| self.render(a)).collect();
+        let named = |names: Vec<&String>| -> Vec<String> {
+            names.iter().zip(&args).map(|(f, a)| format!("{}: {}", f, a)).collect()
+        };
+        match ctor {
+            Ctor::Bool(b) => b.to_string(),
+            Ctor::Int(n, _) => n.to_string(),
+            Ctor::Float(bits) => f64::from_bits(*bits).to_string(),
+            Ctor::Str(s) => format!("{:?}", s),
+            Ctor::Opaque => "_".to_string(),
+            Ctor::Struct(name) => match self.types.get_struct(name) {
+                Some(s) => format!("{} {{ {} }}", name, named(s.fields.iter().map(|(f, _)| f).collect()).join(", ")),
+                None => name.clone(),
+            },
+            Ctor::Record => {
+                let mut fields: Vec<String> = named(self.record_fields.iter().collect())
+                    .into_iter()
+                    .filter(|f| !f.ends_with(": _"))
+                    .collect();
+                fields.push("..".to_string());
+                format!("{{ {} }}", fields.join(", "))
+            }
+            Ctor::Tuple(1) => format!("({},)", args[0]),
+            Ctor::Tuple(_) => format!("({})", args.join(", ")),
+            Ctor::Array(_) => format!("[{}]", args.join(", ")),
+            Ctor::ArrayFrom(_) => {
+                format!("[{}]", args.into_iter().chain(std::iter::once("..".to_string())).collect::<Vec<_>>().join(", "))
+            }
+            Ctor::Variant(name) => match self.types.get_variant(name) {
+                Some((_, v)) if v.shape == VariantShape::Struct => {
+                    format!("{} {{ {} }}", name, named(v.fields.iter().map(|(f, _)| f).collect()).join(", "))
+                }
+                _ if args.is_empty() => name.clone(),
+                _ => format!("{}({})", name, args.join(", ")),
+            },
+        }
+    }
+}
+
+/// Replace each row headed by an or-pattern with one row per alternative
+fn expand_or(matrix: &[Vec<Pat>]) -> Vec<Vec<Pat>> {
+    let mut expanded = Vec::new();
+    for row in matrix {
+        match &row[0] {
+            Pat::Or(alternatives) => {
+                let rows: Vec<Vec<Pat>> = alternatives
+                    .iter()
+                    .map(|alt| std::iter::once(alt.clone()).chain(row[1..].iter().cloned()).collect())
+                    .collect();
+                expanded.extend(expand_or(&rows));
+            }
+            _ => expanded.push(row.clone()),
+        }
+    }
+    expanded
+}
+
+/// The rows headed by a wildcard, without their head
+fn default_matrix(matrix: &[Vec<Pat>]) -> Vec<Vec<Pat>> {
+    matrix
+        .iter()
+        .filter(|row| matches!(row[0], Pat::Wild))
+        .map(|row| row[1..].to_vec())
+        .collect()
+}
+
+/// Split `lo..=hi` so that each piece lies either inside or outside every
+/// integer range heading a row of `matrix`
+fn split_range(lo: i64, hi: i64, matrix: &[Vec<Pat>]) -> Vec<(i64, i64)> {
+    let mut cuts: Vec<i128> = vec![lo as i128, hi as i128 + 1];
+    for row in matrix {
+        if let Pat::Ctor(Ctor::Int(a, b), _) = &row[0] {
+            for cut in [*a as i128, *b as i128 + 1] {
+                if cut > lo as i128 && cut <= hi as i128 {
+                    cuts.push(cut);
+                }
+            }
+        }
+    }
+    cuts.sort_unstable();
+    cuts.dedup();
+    cuts.windows(2).map(|w| (w[0] as i64, (w[1] - 1) as i64)).collect()
+}
+
+/// An integer outside every range heading the column, preferring small
+/// non-negative ones
+fn uncovered_int(heads: &[&Pat]) -> Option<i64> {
+    let ranges: Vec<(i64, i64)> = heads
+        .iter()
+        .filter_map(|h| match h {
+            Pat::Ctor(Ctor::Int(lo, hi), _) => Some((*lo, *hi)),
+            _ => None,
+        })
+        .collect();
+    if ranges.is_empty() {
+        return None;
+    }
+    let candidates = std::iter::once(0).chain(ranges.iter().flat_map(|(lo, hi)| [hi.checked_add(1), lo.checked_sub(1)]).flatten());
+    let mut candidates: Vec<i64> = candidates.collect();
+    candidates.sort_by_key(|n| (*n < 0, n.unsigned_abs()));
+    candidates.into_iter().find(|n| !ranges.iter().any(|(lo, hi)| lo <= n && n <= hi))
+}
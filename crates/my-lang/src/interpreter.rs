@@ -4,11 +4,17 @@
 //! executes the AST without compilation to bytecode.
 
 use std::cell::RefCell;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
 use std::rc::Rc;
 
+use crate::ai::{AiBackend, AiModel, AiRequest, MockBackend};
 use crate::ast::*;
+use crate::constraints::{check_format, validation_request, Rule, ValidationError};
+use crate::library::mylang::prompt::{json_output, PromptTemplate};
+use crate::modules::{Import, ModuleGraph};
+use crate::structured::Shapes;
+use crate::token::{FileId, Span};
 use thiserror::Error;
 
 // ============================================================================
@@ -30,8 +36,20 @@ pub enum Value {
     Unit,
     /// Array value
     Array(Vec<Value>),
-    /// Record/struct value
+    /// Tuple value; the empty tuple is `Unit`
+    Tuple(Vec<Value>),
+    /// Record value
     Record(HashMap<String, Value>),
+    /// Struct value
+    Struct(StructValue),
+    /// Enum variant value
+    Variant(VariantValue),
+    /// Constructor function of a tuple-like enum variant
+    VariantConstructor {
+        enum_name: String,
+        variant: String,
+        arity: usize,
+    },
     /// Function value (closure)
     Function(Rc<FunctionValue>),
     /// Native/built-in function
@@ -49,12 +67,64 @@ impl PartialEq for Value {
             (Value::Bool(a), Value::Bool(b)) => a == b,
             (Value::Unit, Value::Unit) => true,
             (Value::Array(a), Value::Array(b)) => a == b,
+            (Value::Tuple(a), Value::Tuple(b)) => a == b,
             (Value::Record(a), Value::Record(b)) => a == b,
+            (Value::Struct(a), Value::Struct(b)) => a == b,
+            (Value::Variant(a), Value::Variant(b)) => a == b,
             _ => false,
         }
     }
 }
 
+impl Value {
+    /// `Ok(value)` from the prelude `Result`
+    pub fn ok(value: Value) -> Value {
+        Value::prelude_variant("Result", "Ok", Some(value))
+    }
+
+    /// `Err(value)` from the prelude `Result`
+    pub fn err(value: Value) -> Value {
+        Value::prelude_variant("Result", "Err", Some(value))
+    }
+
+    /// `Some(value)` from the prelude `Option`
+    pub fn some(value: Value) -> Value {
+        Value::prelude_variant("Option", "Some", Some(value))
+    }
+
+    /// `None` from the prelude `Option`
+    pub fn none() -> Value {
+        Value::prelude_variant("Option", "None", None)
+    }
+
+    /// Runtime type name, which selects the impl a method call dispatches to
+    pub fn type_name(&self) -> &str {
+        match self {
+            Value::Int(_) => "Int",
+            Value::Float(_) => "Float",
+            Value::String(_) => "String",
+            Value::Bool(_) => "Bool",
+            Value::Unit => "Unit",
+            Value::Array(_) => "Array",
+            Value::Tuple(_) => "Tuple",
+            Value::Record(_) => "Record",
+            Value::Struct(s) => &s.name,
+            Value::Variant(v) => &v.enum_name,
+            Value::Function(_) | Value::VariantConstructor { .. } => "Function",
+            Value::NativeFunction(_) => "NativeFunction",
+            Value::AiResult(_) => "AiResult",
+        }
+    }
+
+    fn prelude_variant(enum_name: &str, variant: &str, payload: Option<Value>) -> Value {
+        Value::Variant(VariantValue {
+            enum_name: enum_name.to_string(),
+            variant: variant.to_string(),
+            fields: payload.into_iter().map(|v| ("0".to_string(), v)).collect(),
+        })
+    }
+}
+
 impl fmt::Display for Value {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -73,6 +143,19 @@ impl fmt::Display for Value {
                 }
                 write!(f, "]")
             }
+            Value::Tuple(items) => {
+                write!(f, "(")?;
+                for (i, v) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", v)?;
+                }
+                if items.len() == 1 {
+                    write!(f, ",")?;
+                }
+                write!(f, ")")
+            }
             Value::Record(fields) => {
                 write!(f, "{{ ")?;
                 for (i, (k, v)) in fields.iter().enumerate() {
@@ -83,6 +166,43 @@ impl fmt::Display for Value {
                 }
                 write!(f, " }}")
             }
+            Value::Struct(s) => {
+                write!(f, "{} {{ ", s.name)?;
+                for (i, (k, v)) in s.fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}: {}", k, v)?;
+                }
+                write!(f, " }}")
+            }
+            Value::Variant(v) => {
+                write!(f, "{}", v.variant)?;
+                if v.fields.is_empty() {
+                    return Ok(());
+                }
+                let positional = v.fields.iter().all(|(n, _)| n.parse::<usize>().is_ok());
+                if positional {
+                    write!(f, "(")?;
+                    for (i, (_, value)) in v.fields.iter().enumerate() {
+                        if i > 0 {
+                            write!(f, ", ")?;
+                        }
+                        write!(f, "{}", value)?;
+                    }
+                    write!(f, ")")
+                } else {
+                    write!(f, " {{ ")?;
+                    for (i, (name, value)) in v.fields.iter().enumerate() {
+                        if i > 0 {
+                            write!(f, ", ")?;
+                        }
+                        write!(f, "{}: {}", name, value)?;
+                    }
+                    write!(f, " }}")
+                }
+            }
+            Value::VariantConstructor { variant, .. } => write!(f, "<constructor:{}>", variant),
             Value::Function(_) => write!(f, "<function>"),
             Value::NativeFunction(nf) => write!(f, "<native:{}>", nf.name),
             Value::AiResult(r) => write!(f, "<ai_result:{}>", r.value),
@@ -94,7 +214,9 @@ impl fmt::Display for Value {
 #[derive(Debug)]
 pub struct FunctionValue {
     pub name: String,
-    pub params: Vec<String>,
+    pub params: Vec<Param>,
+    pub return_type: Option<Type>,
+    pub contract: Option<Contract>,
     pub body: Block,
     pub closure: Env,
 }
@@ -116,6 +238,33 @@ impl fmt::Debug for NativeFunction {
     }
 }
 
+/// Struct value
+#[derive(Debug, Clone, PartialEq)]
+pub struct StructValue {
+    pub name: String,
+    /// Fields in declaration order
+    pub fields: Vec<(String, Value)>,
+}
+
+impl StructValue {
+    pub fn get(&self, field: &str) -> Option<&Value> {
+        self.fields.iter().find(|(n, _)| n == field).map(|(_, v)| v)
+    }
+
+    fn get_mut(&mut self, field: &str) -> Option<&mut Value> {
+        self.fields.iter_mut().find(|(n, _)| n == field).map(|(_, v)| v)
+    }
+}
+
+/// Enum variant value
+#[derive(Debug, Clone, PartialEq)]
+pub struct VariantValue {
+    pub enum_name: String,
+    pub variant: String,
+    /// Payload in declaration order; tuple variants use "0", "1", ... as names
+    pub fields: Vec<(String, Value)>,
+}
+
 /// AI result value (placeholder for AI operations)
 #[derive(Debug, Clone)]
 pub struct AiResultValue {
@@ -128,9 +277,13 @@ pub struct AiResultValue {
 // ============================================================================
 
 /// Environment for variable bindings
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct Environment {
     values: HashMap<String, Value>,
+    /// Names in this scope bound with `let mut` or as `mut` parameters
+    mutable: HashSet<String>,
+    /// Declared types of names in this scope bound with one
+    types: HashMap<String, Type>,
     parent: Option<Env>,
 }
 
@@ -140,6 +293,8 @@ impl Environment {
     pub fn new() -> Env {
         Rc::new(RefCell::new(Environment {
             values: HashMap::new(),
+            mutable: HashSet::new(),
+            types: HashMap::new(),
             parent: None,
         }))
     }
@@ -147,14 +302,45 @@ impl Environment {
     pub fn with_parent(parent: Env) -> Env {
         Rc::new(RefCell::new(Environment {
             values: HashMap::new(),
+            mutable: HashSet::new(),
+            types: HashMap::new(),
             parent: Some(parent),
         }))
     }
 
+    /// Bind an immutable name, shadowing any earlier binding in this scope
     pub fn define(&mut self, name: String, value: Value) {
+        self.bind(name, value, false);
+    }
+
+    /// Bind a name that `set` may later reassign when `mutable`
+    pub fn bind(&mut self, name: String, value: Value, mutable: bool) {
+        if mutable {
+            self.mutable.insert(name.clone());
+        } else {
+            self.mutable.remove(&name);
+        }
+        self.types.remove(&name);
         self.values.insert(name, value);
     }
 
+    /// Record the type a name in this scope was declared with, which values
+    /// assigned to it must satisfy
+    pub fn declare(&mut self, name: String, ty: Type) {
+        self.types.insert(name, ty);
+    }
+
+    /// The declared type of the binding `name` refers to
+    pub fn declared_type(&self, name: &str) -> Option<Type> {
+        if self.values.contains_key(name) {
+            self.types.get(name).cloned()
+        } else if let Some(parent) = &self.parent {
+            parent.borrow().declared_type(name)
+        } else {
+            None
+        }
+    }
+
     pub fn get(&self, name: &str) -> Option<Value> {
         if let Some(value) = self.values.get(name) {
             Some(value.clone())
@@ -167,6 +353,9 @@ impl Environment {
 
     pub fn set(&mut self, name: &str, value: Value) -> Result<(), RuntimeError> {
         if self.values.contains_key(name) {
+            if !self.mutable.contains(name) {
+                return Err(RuntimeError::ImmutableAssignment(name.to_string()));
+            }
             self.values.insert(name.to_string(), value);
             Ok(())
         } else if let Some(parent) = &self.parent {
@@ -177,15 +366,6 @@ impl Environment {
     }
 }
 
-impl Default for Environment {
-    fn default() -> Self {
-        Environment {
-            values: HashMap::new(),
-            parent: None,
-        }
-    }
-}
-
 // ============================================================================
 // RUNTIME ERRORS
 // ============================================================================
@@ -204,6 +384,9 @@ pub enum RuntimeError {
     #[error("division by zero")]
     DivisionByZero,
 
+    #[error("integer overflow in {0}")]
+    Overflow(String),
+
     #[error("wrong number of arguments: expected {expected}, got {got}")]
     ArityMismatch { expected: usize, got: usize },
 
@@ -213,18 +396,56 @@ pub enum RuntimeError {
     #[error("return value")]
     Return(Value),
 
+    #[error("break outside of loop")]
+    Break { label: Option<String>, value: Value },
+
+    #[error("continue outside of loop")]
+    Continue { label: Option<String> },
+
     #[error("index out of bounds: {index} (length {length})")]
     IndexOutOfBounds { index: i64, length: usize },
 
+    #[error("invalid slice range: {start}..{end} (length {length})")]
+    InvalidSliceRange { start: i64, end: i64, length: usize },
+
     #[error("field not found: {0}")]
     FieldNotFound(String),
 
+    #[error("cannot assign to immutable variable '{0}'")]
+    ImmutableAssignment(String),
+
+    #[error("contract violation: {clause} condition of '{name}' does not hold at line {}, column {}", .span.line, .span.column)]
+    ContractViolation { clause: String, name: String, span: Span },
+
+    #[error("no method '{method}' on type '{ty}'")]
+    UndefinedMethod { ty: String, method: String },
+
     #[error("pattern match failed")]
     PatternMatchFailed,
 
+    #[error("unhandled effect operation: {effect}.{op}")]
+    UnhandledEffect { effect: String, op: String },
+
+    #[error("resume outside of a handler clause")]
+    Resume(Value),
+
+    /// A handler clause finished without resuming, so its `handle` expression
+    /// evaluates to the clause's outcome
+    #[error("effect handler exited")]
+    HandlerExit { handler: usize, result: Box<Result<Value, RuntimeError>> },
+
     #[error("AI operation not available in interpreter: {0}")]
     AiNotAvailable(String),
 
+    #[error("AI backend error: {0}")]
+    AiBackend(String),
+
+    #[error("validation error: {0}")]
+    Validation(ValidationError),
+
+    #[error("AI reply is not a valid '{ty}' after {attempts} attempts: {message}")]
+    InvalidAiOutput { ty: String, attempts: usize, message: String },
+
     #[error("runtime error: {0}")]
     Custom(String),
 }
@@ -245,6 +466,34 @@ pub struct Interpreter {
     pub prompts: HashMap<String, PromptDecl>,
     /// Struct definitions
     pub structs: HashMap<String, StructDecl>,
+    /// Enum definitions
+    pub enums: HashMap<String, EnumDecl>,
+    /// Trait definitions
+    pub traits: HashMap<String, TraitDecl>,
+    /// Methods by runtime type name (see `Value::type_name`), then method name
+    pub methods: HashMap<String, HashMap<String, Value>>,
+    /// Evaluate `pre`, `post` and `invariant` clauses; release runs turn this off
+    pub check_contracts: bool,
+    /// Executes AI expressions; offline `MockBackend` by default
+    pub ai_backend: Box<dyn AiBackend>,
+    /// Verdicts of AI validations, by prompt
+    ai_verdicts: HashMap<String, bool>,
+    /// Installed effect handlers, innermost last
+    handlers: Vec<HandlerFrame>,
+    /// Id of the next `handle` expression evaluated
+    next_handler: usize,
+    /// Declared return type of the function being called
+    return_type: Option<Type>,
+}
+
+/// An effect handler installed by a `handle` expression being evaluated
+#[derive(Clone)]
+struct HandlerFrame {
+    id: usize,
+    effect: String,
+    clauses: Vec<HandlerClause>,
+    /// Environment of the `handle` expression, which its clauses run in
+    env: Env,
 }
 
 impl Interpreter {
@@ -261,21 +510,101 @@ impl Interpreter {
 
         let env = globals.clone();
 
-        Interpreter {
+        let mut interpreter = Interpreter {
             globals,
             env,
             ai_models: HashMap::new(),
             prompts: HashMap::new(),
             structs: HashMap::new(),
-        }
+            enums: HashMap::new(),
+            traits: HashMap::new(),
+            methods: HashMap::new(),
+            check_contracts: true,
+            ai_backend: Box::new(MockBackend::new()),
+            ai_verdicts: HashMap::new(),
+            handlers: Vec::new(),
+            next_handler: 0,
+            return_type: None,
+        };
+        let prelude = crate::stdlib::prelude();
+        interpreter.declare(prelude.iter());
+        interpreter
     }
 
     /// Run a complete program
     pub fn run(&mut self, program: &Program) -> Result<Value, RuntimeError> {
         let mut last_value = Value::Unit;
 
-        // First pass: collect declarations
+        let derived = crate::stdlib::derived_impls(&program.items);
+        self.declare(program.items.iter().chain(&derived));
+
+        // Second pass: define functions
         for item in &program.items {
+            if let TopLevel::Function(func) = item {
+                let fn_value = self.function_value(func);
+                self.env.borrow_mut().define(func.name.name.clone(), fn_value);
+            }
+        }
+
+        // Third pass: execute main if it exists, otherwise execute all statements
+        let main_fn = self.env.borrow().get("main");
+        if let Some(main_fn) = main_fn {
+            last_value = self.call_value(&main_fn, vec![])?;
+        }
+
+        Ok(last_value)
+    }
+
+    /// Run a program loaded from several files. Each module gets its own
+    /// environment, holding its functions and what it imports; modules are
+    /// set up after the modules they import, then the entry module's `main` runs.
+    pub fn run_modules(&mut self, graph: &ModuleGraph) -> Result<Value, RuntimeError> {
+        let mut envs: HashMap<FileId, Env> = HashMap::new();
+        for &file in &graph.order {
+            let module = graph.module(file);
+            self.env = Environment::with_parent(self.globals.clone());
+            let derived = crate::stdlib::derived_impls(&module.program.items);
+            self.declare(module.program.items.iter().chain(&derived));
+            for item in &module.program.items {
+                if let TopLevel::Function(func) = item {
+                    let fn_value = self.function_value(func);
+                    self.env.borrow_mut().define(func.name.name.clone(), fn_value);
+                }
+            }
+
+            // Types are global; functions are bound into the importing module
+            for import in &module.imports {
+                match import {
+                    Import::Item { module, name } => {
+                        if let Some(value) = envs[module].borrow().get(name) {
+                            self.env.borrow_mut().define(name.clone(), value);
+                        }
+                    }
+                    Import::Module { module, alias } => {
+                        for item in &graph.module(*module).program.items {
+                            let (TopLevel::Function(f), true) = (item, item.is_public()) else { continue };
+                            if let Some(value) = envs[module].borrow().get(&f.name.name) {
+                                self.env.borrow_mut().define(format!("{}::{}", alias, f.name.name), value);
+                            }
+                        }
+                    }
+                }
+            }
+            envs.insert(file, self.env.clone());
+        }
+
+        self.env = envs[&0].clone();
+        let main_fn = self.env.borrow().get("main");
+        match main_fn {
+            Some(main_fn) => self.call_value(&main_fn, vec![]),
+            None => Ok(Value::Unit),
+        }
+    }
+
+    /// Collect type, trait and impl declarations. Impls come last so that they can
+    /// inherit the default methods of traits declared anywhere.
+    fn declare<'a>(&mut self, items: impl Iterator<Item = &'a TopLevel> + Clone) {
+        for item in items.clone() {
             match item {
                 TopLevel::AiModel(model) => {
                     self.ai_models.insert(model.name.name.clone(), model.clone());
@@ -286,30 +615,108 @@ impl Interpreter {
                 TopLevel::Struct(s) => {
                     self.structs.insert(s.name.name.clone(), s.clone());
                 }
+                TopLevel::Enum(e) => {
+                    self.define_enum(e);
+                }
+                TopLevel::Trait(t) => {
+                    self.traits.insert(t.name.name.clone(), t.clone());
+                }
                 _ => {}
             }
         }
 
-        // Second pass: define functions
-        for item in &program.items {
-            if let TopLevel::Function(func) = item {
-                let fn_value = Value::Function(Rc::new(FunctionValue {
-                    name: func.name.name.clone(),
-                    params: func.params.iter().map(|p| p.name.name.clone()).collect(),
-                    body: func.body.clone(),
+        for item in items {
+            if let TopLevel::Impl(i) = item {
+                self.define_impl(i);
+            }
+        }
+    }
+
+    fn function_value(&self, func: &FnDecl) -> Value {
+        Value::Function(Rc::new(FunctionValue {
+            name: func.name.name.clone(),
+            params: func.params.clone(),
+            return_type: func.return_type.clone(),
+            contract: func.contract.clone(),
+            body: func.body.clone(),
+            closure: self.env.clone(),
+        }))
+    }
+
+    /// Register the methods of an impl block, filling in trait defaults it does not override
+    fn define_impl(&mut self, i: &ImplDecl) {
+        let Some(key) = crate::types::ast_type_to_ty(&i.self_ty).impl_key() else {
+            return;
+        };
+        let mut methods: HashMap<String, Value> = i.methods
+            .iter()
+            .map(|m| (m.name.name.clone(), self.function_value(m)))
+            .collect();
+
+        let defaults = i.trait_name
+            .as_ref()
+            .and_then(|t| self.traits.get(&t.name))
+            .map(|t| t.methods.as_slice())
+            .unwrap_or_default();
+        for method in defaults {
+            if let (Some(body), false) = (&method.default, methods.contains_key(&method.name.name)) {
+                let value = Value::Function(Rc::new(FunctionValue {
+                    name: method.name.name.clone(),
+                    params: method.params.clone(),
+                    return_type: method.return_type.clone(),
+                    contract: None,
+                    body: body.clone(),
                     closure: self.env.clone(),
                 }));
-                self.env.borrow_mut().define(func.name.name.clone(), fn_value);
+                methods.insert(method.name.name.clone(), value);
             }
         }
 
-        // Third pass: execute main if it exists, otherwise execute all statements
-        let main_fn = self.env.borrow().get("main");
-        if let Some(main_fn) = main_fn {
-            last_value = self.call_value(&main_fn, vec![])?;
+        self.methods.entry(key).or_default().extend(methods);
+    }
+
+    /// Register an enum and bind its unit variants and tuple constructors
+    fn define_enum(&mut self, e: &EnumDecl) {
+        for variant in &e.variants {
+            let value = match &variant.kind {
+                VariantKind::Unit => Value::Variant(VariantValue {
+                    enum_name: e.name.name.clone(),
+                    variant: variant.name.name.clone(),
+                    fields: vec![],
+                }),
+                VariantKind::Tuple(types) => Value::VariantConstructor {
+                    enum_name: e.name.name.clone(),
+                    variant: variant.name.name.clone(),
+                    arity: types.len(),
+                },
+                // Struct-like variants are built with `Name { .. }`
+                VariantKind::Struct(_) => continue,
+            };
+            self.env.borrow_mut().define(variant.name.name.clone(), value);
         }
+        self.enums.insert(e.name.name.clone(), e.clone());
+    }
 
-        Ok(last_value)
+    fn find_variant(&self, name: &str) -> Option<(&EnumDecl, &EnumVariant)> {
+        self.enums
+            .values()
+            .find_map(|e| e.variants.iter().find(|v| v.name.name == name).map(|v| (e, v)))
+    }
+
+    /// `?`: unwrap `Ok`/`Some`, or return `Err`/`None` from the enclosing function
+    fn propagate(&self, value: Value) -> Result<Value, RuntimeError> {
+        match value {
+            Value::Variant(mut v) if matches!((v.enum_name.as_str(), v.variant.as_str()), ("Result", "Ok") | ("Option", "Some")) => {
+                Ok(v.fields.pop().map(|(_, value)| value).unwrap_or(Value::Unit))
+            }
+            Value::Variant(v) if matches!((v.enum_name.as_str(), v.variant.as_str()), ("Result", "Err") | ("Option", "None")) => {
+                Err(RuntimeError::Return(Value::Variant(v)))
+            }
+            other => Err(RuntimeError::TypeError {
+                expected: "Result or Option".to_string(),
+                got: format!("{}", other),
+            }),
+        }
     }
 
     /// Evaluate an expression
@@ -318,17 +725,110 @@ impl Interpreter {
             Expr::Literal(lit) => self.eval_literal(lit),
             Expr::Ident(ident) => self.eval_ident(ident),
             Expr::Binary { left, op, right, .. } => self.eval_binary(left, op, right),
+            Expr::Assign { target, op, value, .. } => {
+                let value = match op {
+                    Some(op) => self.eval_binary(target, op, value)?,
+                    None => self.eval(value)?,
+                };
+                self.assign(target, value.clone())?;
+                Ok(value)
+            }
             Expr::Unary { op, operand, .. } => self.eval_unary(op, operand),
             Expr::Call { callee, args, .. } => self.eval_call(callee, args),
             Expr::Field { object, field, .. } => self.eval_field(object, field),
+            Expr::MethodCall { receiver, method, args, .. } => self.eval_method_call(receiver, method, args),
+            // `module::function` for functions of modules imported with `use`
+            Expr::Path { ty, name, .. } => match self.env.borrow().get(&format!("{}::{}", ty.name, name.name)) {
+                Some(function) => Ok(function),
+                None => self.find_method(&ty.name, &name.name),
+            },
+            Expr::StructLit { name, fields, .. } => self.eval_struct_lit(name, fields),
+            Expr::Index { object, index, .. } => self.eval_index(object, index),
+            Expr::Slice { object, start, end, .. } => {
+                self.eval_slice(object, start.as_deref(), end.as_deref())
+            }
+            Expr::Interpolation { parts, .. } => {
+                let mut text = String::new();
+                for part in parts {
+                    match part {
+                        StringPart::Text(s) => text.push_str(s),
+                        StringPart::Expr(e) => text.push_str(&self.eval(e)?.to_string()),
+                    }
+                }
+                Ok(Value::String(text))
+            }
             Expr::Array { elements, .. } => self.eval_array(elements),
+            Expr::Tuple { elements, .. } => {
+                let values = elements.iter().map(|e| self.eval(e)).collect::<Result<Vec<_>, _>>()?;
+                Ok(if values.is_empty() { Value::Unit } else { Value::Tuple(values) })
+            }
             Expr::Record { fields, .. } => self.eval_record(fields),
             Expr::Block(block) => self.eval_block(block),
             Expr::Match { scrutinee, arms, .. } => self.eval_match(scrutinee, arms),
+            Expr::Loop { label, body, .. } => self.eval_loop(label.as_ref(), body),
             Expr::Lambda { params, body, .. } => self.eval_lambda(params, body),
             Expr::Ai(ai_expr) => self.eval_ai(ai_expr),
-            Expr::Try { operand, .. } => self.eval(operand),
+            Expr::Try { operand, propagate, .. } => {
+                let value = self.eval(operand)?;
+                if *propagate {
+                    self.propagate(value)
+                } else {
+                    Ok(value)
+                }
+            }
             Expr::Restrict { operand, .. } => self.eval(operand),
+            Expr::Perform { effect, op, args, .. } => self.eval_perform(effect, op, args),
+            Expr::Handle { body, effect, clauses, .. } => self.eval_handle(body, effect, clauses),
+            Expr::Resume { value, .. } => Err(RuntimeError::Resume(self.eval(value)?)),
+        }
+    }
+
+    /// Run `body` with a handler for `effect` installed
+    fn eval_handle(&mut self, body: &Block, effect: &Ident, clauses: &[HandlerClause]) -> Result<Value, RuntimeError> {
+        let id = self.next_handler;
+        self.next_handler += 1;
+        self.handlers.push(HandlerFrame {
+            id,
+            effect: effect.name.clone(),
+            clauses: clauses.to_vec(),
+            env: self.env.clone(),
+        });
+        let result = self.eval_block(body);
+        self.handlers.pop();
+
+        match result {
+            Err(RuntimeError::HandlerExit { handler, result }) if handler == id => *result,
+            result => result,
+        }
+    }
+
+    /// Run the innermost handler clause for `effect.op`. The clause runs where
+    /// its handler was installed, without that handler or any inner one; if it
+    /// resumes, the `perform` evaluates to the resumed value, and otherwise its
+    /// outcome unwinds to the `handle` expression.
+    fn eval_perform(&mut self, effect: &Ident, op: &Ident, args: &[Expr]) -> Result<Value, RuntimeError> {
+        let args = args.iter().map(|a| self.eval(a)).collect::<Result<Vec<_>, _>>()?;
+        let unhandled = || RuntimeError::UnhandledEffect { effect: effect.name.clone(), op: op.name.clone() };
+        let index = self.handlers.iter().rposition(|h| h.effect == effect.name).ok_or_else(unhandled)?;
+        let frame = self.handlers[index].clone();
+        let clause = frame.clauses.iter().find(|c| c.op.name == op.name).ok_or_else(unhandled)?;
+        if clause.params.len() != args.len() {
+            return Err(RuntimeError::ArityMismatch { expected: clause.params.len(), got: args.len() });
+        }
+
+        let clause_env = Environment::with_parent(frame.env.clone());
+        for (param, arg) in clause.params.iter().zip(args) {
+            clause_env.borrow_mut().define(param.name.clone(), arg);
+        }
+        let inner = self.handlers.split_off(index);
+        let prev_env = std::mem::replace(&mut self.env, clause_env);
+        let result = self.eval(&clause.body);
+        self.env = prev_env;
+        self.handlers.extend(inner);
+
+        match result {
+            Err(RuntimeError::Resume(value)) => Ok(value),
+            result => Err(RuntimeError::HandlerExit { handler: frame.id, result: Box::new(result) }),
         }
     }
 
@@ -349,16 +849,6 @@ impl Interpreter {
     }
 
     fn eval_binary(&mut self, left: &Expr, op: &BinaryOp, right: &Expr) -> Result<Value, RuntimeError> {
-        // Handle assignment specially
-        if let BinaryOp::Assign = op {
-            let value = self.eval(right)?;
-            if let Expr::Ident(ident) = left {
-                self.env.borrow_mut().set(&ident.name, value.clone())?;
-                return Ok(value);
-            }
-            return Err(RuntimeError::Custom("invalid assignment target".to_string()));
-        }
-
         // Short-circuit evaluation for logical operators
         if let BinaryOp::And = op {
             let left_val = self.eval(left)?;
@@ -406,18 +896,27 @@ impl Interpreter {
         let right_val = self.eval(right)?;
 
         match (op, &left_val, &right_val) {
-            // Integer arithmetic
-            (BinaryOp::Add, Value::Int(a), Value::Int(b)) => Ok(Value::Int(a + b)),
-            (BinaryOp::Sub, Value::Int(a), Value::Int(b)) => Ok(Value::Int(a - b)),
-            (BinaryOp::Mul, Value::Int(a), Value::Int(b)) => Ok(Value::Int(a * b)),
-            (BinaryOp::Div, Value::Int(_), Value::Int(0)) => Err(RuntimeError::DivisionByZero),
-            (BinaryOp::Div, Value::Int(a), Value::Int(b)) => Ok(Value::Int(a / b)),
+            // Integer arithmetic, where overflow is an error
+            (BinaryOp::Div | BinaryOp::Rem, Value::Int(_), Value::Int(0)) => Err(RuntimeError::DivisionByZero),
+            (BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div | BinaryOp::Rem | BinaryOp::Shl | BinaryOp::Shr,
+             Value::Int(a), Value::Int(b)) => checked_int_op(*op, *a, *b)
+                .map(Value::Int)
+                .ok_or_else(|| RuntimeError::Overflow(format!("{} {} {}", a, op.symbol(), b))),
+
+            // Bitwise operators, which do not short-circuit on booleans
+            (BinaryOp::BitAnd, Value::Int(a), Value::Int(b)) => Ok(Value::Int(a & b)),
+            (BinaryOp::BitOr, Value::Int(a), Value::Int(b)) => Ok(Value::Int(a | b)),
+            (BinaryOp::BitXor, Value::Int(a), Value::Int(b)) => Ok(Value::Int(a ^ b)),
+            (BinaryOp::BitAnd, Value::Bool(a), Value::Bool(b)) => Ok(Value::Bool(a & b)),
+            (BinaryOp::BitOr, Value::Bool(a), Value::Bool(b)) => Ok(Value::Bool(a | b)),
+            (BinaryOp::BitXor, Value::Bool(a), Value::Bool(b)) => Ok(Value::Bool(a ^ b)),
 
             // Float arithmetic
             (BinaryOp::Add, Value::Float(a), Value::Float(b)) => Ok(Value::Float(a + b)),
             (BinaryOp::Sub, Value::Float(a), Value::Float(b)) => Ok(Value::Float(a - b)),
             (BinaryOp::Mul, Value::Float(a), Value::Float(b)) => Ok(Value::Float(a * b)),
             (BinaryOp::Div, Value::Float(a), Value::Float(b)) => Ok(Value::Float(a / b)),
+            (BinaryOp::Rem, Value::Float(a), Value::Float(b)) => Ok(Value::Float(a % b)),
 
             // Mixed numeric (promote to float)
             (BinaryOp::Add, Value::Int(a), Value::Float(b)) => Ok(Value::Float(*a as f64 + b)),
@@ -428,6 +927,8 @@ impl Interpreter {
             (BinaryOp::Mul, Value::Float(a), Value::Int(b)) => Ok(Value::Float(a * *b as f64)),
             (BinaryOp::Div, Value::Int(a), Value::Float(b)) => Ok(Value::Float(*a as f64 / b)),
             (BinaryOp::Div, Value::Float(a), Value::Int(b)) => Ok(Value::Float(a / *b as f64)),
+            (BinaryOp::Rem, Value::Int(a), Value::Float(b)) => Ok(Value::Float(*a as f64 % b)),
+            (BinaryOp::Rem, Value::Float(a), Value::Int(b)) => Ok(Value::Float(a % *b as f64)),
 
             // String concatenation
             (BinaryOp::Add, Value::String(a), Value::String(b)) => {
@@ -463,7 +964,9 @@ impl Interpreter {
     fn eval_unary(&mut self, op: &UnaryOp, operand: &Expr) -> Result<Value, RuntimeError> {
         let value = self.eval(operand)?;
         match (op, &value) {
-            (UnaryOp::Neg, Value::Int(n)) => Ok(Value::Int(-n)),
+            (UnaryOp::Neg, Value::Int(n)) => {
+                n.checked_neg().map(Value::Int).ok_or_else(|| RuntimeError::Overflow(format!("-({})", n)))
+            }
             (UnaryOp::Neg, Value::Float(f)) => Ok(Value::Float(-f)),
             (UnaryOp::Not, Value::Bool(b)) => Ok(Value::Bool(!b)),
             (UnaryOp::Ref, _) => Ok(value), // Reference is a no-op in interpreter
@@ -477,14 +980,44 @@ impl Interpreter {
 
     fn eval_call(&mut self, callee: &Expr, args: &[Expr]) -> Result<Value, RuntimeError> {
         let callee_val = self.eval(callee)?;
-        let arg_vals: Vec<Value> = args
-            .iter()
-            .map(|a| self.eval(a))
-            .collect::<Result<Vec<_>, _>>()?;
+        let arg_vals = self.eval_args(&callee_val, 0, args)?;
 
         self.call_value(&callee_val, arg_vals)
     }
 
+    /// Evaluate the arguments of a call to `callee`, matched against its
+    /// parameters after the first `skip`
+    fn eval_args(&mut self, callee: &Value, skip: usize, args: &[Expr]) -> Result<Vec<Value>, RuntimeError> {
+        let func = match callee {
+            Value::Function(func) => Some(func.clone()),
+            _ => None,
+        };
+        args.iter()
+            .enumerate()
+            .map(|(i, arg)| {
+                let ty = func.as_ref().and_then(|f| f.params.get(skip + i)).map(|p| &p.ty);
+                self.eval_as(arg, ty)
+            })
+            .collect()
+    }
+
+    /// Evaluate `expr` where a value of type `ty` is expected. There `ai
+    /// generate` produces a record of an `#[ai_generate]` struct `ty` names,
+    /// directly or as `AI<T>` or `Effect<T, ...>`, rather than text.
+    fn eval_as(&mut self, expr: &Expr, ty: Option<&Type>) -> Result<Value, RuntimeError> {
+        let ty = match ty {
+            Some(Type::Ai { inner, .. } | Type::Effect { inner, .. }) => Some(inner.as_ref()),
+            ty => ty,
+        };
+        if let (Some(Type::Named(name)), Expr::Ai(ai_expr)) = (ty, expr) {
+            let decl = self.structs.get(&name.name).filter(|d| d.modifiers.contains(&StructModifier::AiGenerate));
+            if let (Some(decl), AiKeyword::Generate) = (decl.cloned(), ai_expr.keyword()) {
+                return self.eval_ai_generate(ai_expr, &decl);
+            }
+        }
+        self.eval(expr)
+    }
+
     fn call_value(&mut self, callee: &Value, args: Vec<Value>) -> Result<Value, RuntimeError> {
         match callee {
             Value::Function(func) => {
@@ -495,27 +1028,62 @@ impl Interpreter {
                     });
                 }
 
+                for (param, arg) in func.params.iter().zip(&args) {
+                    self.check_constraints(arg, &param.ty, &param.name.name)?;
+                }
+
                 // Create new environment with closure as parent
                 let call_env = Environment::with_parent(func.closure.clone());
 
                 // Bind parameters
                 for (param, arg) in func.params.iter().zip(args) {
-                    call_env.borrow_mut().define(param.clone(), arg);
+                    let mut env = call_env.borrow_mut();
+                    env.bind(param.name.name.clone(), arg, param.mutable);
+                    env.declare(param.name.name.clone(), param.ty.clone());
                 }
 
                 // Execute function body
                 let prev_env = self.env.clone();
                 self.env = call_env;
+                let prev_return_type = std::mem::replace(&mut self.return_type, func.return_type.clone());
+
+                let contract = func.contract.as_ref().filter(|_| self.check_contracts);
+                let on_entry = |c: &ContractClause| matches!(c, ContractClause::Pre(..) | ContractClause::Invariant(..));
+                let mut result = contract
+                    .map_or(Ok(()), |c| self.check_contract(&func.name, c, on_entry))
+                    .and_then(|()| match self.exec_block(&func.body) {
+                        Ok(v) => Ok(v),
+                        Err(RuntimeError::Return(v)) => Ok(v),
+                        Err(e) => Err(e),
+                    });
 
-                let result = match self.exec_block(&func.body) {
-                    Ok(v) => Ok(v),
-                    Err(RuntimeError::Return(v)) => Ok(v),
-                    Err(e) => Err(e),
-                };
+                if let (Ok(value), Some(contract)) = (&result, contract) {
+                    let post_env = Environment::with_parent(self.env.clone());
+                    post_env.borrow_mut().define("result".to_string(), value.clone());
+                    self.env = post_env;
+                    let on_return = |c: &ContractClause| matches!(c, ContractClause::Post(..) | ContractClause::Invariant(..));
+                    if let Err(e) = self.check_contract(&func.name, contract, on_return) {
+                        result = Err(e);
+                    }
+                }
 
                 self.env = prev_env;
+                self.return_type = prev_return_type;
                 result
             }
+            Value::VariantConstructor { enum_name, variant, arity } => {
+                if *arity != args.len() {
+                    return Err(RuntimeError::ArityMismatch {
+                        expected: *arity,
+                        got: args.len(),
+                    });
+                }
+                Ok(Value::Variant(VariantValue {
+                    enum_name: enum_name.clone(),
+                    variant: variant.clone(),
+                    fields: args.into_iter().enumerate().map(|(i, v)| (i.to_string(), v)).collect(),
+                }))
+            }
             Value::NativeFunction(nf) => {
                 if nf.arity != args.len() {
                     return Err(RuntimeError::ArityMismatch {
@@ -529,6 +1097,38 @@ impl Interpreter {
         }
     }
 
+    /// `receiver.method(args)`: call a function stored in a field of that name, or else
+    /// dispatch on the receiver's runtime type
+    fn eval_method_call(&mut self, receiver: &Expr, method: &Ident, args: &[Expr]) -> Result<Value, RuntimeError> {
+        let recv_val = self.eval(receiver)?;
+
+        let field = match &recv_val {
+            Value::Record(fields) => fields.get(&method.name),
+            Value::Struct(s) => s.get(&method.name),
+            _ => None,
+        };
+        if let Some(callee @ (Value::Function(_) | Value::NativeFunction(_))) = field.cloned() {
+            let arg_vals = self.eval_args(&callee, 0, args)?;
+            return self.call_value(&callee, arg_vals);
+        }
+
+        let callee = self.find_method(recv_val.type_name(), &method.name)?;
+        let mut arg_vals = self.eval_args(&callee, 1, args)?;
+        arg_vals.insert(0, recv_val);
+        self.call_value(&callee, arg_vals)
+    }
+
+    fn find_method(&self, type_name: &str, method: &str) -> Result<Value, RuntimeError> {
+        self.methods
+            .get(type_name)
+            .and_then(|methods| methods.get(method))
+            .cloned()
+            .ok_or_else(|| RuntimeError::UndefinedMethod {
+                ty: type_name.to_string(),
+                method: method.to_string(),
+            })
+    }
+
     fn eval_field(&mut self, object: &Expr, field: &Ident) -> Result<Value, RuntimeError> {
         let obj_val = self.eval(object)?;
         match obj_val {
@@ -536,6 +1136,22 @@ impl Interpreter {
                 .get(&field.name)
                 .cloned()
                 .ok_or_else(|| RuntimeError::FieldNotFound(field.name.clone())),
+            Value::Struct(s) => s
+                .get(&field.name)
+                .cloned()
+                .ok_or_else(|| RuntimeError::FieldNotFound(field.name.clone())),
+            Value::Variant(v) => v
+                .fields
+                .iter()
+                .find(|(n, _)| n == &field.name)
+                .map(|(_, value)| value.clone())
+                .ok_or_else(|| RuntimeError::FieldNotFound(field.name.clone())),
+            Value::Tuple(items) => field
+                .name
+                .parse::<usize>()
+                .ok()
+                .and_then(|i| items.get(i).cloned())
+                .ok_or_else(|| RuntimeError::FieldNotFound(field.name.clone())),
             _ => Err(RuntimeError::TypeError {
                 expected: "record".to_string(),
                 got: format!("{:?}", obj_val),
@@ -543,51 +1159,401 @@ impl Interpreter {
         }
     }
 
-    fn eval_array(&mut self, elements: &[Expr]) -> Result<Value, RuntimeError> {
-        let values: Vec<Value> = elements
-            .iter()
-            .map(|e| self.eval(e))
-            .collect::<Result<Vec<_>, _>>()?;
-        Ok(Value::Array(values))
-    }
-
-    fn eval_record(&mut self, fields: &[RecordField]) -> Result<Value, RuntimeError> {
-        let mut map = HashMap::new();
-        for field in fields {
-            let value = self.eval(&field.value)?;
-            map.insert(field.name.name.clone(), value);
+    /// Evaluate the clauses of `contract` that `applies` selects, in the current environment
+    fn check_contract(
+        &mut self,
+        name: &str,
+        contract: &Contract,
+        applies: fn(&ContractClause) -> bool,
+    ) -> Result<(), RuntimeError> {
+        for clause in contract.clauses.iter().filter(|c| applies(c)) {
+            let (kind, condition) = match clause {
+                ContractClause::Pre(condition, _) => ("pre", condition),
+                ContractClause::Post(condition, _) => ("post", condition),
+                ContractClause::Invariant(condition, _) => ("invariant", condition),
+                ContractClause::AiCheck(..) | ContractClause::AiEnsure(..) => continue,
+            };
+            match self.eval(condition)? {
+                Value::Bool(true) => {}
+                Value::Bool(false) => {
+                    return Err(RuntimeError::ContractViolation {
+                        clause: kind.to_string(),
+                        name: name.to_string(),
+                        span: clause.span(),
+                    })
+                }
+                other => {
+                    return Err(RuntimeError::TypeError {
+                        expected: "bool".to_string(),
+                        got: format!("{:?}", other),
+                    })
+                }
+            }
         }
-        Ok(Value::Record(map))
+        Ok(())
     }
 
-    fn eval_block(&mut self, block: &Block) -> Result<Value, RuntimeError> {
-        let block_env = Environment::with_parent(self.env.clone());
-        let prev_env = self.env.clone();
-        self.env = block_env;
-
-        let result = self.exec_block(block);
+    /// Check the invariants of a struct value, with `self` and its fields in scope
+    fn check_invariants(&mut self, value: &Value) -> Result<(), RuntimeError> {
+        let Value::Struct(s) = value else {
+            return Ok(());
+        };
+        let contract = self.structs.get(&s.name).and_then(|d| d.contract.clone());
+        let Some(contract) = contract.filter(|_| self.check_contracts) else {
+            return Ok(());
+        };
 
+        let env = Environment::with_parent(self.globals.clone());
+        for (field, field_value) in &s.fields {
+            env.borrow_mut().define(field.clone(), field_value.clone());
+        }
+        env.borrow_mut().define("self".to_string(), value.clone());
+        let prev_env = std::mem::replace(&mut self.env, env);
+        let result = self.check_contract(&s.name, &contract, |c| matches!(c, ContractClause::Invariant(..)));
         self.env = prev_env;
         result
     }
 
-    fn eval_match(&mut self, scrutinee: &Expr, arms: &[MatchArm]) -> Result<Value, RuntimeError> {
-        let value = self.eval(scrutinee)?;
-
-        for arm in arms {
-            if let Some(bindings) = self.match_pattern(&arm.pattern, &value) {
-                // Create new environment with bindings
-                let match_env = Environment::with_parent(self.env.clone());
-                for (name, val) in bindings {
-                    match_env.borrow_mut().define(name, val);
+    /// Check `value` against the constraints in `ty`, including those of
+    /// array elements and `Option` payloads
+    fn check_constraints(&mut self, value: &Value, ty: &Type, path: &str) -> Result<(), RuntimeError> {
+        match (ty, value) {
+            (Type::Constrained { base, constraints, .. }, _) => {
+                self.check_constraints(value, base, path)?;
+                for rule in constraints.iter().filter_map(Rule::of) {
+                    self.check_rule(value, rule, path)?;
+                }
+                Ok(())
+            }
+            (Type::Array { element, .. }, Value::Array(items)) => items
+                .iter()
+                .enumerate()
+                .try_for_each(|(i, item)| self.check_constraints(item, element, &format!("{}[{}]", path, i))),
+            (Type::Generic { name, args, .. }, Value::Variant(v)) if name.name == "Option" && args.len() == 1 => {
+                match v.fields.first() {
+                    Some((_, payload)) => self.check_constraints(payload, &args[0], path),
+                    None => Ok(()),
+                }
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Check the value of a struct field against its type and `#[ai_validate]`
+    fn check_field(&mut self, field: &StructField, value: &Value, owner: &str) -> Result<(), RuntimeError> {
+        let path = format!("{}.{}", owner, field.name.name);
+        self.check_constraints(value, &field.ty, &path)?;
+        for modifier in &field.modifiers {
+            if let FieldModifier::AiValidate(requirement) = modifier {
+                self.check_rule(value, Rule::ai_validate(requirement), &path)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn check_rule(&mut self, value: &Value, rule: Rule, path: &str) -> Result<(), RuntimeError> {
+        let text = value.to_string();
+        let outcome = match rule {
+            Rule::Format(format) => check_format(format, &text),
+            Rule::Ai { keyword, requirement } => {
+                let request = validation_request(keyword, requirement, &text);
+                let verdict = match self.ai_verdicts.get(&request.prompt) {
+                    Some(verdict) => *verdict,
+                    None => {
+                        let reply = self.ai_backend.complete(&request).map_err(RuntimeError::AiBackend)?;
+                        let reply = reply.trim().to_lowercase();
+                        let verdict = reply.starts_with("true") || reply.starts_with("yes");
+                        self.ai_verdicts.insert(request.prompt, verdict);
+                        verdict
+                    }
+                };
+                if verdict { Ok(()) } else { Err(format!("{:?} was judged invalid", text)) }
+            }
+        };
+        outcome.map_err(|message| {
+            RuntimeError::Validation(ValidationError { path: path.to_string(), constraint: rule.describe(), message })
+        })
+    }
+
+    fn eval_struct_lit(&mut self, name: &Ident, fields: &[RecordField]) -> Result<Value, RuntimeError> {
+        let mut values = HashMap::new();
+        for field in fields {
+            let value = self.eval(&field.value)?;
+            values.insert(field.name.name.clone(), value);
+        }
+
+        let (enum_name, decl_fields) = match self.find_variant(&name.name) {
+            _ if self.structs.contains_key(&name.name) => (None, self.structs[&name.name].fields.clone()),
+            Some((e, EnumVariant { kind: VariantKind::Struct(decl_fields), .. })) => {
+                (Some(e.name.name.clone()), decl_fields.clone())
+            }
+            _ => return Err(RuntimeError::Custom(format!("unknown struct or variant: {}", name.name))),
+        };
+
+        // Keep the payload in declaration order
+        let fields = decl_fields
+            .iter()
+            .map(|f| {
+                values
+                    .remove(&f.name.name)
+                    .map(|v| (f.name.name.clone(), v))
+                    .ok_or_else(|| RuntimeError::FieldNotFound(f.name.name.clone()))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        for (decl, (_, value)) in decl_fields.iter().zip(&fields) {
+            self.check_field(decl, value, &name.name)?;
+        }
+
+        let value = match enum_name {
+            Some(enum_name) => Value::Variant(VariantValue {
+                enum_name,
+                variant: name.name.clone(),
+                fields,
+            }),
+            None => Value::Struct(StructValue {
+                name: name.name.clone(),
+                fields,
+            }),
+        };
+        self.check_invariants(&value)?;
+        Ok(value)
+    }
+
+    fn eval_index(&mut self, object: &Expr, index: &Expr) -> Result<Value, RuntimeError> {
+        let obj_val = self.eval(object)?;
+        let idx_val = self.eval(index)?;
+        match (&obj_val, &idx_val) {
+            (Value::Array(items), Value::Int(i)) => {
+                let pos = Self::check_index(*i, items.len())?;
+                Ok(items[pos].clone())
+            }
+            (Value::String(s), Value::Int(i)) => {
+                let chars: Vec<char> = s.chars().collect();
+                let pos = Self::check_index(*i, chars.len())?;
+                Ok(Value::String(chars[pos].to_string()))
+            }
+            (Value::Record(fields), Value::String(key)) => fields
+                .get(key)
+                .cloned()
+                .ok_or_else(|| RuntimeError::FieldNotFound(key.clone())),
+            (Value::Struct(s), Value::String(key)) => s
+                .get(key)
+                .cloned()
+                .ok_or_else(|| RuntimeError::FieldNotFound(key.clone())),
+            _ => Err(RuntimeError::TypeError {
+                expected: "array, string or record".to_string(),
+                got: format!("{:?}[{:?}]", obj_val, idx_val),
+            }),
+        }
+    }
+
+    fn eval_slice(&mut self, object: &Expr, start: Option<&Expr>, end: Option<&Expr>) -> Result<Value, RuntimeError> {
+        let obj_val = self.eval(object)?;
+        let length = match &obj_val {
+            Value::Array(items) => items.len(),
+            Value::String(s) => s.chars().count(),
+            _ => {
+                return Err(RuntimeError::TypeError {
+                    expected: "array or string".to_string(),
+                    got: format!("{:?}", obj_val),
+                })
+            }
+        };
+
+        let lo = match start {
+            Some(e) => self.eval_int(e)?,
+            None => 0,
+        };
+        let hi = match end {
+            Some(e) => self.eval_int(e)?,
+            None => length as i64,
+        };
+        if lo < 0 || hi < lo || hi as usize > length {
+            return Err(RuntimeError::InvalidSliceRange { start: lo, end: hi, length });
+        }
+        let (lo, hi) = (lo as usize, hi as usize);
+
+        Ok(match obj_val {
+            Value::Array(items) => Value::Array(items[lo..hi].to_vec()),
+            Value::String(s) => Value::String(s.chars().skip(lo).take(hi - lo).collect()),
+            _ => unreachable!("checked above"),
+        })
+    }
+
+    fn eval_int(&mut self, expr: &Expr) -> Result<i64, RuntimeError> {
+        match self.eval(expr)? {
+            Value::Int(n) => Ok(n),
+            other => Err(RuntimeError::TypeError {
+                expected: "int".to_string(),
+                got: format!("{:?}", other),
+            }),
+        }
+    }
+
+    /// Convert an `Int` index into a position, checking it against `length`
+    fn check_index(index: i64, length: usize) -> Result<usize, RuntimeError> {
+        if index >= 0 && (index as usize) < length {
+            Ok(index as usize)
+        } else {
+            Err(RuntimeError::IndexOutOfBounds { index, length })
+        }
+    }
+
+    /// Store a value into an assignable place (`x`, `a[i]`, `rec["key"]`)
+    fn assign(&mut self, target: &Expr, value: Value) -> Result<(), RuntimeError> {
+        match target {
+            Expr::Ident(ident) => {
+                let declared = self.env.borrow().declared_type(&ident.name);
+                if let Some(ty) = declared {
+                    self.check_constraints(&value, &ty, &ident.name)?;
+                }
+                self.env.borrow_mut().set(&ident.name, value)
+            }
+            Expr::Index { object, index, .. } => {
+                // Values are copied, so update the container and write it back
+                let mut container = self.eval(object)?;
+                let idx_val = self.eval(index)?;
+                match (&mut container, idx_val) {
+                    (Value::Array(items), Value::Int(i)) => {
+                        let pos = Self::check_index(i, items.len())?;
+                        items[pos] = value;
+                    }
+                    (Value::Record(fields), Value::String(key)) => {
+                        let slot = fields
+                            .get_mut(&key)
+                            .ok_or_else(|| RuntimeError::FieldNotFound(key.clone()))?;
+                        *slot = value;
+                    }
+                    (Value::Struct(s), Value::String(key)) => {
+                        let slot = s
+                            .get_mut(&key)
+                            .ok_or_else(|| RuntimeError::FieldNotFound(key.clone()))?;
+                        *slot = value;
+                    }
+                    (other, idx_val) => {
+                        return Err(RuntimeError::TypeError {
+                            expected: "array or record".to_string(),
+                            got: format!("{:?}[{:?}]", other, idx_val),
+                        })
+                    }
+                }
+                self.check_invariants(&container)?;
+                self.assign(object, container)
+            }
+            Expr::Field { object, field, .. } => {
+                let mut container = self.eval(object)?;
+                let slot = match &mut container {
+                    Value::Record(fields) => fields.get_mut(&field.name),
+                    Value::Struct(s) => s.get_mut(&field.name),
+                    Value::Tuple(items) => field.name.parse::<usize>().ok().and_then(|i| items.get_mut(i)),
+                    other => {
+                        return Err(RuntimeError::TypeError {
+                            expected: "record".to_string(),
+                            got: format!("{:?}", other),
+                        })
+                    }
+                };
+                *slot.ok_or_else(|| RuntimeError::FieldNotFound(field.name.clone()))? = value.clone();
+                if let Value::Struct(s) = &container {
+                    let decl = self.structs.get(&s.name).and_then(|d| d.fields.iter().find(|f| f.name.name == field.name)).cloned();
+                    if let Some(decl) = decl {
+                        self.check_field(&decl, &value, &s.name)?;
+                    }
+                }
+                self.check_invariants(&container)?;
+                self.assign(object, container)
+            }
+            _ => Err(RuntimeError::Custom("invalid assignment target".to_string())),
+        }
+    }
+
+    fn eval_array(&mut self, elements: &[Expr]) -> Result<Value, RuntimeError> {
+        let values: Vec<Value> = elements
+            .iter()
+            .map(|e| self.eval(e))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Value::Array(values))
+    }
+
+    fn eval_record(&mut self, fields: &[RecordField]) -> Result<Value, RuntimeError> {
+        let mut map = HashMap::new();
+        for field in fields {
+            let value = self.eval(&field.value)?;
+            map.insert(field.name.name.clone(), value);
+        }
+        Ok(Value::Record(map))
+    }
+
+    fn eval_block(&mut self, block: &Block) -> Result<Value, RuntimeError> {
+        let block_env = Environment::with_parent(self.env.clone());
+        let prev_env = self.env.clone();
+        self.env = block_env;
+
+        let result = self.exec_block(block);
+
+        self.env = prev_env;
+        result
+    }
+
+    fn eval_loop(&mut self, label: Option<&Ident>, body: &Block) -> Result<Value, RuntimeError> {
+        loop {
+            match self.eval_block(body) {
+                Ok(_) => {}
+                Err(RuntimeError::Break { label: l, value }) if Self::targets(label, &l) => {
+                    return Ok(value);
+                }
+                Err(RuntimeError::Continue { label: l }) if Self::targets(label, &l) => {}
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Run one iteration of a `while`/`for` body.
+    /// Returns `Ok(false)` when the loop should stop.
+    fn exec_loop_body(&mut self, label: Option<&Ident>, body: &Block) -> Result<bool, RuntimeError> {
+        match self.eval_block(body) {
+            Ok(_) => Ok(true),
+            Err(RuntimeError::Break { label: l, .. }) if Self::targets(label, &l) => Ok(false),
+            Err(RuntimeError::Continue { label: l }) if Self::targets(label, &l) => Ok(true),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Does a `break`/`continue` with `target` apply to the loop labelled `label`?
+    fn targets(label: Option<&Ident>, target: &Option<String>) -> bool {
+        match target {
+            None => true,
+            Some(t) => label.map(|l| &l.name == t).unwrap_or(false),
+        }
+    }
+
+    fn eval_match(&mut self, scrutinee: &Expr, arms: &[MatchArm]) -> Result<Value, RuntimeError> {
+        let value = self.eval(scrutinee)?;
+
+        for arm in arms {
+            if let Some(bindings) = self.match_pattern(&arm.pattern, &value) {
+                // Create new environment with bindings
+                let match_env = Environment::with_parent(self.env.clone());
+                for (name, val) in bindings {
+                    match_env.borrow_mut().define(name, val);
+                }
+
+                let prev_env = self.env.clone();
+                self.env = match_env;
+                let guard = match &arm.guard {
+                    Some(guard) => self.eval(guard).map(|v| matches!(v, Value::Bool(true))),
+                    None => Ok(true),
+                };
+                let result = match guard {
+                    Ok(true) => Some(self.eval(&arm.body)),
+                    Ok(false) => None,
+                    Err(e) => Some(Err(e)),
+                };
+                self.env = prev_env;
+
+                if let Some(result) = result {
+                    return result;
                 }
-
-                let prev_env = self.env.clone();
-                self.env = match_env;
-                let result = self.eval(&arm.body);
-                self.env = prev_env;
-
-                return result;
             }
         }
 
@@ -597,7 +1563,16 @@ impl Interpreter {
     fn match_pattern(&self, pattern: &Pattern, value: &Value) -> Option<Vec<(String, Value)>> {
         match pattern {
             Pattern::Wildcard(_) => Some(vec![]),
-            Pattern::Ident(ident) => Some(vec![(ident.name.clone(), value.clone())]),
+            Pattern::Ident(ident) => {
+                // A bare unit variant name matches that variant rather than binding
+                if let Some((_, EnumVariant { kind: VariantKind::Unit, .. })) = self.find_variant(&ident.name) {
+                    return match value {
+                        Value::Variant(v) if v.variant == ident.name => Some(vec![]),
+                        _ => None,
+                    };
+                }
+                Some(vec![(ident.name.clone(), value.clone())])
+            }
             Pattern::Literal(lit) => {
                 let lit_val = match lit {
                     Literal::Int(n, _) => Value::Int(*n),
@@ -612,7 +1587,30 @@ impl Interpreter {
                 }
             }
             Pattern::Constructor { name, args, .. } => {
-                // For now, treat constructor patterns as matching records
+                if let Value::Variant(v) = value {
+                    if v.variant != name.name || v.fields.len() != args.len() {
+                        return None;
+                    }
+                    let mut bindings = vec![];
+                    for (arg, (_, field_val)) in args.iter().zip(&v.fields) {
+                        bindings.append(&mut self.match_pattern(arg, field_val)?);
+                    }
+                    return Some(bindings);
+                }
+
+                // A struct's fields match positionally in declaration order
+                if let Value::Struct(s) = value {
+                    if s.name != name.name || s.fields.len() != args.len() {
+                        return None;
+                    }
+                    let mut bindings = vec![];
+                    for (arg, (_, field_val)) in args.iter().zip(&s.fields) {
+                        bindings.append(&mut self.match_pattern(arg, field_val)?);
+                    }
+                    return Some(bindings);
+                }
+
+                // Otherwise treat constructor patterns as matching records
                 if let Value::Record(fields) = value {
                     if fields.contains_key(&name.name) {
                         let mut bindings = vec![];
@@ -634,12 +1632,101 @@ impl Interpreter {
                     None
                 }
             }
+            Pattern::Struct { name, fields, .. } => {
+                let lookup = |field: &str| -> Option<&Value> {
+                    match value {
+                        Value::Variant(v) if v.variant == name.name => {
+                            v.fields.iter().find(|(n, _)| n == field).map(|(_, v)| v)
+                        }
+                        Value::Struct(s) if s.name == name.name => s.get(field),
+                        _ => None,
+                    }
+                };
+
+                let is_match = match value {
+                    Value::Variant(v) => v.variant == name.name,
+                    Value::Struct(s) => s.name == name.name,
+                    _ => false,
+                };
+                if !is_match {
+                    return None;
+                }
+
+                let mut bindings = vec![];
+                for (field, pattern) in fields {
+                    bindings.append(&mut self.match_pattern(pattern, lookup(&field.name)?)?);
+                }
+                Some(bindings)
+            }
+            Pattern::Record { fields, .. } => {
+                let lookup = |field: &str| -> Option<&Value> {
+                    match value {
+                        Value::Record(r) => r.get(field),
+                        Value::Struct(s) => s.get(field),
+                        _ => None,
+                    }
+                };
+
+                let mut bindings = vec![];
+                for (field, pattern) in fields {
+                    bindings.append(&mut self.match_pattern(pattern, lookup(&field.name)?)?);
+                }
+                Some(bindings)
+            }
+            Pattern::Tuple(elements, _) => {
+                let items = match value {
+                    Value::Tuple(items) if items.len() == elements.len() => items.as_slice(),
+                    Value::Unit if elements.is_empty() => &[],
+                    _ => return None,
+                };
+                let mut bindings = vec![];
+                for (pattern, item) in elements.iter().zip(items) {
+                    bindings.append(&mut self.match_pattern(pattern, item)?);
+                }
+                Some(bindings)
+            }
+            Pattern::Slice { prefix, rest, suffix, .. } => {
+                let Value::Array(items) = value else {
+                    return None;
+                };
+                let fixed = prefix.len() + suffix.len();
+                if items.len() < fixed || (rest.is_none() && items.len() != fixed) {
+                    return None;
+                }
+
+                let tail = items.len() - suffix.len();
+                let mut bindings = vec![];
+                for (pattern, item) in prefix.iter().zip(items).chain(suffix.iter().zip(&items[tail..])) {
+                    bindings.append(&mut self.match_pattern(pattern, item)?);
+                }
+                if let Some(rest) = rest {
+                    let middle = Value::Array(items[prefix.len()..tail].to_vec());
+                    bindings.append(&mut self.match_pattern(rest, &middle)?);
+                }
+                Some(bindings)
+            }
+            Pattern::Range { start, end, inclusive, .. } => {
+                let in_range = match (start, end, value) {
+                    (Literal::Int(lo, _), Literal::Int(hi, _), Value::Int(n)) => {
+                        lo <= n && if *inclusive { n <= hi } else { n < hi }
+                    }
+                    (Literal::Float(lo, _), Literal::Float(hi, _), Value::Float(x)) => {
+                        lo <= x && if *inclusive { x <= hi } else { x < hi }
+                    }
+                    _ => false,
+                };
+                in_range.then(Vec::new)
+            }
+            Pattern::Or(alternatives, _) => alternatives.iter().find_map(|p| self.match_pattern(p, value)),
+            Pattern::Binding { name, pattern, .. } => {
+                let mut bindings = vec![(name.name.clone(), value.clone())];
+                bindings.append(&mut self.match_pattern(pattern, value)?);
+                Some(bindings)
+            }
         }
     }
 
     fn eval_lambda(&mut self, params: &[Param], body: &LambdaBody) -> Result<Value, RuntimeError> {
-        let param_names: Vec<String> = params.iter().map(|p| p.name.name.clone()).collect();
-
         // Convert lambda body to block
         let block = match body {
             LambdaBody::Expr(expr) => Block {
@@ -654,49 +1741,147 @@ impl Interpreter {
 
         Ok(Value::Function(Rc::new(FunctionValue {
             name: "<lambda>".to_string(),
-            params: param_names,
+            params: params.to_vec(),
+            return_type: None,
+            contract: None,
             body: block,
             closure: self.env.clone(),
         })))
     }
 
     fn eval_ai(&mut self, ai_expr: &AiExpr) -> Result<Value, RuntimeError> {
-        // AI operations return placeholder values in the interpreter
-        match ai_expr {
-            AiExpr::Quick { query, .. } => {
-                Ok(Value::AiResult(AiResultValue {
-                    operation: "quick".to_string(),
-                    value: format!("<ai response to: {}>", query),
-                }))
+        let request = self.ai_request(ai_expr)?;
+        match request.operation {
+            AiKeyword::Embed => {
+                let vector = self.ai_backend.embed(&request).map_err(RuntimeError::AiBackend)?;
+                Ok(Value::Array(vector.into_iter().map(Value::Float).collect()))
             }
-            AiExpr::Block { keyword, .. } => {
-                Ok(Value::AiResult(AiResultValue {
-                    operation: format!("{:?}", keyword).to_lowercase(),
-                    value: "<ai block result>".to_string(),
-                }))
+            AiKeyword::Verify | AiKeyword::Validate => {
+                let reply = self.ai_backend.complete(&request).map_err(RuntimeError::AiBackend)?;
+                let verdict = reply.trim().to_lowercase();
+                Ok(Value::Bool(verdict.starts_with("true") || verdict.starts_with("yes")))
             }
-            AiExpr::Call { keyword, .. } => {
-                Ok(Value::AiResult(AiResultValue {
-                    operation: format!("{:?}", keyword).to_lowercase(),
-                    value: "<ai call result>".to_string(),
-                }))
+            _ => {
+                let reply = self.ai_backend.complete(&request).map_err(RuntimeError::AiBackend)?;
+                Ok(Value::String(reply))
             }
-            AiExpr::PromptInvocation { name, .. } => {
-                Ok(Value::AiResult(AiResultValue {
-                    operation: "prompt".to_string(),
-                    value: format!("<result of prompt {}>", name.name),
-                }))
+        }
+    }
+
+    /// Generate a record of the struct `decl`: ask for JSON matching the
+    /// struct's schema, and ask again with the problem if the reply does not
+    /// match it
+    fn eval_ai_generate(&mut self, ai_expr: &AiExpr, decl: &StructDecl) -> Result<Value, RuntimeError> {
+        const ATTEMPTS: usize = 3;
+        let mut request = self.ai_request(ai_expr)?;
+        let shapes = Shapes { structs: &self.structs, enums: &self.enums };
+        let schema = shapes.schema(decl);
+        let task = json_output(&request.prompt, &schema);
+        request.prompt = task.clone();
+        request.schema = Some(schema);
+        let mut problem = String::new();
+        for _ in 0..ATTEMPTS {
+            let reply = self.ai_backend.complete(&request).map_err(RuntimeError::AiBackend)?;
+            let shapes = Shapes { structs: &self.structs, enums: &self.enums };
+            match shapes.parse_reply(&reply, decl) {
+                Ok(record) => match self.check_record(&record, decl) {
+                    Ok(()) => return Ok(record),
+                    Err(RuntimeError::Validation(e)) => problem = e.to_string(),
+                    Err(e) => return Err(e),
+                },
+                Err(e) => problem = e,
+            }
+            request.prompt = format!(
+                "{}\n\nYour previous reply was invalid: {}. Respond again with only the corrected JSON.",
+                task, problem
+            );
+        }
+        Err(RuntimeError::InvalidAiOutput { ty: decl.name.name.clone(), attempts: ATTEMPTS, message: problem })
+    }
+
+    fn check_record(&mut self, record: &Value, decl: &StructDecl) -> Result<(), RuntimeError> {
+        let Value::Record(fields) = record else { return Ok(()) };
+        for field in &decl.fields {
+            if let Some(value) = fields.get(&field.name.name) {
+                self.check_field(field, value, &decl.name.name)?;
             }
         }
+        Ok(())
+    }
+
+    /// Evaluate the operands of an AI expression and render its prompt.
+    /// Block fields other than `prompt` and `model` are appended as
+    /// `name: value` lines; prompt invocations fill the template's
+    /// placeholders with their arguments, in order or by name.
+    fn ai_request(&mut self, ai_expr: &AiExpr) -> Result<AiRequest, RuntimeError> {
+        let mut model = None;
+        let (operation, prompt) = match ai_expr {
+            AiExpr::Quick { query, .. } => (AiKeyword::Query, query.clone()),
+            AiExpr::Block { keyword, body, .. } => {
+                let mut lines = Vec::new();
+                for item in body {
+                    match item {
+                        AiBodyItem::Field { name, value } if name.name == "model" => {
+                            model = Some(self.ai_model(value)?);
+                        }
+                        AiBodyItem::Field { name, value } if name.name == "prompt" => {
+                            lines.push(self.eval(value)?.to_string());
+                        }
+                        AiBodyItem::Field { name, value } => {
+                            lines.push(format!("{}: {}", name.name, self.eval(value)?));
+                        }
+                        AiBodyItem::Literal(text) => lines.push(text.clone()),
+                    }
+                }
+                (*keyword, lines.join("\n"))
+            }
+            AiExpr::Call { keyword, args, .. } => {
+                let args = args.iter().map(|arg| self.eval(arg).map(|v| v.to_string())).collect::<Result<Vec<_>, _>>()?;
+                (*keyword, args.join("\n"))
+            }
+            AiExpr::PromptInvocation { name, args, .. } => {
+                let decl = self.prompts.get(&name.name).ok_or_else(|| RuntimeError::UndefinedFunction(format!("{}!", name.name)))?;
+                let mut template = PromptTemplate::new(&decl.template);
+                let placeholders = decl.placeholders.clone();
+                for (i, arg) in args.iter().enumerate() {
+                    let Some(var) = arg.name.as_ref().map(|n| &n.name).or(placeholders.get(i)) else { continue };
+                    let value = self.eval(&arg.value)?.to_string();
+                    template.set(var, &value);
+                }
+                (AiKeyword::Query, template.render())
+            }
+        };
+        Ok(AiRequest { operation, prompt, model, schema: None })
+    }
+
+    /// The `ai_model` a `model:` field names
+    fn ai_model(&self, value: &Expr) -> Result<AiModel, RuntimeError> {
+        let Expr::Ident(name) = value else {
+            return Err(RuntimeError::TypeError { expected: "ai_model name".to_string(), got: format!("{:?}", value) });
+        };
+        self.ai_models.get(&name.name).map(AiModel::from).ok_or_else(|| RuntimeError::UndefinedVariable(name.name.clone()))
     }
 
     /// Execute a statement
     pub fn exec(&mut self, stmt: &Stmt) -> Result<Value, RuntimeError> {
         match stmt {
             Stmt::Expr(expr) => self.eval(expr),
-            Stmt::Let { mutable: _, name, value, .. } => {
-                let val = self.eval(value)?;
-                self.env.borrow_mut().define(name.name.clone(), val);
+            Stmt::Let { mutable, pattern, ty, value, .. } => {
+                let val = self.eval_as(value, ty.as_ref())?;
+                if let Some(ty) = ty {
+                    let path = match pattern {
+                        Pattern::Ident(name) => name.name.as_str(),
+                        _ => "value",
+                    };
+                    self.check_constraints(&val, ty, path)?;
+                }
+                let bindings = self.match_pattern(pattern, &val).ok_or(RuntimeError::PatternMatchFailed)?;
+                for (name, value) in bindings {
+                    self.env.borrow_mut().bind(name, value, *mutable);
+                }
+                if let (Pattern::Ident(name), Some(ty)) = (pattern, ty) {
+                    self.env.borrow_mut().declare(name.name.clone(), ty.clone());
+                }
                 Ok(Value::Unit)
             }
             Stmt::If { condition, then_block, else_block, .. } => {
@@ -716,12 +1901,74 @@ impl Interpreter {
                     }),
                 }
             }
-            Stmt::Return { value, .. } => {
+            Stmt::While { label, condition, body, .. } => {
+                loop {
+                    match self.eval(condition)? {
+                        Value::Bool(true) => {
+                            if !self.exec_loop_body(label.as_ref(), body)? {
+                                break;
+                            }
+                        }
+                        Value::Bool(false) => break,
+                        other => {
+                            return Err(RuntimeError::TypeError {
+                                expected: "bool".to_string(),
+                                got: format!("{:?}", other),
+                            })
+                        }
+                    }
+                }
+                Ok(Value::Unit)
+            }
+            Stmt::For { label, binding, iterable, body, .. } => {
+                let items = match self.eval(iterable)? {
+                    Value::Array(items) => items,
+                    Value::String(s) => s.chars().map(|c| Value::String(c.to_string())).collect(),
+                    other => {
+                        return Err(RuntimeError::TypeError {
+                            expected: "array or string".to_string(),
+                            got: format!("{:?}", other),
+                        })
+                    }
+                };
+
+                for item in items {
+                    // Each iteration gets a fresh scope holding the loop binding
+                    let iter_env = Environment::with_parent(self.env.clone());
+                    iter_env.borrow_mut().define(binding.name.clone(), item);
+
+                    let prev_env = self.env.clone();
+                    self.env = iter_env;
+                    let result = self.exec_loop_body(label.as_ref(), body);
+                    self.env = prev_env;
+
+                    if !result? {
+                        break;
+                    }
+                }
+                Ok(Value::Unit)
+            }
+            Stmt::Break { label, value, .. } => {
                 let val = if let Some(expr) = value {
                     self.eval(expr)?
                 } else {
                     Value::Unit
                 };
+                Err(RuntimeError::Break {
+                    label: label.as_ref().map(|l| l.name.clone()),
+                    value: val,
+                })
+            }
+            Stmt::Continue { label, .. } => Err(RuntimeError::Continue {
+                label: label.as_ref().map(|l| l.name.clone()),
+            }),
+            Stmt::Return { value, .. } => {
+                let val = if let Some(expr) = value {
+                    let return_type = self.return_type.clone();
+                    self.eval_as(expr, return_type.as_ref())?
+                } else {
+                    Value::Unit
+                };
                 Err(RuntimeError::Return(val))
             }
             Stmt::Go { block, .. } => {
@@ -732,9 +1979,13 @@ impl Interpreter {
                 // In interpreter, just evaluate the expression
                 self.eval(value)
             }
-            Stmt::Try { value, .. } => {
-                // In interpreter, just evaluate and return
-                self.eval(value)
+            Stmt::Try { value, propagate, .. } => {
+                let value = self.eval(value)?;
+                if *propagate {
+                    self.propagate(value)
+                } else {
+                    Ok(value)
+                }
             }
             Stmt::Comptime { block, .. } => {
                 // Execute comptime block at runtime (in interpreter)
@@ -747,6 +1998,10 @@ impl Interpreter {
                     AiStmtBody::Expr(expr) => self.eval(expr),
                 }
             }
+            Stmt::Error(span) => Err(RuntimeError::Custom(format!(
+                "cannot run the statement at line {}, column {}, which failed to parse",
+                span.line, span.column
+            ))),
         }
     }
 
@@ -765,6 +2020,22 @@ impl Default for Interpreter {
     }
 }
 
+/// `a op b` for an arithmetic or shift operator, or `None` if it overflows.
+/// A shift overflows when `b` is negative or at least 64.
+fn checked_int_op(op: BinaryOp, a: i64, b: i64) -> Option<i64> {
+    let shift = u32::try_from(b).ok();
+    match op {
+        BinaryOp::Add => a.checked_add(b),
+        BinaryOp::Sub => a.checked_sub(b),
+        BinaryOp::Mul => a.checked_mul(b),
+        BinaryOp::Div => a.checked_div(b),
+        BinaryOp::Rem => a.checked_rem(b),
+        BinaryOp::Shl => shift.and_then(|s| a.checked_shl(s)),
+        BinaryOp::Shr => shift.and_then(|s| a.checked_shr(s)),
+        _ => None,
+    }
+}
+
 // ============================================================================
 // TESTS
 // ============================================================================
@@ -954,4 +2225,853 @@ mod tests {
         let result = eval_program(program);
         assert!(matches!(result, Err(RuntimeError::UndefinedVariable(_))));
     }
+
+    #[test]
+    fn test_while_loop() {
+        let program = r#"
+            fn main() -> Int {
+                let mut i = 0;
+                let mut sum = 0;
+                while i < 10000 {
+                    i = i + 1;
+                    sum = sum + i;
+                }
+                return sum;
+            }
+        "#;
+        let result = eval_program(program);
+        assert!(matches!(result, Ok(Value::Int(50005000))));
+    }
+
+    #[test]
+    fn test_for_loop() {
+        let program = r#"
+            fn main() -> Int {
+                let mut sum = 0;
+                for x in [1, 2, 3, 4] {
+                    if x == 3 { continue; }
+                    sum = sum + x;
+                }
+                return sum;
+            }
+        "#;
+        let result = eval_program(program);
+        assert!(matches!(result, Ok(Value::Int(7))));
+    }
+
+    #[test]
+    fn test_labelled_break() {
+        let program = r#"
+            fn main() -> Int {
+                let mut count = 0;
+                'outer: for i in [1, 2, 3] {
+                    for j in [1, 2, 3] {
+                        if j == 2 { continue 'outer; }
+                        if i == 3 { break 'outer; }
+                        count = count + 1;
+                    }
+                }
+                return count;
+            }
+        "#;
+        let result = eval_program(program);
+        assert!(matches!(result, Ok(Value::Int(2))));
+    }
+
+    #[test]
+    fn test_loop_break_value() {
+        let program = r#"
+            fn main() -> Int {
+                let mut n = 1;
+                let x = loop {
+                    n = n * 2;
+                    if n > 100 { break n; }
+                };
+                return x;
+            }
+        "#;
+        let result = eval_program(program);
+        assert!(matches!(result, Ok(Value::Int(128))));
+    }
+
+    #[test]
+    fn test_index_and_slice() {
+        let program = r#"
+            fn main() -> Int {
+                let mut xs = [10, 20, 30, 40];
+                xs[1] = xs[0] + xs[3];
+                let mid = xs[1..3];
+                let rec = { a: 1, b: 2 };
+                return mid[0] + len(xs[..2]) + rec["b"];
+            }
+        "#;
+        let result = eval_program(program);
+        assert!(matches!(result, Ok(Value::Int(54))));
+    }
+
+    #[test]
+    fn test_string_index_and_slice() {
+        let program = r#"
+            fn main() -> String {
+                let s = "hello";
+                return s[1..3] + s[4];
+            }
+        "#;
+        let result = eval_program(program);
+        assert!(matches!(result, Ok(Value::String(s)) if s == "elo"));
+    }
+
+    #[test]
+    fn test_index_out_of_bounds() {
+        let program = r#"
+            fn main() -> Int {
+                let xs = [1, 2, 3];
+                return xs[3];
+            }
+        "#;
+        let result = eval_program(program);
+        assert!(matches!(result, Err(RuntimeError::IndexOutOfBounds { index: 3, length: 3 })));
+
+        let program = r#"
+            fn main() {
+                let mut xs = [1, 2, 3];
+                xs[-1] = 0;
+            }
+        "#;
+        let result = eval_program(program);
+        assert!(matches!(result, Err(RuntimeError::IndexOutOfBounds { index: -1, .. })));
+    }
+
+    #[test]
+    fn test_invalid_slice_range() {
+        let result = eval_program("fn main() -> [Int] { return [1, 2, 3][2..1]; }");
+        assert!(matches!(result, Err(RuntimeError::InvalidSliceRange { start: 2, end: 1, length: 3 })));
+
+        let result = eval_program(r#"fn main() -> String { return "abc"[1..4]; }"#);
+        let message = result.unwrap_err().to_string();
+        assert_eq!(message, "invalid slice range: 1..4 (length 3)");
+    }
+
+    #[test]
+    fn test_enum_variants() {
+        let program = r#"
+            enum Shape {
+                Empty,
+                Rect(Int, Int),
+                Square { side: Int },
+            }
+
+            fn area(s: Shape) -> Int {
+                return match s {
+                    Empty => 0,
+                    Rect(w, h) => w * h,
+                    Square { side } => side * side,
+                };
+            }
+
+            fn main() -> Int {
+                return area(Rect(2, 3)) + area(Square { side: 4 }) + area(Empty);
+            }
+        "#;
+        let result = eval_program(program);
+        assert!(matches!(result, Ok(Value::Int(22))));
+    }
+
+    #[test]
+    fn test_variant_display() {
+        let program = r#"
+            enum Opt<T> { Nothing, Just(T) }
+            fn main() -> String {
+                return to_string(Just(5)) + " " + to_string(Nothing);
+            }
+        "#;
+        let result = eval_program(program);
+        assert!(matches!(result, Ok(Value::String(s)) if s == "Just(5) Nothing"));
+    }
+
+    #[test]
+    fn test_try_propagates_errors() {
+        let program = r#"
+            fn sum(a: String, b: String) -> Result<Int, String> {
+                let x = try to_int(a)?;
+                let y = try to_int(b)?;
+                return Ok(x + y);
+            }
+
+            fn main() -> String {
+                let good = match sum("2", "3") { Ok(n) => n, Err(e) => 0 };
+                let bad = match sum("2", "three") { Ok(n) => "ok", Err(e) => e };
+                return to_string(good) + " " + bad;
+            }
+        "#;
+        let result = eval_program(program);
+        assert!(matches!(result, Ok(Value::String(s)) if s.starts_with("5 invalid integer 'three'")));
+    }
+
+    #[test]
+    fn test_try_on_option() {
+        let program = r#"
+            fn first_even(xs: [Int]) -> Option<Int> {
+                for x in xs {
+                    if x - (x / 2) * 2 == 0 { return Some(x); }
+                }
+                return None;
+            }
+
+            fn double_first_even(xs: [Int]) -> Option<Int> {
+                let x = try first_even(xs)?;
+                return Some(x * 2);
+            }
+
+            fn main() -> String {
+                return to_string(double_first_even([1, 4])) + " " + to_string(double_first_even([1, 3]));
+            }
+        "#;
+        let result = eval_program(program);
+        assert!(matches!(result, Ok(Value::String(s)) if s == "Some(8) None"));
+    }
+
+    #[test]
+    fn test_inherent_methods() {
+        let program = r#"
+            struct Point { x: Int, y: Int }
+
+            impl Point {
+                fn new(x: Int, y: Int) -> Self { return Point { x: x, y: y }; }
+                fn shift(self, by: Int) -> Self { return Point::new(self.x + by, self.y + by); }
+                fn sum(self) -> Int { return self.x + self.y; }
+            }
+
+            fn main() -> Int {
+                return Point::new(1, 2).shift(10).sum();
+            }
+        "#;
+        let result = eval_program(program);
+        assert!(matches!(result, Ok(Value::Int(23))));
+    }
+
+    #[test]
+    fn test_trait_dispatch() {
+        let program = r#"
+            trait Shape {
+                fn area(self) -> Int;
+                fn describe(self) -> String { return self.name() + " " + to_string(self.area()); }
+                fn name(self) -> String { return "shape"; }
+            }
+
+            struct Square { side: Int }
+            struct Rect { w: Int, h: Int }
+
+            impl Shape for Square {
+                fn area(self) -> Int { return self.side * self.side; }
+                fn name(self) -> String { return "square"; }
+            }
+
+            impl Shape for Rect {
+                fn area(self) -> Int { return self.w * self.h; }
+            }
+
+            fn main() -> String {
+                let shapes = [Square { side: 3 }.describe(), Rect { w: 2, h: 5 }.describe()];
+                return shapes[0] + ", " + shapes[1];
+            }
+        "#;
+        let result = eval_program(program);
+        assert!(matches!(result, Ok(Value::String(s)) if s == "square 9, shape 10"));
+    }
+
+    #[test]
+    fn test_function_field_call() {
+        let program = r#"
+            fn main() -> Int {
+                let handler = { run: |x: Int| => x * 2 };
+                return handler.run(21);
+            }
+        "#;
+        let result = eval_program(program);
+        assert!(matches!(result, Ok(Value::Int(42))));
+    }
+
+    #[test]
+    fn test_derived_impls() {
+        let program = r#"
+            #[derive(Debug, Clone)]
+            struct Point { x: Int, y: Int }
+
+            fn main() -> String {
+                let p = Point { x: 1, y: 2 };
+                return p.clone().debug() + " " + Some(3).debug() + " " + 4.debug();
+            }
+        "#;
+        let result = eval_program(program);
+        assert!(matches!(&result, Ok(Value::String(s)) if s == "Point { x: 1, y: 2 } Some(3) 4"), "{:?}", result);
+    }
+
+    #[test]
+    fn test_undefined_method_at_runtime() {
+        let program = r#"
+            fn main() -> Int {
+                return 1.area();
+            }
+        "#;
+        let result = eval_program(program);
+        assert!(matches!(result, Err(RuntimeError::UndefinedMethod { ty, method }) if ty == "Int" && method == "area"));
+    }
+
+    #[test]
+    fn test_rich_patterns() {
+        let program = r#"
+            fn describe(xs: [Int]) -> String {
+                return match xs {
+                    [] => "empty",
+                    [x] if x < 0 => "one negative",
+                    [0 | 1] => "one bit",
+                    [first, ..rest] if len(rest) > 1 => to_string(first) + " and " + to_string(len(rest)) + " more",
+                    [.., last @ 10..=99] => "ends with " + to_string(last),
+                    _ => "other",
+                };
+            }
+
+            fn main() -> String {
+                let person = {name: "Ada", age: 36};
+                let who = match person {
+                    {name, age: 0..=17} => name + " (minor)",
+                    {name, ..} => name,
+                };
+                return describe([]) + ", " + describe([-4]) + ", " + describe([1]) + ", "
+                    + describe([5, 6, 7]) + ", " + describe([3, 42]) + ", " + describe([3]) + ", " + who;
+            }
+        "#;
+        let result = eval_program(program);
+        assert!(matches!(&result, Ok(Value::String(s))
+            if s == "empty, one negative, one bit, 5 and 2 more, ends with 42, other, Ada"), "{:?}", result);
+    }
+
+    #[test]
+    fn test_tuples_and_destructuring_let() {
+        let program = r#"
+            fn divmod(a: Int, b: Int) -> (Int, Int) {
+                return (a / b, a - a / b * b);
+            }
+
+            fn main() -> String {
+                let (q, r) = divmod(17, 5);
+                let (label, {y, ..}) = ("point", {x: 1, y: 2});
+                let [first, ..rest] = [7, 8, 9];
+                let nested = ((q, r), label);
+                return to_string(nested.0.1) + " " + label + " " + to_string(q + y + first + len(rest))
+                    + " " + to_string(nested);
+            }
+        "#;
+        let result = eval_program(program);
+        assert!(matches!(&result, Ok(Value::String(s)) if s == "2 point 14 ((3, 2), point)"), "{:?}", result);
+    }
+
+    #[test]
+    fn test_field_assignment() {
+        let program = r#"
+            struct Point { x: Int, y: Int }
+
+            fn main() -> String {
+                let mut p = Point { x: 1, y: 2 };
+                let mut r = {name: "a", inner: {n: 1}};
+                let mut t = (1, (2, 3));
+                p.x = 10;
+                r.inner.n = r.inner.n + 41;
+                t.1.0 = p.x + p.y;
+                return to_string(p.x) + " " + to_string(r.inner.n) + " " + to_string(t);
+            }
+        "#;
+        let result = eval_program(program);
+        assert!(matches!(&result, Ok(Value::String(s)) if s == "10 42 (1, (12, 3))"), "{:?}", result);
+    }
+
+    #[test]
+    fn test_immutable_assignment_at_runtime() {
+        let program = r#"
+            fn bump(n: Int) -> Int {
+                n = n + 1;
+                return n;
+            }
+
+            fn main() -> Int {
+                return bump(1);
+            }
+        "#;
+        let result = eval_program(program);
+        assert!(matches!(&result, Err(RuntimeError::ImmutableAssignment(name)) if name == "n"), "{:?}", result);
+
+        let program = r#"
+            fn main() -> Int {
+                let p = {x: 1};
+                p.x = 2;
+                return p.x;
+            }
+        "#;
+        let result = eval_program(program);
+        assert!(matches!(&result, Err(RuntimeError::ImmutableAssignment(name)) if name == "p"), "{:?}", result);
+    }
+
+    #[test]
+    fn test_function_contracts() {
+        let program = r#"
+            fn isqrt(n: Int) -> Int
+                where pre: n >= 0, post: result * result <= n
+            {
+                let mut r = 0;
+                while (r + 1) * (r + 1) <= n { r = r + 1; }
+                return r;
+            }
+
+            fn broken(n: Int) -> Int where post: result > n {
+                return n;
+            }
+
+            fn main() -> Int {
+                return isqrt(17);
+            }
+        "#;
+        assert!(matches!(eval_program(program), Ok(Value::Int(4))));
+
+        let result = eval_program(&program.replace("isqrt(17)", "isqrt(0 - 1)"));
+        assert!(matches!(&result, Err(RuntimeError::ContractViolation { clause, name, span })
+            if clause == "pre" && name == "isqrt" && span.line == 3), "{:?}", result);
+
+        let result = eval_program(&program.replace("isqrt(17)", "broken(1)"));
+        assert!(matches!(&result, Err(RuntimeError::ContractViolation { clause, name, span })
+            if clause == "post" && name == "broken" && span.line == 10), "{:?}", result);
+    }
+
+    #[test]
+    fn test_struct_invariants() {
+        let program = r#"
+            struct Account where invariant: balance >= 0 {
+                owner: String,
+                balance: Int
+            }
+
+            fn main() -> Int {
+                let mut a = Account { owner: "ada", balance: 10 };
+                a.balance = a.balance - 4;
+                a.balance = a.balance - 7;
+                return a.balance;
+            }
+        "#;
+        let result = eval_program(program);
+        assert!(matches!(&result, Err(RuntimeError::ContractViolation { clause, name, .. })
+            if clause == "invariant" && name == "Account"), "{:?}", result);
+
+        let result = eval_program(&program.replace("balance: 10", "balance: -1"));
+        assert!(matches!(&result, Err(RuntimeError::ContractViolation { .. })), "{:?}", result);
+
+        // Release runs skip the checks
+        let mut interpreter = Interpreter::new();
+        interpreter.check_contracts = false;
+        let result = interpreter.run(&parse(program).unwrap());
+        assert!(matches!(result, Ok(Value::Int(-1))));
+    }
+
+    #[test]
+    fn test_effect_handlers() {
+        let program = r#"
+            effect Logger {
+                op log: String
+            }
+            effect Fail {
+                op fail: String -> Int
+            }
+
+            fn greet(name: String) -> Int {
+                perform Logger.log("hello " + name);
+                perform Logger.log("bye " + name);
+                return 42;
+            }
+
+            fn checked_div(a: Int, b: Int) -> Int {
+                if b == 0 {
+                    return perform Fail.fail("division by zero");
+                }
+                return a / b;
+            }
+
+            fn main() -> Int {
+                let mut logged = 0;
+                let n = handle { greet("ada"); } with Logger {
+                    log(m) => { logged = logged + len(m); resume(()); },
+                };
+                let d = handle { checked_div(n, 0) + 1000; } with Fail { fail(msg) => 0 - 1 };
+                return n * 1000 + logged * 10 + d;
+            }
+        "#;
+        // Both logs resume; the failure discards the rest of its handled body
+        assert!(matches!(eval_program(program), Ok(Value::Int(42159))), "{:?}", eval_program(program));
+
+        let result = eval_program(&program.replace("checked_div(n, 0)", "checked_div(n, 2)"));
+        assert!(matches!(result, Ok(Value::Int(43181))), "{:?}", result);
+
+        let result = eval_program(&program.replace("with Logger", "with Fail").replace("log(m)", "fail(m)"));
+        assert!(matches!(&result, Err(RuntimeError::UnhandledEffect { effect, op })
+            if effect == "Logger" && op == "log"), "{:?}", result);
+    }
+
+    #[test]
+    fn test_nested_handlers() {
+        let program = r#"
+            effect Ask {
+                op ask: () -> Int
+            }
+
+            fn main() -> Int {
+                return handle {
+                    let inner = handle { perform Ask.ask() * 10; } with Ask {
+                        ask() => resume(perform Ask.ask() + 1),
+                    };
+                    inner + perform Ask.ask();
+                } with Ask {
+                    ask() => resume(4),
+                };
+            }
+        "#;
+        // The inner clause asks the outer handler, which is still installed
+        assert!(matches!(eval_program(program), Ok(Value::Int(54))), "{:?}", eval_program(program));
+    }
+
+    #[test]
+    fn test_ai_backend() {
+        use crate::ai::{AiBackend, AiRequest, MockBackend};
+
+        /// Records every request, answering with a mock
+        struct Recorder(Rc<RefCell<Vec<AiRequest>>>, MockBackend);
+        impl AiBackend for Recorder {
+            fn complete(&mut self, request: &AiRequest) -> Result<String, String> {
+                self.0.borrow_mut().push(request.clone());
+                self.1.complete(request)
+            }
+            fn embed(&mut self, request: &AiRequest) -> Result<Vec<f64>, String> {
+                self.0.borrow_mut().push(request.clone());
+                self.1.embed(request)
+            }
+        }
+
+        let program = parse(r#"
+            ai_model fast {
+                provider: "ollama"
+                model: "llama3"
+                temperature: 0.2
+                cache: false
+            }
+            prompt translate { "Translate {text} from {source} to English" }
+            prompt greet { "Greet {name} in {lang}" }
+
+            fn main() -> String {
+                let word = "hola";
+                let english = translate!(word, "Spanish");
+                let ok = ai verify { prompt: "Is " + english + " a greeting?" model: fast };
+                let v = ai embed(english);
+                let greeting = greet!(lang: "French", name: word);
+                if ok { return english + " " + ai! { "Say bye" } + " " + to_string(len(v)); }
+                return "no";
+            }
+        "#).unwrap();
+        let requests = Rc::new(RefCell::new(Vec::new()));
+        let mut interpreter = Interpreter::new();
+        interpreter.ai_backend = Box::new(Recorder(
+            requests.clone(),
+            MockBackend::new().reply("Translate hola", "hello").reply("Is hello", "Yes."),
+        ));
+        let result = interpreter.run(&program);
+        assert!(matches!(&result, Ok(Value::String(s)) if s == "hello <query response to: Say bye> 8"), "{:?}", result);
+
+        let requests = requests.borrow();
+        let prompts: Vec<&str> = requests.iter().map(|r| r.prompt.as_str()).collect();
+        assert_eq!(prompts, [
+            "Translate hola from Spanish to English",
+            "Is hello a greeting?",
+            "hello",
+            "Greet hola in French",
+            "Say bye",
+        ]);
+        assert_eq!(requests[1].operation, AiKeyword::Verify);
+        let model = requests[1].model.as_ref().unwrap();
+        assert_eq!((model.provider.as_deref(), model.model.as_deref()), (Some("ollama"), Some("llama3")));
+        assert_eq!((model.temperature, model.cache), (Some(0.2), false));
+        assert!(requests[0].model.is_none());
+    }
+
+    #[test]
+    fn test_ai_generate_struct() {
+        use crate::ai::MockBackend;
+
+        let source = r#"
+            enum Mood { Calm, Tense }
+            #[ai_generate]
+            struct Article {
+                title: String,
+                tags: [String],
+                words: Int,
+                summary: Option<String>,
+                mood: Mood,
+            }
+            fn main() -> String {
+                let a: Article = ai generate { prompt: "Write an article about tides" };
+                let summary = match a.summary { Some(s) => s, None => "no summary" };
+                let calm = match a.mood { Calm => "calm", Tense => "tense" };
+                return a.title + " " + to_string(a.words + len(a.tags)) + " " + summary + " " + calm;
+            }
+        "#;
+        let run = |backend: MockBackend| {
+            let mut interpreter = Interpreter::new();
+            interpreter.ai_backend = Box::new(backend);
+            interpreter.run(&parse(source).unwrap())
+        };
+
+        // The first reply is malformed; the retry is told why
+        let backend = MockBackend::new()
+            .reply("invalid: field 'words' should be an integer, not \"many\"",
+                r#"{"title": "Tides", "tags": ["sea", "moon"], "words": 40, "mood": "Calm"}"#)
+            .reply("\"words\":{\"description\":\"\",\"type\":\"integer\"}",
+                "```json\n{\"title\": \"Tides\", \"tags\": [], \"words\": \"many\", \"mood\": \"Calm\"}\n```");
+        let result = run(backend);
+        assert!(matches!(&result, Ok(Value::String(s)) if s == "Tides 42 no summary calm"), "{:?}", result);
+
+        let backend = MockBackend::new().reply("tides", r#"{"title": "Tides", "tags": [1], "words": 40, "mood": "Calm"}"#);
+        let result = run(backend);
+        assert!(matches!(&result, Err(RuntimeError::InvalidAiOutput { ty, attempts: 3, message })
+            if ty == "Article" && message == "field 'tags[0]' should be a string, not 1"), "{:?}", result);
+    }
+
+    #[test]
+    fn test_ai_generate_with_mock() {
+        let source = r#"
+            enum Mood { Calm, Tense }
+            struct Author { name: String }
+            #[ai_generate]
+            struct Article {
+                title: String,
+                tags: [String],
+                words: Int,
+                summary: Option<String>,
+                mood: Mood,
+                author: Author,
+            }
+            fn main() -> String {
+                let a: Article = ai generate { prompt: "Write an article about tides" };
+                let mood = match a.mood { Calm => "calm", Tense => "tense" };
+                return a.title + " " + a.tags[0] + " " + to_string(a.words) + " " + mood + " " + a.author.name;
+            }
+        "#;
+        let result = Interpreter::new().run(&parse(source).unwrap());
+        assert!(matches!(&result, Ok(Value::String(s)) if s == "title tags 0 calm name"), "{:?}", result);
+    }
+
+    #[test]
+    fn test_ai_generate_positions() {
+        use crate::ai::MockBackend;
+
+        let source = r#"
+            #[ai_generate]
+            struct Tide { height: Int }
+            struct Plain { height: Int }
+            fn draft() -> AI<Tide> {
+                return ai generate { prompt: "high tide" };
+            }
+            fn height(t: Tide) -> Int { return t.height; }
+            fn plain() -> Plain {
+                return ai generate { prompt: "low tide" };
+            }
+            fn main() -> String {
+                let t: AI<Tide> = draft();
+                let arg = height(ai generate { prompt: "high tide" });
+                return to_string(t.height + arg) + " " + to_string(plain());
+            }
+        "#;
+        let backend = MockBackend::new()
+            .reply("high tide", r#"{"height": 3}"#)
+            .reply("low tide", "no record");
+        let mut interpreter = Interpreter::new();
+        interpreter.ai_backend = Box::new(backend);
+        let result = interpreter.run(&parse(source).unwrap());
+        assert!(matches!(&result, Ok(Value::String(s)) if s == "6 no record"), "{:?}", result);
+    }
+
+    #[test]
+    fn test_constraint_validation() {
+        use crate::ai::{AiBackend, AiRequest, MockBackend};
+        use crate::constraints::ValidationError;
+
+        /// Counts the requests it answers
+        struct Counter(Rc<RefCell<usize>>, MockBackend);
+        impl AiBackend for Counter {
+            fn complete(&mut self, request: &AiRequest) -> Result<String, String> {
+                *self.0.borrow_mut() += 1;
+                self.1.complete(request)
+            }
+            fn embed(&mut self, request: &AiRequest) -> Result<Vec<f64>, String> {
+                self.1.embed(request)
+            }
+        }
+
+        let run = |body: &str| {
+            let source = format!(r#"
+                struct User {{
+                    #[ai_validate("a real person's name")]
+                    name: String,
+                    email: String where ai_format: "email",
+                    tags: [String where ai_format: "[a-z]+"],
+                }}
+                fn greet(name: String where ai_check: "polite") -> String {{ return "Hi " + name; }}
+                fn main() -> String {{ {} }}
+            "#, body);
+            let calls = Rc::new(RefCell::new(0));
+            let mut interpreter = Interpreter::new();
+            interpreter.ai_backend = Box::new(Counter(calls.clone(), MockBackend::new().reply("Value: Bot", "false")));
+            let result = interpreter.run(&parse(&source).unwrap());
+            let calls = *calls.borrow();
+            (result, calls)
+        };
+        let failure = |path: &str, constraint: &str, message: &str| ValidationError {
+            path: path.to_string(),
+            constraint: constraint.to_string(),
+            message: message.to_string(),
+        };
+
+        let (result, calls) = run(r#"
+            let u = User { name: "Ada", email: "ada@example.org", tags: ["math"] };
+            let v = User { name: "Ada", email: "ada@example.org", tags: [] };
+            let n: Int where ai_format: "[0-9]{3}" = 451;
+            return greet(u.name) + " " + greet(v.name) + " " + to_string(n);
+        "#);
+        assert!(matches!(&result, Ok(Value::String(s)) if s == "Hi Ada Hi Ada 451"), "{:?}", result);
+        // One verdict for the name field and one for the argument, each cached
+        assert_eq!(calls, 2);
+
+        let (result, _) = run(r#"return User { name: "Ada", email: "ada@example.org", tags: ["ok", "NO"] }.name;"#);
+        assert!(matches!(&result, Err(RuntimeError::Validation(e))
+            if *e == failure("User.tags[1]", "ai_format: \"[a-z]+\"", "\"NO\" does not match")), "{:?}", result);
+
+        let (result, _) = run(r#"let e: String where ai_format: "email" = "ada"; return e;"#);
+        assert!(matches!(&result, Err(RuntimeError::Validation(e))
+            if *e == failure("e", "ai_format: \"email\"", "\"ada\" is not an email address")), "{:?}", result);
+
+        // Assignments are checked against the declared type and field
+        let (result, _) = run(r#"
+            let mut e: String where ai_format: "email" = "ada@example.org";
+            e = "grace@example.org";
+            let mut u = User { name: "Ada", email: e, tags: [] };
+            u.email = "ada@example.org";
+            u.tags = ["math"];
+            return u.email + " " + e;
+        "#);
+        assert!(matches!(&result, Ok(Value::String(s)) if s == "ada@example.org grace@example.org"), "{:?}", result);
+
+        let (result, _) = run(r#"let mut e: String where ai_format: "email" = "ada@example.org"; e = "ada"; return e;"#);
+        assert!(matches!(&result, Err(RuntimeError::Validation(e))
+            if *e == failure("e", "ai_format: \"email\"", "\"ada\" is not an email address")), "{:?}", result);
+
+        let (result, _) = run(r#"
+            let mut u = User { name: "Ada", email: "ada@example.org", tags: [] };
+            u.email = "ada";
+            return u.name;
+        "#);
+        assert!(matches!(&result, Err(RuntimeError::Validation(e))
+            if *e == failure("User.email", "ai_format: \"email\"", "\"ada\" is not an email address")), "{:?}", result);
+
+        let (result, _) = run(r#"
+            let mut u = User { name: "Ada", email: "ada@example.org", tags: ["math"] };
+            u.tags[0] = "NO";
+            return u.name;
+        "#);
+        assert!(matches!(&result, Err(RuntimeError::Validation(e))
+            if *e == failure("User.tags[0]", "ai_format: \"[a-z]+\"", "\"NO\" does not match")), "{:?}", result);
+
+        let (result, _) = run(r#"return greet("Bot");"#);
+        assert!(matches!(&result, Err(RuntimeError::Validation(e))
+            if *e == failure("name", "ai_check: \"polite\"", "\"Bot\" was judged invalid")), "{:?}", result);
+
+        let (result, _) = run(r#"return User { name: "Bot", email: "bot@example.org", tags: [] }.name;"#);
+        assert!(matches!(&result, Err(e) if e.to_string()
+            == "validation error: 'User.name' does not satisfy #[ai_validate(\"a real person's name\")]: \"Bot\" was judged invalid"),
+            "{:?}", result);
+    }
+
+    #[test]
+    fn test_string_interpolation() {
+        let program = r#"
+            struct Point { x: Int, y: Int }
+
+            fn main() -> String {
+                let name = "Ada";
+                let age = 36;
+                let p = Point { x: 1, y: 2 };
+                let poem = """
+Hello {name}, you are {age + 1}
+at ({p.x}, {p.y})\t{str_upper("ok")} \{done}""";
+                return poem;
+            }
+        "#;
+        let result = eval_program(program);
+        let expected = "Hello Ada, you are 37\nat (1, 2)\tOK {done}";
+        assert!(matches!(&result, Ok(Value::String(s)) if s == expected), "{:?}", result);
+    }
+
+    #[test]
+    fn test_integer_operators() {
+        let program = r#"
+            fn main() -> Int {
+                let mut x = 1_000;
+                x += 0x10;
+                x -= 0o10;
+                x *= 0b11;
+                x /= 4;
+                x %= 100;
+                let mut flags = 0b0101;
+                flags &= 0b0110;
+                flags |= 0b1000;
+                flags ^= 0b0001;
+                flags <<= 2;
+                flags >>= 1;
+                let mixed = 1 + 6 & 3 | 8 ^ 2 << 1;
+                let both = true & false | true ^ false;
+                if both {
+                    return x * 10_000 + flags * 100 + mixed;
+                }
+                return 0;
+            }
+        "#;
+        let result = eval_program(program);
+        assert!(matches!(result, Ok(Value::Int(562_615))), "{:?}", result);
+    }
+
+    #[test]
+    fn test_integer_overflow() {
+        for (expr, message) in [
+            ("9223372036854775807 + 1", "integer overflow in 9223372036854775807 + 1"),
+            ("-9223372036854775807 - 2", "integer overflow in -9223372036854775807 - 2"),
+            ("4611686018427387904 * 2", "integer overflow in 4611686018427387904 * 2"),
+            ("1 << 64", "integer overflow in 1 << 64"),
+            ("1 >> -1", "integer overflow in 1 >> -1"),
+            ("abs(-9223372036854775807 - 1)", "integer overflow in abs(-9223372036854775808)"),
+            ("pow(10, 19)", "integer overflow in pow(10, 19)"),
+            ("7 % 0", "division by zero"),
+        ] {
+            let result = eval_program(&format!("fn main() -> Int {{ return {}; }}", expr));
+            assert_eq!(result.map_err(|e| e.to_string()).err().as_deref(), Some(message), "{}", expr);
+        }
+
+        let program = r#"
+            fn main() -> Int {
+                let max = 0x7FFF_FFFF_FFFF_FFFF;
+                let min = -max - 1;
+                let wrapped = wrapping_add(max, 1) == min && wrapping_sub(min, 1) == max && wrapping_mul(max, 2) == -2;
+                let saturated = saturating_add(max, 1) == max && saturating_sub(min, 1) == min
+                    && saturating_mul(min, 2) == min;
+                if wrapped && saturated {
+                    return 1;
+                }
+                return 0;
+            }
+        "#;
+        let result = eval_program(program);
+        assert!(matches!(result, Ok(Value::Int(1))), "{:?}", result);
+    }
 }
@@ -1,6 +1,6 @@
 //! Lexer for My Language with AI integration
 
-use crate::token::{Span, Token, TokenKind};
+use crate::token::{FileId, Span, Token, TokenKind};
 use std::iter::Peekable;
 use std::str::Chars;
 
@@ -10,6 +10,9 @@ pub struct Lexer<'a> {
     pos: usize,
     line: usize,
     column: usize,
+    file: FileId,
+    /// Whether whitespace and comments are tokens too
+    trivia: bool,
 }
 
 impl<'a> Lexer<'a> {
@@ -20,13 +23,29 @@ impl<'a> Lexer<'a> {
             pos: 0,
             line: 1,
             column: 1,
+            file: 0,
+            trivia: false,
         }
     }
 
+    /// Mark the spans of the tokens as belonging to `file`
+    pub fn with_file(mut self, file: FileId) -> Self {
+        self.file = file;
+        self
+    }
+
+    /// Keep whitespace and comments as `Whitespace`, `LineComment` and
+    /// `BlockComment` tokens, so the tokens cover the whole input
+    pub fn with_trivia(mut self) -> Self {
+        self.trivia = true;
+        self
+    }
+
     pub fn tokenize(&mut self) -> Vec<Token> {
         let mut tokens = Vec::new();
         loop {
-            let token = self.next_token();
+            let mut token = self.next_token();
+            token.span.file = self.file;
             let is_eof = token.kind == TokenKind::Eof;
             tokens.push(token);
             if is_eof {
@@ -37,7 +56,16 @@ impl<'a> Lexer<'a> {
     }
 
     fn next_token(&mut self) -> Token {
-        self.skip_whitespace_and_comments();
+        loop {
+            let (start, line, column) = (self.pos, self.line, self.column);
+            match self.scan_trivia() {
+                Some(kind) if self.trivia => {
+                    return Token::new(kind, Span::new(start, self.pos, line, column), &self.input[start..self.pos]);
+                }
+                Some(_) => {}
+                None => break,
+            }
+        }
 
         let start = self.pos;
         let start_line = self.line;
@@ -61,22 +89,36 @@ impl<'a> Lexer<'a> {
             ']' => TokenKind::RBracket,
             ',' => TokenKind::Comma,
             ';' => TokenKind::Semicolon,
-            '.' => TokenKind::Dot,
+            '.' => {
+                if self.peek() == Some(&'.') {
+                    self.advance();
+                    if self.peek() == Some(&'=') {
+                        self.advance();
+                        TokenKind::DotDotEq
+                    } else {
+                        TokenKind::DotDot
+                    }
+                } else {
+                    TokenKind::Dot
+                }
+            }
             '@' => TokenKind::At,
-            '+' => TokenKind::Plus,
-            '*' => TokenKind::Star,
             '?' => TokenKind::Question,
 
             // Multi-character tokens
+            '+' => self.then_eq(TokenKind::Plus, TokenKind::PlusEq),
+            '*' => self.then_eq(TokenKind::Star, TokenKind::StarEq),
+            '%' => self.then_eq(TokenKind::Percent, TokenKind::PercentEq),
+            '^' => self.then_eq(TokenKind::Caret, TokenKind::CaretEq),
             '-' => {
                 if self.peek() == Some(&'>') {
                     self.advance();
                     TokenKind::Arrow
                 } else {
-                    TokenKind::Minus
+                    self.then_eq(TokenKind::Minus, TokenKind::MinusEq)
                 }
             }
-            '/' => TokenKind::Slash,
+            '/' => self.then_eq(TokenKind::Slash, TokenKind::SlashEq),
             '=' => {
                 if self.peek() == Some(&'=') {
                     self.advance();
@@ -97,11 +139,11 @@ impl<'a> Lexer<'a> {
                 }
             }
             '<' => {
-                if self.peek() == Some(&'=') {
+                if self.peek() == Some(&'<') {
                     self.advance();
-                    TokenKind::LtEq
+                    self.then_eq(TokenKind::LtLt, TokenKind::LtLtEq)
                 } else {
-                    TokenKind::Lt
+                    self.then_eq(TokenKind::Lt, TokenKind::LtEq)
                 }
             }
             '>' => {
@@ -117,15 +159,18 @@ impl<'a> Lexer<'a> {
                     self.advance();
                     TokenKind::AndAnd
                 } else {
-                    TokenKind::Ampersand
+                    self.then_eq(TokenKind::Ampersand, TokenKind::AmpersandEq)
                 }
             }
             '|' => {
                 if self.peek() == Some(&'|') {
                     self.advance();
                     TokenKind::OrOr
-                } else {
+                } else if self.input[self.pos..].starts_with("=>") {
+                    // The end of lambda parameters: `|x|=> x`
                     TokenKind::Pipe
+                } else {
+                    self.then_eq(TokenKind::Pipe, TokenKind::PipeEq)
                 }
             }
             ':' => {
@@ -148,11 +193,21 @@ impl<'a> Lexer<'a> {
             // String literals
             '"' => return self.scan_string(start, start_line, start_column),
 
+            // Loop labels: 'outer
+            '\'' if self.peek().map(|c| c.is_alphabetic() || *c == '_').unwrap_or(false) => {
+                return self.scan_label(start, start_line, start_column)
+            }
+
             // Numbers
             c if c.is_ascii_digit() => {
                 return self.scan_number(c, start, start_line, start_column)
             }
 
+            // Raw strings: r"..." and r#"..."#
+            'r' if self.input[self.pos..].trim_start_matches('#').starts_with('"') => {
+                return self.scan_raw_string(start, start_line, start_column)
+            }
+
             // Identifiers and keywords
             c if c.is_alphabetic() || c == '_' => {
                 return self.scan_identifier(c, start, start_line, start_column)
@@ -165,39 +220,88 @@ impl<'a> Lexer<'a> {
         Token::new(kind, Span::new(start, self.pos, start_line, start_column), literal)
     }
 
+    /// A `"..."` or `"""..."""` string, whose literal is the source text,
+    /// quotes included; the parser decodes it. An unterminated string is an
+    /// `Error` token.
     fn scan_string(&mut self, start: usize, start_line: usize, start_column: usize) -> Token {
-        let content_start = self.pos;
-        while let Some(&ch) = self.peek() {
-            if ch == '"' {
-                break;
-            }
-            if ch == '\\' {
-                self.advance();
-                self.advance(); // Skip escaped character
-            } else {
-                self.advance();
+        let multi_line = self.input[self.pos..].starts_with("\"\"");
+        if multi_line {
+            self.advance();
+            self.advance();
+        }
+        let terminated = loop {
+            match self.advance() {
+                None => break false,
+                Some('\\') => {
+                    self.advance(); // Skip escaped character
+                }
+                Some('"') if !multi_line => break true,
+                Some('"') if self.input[self.pos..].starts_with("\"\"") => {
+                    self.advance();
+                    self.advance();
+                    break true;
+                }
+                Some(_) => {}
             }
+        };
+        self.string_token(terminated, start, start_line, start_column)
+    }
+
+    /// A `r"..."` string, or `r#"..."#` with as many `#` as needed to
+    /// contain `"#`; the `r` has been consumed
+    fn scan_raw_string(&mut self, start: usize, start_line: usize, start_column: usize) -> Token {
+        let mut closing = String::from("\"");
+        while self.peek() == Some(&'#') {
+            self.advance();
+            closing.push('#');
         }
-        let content_end = self.pos;
+        self.advance(); // Opening quote
+        let terminated = loop {
+            if self.input[self.pos..].starts_with(&closing) {
+                closing.chars().for_each(|_| {
+                    self.advance();
+                });
+                break true;
+            }
+            if self.advance().is_none() {
+                break false;
+            }
+        };
+        self.string_token(terminated, start, start_line, start_column)
+    }
 
-        // Consume closing quote
-        if self.peek() == Some(&'"') {
+    /// `with_eq` if the next character is `=`, which is consumed
+    fn then_eq(&mut self, alone: TokenKind, with_eq: TokenKind) -> TokenKind {
+        if self.peek() == Some(&'=') {
             self.advance();
+            with_eq
+        } else {
+            alone
         }
+    }
 
-        let content = &self.input[content_start..content_end];
-        Token::new(
-            TokenKind::StringLit,
-            Span::new(start, self.pos, start_line, start_column),
-            content,
-        )
+    fn string_token(&self, terminated: bool, start: usize, start_line: usize, start_column: usize) -> Token {
+        let kind = if terminated { TokenKind::StringLit } else { TokenKind::Error };
+        Token::new(kind, Span::new(start, self.pos, start_line, start_column), &self.input[start..self.pos])
     }
 
-    fn scan_number(&mut self, _first: char, start: usize, start_line: usize, start_column: usize) -> Token {
+    /// A decimal number, or a `0x`, `0o` or `0b` integer, with `_` allowed
+    /// between digits. Every letter and digit after a radix prefix is taken,
+    /// so the parser reports `0b102` as one invalid literal.
+    fn scan_number(&mut self, first: char, start: usize, start_line: usize, start_column: usize) -> Token {
         let mut is_float = false;
 
+        if first == '0' && matches!(self.peek(), Some('x' | 'o' | 'b')) {
+            self.advance();
+            while self.peek().is_some_and(|ch| ch.is_ascii_alphanumeric() || *ch == '_') {
+                self.advance();
+            }
+            let literal = &self.input[start..self.pos];
+            return Token::new(TokenKind::IntLit, Span::new(start, self.pos, start_line, start_column), literal);
+        }
+
         while let Some(&ch) = self.peek() {
-            if ch.is_ascii_digit() {
+            if ch.is_ascii_digit() || ch == '_' {
                 self.advance();
             } else if ch == '.' && !is_float {
                 // Look ahead to see if this is a float
@@ -248,11 +352,27 @@ impl<'a> Lexer<'a> {
         Token::new(kind, Span::new(start, self.pos, start_line, start_column), literal)
     }
 
+    fn scan_label(&mut self, start: usize, start_line: usize, start_column: usize) -> Token {
+        while let Some(&ch) = self.peek() {
+            if ch.is_alphanumeric() || ch == '_' {
+                self.advance();
+            } else {
+                break;
+            }
+        }
+
+        let literal = &self.input[start..self.pos];
+        Token::new(TokenKind::Label, Span::new(start, self.pos, start_line, start_column), literal)
+    }
+
     fn keyword_or_ident(&self, s: &str) -> TokenKind {
         match s {
             // Standard keywords
             "fn" => TokenKind::Fn,
             "struct" => TokenKind::Struct,
+            "enum" => TokenKind::Enum,
+            "trait" => TokenKind::Trait,
+            "impl" => TokenKind::Impl,
             "effect" => TokenKind::Effect,
             "where" => TokenKind::Where,
             "pre" => TokenKind::Pre,
@@ -270,7 +390,18 @@ impl<'a> Lexer<'a> {
             "restrict" => TokenKind::Restrict,
             "match" => TokenKind::Match,
             "use" => TokenKind::Use,
+            "pub" => TokenKind::Pub,
             "op" => TokenKind::Op,
+            "while" => TokenKind::While,
+            "for" => TokenKind::For,
+            "in" => TokenKind::In,
+            "loop" => TokenKind::Loop,
+            "break" => TokenKind::Break,
+            "continue" => TokenKind::Continue,
+            "perform" => TokenKind::Perform,
+            "handle" => TokenKind::Handle,
+            "with" => TokenKind::With,
+            "resume" => TokenKind::Resume,
             "true" => TokenKind::True,
             "false" => TokenKind::False,
             "async" => TokenKind::Ident, // Handled as modifier
@@ -323,57 +454,140 @@ impl<'a> Lexer<'a> {
         self.chars.peek()
     }
 
-    fn skip_whitespace_and_comments(&mut self) {
-        loop {
-            match self.peek() {
-                Some(&ch) if ch.is_whitespace() => {
+    /// Consume one run of whitespace or one comment, saying which it was
+    fn scan_trivia(&mut self) -> Option<TokenKind> {
+        let remaining = &self.input[self.pos..];
+        if remaining.starts_with(char::is_whitespace) {
+            while self.peek().is_some_and(|ch| ch.is_whitespace()) {
+                self.advance();
+            }
+            Some(TokenKind::Whitespace)
+        } else if remaining.starts_with("//") {
+            // Line comment
+            while self.peek().is_some_and(|&ch| ch != '\n') {
+                self.advance();
+            }
+            Some(TokenKind::LineComment)
+        } else if remaining.starts_with("/*") || remaining.starts_with("(*") {
+            // Block comment, or EBNF-style comment (* ... *)
+            let close = if remaining.starts_with("/*") { '/' } else { ')' };
+            self.advance(); // consume / or (
+            self.advance(); // consume *
+            while let Some(ch) = self.advance() {
+                if ch == '*' && self.peek() == Some(&close) {
                     self.advance();
+                    break;
                 }
-                Some(&'/') => {
-                    // Look ahead for comment
-                    let remaining = &self.input[self.pos..];
-                    if remaining.starts_with("//") {
-                        // Line comment
-                        while let Some(&ch) = self.peek() {
-                            if ch == '\n' {
-                                break;
-                            }
-                            self.advance();
-                        }
-                    } else if remaining.starts_with("/*") {
-                        // Block comment
-                        self.advance(); // consume /
-                        self.advance(); // consume *
-                        while let Some(ch) = self.advance() {
-                            if ch == '*' && self.peek() == Some(&'/') {
-                                self.advance();
-                                break;
-                            }
-                        }
-                    } else {
-                        break;
-                    }
-                }
-                Some(&'(') => {
-                    // Check for EBNF-style comment (* ... *)
-                    let remaining = &self.input[self.pos..];
-                    if remaining.starts_with("(*") {
-                        self.advance(); // consume (
-                        self.advance(); // consume *
-                        while let Some(ch) = self.advance() {
-                            if ch == '*' && self.peek() == Some(&')') {
-                                self.advance();
-                                break;
-                            }
-                        }
-                    } else {
-                        break;
-                    }
+            }
+            Some(TokenKind::BlockComment)
+        } else {
+            None
+        }
+    }
+}
+
+/// The value of an `IntLit` token, or `None` if it does not fit in an `Int`
+pub fn int_value(literal: &str) -> Option<i64> {
+    signed_int_value(literal, false)
+}
+
+/// The value of an `IntLit` token written after a minus, which reaches one
+/// further than `int_value`: `-9223372036854775808` is the least `Int`
+pub fn negated_int_value(literal: &str) -> Option<i64> {
+    signed_int_value(literal, true)
+}
+
+fn signed_int_value(literal: &str, negative: bool) -> Option<i64> {
+    let digits = literal.replace('_', "");
+    let (radix, digits) = match digits.get(..2) {
+        Some("0x") => (16, &digits[2..]),
+        Some("0o") => (8, &digits[2..]),
+        Some("0b") => (2, &digits[2..]),
+        _ => (10, &digits[..]),
+    };
+    // `from_str_radix` would accept a sign
+    if digits.starts_with(['+', '-']) {
+        return None;
+    }
+    let digits = if negative { format!("-{}", digits) } else { digits.to_string() };
+    i64::from_str_radix(&digits, radix).ok()
+}
+
+/// The value of a `FloatLit` or `IntLit` token
+pub fn float_value(literal: &str) -> Option<f64> {
+    literal.replace('_', "").parse().ok().or_else(|| int_value(literal).map(|n| n as f64))
+}
+
+/// The text of a string literal token between its delimiters
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StringContents<'a> {
+    pub text: &'a str,
+    /// Where `text` starts in the literal
+    pub offset: usize,
+    /// Raw strings have no escapes and no interpolation
+    pub raw: bool,
+}
+
+/// Split a `StringLit` token's literal into its delimiters and contents. A
+/// line break right after the opening `"""` is not part of the string.
+pub fn string_contents(literal: &str) -> StringContents<'_> {
+    if let Some(rest) = literal.strip_prefix('r') {
+        let hashes = rest.len() - rest.trim_start_matches('#').len();
+        let text = &literal[hashes + 2..literal.len() - hashes - 1];
+        return StringContents { text, offset: hashes + 2, raw: true };
+    }
+    if literal.len() >= 6 && literal.starts_with("\"\"\"") {
+        let inner = &literal[3..literal.len() - 3];
+        let skip = if inner.starts_with('\n') {
+            1
+        } else if inner.starts_with("\r\n") {
+            2
+        } else {
+            0
+        };
+        return StringContents { text: &inner[skip..], offset: 3 + skip, raw: false };
+    }
+    StringContents { text: &literal[1..literal.len() - 1], offset: 1, raw: false }
+}
+
+/// Decode the escapes of a string: `\n`, `\r`, `\t`, `\0`, `\\`, `\"`,
+/// `\'`, `\{`, `\}` and `\u{...}`. A `\` at the end of a line joins it to
+/// the next, dropping that line's indentation. The error holds where the bad
+/// escape starts and what is wrong with it.
+pub fn unescape(text: &str) -> Result<String, (usize, String)> {
+    let mut value = String::with_capacity(text.len());
+    let mut chars = text.char_indices().peekable();
+    while let Some((i, ch)) = chars.next() {
+        if ch != '\\' {
+            value.push(ch);
+            continue;
+        }
+        match chars.next().map(|(_, escape)| escape) {
+            Some('n') => value.push('\n'),
+            Some('r') => value.push('\r'),
+            Some('t') => value.push('\t'),
+            Some('0') => value.push('\0'),
+            Some(escape @ ('\\' | '"' | '\'' | '{' | '}')) => value.push(escape),
+            Some('\n' | '\r') => {
+                while chars.next_if(|(_, ch)| ch.is_whitespace()).is_some() {}
+            }
+            Some('u') => {
+                let hex = text[i + 2..].strip_prefix('{').and_then(|rest| rest.split_once('}')).map_or("", |(hex, _)| hex);
+                let ch = Some(hex)
+                    .filter(|hex| (1..=6).contains(&hex.len()) && hex.chars().all(|c| c.is_ascii_hexdigit()))
+                    .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                    .and_then(char::from_u32)
+                    .ok_or_else(|| (i, "invalid unicode escape; expected `\\u{...}` with 1 to 6 hex digits".to_string()))?;
+                value.push(ch);
+                for _ in 0..hex.len() + 2 {
+                    chars.next();
                 }
-                _ => break,
             }
+            Some(escape) => return Err((i, format!("unknown escape `\\{}`", escape))),
+            None => return Err((i, "expected an escape after `\\`".to_string())),
         }
     }
+    Ok(value)
 }
 
 #[cfg(test)]
@@ -445,7 +659,35 @@ mod tests {
         let tokens = lexer.tokenize();
 
         assert_eq!(tokens[0].kind, TokenKind::StringLit);
-        assert_eq!(tokens[0].literal, "hello world");
+        assert_eq!(tokens[0].literal, "\"hello world\"");
+    }
+
+    #[test]
+    fn test_string_forms() {
+        let source = r####""a\"b" r"c\d" r#"say "hi""# """
+two "lines"
+""" "open" "####;
+        let tokens = Lexer::new(source).tokenize();
+        let contents: Vec<_> = tokens[..4].iter().map(|t| string_contents(&t.literal)).collect();
+        assert_eq!(contents[0], StringContents { text: "a\\\"b", offset: 1, raw: false });
+        assert_eq!(contents[1], StringContents { text: "c\\d", offset: 2, raw: true });
+        assert_eq!(contents[2], StringContents { text: "say \"hi\"", offset: 3, raw: true });
+        assert_eq!(contents[3], StringContents { text: "two \"lines\"\n", offset: 4, raw: false });
+        assert_eq!(tokens[4].kind, TokenKind::StringLit);
+
+        let unterminated = Lexer::new("\"abc").tokenize();
+        assert_eq!(unterminated[0].kind, TokenKind::Error);
+        assert_eq!(Lexer::new("r#[x]").tokenize()[0].kind, TokenKind::Ident);
+    }
+
+    #[test]
+    fn test_unescape() {
+        assert_eq!(unescape(r#"a\nb\t\"c\"\\\{\}"#), Ok("a\nb\t\"c\"\\{}".to_string()));
+        assert_eq!(unescape(r"\u{48}\u{1F600}!"), Ok("H\u{1F600}!".to_string()));
+        assert_eq!(unescape("one \\\n    two"), Ok("one two".to_string()));
+        assert_eq!(unescape(r"ok \q"), Err((3, "unknown escape `\\q`".to_string())));
+        assert!(unescape(r"\u{110000}").unwrap_err().1.starts_with("invalid unicode escape"));
+        assert!(unescape(r"\u41").is_err());
     }
 
     #[test]
@@ -484,6 +726,38 @@ mod tests {
         assert_eq!(tokens[3].kind, TokenKind::StringLit);
     }
 
+    #[test]
+    fn test_loop_keywords_and_labels() {
+        let mut lexer = Lexer::new("'outer: while for x in loop break 'outer continue");
+        let tokens = lexer.tokenize();
+
+        assert_eq!(tokens[0].kind, TokenKind::Label);
+        assert_eq!(tokens[0].literal, "'outer");
+        assert_eq!(tokens[1].kind, TokenKind::Colon);
+        assert_eq!(tokens[2].kind, TokenKind::While);
+        assert_eq!(tokens[3].kind, TokenKind::For);
+        assert_eq!(tokens[4].kind, TokenKind::Ident);
+        assert_eq!(tokens[5].kind, TokenKind::In);
+        assert_eq!(tokens[6].kind, TokenKind::Loop);
+        assert_eq!(tokens[7].kind, TokenKind::Break);
+        assert_eq!(tokens[8].kind, TokenKind::Label);
+        assert_eq!(tokens[9].kind, TokenKind::Continue);
+    }
+
+    #[test]
+    fn test_range_dots() {
+        let mut lexer = Lexer::new("a[1..2] 1.5");
+        let tokens = lexer.tokenize();
+
+        assert_eq!(tokens[2].kind, TokenKind::IntLit);
+        assert_eq!(tokens[3].kind, TokenKind::DotDot);
+        assert_eq!(tokens[4].kind, TokenKind::IntLit);
+        assert_eq!(tokens[6].kind, TokenKind::FloatLit);
+
+        let tokens = Lexer::new("1..=9").tokenize();
+        assert_eq!(tokens[1].kind, TokenKind::DotDotEq);
+    }
+
     #[test]
     fn test_line_comments() {
         let mut lexer = Lexer::new("fn // comment\nmain");
@@ -503,4 +777,51 @@ mod tests {
         assert_eq!(tokens[1].kind, TokenKind::Ident);
         assert_eq!(tokens[1].literal, "main");
     }
+
+    #[test]
+    fn test_trivia_tokens() {
+        let source = "fn /* block */ main // line\n(* ebnf *)";
+        let tokens = Lexer::new(source).with_trivia().tokenize();
+        let kinds: Vec<&TokenKind> = tokens.iter().map(|t| &t.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                &TokenKind::Fn,
+                &TokenKind::Whitespace,
+                &TokenKind::BlockComment,
+                &TokenKind::Whitespace,
+                &TokenKind::Ident,
+                &TokenKind::Whitespace,
+                &TokenKind::LineComment,
+                &TokenKind::Whitespace,
+                &TokenKind::BlockComment,
+                &TokenKind::Eof,
+            ]
+        );
+        let text: String = tokens.iter().map(|t| &source[t.span.start..t.span.end]).collect();
+        assert_eq!(text, source);
+        assert_eq!(tokens[6].literal, "// line");
+    }
+
+    #[test]
+    fn test_numeric_tokens() {
+        let tokens = Lexer::new("0xFF_FF 0o17 0b1010 1_000 2_5.5_0 0b102 % ^ << <<= += -= *= /= %= &= |= ^= |x|=> >>=").tokenize();
+        let kinds: Vec<TokenKind> = tokens.iter().map(|t| t.kind.clone()).collect();
+        use TokenKind::*;
+        assert_eq!(kinds, vec![
+            IntLit, IntLit, IntLit, IntLit, FloatLit, IntLit, Percent, Caret, LtLt, LtLtEq, PlusEq, MinusEq, StarEq,
+            SlashEq, PercentEq, AmpersandEq, PipeEq, CaretEq, Pipe, Ident, Pipe, FatArrow, Gt, GtEq, Eof,
+        ]);
+        let values: Vec<Option<i64>> = tokens[..6].iter().map(|t| int_value(&t.literal)).collect();
+        assert_eq!(values, vec![Some(0xFFFF), Some(0o17), Some(0b1010), Some(1000), None, None]);
+        assert_eq!(float_value(&tokens[4].literal), Some(25.5));
+        assert_eq!(int_value("0x7FFF_FFFF_FFFF_FFFF"), Some(i64::MAX));
+        assert_eq!(int_value("0x8000_0000_0000_0000"), None);
+        assert_eq!(negated_int_value("0x8000_0000_0000_0000"), Some(i64::MIN));
+        assert_eq!(negated_int_value("9223372036854775808"), Some(i64::MIN));
+        assert_eq!(negated_int_value("9223372036854775809"), None);
+        assert_eq!(negated_int_value("0x-1"), None);
+        assert_eq!(int_value("0x"), None);
+        assert_eq!(int_value("0x+1"), None);
+    }
 }
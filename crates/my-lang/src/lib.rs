@@ -5,32 +5,43 @@
 //!
 //! - AI model declarations and configuration
 //! - Prompt templates
-//! - AI expressions (query, verify, generate, embed, classify, optimize, test)
+//! - AI expressions (query, verify, generate, embed, classify, optimize, test),
+//!   executed by a pluggable `ai::AiBackend`, recorded to and replayed from cassettes
 //! - AI type constraints (ai_check, ai_valid, ai_format, ai_infer)
 //! - AI effect types (AI<T>)
+//! - Algebraic effects with `perform` and `handle`, tracked in `Effect<T, ...>` rows
 //! - AI-based contracts (pre/post conditions with AI verification)
 
+pub mod ai;
 pub mod ast;
+pub mod cassette;
 pub mod checker;
+pub mod constraints;
+pub mod cst;
+pub mod effects;
+pub mod exhaustiveness;
 pub mod interpreter;
 pub mod lexer;
+pub mod modules;
 pub mod parser;
 pub mod scope;
 pub mod stdlib;
+pub mod structured;
 pub mod token;
 pub mod types;
+pub mod verify;
 
 // Library modules (common utilities and language-specific features)
 #[path = "../lib/mod.rs"]
 pub mod library;
 
 pub use ast::*;
-pub use checker::{check, CheckError, Checker};
+pub use checker::{check, check_modules, CheckError, Checker, ModuleDiagnostics};
 pub use interpreter::{Interpreter, RuntimeError, Value};
 pub use lexer::Lexer;
 pub use parser::{ParseError, ParseResult, Parser};
 pub use scope::{Symbol, SymbolKind, SymbolTable};
-pub use token::{Span, Token, TokenKind};
+pub use token::{FileId, Span, Token, TokenKind};
 pub use types::Ty;
 
 // Library prelude for easy access to common functions and types
@@ -44,6 +55,13 @@ pub fn parse(source: &str) -> ParseResult<Program> {
     parser.parse_program()
 }
 
+/// Parse source code, recovering from syntax errors: returns the program,
+/// with error nodes where it failed to parse, and every error found
+pub fn parse_with_errors(source: &str) -> (Program, Vec<ParseError>) {
+    let tokens = Lexer::new(source).tokenize();
+    Parser::new(tokens).parse_with_errors()
+}
+
 /// Parse and type-check source code
 pub fn compile(source: &str) -> Result<Program, CompileError> {
     let program = parse(source).map_err(CompileError::Parse)?;
@@ -53,10 +71,21 @@ pub fn compile(source: &str) -> Result<Program, CompileError> {
 
 /// Parse, type-check, and evaluate source code
 pub fn eval(source: &str) -> Result<Value, EvalError> {
+    eval_with_contracts(source, true)
+}
+
+/// Like `eval`, with runtime contract checks switched on or off
+pub fn eval_with_contracts(source: &str, check_contracts: bool) -> Result<Value, EvalError> {
+    let mut interpreter = Interpreter::new();
+    interpreter.check_contracts = check_contracts;
+    eval_in(&mut interpreter, source)
+}
+
+/// Like `eval`, with a configured interpreter
+pub fn eval_in(interpreter: &mut Interpreter, source: &str) -> Result<Value, EvalError> {
     let program = parse(source).map_err(EvalError::Parse)?;
     // Type checking is optional for the interpreter
     let _ = check(&program);
-    let mut interpreter = Interpreter::new();
     interpreter.run(&program).map_err(EvalError::Runtime)
 }
 
@@ -222,4 +251,12 @@ mod tests {
             assert_eq!(f.params.len(), 5);
         }
     }
+
+    #[test]
+    fn test_examples_type_check() {
+        for source in [include_str!("../../../examples/hello.ml"), include_str!("../../../examples/demo.ml")] {
+            let result = compile(source);
+            assert!(result.is_ok(), "{:?}", result.err());
+        }
+    }
 }
@@ -0,0 +1,435 @@
+//! Module loader for multi-file programs
+//!
+//! `use a::b::{c, d};` imports the `pub` declarations `c` and `d` of the
+//! module `a::b`, and `use a::b;` imports the module itself, whose `pub`
+//! functions are then called as `b::name(..)`. A module path names a file
+//! relative to the directory of the entry file, `a::b` being `a/b.ml` or
+//! `a/b/mod.ml`; a path starting with the name of a package dependency is
+//! looked up in that package's root instead, with the package itself being
+//! its `lib.ml`. Paths under `std` name the built-in library and load nothing.
+//!
+//! Loading yields a [`ModuleGraph`] of every module reachable from the entry
+//! file, which is checked and run module by module. Functions live in the
+//! namespace of their module, next to what it imports; types, traits and
+//! impls share one namespace, as the runtime keys them by name. An item may
+//! not be imported under a name its module already declares or imports.
+
+use crate::ast::*;
+use crate::lexer::Lexer;
+use crate::parser::{ParseError, Parser};
+use crate::token::{FileId, Span};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// Extension of source files
+pub const EXTENSION: &str = "ml";
+
+#[derive(Debug, Error)]
+pub enum ModuleError {
+    #[error("cannot read '{}': {message}", .path.display())]
+    Io { path: PathBuf, message: String },
+
+    #[error("{}: {error}", .path.display())]
+    Parse { path: PathBuf, error: ParseError },
+
+    #[error("unresolved import '{import}' at {}:{line}:{column}", .path.display())]
+    UnresolvedImport { import: String, path: PathBuf, line: usize, column: usize },
+
+    #[error("'{item}' is private to module '{module}', imported at {}:{line}:{column}", .path.display())]
+    PrivateItem { item: String, module: String, path: PathBuf, line: usize, column: usize },
+
+    #[error("'{item}' imported at {}:{line}:{column} is already declared in that module", .path.display())]
+    ImportClash { item: String, path: PathBuf, line: usize, column: usize },
+
+    #[error("import cycle: {}", .0.join(" -> "))]
+    Cycle(Vec<String>),
+}
+
+/// What a `use` declaration brings into scope
+#[derive(Debug, Clone, PartialEq)]
+pub enum Import {
+    /// A `pub` declaration of the module with file id `module`
+    Item { module: FileId, name: String },
+    /// The module with file id `module`, whose `pub` functions are reached as `alias::name`
+    Module { module: FileId, alias: String },
+}
+
+#[derive(Debug, Clone)]
+pub struct Module {
+    /// `a::b` for `a/b.ml`; the file stem for the entry file
+    pub name: String,
+    pub path: PathBuf,
+    pub program: Program,
+    pub imports: Vec<Import>,
+}
+
+/// The modules of a program, indexed by the file ids of their spans; the
+/// entry file is module 0
+#[derive(Debug, Clone)]
+pub struct ModuleGraph {
+    pub modules: Vec<Module>,
+    /// File ids with every module after the modules it imports
+    pub order: Vec<FileId>,
+}
+
+impl ModuleGraph {
+    pub fn entry(&self) -> &Module {
+        &self.modules[0]
+    }
+
+    pub fn module(&self, file: FileId) -> &Module {
+        &self.modules[file as usize]
+    }
+
+    /// `path:line:column` of a span
+    pub fn location(&self, span: Span) -> String {
+        format!("{}:{}:{}", self.module(span.file).path.display(), span.line, span.column)
+    }
+
+    /// The declarations the module `file` imports, renamed as it uses them:
+    /// imported items keep their names, and the `pub` functions of imported
+    /// modules become `alias::name`. The types, traits and impls of imported
+    /// modules come along, as the signatures of what is imported refer to them.
+    pub fn imported_items(&self, file: FileId) -> Vec<TopLevel> {
+        let mut items = Vec::new();
+        let mut sources: Vec<FileId> = Vec::new();
+        for import in &self.module(file).imports {
+            match import {
+                Import::Item { module, name } => {
+                    let program = &self.module(*module).program;
+                    items.extend(program.items.iter().filter(|i| i.name().is_some_and(|n| &n.name == name)).cloned());
+                    sources.push(*module);
+                }
+                Import::Module { module, alias } => {
+                    for item in &self.module(*module).program.items {
+                        if let (TopLevel::Function(f), true) = (item, item.is_public()) {
+                            let mut f = f.clone();
+                            f.name.name = format!("{}::{}", alias, f.name.name);
+                            items.push(TopLevel::Function(f));
+                        }
+                    }
+                    sources.push(*module);
+                }
+            }
+        }
+        sources.sort_unstable();
+        sources.dedup();
+        for module in sources {
+            for item in &self.module(module).program.items {
+                let shared = matches!(item, TopLevel::Struct(_) | TopLevel::Enum(_) | TopLevel::Trait(_) | TopLevel::Impl(_));
+                if shared && !items.contains(item) {
+                    items.push(item.clone());
+                }
+            }
+        }
+        items
+    }
+}
+
+/// Loads the modules of a program, resolving package paths in the roots
+/// registered with [`Loader::package`]
+#[derive(Debug, Clone, Default)]
+pub struct Loader {
+    packages: HashMap<String, PathBuf>,
+}
+
+impl Loader {
+    pub fn new() -> Self {
+        Loader::default()
+    }
+
+    /// Resolve module paths starting with `name` in the directory `root`
+    pub fn package(mut self, name: &str, root: impl Into<PathBuf>) -> Self {
+        self.packages.insert(name.to_string(), root.into());
+        self
+    }
+
+    /// Load the program whose entry file is `entry`, with every module it imports
+    pub fn load(&self, entry: &Path) -> Result<ModuleGraph, ModuleError> {
+        let name = entry.file_stem().map_or_else(String::new, |s| s.to_string_lossy().into_owned());
+        let mut load = Load {
+            loader: self,
+            base: entry.parent().map(Path::to_path_buf).unwrap_or_default(),
+            modules: Vec::new(),
+            files: HashMap::new(),
+            stack: Vec::new(),
+            order: Vec::new(),
+        };
+        load.visit(entry.to_path_buf(), name)?;
+        Ok(ModuleGraph { modules: load.modules, order: load.order })
+    }
+}
+
+/// The state of one `Loader::load`
+struct Load<'a> {
+    loader: &'a Loader,
+    /// Directory of the entry file
+    base: PathBuf,
+    modules: Vec<Module>,
+    /// File id of each loaded file, by canonical path
+    files: HashMap<PathBuf, FileId>,
+    /// Modules being loaded, importers first
+    stack: Vec<FileId>,
+    order: Vec<FileId>,
+}
+
+impl Load<'_> {
+    /// Load the module in `path` and the modules it imports
+    fn visit(&mut self, path: PathBuf, name: String) -> Result<FileId, ModuleError> {
+        let canonical = path.canonicalize().unwrap_or_else(|_| path.clone());
+        if let Some(&id) = self.files.get(&canonical) {
+            if let Some(start) = self.stack.iter().position(|&m| m == id) {
+                let mut cycle: Vec<String> = self.stack[start..].iter().map(|&m| self.modules[m as usize].name.clone()).collect();
+                cycle.push(name);
+                return Err(ModuleError::Cycle(cycle));
+            }
+            return Ok(id);
+        }
+
+        let source = std::fs::read_to_string(&path)
+            .map_err(|e| ModuleError::Io { path: path.clone(), message: e.to_string() })?;
+        let id = self.modules.len() as FileId;
+        let tokens = Lexer::new(&source).with_file(id).tokenize();
+        let program = Parser::new(tokens)
+            .parse_program()
+            .map_err(|error| ModuleError::Parse { path: path.clone(), error })?;
+        let decls: Vec<ImportDecl> = program
+            .items
+            .iter()
+            .filter_map(|item| match item {
+                TopLevel::Import(decl) => Some(decl.clone()),
+                _ => None,
+            })
+            .collect();
+        self.modules.push(Module { name, path, program, imports: Vec::new() });
+        self.files.insert(canonical, id);
+
+        self.stack.push(id);
+        for decl in &decls {
+            let imports = self.resolve(decl)?;
+            self.modules[id as usize].imports.extend(imports);
+        }
+        self.stack.pop();
+        self.order.push(id);
+        Ok(id)
+    }
+
+    /// Load the module a `use` declaration names, and check what it imports from it
+    fn resolve(&mut self, decl: &ImportDecl) -> Result<Vec<Import>, ModuleError> {
+        let segments: Vec<&str> = decl.path.iter().map(|s| s.name.as_str()).collect();
+        if segments.first() == Some(&"std") {
+            return Ok(Vec::new());
+        }
+        let unresolved = |load: &Self, import: String| {
+            let path = load.modules[decl.span.file as usize].path.clone();
+            ModuleError::UnresolvedImport { import, path, line: decl.span.line, column: decl.span.column }
+        };
+
+        // `use a::b;` imports the module `a::b`, or else the item `b` of `a`
+        let (module_path, items) = match &decl.items {
+            Some(items) => (&segments[..], items.iter().map(|i| (i.name.as_str(), i.span)).collect()),
+            None => match (self.find(&segments), segments.split_last()) {
+                (Some(file), Some((alias, _))) => {
+                    let module = self.visit(file, segments.join("::"))?;
+                    return Ok(vec![Import::Module { module, alias: alias.to_string() }]);
+                }
+                (None, Some((item, module_path))) if !module_path.is_empty() => (module_path, vec![(*item, decl.span)]),
+                _ => return Err(unresolved(self, segments.join("::"))),
+            },
+        };
+
+        let Some(file) = self.find(module_path) else {
+            return Err(unresolved(self, segments.join("::")));
+        };
+        let module_name = module_path.join("::");
+        let module = self.visit(file, module_name.clone())?;
+        let mut imports = Vec::new();
+        for (name, span) in items {
+            let item = self.modules[module as usize].program.items.iter().find(|i| i.name().is_some_and(|n| n.name == name));
+            match item {
+                Some(item) if item.is_public() => {
+                    let importer = &self.modules[decl.span.file as usize];
+                    let declared = importer.program.items.iter().any(|i| i.name().is_some_and(|n| n.name == name));
+                    let imported = importer.imports.iter().chain(&imports).any(|i| {
+                        matches!(i, Import::Item { module: m, name: n } if n == name && *m != module)
+                    });
+                    if declared || imported {
+                        return Err(ModuleError::ImportClash {
+                            item: name.to_string(),
+                            path: importer.path.clone(),
+                            line: span.line,
+                            column: span.column,
+                        });
+                    }
+                    imports.push(Import::Item { module, name: name.to_string() });
+                }
+                Some(_) => {
+                    return Err(ModuleError::PrivateItem {
+                        item: name.to_string(),
+                        module: module_name,
+                        path: self.modules[decl.span.file as usize].path.clone(),
+                        line: span.line,
+                        column: span.column,
+                    });
+                }
+                None => return Err(unresolved(self, format!("{}::{}", module_name, name))),
+            }
+        }
+        Ok(imports)
+    }
+
+    /// The file of the module at `segments`, if there is one
+    fn find(&self, segments: &[&str]) -> Option<PathBuf> {
+        let (first, rest) = segments.split_first()?;
+        let (mut dir, rest) = match self.loader.packages.get(*first) {
+            Some(root) if rest.is_empty() => return Some(root.join("lib").with_extension(EXTENSION)).filter(|p| p.is_file()),
+            Some(root) => (root.clone(), rest),
+            None => (self.base.clone(), segments),
+        };
+        let (last, parents) = rest.split_last()?;
+        for parent in parents {
+            dir.push(parent);
+        }
+        [dir.join(last).with_extension(EXTENSION), dir.join(last).join("mod").with_extension(EXTENSION)]
+            .into_iter()
+            .find(|p| p.is_file())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::{Interpreter, Value};
+
+    /// A directory of source files, removed when dropped
+    struct Sources(PathBuf);
+
+    impl Sources {
+        fn new(name: &str, files: &[(&str, &str)]) -> Self {
+            let dir = std::env::temp_dir().join(format!("my-lang-{}-{}", name, std::process::id()));
+            for (path, source) in files {
+                let path = dir.join(path);
+                std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+                std::fs::write(path, source).unwrap();
+            }
+            Sources(dir)
+        }
+
+        fn load(&self, loader: &Loader) -> Result<ModuleGraph, ModuleError> {
+            loader.load(&self.0.join("main.ml"))
+        }
+    }
+
+    impl Drop for Sources {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn test_load_and_run() {
+        let sources = Sources::new("modules-run", &[
+            ("main.ml", r#"
+use shapes::{Square, area};
+use util::math;
+fn helper() -> Int { return 100; }
+fn main() -> Int { return area(Square { side: 3 }) + math::twice(helper()); }
+"#),
+            ("shapes.ml", r#"
+pub struct Square { side: Int }
+pub fn area(s: Square) -> Int { return helper(s.side); }
+fn helper(x: Int) -> Int { return x * x; }
+"#),
+            ("util/math/mod.ml", "pub fn twice(x: Int) -> Int { return x * 2; }"),
+        ]);
+        let graph = sources.load(&Loader::new()).unwrap();
+        let names: Vec<&str> = graph.order.iter().map(|&f| graph.module(f).name.as_str()).collect();
+        assert_eq!(names, vec!["shapes", "util::math", "main"]);
+        assert_eq!(
+            graph.entry().imports,
+            vec![
+                Import::Item { module: 1, name: "Square".to_string() },
+                Import::Item { module: 1, name: "area".to_string() },
+                Import::Module { module: 2, alias: "math".to_string() },
+            ]
+        );
+        assert!(crate::check_modules(&graph).is_ok());
+        let result = Interpreter::new().run_modules(&graph);
+        assert!(matches!(result, Ok(Value::Int(209))), "{:?}", result);
+    }
+
+    #[test]
+    fn test_check_errors_name_their_file() {
+        let sources = Sources::new("modules-check", &[
+            ("main.ml", "use lib;\nfn main() -> Int { return lib::count(); }"),
+            ("lib.ml", "pub fn count() -> Int {\n    return missing;\n}"),
+        ]);
+        let graph = sources.load(&Loader::new()).unwrap();
+        let errors = crate::check_modules(&graph).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        let (file, error) = &errors[0];
+        assert_eq!(graph.module(*file).name, "lib");
+        assert_eq!(error.to_string(), "undefined variable 'missing' at line 2, column 12");
+    }
+
+    #[test]
+    fn test_private_functions() {
+        let sources = Sources::new("modules-private", &[
+            ("main.ml", "use util;\nfn main() -> Int {\n    return util::secret() + util::open();\n}"),
+            ("util.ml", "fn secret() -> Int { return 1; }\npub fn open() -> Int { return secret(); }"),
+        ]);
+        let graph = sources.load(&Loader::new()).unwrap();
+        let errors = crate::check_modules(&graph).unwrap_err();
+        let messages: Vec<String> = errors.iter().map(|(_, e)| e.to_string()).collect();
+        assert_eq!(messages, vec!["'util::secret' is private to module 'util' at line 3, column 12"]);
+    }
+
+    #[test]
+    fn test_import_errors() {
+        let sources = Sources::new("modules-errors", &[
+            ("main.ml", "use a;\nuse b::{hidden};\nfn main() { }"),
+            ("a.ml", "use b;\npub fn f() { }"),
+            ("b.ml", "use a::{f};\nfn hidden() { }"),
+        ]);
+        let err = sources.load(&Loader::new()).unwrap_err();
+        assert_eq!(err.to_string(), "import cycle: a -> b -> a");
+
+        std::fs::write(sources.0.join("b.ml"), "fn hidden() { }").unwrap();
+        let err = sources.load(&Loader::new()).unwrap_err();
+        assert!(matches!(&err, ModuleError::PrivateItem { item, line: 2, column: 9, .. } if item == "hidden"), "{:?}", err);
+
+        std::fs::write(sources.0.join("main.ml"), "use a::{g};\nfn main() { }").unwrap();
+        let err = sources.load(&Loader::new()).unwrap_err();
+        assert!(matches!(&err, ModuleError::UnresolvedImport { import, line: 1, .. } if import == "a::g"), "{:?}", err);
+
+        std::fs::write(sources.0.join("main.ml"), "use std::io;\nuse c::d;\nfn main() { }").unwrap();
+        let err = sources.load(&Loader::new()).unwrap_err();
+        assert!(matches!(&err, ModuleError::UnresolvedImport { import, line: 2, .. } if import == "c::d"), "{:?}", err);
+
+        // An import may not reuse the name of a declaration or of another import
+        std::fs::write(sources.0.join("b.ml"), "pub fn f() { }").unwrap();
+        std::fs::write(sources.0.join("main.ml"), "use a::{f};\nfn f() { }\nfn main() { }").unwrap();
+        let err = sources.load(&Loader::new()).unwrap_err();
+        assert!(matches!(&err, ModuleError::ImportClash { item, line: 1, column: 9, .. } if item == "f"), "{:?}", err);
+
+        std::fs::write(sources.0.join("a.ml"), "pub fn f() { }").unwrap();
+        std::fs::write(sources.0.join("main.ml"), "use a::{f};\nuse b::{f};\nfn main() { }").unwrap();
+        let err = sources.load(&Loader::new()).unwrap_err();
+        assert!(matches!(&err, ModuleError::ImportClash { item, line: 2, column: 9, .. } if item == "f"), "{:?}", err);
+    }
+
+    #[test]
+    fn test_package_roots() {
+        let sources = Sources::new("modules-packages", &[
+            ("app/main.ml", "use text;\nuse text::case::{upper};\nfn main() -> String { return upper(text::greeting()); }"),
+            ("text/lib.ml", "pub fn greeting() -> String { return \"hi\"; }"),
+            ("text/case.ml", "pub fn upper(s: String) -> String { return str_upper(s); }"),
+        ]);
+        let loader = Loader::new().package("text", sources.0.join("text"));
+        let graph = loader.load(&sources.0.join("app/main.ml")).unwrap();
+        assert_eq!(graph.modules.len(), 3);
+        let result = Interpreter::new().run_modules(&graph);
+        assert!(matches!(&result, Ok(Value::String(s)) if s == "HI"), "{:?}", result);
+    }
+}
@@ -3,42 +3,184 @@
 //! Implements a recursive descent parser for the complete grammar.
 
 use crate::ast::*;
+use crate::cst::SyntaxKind;
+use crate::lexer::{float_value, int_value, negated_int_value, string_contents, unescape, Lexer};
+use crate::library::mylang::prompt::PromptTemplate;
 use crate::token::{Span, Token, TokenKind};
+use std::ops::Range;
 use thiserror::Error;
 
 #[derive(Error, Debug, Clone)]
 pub enum ParseError {
-    #[error("unexpected token: expected {expected}, found {found} at line {line}, column {column}")]
+    #[error("unexpected token: expected {expected}, found {found} at line {}, column {}", .span.line, .span.column)]
     UnexpectedToken {
         expected: String,
         found: String,
-        line: usize,
-        column: usize,
+        span: Span,
     },
     #[error("unexpected end of input")]
-    UnexpectedEof,
-    #[error("invalid literal: {0}")]
-    InvalidLiteral(String),
+    UnexpectedEof { span: Span },
+    #[error("invalid literal: {literal}")]
+    InvalidLiteral { literal: String, span: Span },
+    #[error("{message} at line {}, column {}", .span.line, .span.column)]
+    InvalidString { message: String, span: Span },
+}
+
+impl ParseError {
+    /// The token the error was found at
+    pub fn span(&self) -> Span {
+        match self {
+            ParseError::UnexpectedToken { span, .. }
+            | ParseError::UnexpectedEof { span }
+            | ParseError::InvalidLiteral { span, .. }
+            | ParseError::InvalidString { span, .. } => *span,
+        }
+    }
 }
 
 pub type ParseResult<T> = Result<T, ParseError>;
 
+/// Name, type parameters, parameters and return type of a function
+type FnSignature = (Ident, Vec<TypeParam>, Vec<Param>, Option<Type>);
+
 pub struct Parser {
     tokens: Vec<Token>,
     pos: usize,
+    /// Errors recovered from so far
+    errors: Vec<ParseError>,
+    /// Declarations, statements and blocks parsed so far, as ranges of token
+    /// indices, for the concrete syntax tree
+    nodes: Vec<(SyntaxKind, Range<usize>)>,
+    /// Whether the head of an `if`, `while`, `for` or `match` is being parsed,
+    /// where `Name {}` is a name followed by an empty body
+    in_head: bool,
 }
 
 impl Parser {
     pub fn new(tokens: Vec<Token>) -> Self {
-        Self { tokens, pos: 0 }
+        Self { tokens, pos: 0, errors: Vec::new(), nodes: Vec::new(), in_head: false }
+    }
+
+    /// The syntax nodes recorded by the last parse, innermost first
+    pub(crate) fn take_nodes(&mut self) -> Vec<(SyntaxKind, Range<usize>)> {
+        std::mem::take(&mut self.nodes)
+    }
+
+    /// Record a syntax node from token `start` up to the current token
+    fn node(&mut self, kind: SyntaxKind, start: usize) {
+        self.nodes.push((kind, start..self.pos));
     }
 
     pub fn parse_program(&mut self) -> ParseResult<Program> {
+        let (program, mut errors) = self.parse_with_errors();
+        if errors.is_empty() {
+            Ok(program)
+        } else {
+            Err(errors.swap_remove(0))
+        }
+    }
+
+    /// Parse the whole program, recovering from errors: a declaration or
+    /// statement that fails to parse becomes an error node, and parsing
+    /// resumes at the next one. Returns the program with every error, in
+    /// source order.
+    pub fn parse_with_errors(&mut self) -> (Program, Vec<ParseError>) {
         let mut items = Vec::new();
         while !self.is_at_end() {
-            items.push(self.parse_top_level()?);
+            let start = self.pos;
+            match self.parse_top_level() {
+                Ok(item) => {
+                    self.node(SyntaxKind::of_item(&item), start);
+                    items.push(item);
+                }
+                Err(error) => {
+                    let span = self.recover(error, start, true);
+                    self.node(SyntaxKind::Error, start);
+                    items.push(TopLevel::Error(span));
+                }
+            }
+        }
+        let mut errors = std::mem::take(&mut self.errors);
+        errors.sort_by_key(|e| e.span().start);
+        (Program { items }, errors)
+    }
+
+    /// Record `error` and skip what failed to parse, returning the span of
+    /// the skipped code
+    fn recover(&mut self, error: ParseError, start: usize, top_level: bool) -> Span {
+        let span = error.span();
+        // A block cut short by a statement's error fails again at the same
+        // token, which is the same problem
+        if self.errors.last().map(ParseError::span) != Some(span) {
+            self.errors.push(error);
+        }
+        self.synchronize(start, top_level);
+        let skipped = self.span_from(span);
+        // Nothing is skipped when the error is at the start of what follows
+        Span { end: skipped.end.max(span.start), ..skipped }
+    }
+
+    /// Skip past a declaration or statement that failed to parse, which
+    /// started at token `start`: up to and including the next `;` outside
+    /// braces, or up to the next `}` closing an enclosing block or the next
+    /// token starting a declaration or, in a block, a statement
+    fn synchronize(&mut self, start: usize, top_level: bool) {
+        // Always skip something, or the same error would come back
+        if self.pos == start {
+            self.advance();
+        }
+        let mut depth = 0usize;
+        while let Some(kind) = self.peek_kind() {
+            match kind {
+                TokenKind::Eof => return,
+                TokenKind::LBrace => depth += 1,
+                TokenKind::RBrace if depth == 0 && !top_level => return,
+                TokenKind::RBrace => depth = depth.saturating_sub(1),
+                TokenKind::Semicolon if depth == 0 => {
+                    self.advance();
+                    return;
+                }
+                _ if depth == 0 && (self.starts_declaration() || !top_level && self.starts_statement()) => return,
+                _ => {}
+            }
+            self.advance();
         }
-        Ok(Program { items })
+    }
+
+    fn starts_declaration(&self) -> bool {
+        match self.peek_kind() {
+            Some(
+                TokenKind::Fn
+                | TokenKind::Struct
+                | TokenKind::Enum
+                | TokenKind::Trait
+                | TokenKind::Impl
+                | TokenKind::Effect
+                | TokenKind::Use
+                | TokenKind::Pub
+                | TokenKind::AiModel
+                | TokenKind::Prompt
+                | TokenKind::HashBracket,
+            ) => true,
+            Some(TokenKind::Ident) => self.peek_literal() == Some("async") && self.next_is(TokenKind::Fn),
+            _ => false,
+        }
+    }
+
+    fn starts_statement(&self) -> bool {
+        matches!(
+            self.peek_kind(),
+            Some(
+                TokenKind::Let
+                    | TokenKind::If
+                    | TokenKind::While
+                    | TokenKind::For
+                    | TokenKind::Loop
+                    | TokenKind::Return
+                    | TokenKind::Break
+                    | TokenKind::Continue
+            )
+        )
     }
 
     // ============================================
@@ -47,19 +189,37 @@ impl Parser {
 
     fn parse_top_level(&mut self) -> ParseResult<TopLevel> {
         // Check for modifiers/attributes first
-        if self.check(TokenKind::HashBracket) {
-            let modifiers = self.parse_attributes()?;
-            return self.parse_top_level_with_modifiers(modifiers);
+        let attrs = if self.check(TokenKind::HashBracket) { Some(self.parse_attributes()?) } else { None };
+
+        // `pub` exports the declaration from its module
+        let public = self.check(TokenKind::Pub);
+        if public {
+            self.advance();
         }
+        let not_exportable = public.then(|| self.error("declaration that can be exported"));
 
+        let mut item = match attrs {
+            Some(attrs) => self.parse_top_level_with_modifiers(attrs)?,
+            None => self.parse_declaration()?,
+        };
+        match not_exportable {
+            Some(error) if !item.set_public() => Err(error),
+            _ => Ok(item),
+        }
+    }
+
+    fn parse_declaration(&mut self) -> ParseResult<TopLevel> {
         match self.peek_kind() {
             Some(TokenKind::Fn) => {
-                Ok(TopLevel::Function(self.parse_fn_decl(vec![])?))
+                Ok(TopLevel::Function(Box::new(self.parse_fn_decl(vec![])?)))
             }
             Some(TokenKind::Ident) if self.peek_literal() == Some("async") => {
-                Ok(TopLevel::Function(self.parse_fn_decl(vec![])?))
+                Ok(TopLevel::Function(Box::new(self.parse_fn_decl(vec![])?)))
             }
             Some(TokenKind::Struct) => Ok(TopLevel::Struct(self.parse_struct_decl(vec![])?)),
+            Some(TokenKind::Enum) => Ok(TopLevel::Enum(self.parse_enum_decl(vec![])?)),
+            Some(TokenKind::Trait) => Ok(TopLevel::Trait(self.parse_trait_decl()?)),
+            Some(TokenKind::Impl) => Ok(TopLevel::Impl(self.parse_impl_decl()?)),
             Some(TokenKind::Effect) => Ok(TopLevel::Effect(self.parse_effect_decl()?)),
             Some(TokenKind::Use) => Ok(TopLevel::Import(self.parse_import_decl()?)),
             Some(TokenKind::Comptime) => Ok(TopLevel::Comptime(self.parse_comptime_decl()?)),
@@ -93,17 +253,21 @@ impl Parser {
         match self.peek_kind() {
             Some(TokenKind::Fn) => {
                 let modifiers = self.attrs_to_fn_modifiers(attrs);
-                Ok(TopLevel::Function(self.parse_fn_decl(modifiers)?))
+                Ok(TopLevel::Function(Box::new(self.parse_fn_decl(modifiers)?)))
             }
             Some(TokenKind::Ident) if self.peek_literal() == Some("async") => {
                 let modifiers = self.attrs_to_fn_modifiers(attrs);
-                Ok(TopLevel::Function(self.parse_fn_decl(modifiers)?))
+                Ok(TopLevel::Function(Box::new(self.parse_fn_decl(modifiers)?)))
             }
             Some(TokenKind::Struct) => {
                 let modifiers = self.attrs_to_struct_modifiers(attrs);
                 Ok(TopLevel::Struct(self.parse_struct_decl(modifiers)?))
             }
-            _ => Err(self.error("fn or struct after attributes")),
+            Some(TokenKind::Enum) => {
+                let modifiers = self.attrs_to_struct_modifiers(attrs);
+                Ok(TopLevel::Enum(self.parse_enum_decl(modifiers)?))
+            }
+            _ => Err(self.error("fn, struct or enum after attributes")),
         }
     }
 
@@ -145,7 +309,7 @@ impl Parser {
         self.expect(TokenKind::RBrace)?;
         let span = self.span_from(start);
 
-        Ok(AiModelDecl { name, attributes, span })
+        Ok(AiModelDecl { name, attributes, public: false, span })
     }
 
     fn parse_ai_model_attr(&mut self) -> ParseResult<AiModelAttr> {
@@ -181,8 +345,9 @@ impl Parser {
         let template = self.parse_string_lit()?;
         self.expect(TokenKind::RBrace)?;
         let span = self.span_from(start);
+        let placeholders = PromptTemplate::new(&template).variables();
 
-        Ok(PromptDecl { name, template, span })
+        Ok(PromptDecl { name, template, placeholders, public: false, span })
     }
 
     // ============================================
@@ -198,18 +363,7 @@ impl Parser {
             modifiers.push(FnModifier::Async);
         }
 
-        self.expect(TokenKind::Fn)?;
-        let name = self.parse_ident()?;
-        self.expect(TokenKind::LParen)?;
-        let params = self.parse_param_list()?;
-        self.expect(TokenKind::RParen)?;
-
-        let return_type = if self.check(TokenKind::Arrow) {
-            self.advance();
-            Some(self.parse_type()?)
-        } else {
-            None
-        };
+        let (name, type_params, params, return_type) = self.parse_fn_signature()?;
 
         let contract = if self.check(TokenKind::Where) {
             Some(self.parse_contract()?)
@@ -223,14 +377,44 @@ impl Parser {
         Ok(FnDecl {
             modifiers,
             name,
+            type_params,
             params,
             return_type,
             contract,
             body,
+            public: false,
             span,
         })
     }
 
+    /// `fn name<T>(params) -> Type`, shared by functions and trait methods
+    fn parse_fn_signature(&mut self) -> ParseResult<FnSignature> {
+        self.expect(TokenKind::Fn)?;
+        let name = self.parse_ident()?;
+
+        let type_params = if self.check(TokenKind::Lt) {
+            self.advance();
+            let params = self.parse_type_params()?;
+            self.expect(TokenKind::Gt)?;
+            params
+        } else {
+            vec![]
+        };
+
+        self.expect(TokenKind::LParen)?;
+        let params = self.parse_param_list()?;
+        self.expect(TokenKind::RParen)?;
+
+        let return_type = if self.check(TokenKind::Arrow) {
+            self.advance();
+            Some(self.parse_type()?)
+        } else {
+            None
+        };
+
+        Ok((name, type_params, params, return_type))
+    }
+
     fn parse_param_list(&mut self) -> ParseResult<Vec<Param>> {
         let mut params = Vec::new();
         if !self.check(TokenKind::RParen) {
@@ -248,11 +432,34 @@ impl Parser {
 
     fn parse_param(&mut self) -> ParseResult<Param> {
         let start = self.current_span();
+
+        // Method receiver: `self`, `&self` or `&mut self`, all typed `Self`
+        let mut mutable = false;
+        if self.check(TokenKind::Ampersand) {
+            self.advance();
+            if self.check(TokenKind::Mut) {
+                self.advance();
+                mutable = true;
+            }
+            if self.peek_literal() != Some("self") {
+                return Err(self.error("self"));
+            }
+        } else if self.check(TokenKind::Mut) {
+            self.advance();
+            mutable = true;
+        }
+        if self.peek_literal() == Some("self") && !self.next_is(TokenKind::Colon) {
+            let name = self.parse_ident()?;
+            let ty = Type::Named(Ident::new("Self", name.span));
+            let span = self.span_from(start);
+            return Ok(Param { name, ty, mutable, span });
+        }
+
         let name = self.parse_ident()?;
         self.expect(TokenKind::Colon)?;
         let ty = self.parse_type()?;
         let span = self.span_from(start);
-        Ok(Param { name, ty, span })
+        Ok(Param { name, ty, mutable, span })
     }
 
     // ============================================
@@ -273,10 +480,18 @@ impl Parser {
             vec![]
         };
 
+        let contract = if self.check(TokenKind::Where) {
+            Some(self.parse_contract()?)
+        } else {
+            None
+        };
+
         self.expect(TokenKind::LBrace)?;
         let mut fields = Vec::new();
         while !self.check(TokenKind::RBrace) && !self.is_at_end() {
+            let first = self.pos;
             fields.push(self.parse_struct_field()?);
+            self.node(SyntaxKind::Field, first);
         }
         self.expect(TokenKind::RBrace)?;
 
@@ -287,19 +502,39 @@ impl Parser {
             name,
             type_params,
             fields,
+            contract,
+            public: false,
             span,
         })
     }
 
-    fn parse_type_params(&mut self) -> ParseResult<Vec<Ident>> {
-        let mut params = vec![self.parse_ident()?];
+    fn parse_type_params(&mut self) -> ParseResult<Vec<TypeParam>> {
+        let mut params = vec![self.parse_type_param()?];
         while self.check(TokenKind::Comma) {
             self.advance();
-            params.push(self.parse_ident()?);
+            params.push(self.parse_type_param()?);
         }
         Ok(params)
     }
 
+    fn parse_type_param(&mut self) -> ParseResult<TypeParam> {
+        let start = self.current_span();
+        let name = self.parse_ident()?;
+
+        let mut bounds = vec![];
+        if self.check(TokenKind::Colon) {
+            self.advance();
+            bounds.push(self.parse_ident()?);
+            while self.check(TokenKind::Plus) {
+                self.advance();
+                bounds.push(self.parse_ident()?);
+            }
+        }
+
+        let span = self.span_from(start);
+        Ok(TypeParam { name, bounds, span })
+    }
+
     fn parse_struct_field(&mut self) -> ParseResult<StructField> {
         let start = self.current_span();
 
@@ -350,6 +585,176 @@ impl Parser {
         Ok(modifiers)
     }
 
+    // ============================================
+    // Traits and Impl Blocks
+    // ============================================
+
+    fn parse_trait_decl(&mut self) -> ParseResult<TraitDecl> {
+        let start = self.current_span();
+        self.expect(TokenKind::Trait)?;
+        let name = self.parse_ident()?;
+        self.expect(TokenKind::LBrace)?;
+
+        let mut methods = Vec::new();
+        while !self.check(TokenKind::RBrace) && !self.is_at_end() {
+            let first = self.pos;
+            methods.push(self.parse_trait_method()?);
+            self.node(SyntaxKind::Method, first);
+        }
+        self.expect(TokenKind::RBrace)?;
+
+        let span = self.span_from(start);
+        Ok(TraitDecl { name, methods, public: false, span })
+    }
+
+    fn parse_trait_method(&mut self) -> ParseResult<TraitMethod> {
+        let start = self.current_span();
+        let (name, type_params, params, return_type) = self.parse_fn_signature()?;
+
+        let default = if self.check(TokenKind::Semicolon) {
+            self.advance();
+            None
+        } else {
+            Some(self.parse_block()?)
+        };
+
+        let span = self.span_from(start);
+        Ok(TraitMethod {
+            name,
+            type_params,
+            params,
+            return_type,
+            default,
+            span,
+        })
+    }
+
+    fn parse_impl_decl(&mut self) -> ParseResult<ImplDecl> {
+        let start = self.current_span();
+        self.expect(TokenKind::Impl)?;
+
+        let type_params = if self.check(TokenKind::Lt) {
+            self.advance();
+            let params = self.parse_type_params()?;
+            self.expect(TokenKind::Gt)?;
+            params
+        } else {
+            vec![]
+        };
+
+        // `impl Trait for Type` or inherent `impl Type`
+        let first = self.parse_type()?;
+        let (trait_name, self_ty) = if self.check(TokenKind::For) {
+            self.advance();
+            let trait_name = match first {
+                Type::Named(ident) => ident,
+                _ => return Err(self.error("trait name")),
+            };
+            (Some(trait_name), self.parse_type()?)
+        } else {
+            (None, first)
+        };
+
+        self.expect(TokenKind::LBrace)?;
+        let mut methods = Vec::new();
+        while !self.check(TokenKind::RBrace) && !self.is_at_end() {
+            let first = self.pos;
+            let modifiers = if self.check(TokenKind::HashBracket) {
+                let attrs = self.parse_attributes()?;
+                self.attrs_to_fn_modifiers(attrs)
+            } else {
+                vec![]
+            };
+            methods.push(self.parse_fn_decl(modifiers)?);
+            self.node(SyntaxKind::Method, first);
+        }
+        self.expect(TokenKind::RBrace)?;
+
+        let span = self.span_from(start);
+        Ok(ImplDecl {
+            type_params,
+            trait_name,
+            self_ty,
+            methods,
+            span,
+        })
+    }
+
+    // ============================================
+    // Enum Declaration
+    // ============================================
+
+    fn parse_enum_decl(&mut self, modifiers: Vec<StructModifier>) -> ParseResult<EnumDecl> {
+        let start = self.current_span();
+        self.expect(TokenKind::Enum)?;
+        let name = self.parse_ident()?;
+
+        let type_params = if self.check(TokenKind::Lt) {
+            self.advance();
+            let params = self.parse_type_params()?;
+            self.expect(TokenKind::Gt)?;
+            params
+        } else {
+            vec![]
+        };
+
+        self.expect(TokenKind::LBrace)?;
+        let mut variants = Vec::new();
+        while !self.check(TokenKind::RBrace) && !self.is_at_end() {
+            variants.push(self.parse_enum_variant()?);
+            if self.check(TokenKind::Comma) {
+                self.advance();
+            } else {
+                break;
+            }
+        }
+        self.expect(TokenKind::RBrace)?;
+
+        let span = self.span_from(start);
+
+        Ok(EnumDecl {
+            modifiers,
+            name,
+            type_params,
+            variants,
+            public: false,
+            span,
+        })
+    }
+
+    fn parse_enum_variant(&mut self) -> ParseResult<EnumVariant> {
+        let start = self.current_span();
+        let name = self.parse_ident()?;
+
+        let kind = if self.check(TokenKind::LParen) {
+            self.advance();
+            let mut types = Vec::new();
+            while !self.check(TokenKind::RParen) && !self.is_at_end() {
+                types.push(self.parse_type()?);
+                if self.check(TokenKind::Comma) {
+                    self.advance();
+                } else {
+                    break;
+                }
+            }
+            self.expect(TokenKind::RParen)?;
+            VariantKind::Tuple(types)
+        } else if self.check(TokenKind::LBrace) {
+            self.advance();
+            let mut fields = Vec::new();
+            while !self.check(TokenKind::RBrace) && !self.is_at_end() {
+                fields.push(self.parse_struct_field()?);
+            }
+            self.expect(TokenKind::RBrace)?;
+            VariantKind::Struct(fields)
+        } else {
+            VariantKind::Unit
+        };
+
+        let span = self.span_from(start);
+        Ok(EnumVariant { name, kind, span })
+    }
+
     // ============================================
     // Effect Declaration
     // ============================================
@@ -368,7 +773,7 @@ impl Parser {
         self.expect(TokenKind::RBrace)?;
         let span = self.span_from(start);
 
-        Ok(EffectDecl { name, ops, span })
+        Ok(EffectDecl { name, ops, public: false, span })
     }
 
     fn parse_effect_op(&mut self) -> ParseResult<EffectOp> {
@@ -462,34 +867,26 @@ impl Parser {
     }
 
     fn parse_contract_clause(&mut self) -> ParseResult<ContractClause> {
-        match self.peek_kind() {
-            Some(TokenKind::Pre) => {
-                self.advance();
-                self.expect(TokenKind::Colon)?;
-                Ok(ContractClause::Pre(self.parse_expr()?))
-            }
-            Some(TokenKind::Post) => {
-                self.advance();
-                self.expect(TokenKind::Colon)?;
-                Ok(ContractClause::Post(self.parse_expr()?))
-            }
-            Some(TokenKind::Invariant) => {
-                self.advance();
-                self.expect(TokenKind::Colon)?;
-                Ok(ContractClause::Invariant(self.parse_expr()?))
-            }
-            Some(TokenKind::AiCheck) => {
-                self.advance();
-                self.expect(TokenKind::Colon)?;
-                Ok(ContractClause::AiCheck(self.parse_string_lit()?))
-            }
-            Some(TokenKind::AiEnsure) => {
-                self.advance();
-                self.expect(TokenKind::Colon)?;
-                Ok(ContractClause::AiEnsure(self.parse_string_lit()?))
-            }
-            _ => Err(self.error("contract clause")),
-        }
+        let start = self.current_span();
+        let kind = match self.peek_kind() {
+            Some(kind @ (TokenKind::Pre
+            | TokenKind::Post
+            | TokenKind::Invariant
+            | TokenKind::AiCheck
+            | TokenKind::AiEnsure)) => kind,
+            _ => return Err(self.error("contract clause")),
+        };
+        self.advance();
+        self.expect(TokenKind::Colon)?;
+
+        let clause = match kind {
+            TokenKind::Pre => ContractClause::Pre(self.parse_expr()?, self.span_from(start)),
+            TokenKind::Post => ContractClause::Post(self.parse_expr()?, self.span_from(start)),
+            TokenKind::Invariant => ContractClause::Invariant(self.parse_expr()?, self.span_from(start)),
+            TokenKind::AiCheck => ContractClause::AiCheck(self.parse_string_lit()?, self.span_from(start)),
+            _ => ContractClause::AiEnsure(self.parse_string_lit()?, self.span_from(start)),
+        };
+        Ok(clause)
     }
 
     // ============================================
@@ -497,24 +894,50 @@ impl Parser {
     // ============================================
 
     fn parse_block(&mut self) -> ParseResult<Block> {
-        let start = self.current_span();
+        let (start, first) = (self.current_span(), self.pos);
         self.expect(TokenKind::LBrace)?;
 
-        let mut stmts = Vec::new();
-        while !self.check(TokenKind::RBrace) && !self.is_at_end() {
-            stmts.push(self.parse_stmt()?);
-        }
+        let stmts = self.parse_stmts();
 
         self.expect(TokenKind::RBrace)?;
+        self.node(SyntaxKind::Block, first);
         let span = self.span_from(start);
 
         Ok(Block { stmts, span })
     }
 
+    /// The statements of a block up to its `}`. A statement that fails to
+    /// parse becomes `Stmt::Error`; a declaration keyword ends the block, as
+    /// its `}` is most likely missing.
+    fn parse_stmts(&mut self) -> Vec<Stmt> {
+        let mut stmts = Vec::new();
+        while !self.check(TokenKind::RBrace) && !self.is_at_end() && !self.starts_declaration() {
+            let start = self.pos;
+            match self.parse_stmt() {
+                Ok(stmt) => {
+                    self.node(SyntaxKind::Stmt, start);
+                    stmts.push(stmt);
+                }
+                Err(error) => {
+                    let span = self.recover(error, start, false);
+                    self.node(SyntaxKind::Error, start);
+                    stmts.push(Stmt::Error(span));
+                }
+            }
+        }
+        stmts
+    }
+
     fn parse_stmt(&mut self) -> ParseResult<Stmt> {
         match self.peek_kind() {
             Some(TokenKind::Let) => self.parse_let_stmt(),
             Some(TokenKind::If) => self.parse_if_stmt(),
+            Some(TokenKind::While) => self.parse_while_stmt(None),
+            Some(TokenKind::For) => self.parse_for_stmt(None),
+            Some(TokenKind::Loop) => self.parse_loop_stmt(None),
+            Some(TokenKind::Break) => self.parse_break_stmt(),
+            Some(TokenKind::Continue) => self.parse_continue_stmt(),
+            Some(TokenKind::Label) => self.parse_labelled_stmt(),
             Some(TokenKind::Go) => self.parse_go_stmt(),
             Some(TokenKind::Return) => self.parse_return_stmt(),
             Some(TokenKind::Await) => self.parse_await_stmt(),
@@ -536,7 +959,7 @@ impl Parser {
             false
         };
 
-        let name = self.parse_ident()?;
+        let pattern = self.parse_pattern()?;
 
         let ty = if self.check(TokenKind::Colon) {
             self.advance();
@@ -552,17 +975,25 @@ impl Parser {
         let span = self.span_from(start);
         Ok(Stmt::Let {
             mutable,
-            name,
+            pattern,
             ty,
             value,
             span,
         })
     }
 
+    /// Parse the head of an `if`, `while`, `for` or `match`, which the body follows
+    fn parse_head_expr(&mut self) -> ParseResult<Expr> {
+        let outer = std::mem::replace(&mut self.in_head, true);
+        let expr = self.parse_expr();
+        self.in_head = outer;
+        expr
+    }
+
     fn parse_if_stmt(&mut self) -> ParseResult<Stmt> {
         let start = self.current_span();
         self.expect(TokenKind::If)?;
-        let condition = self.parse_expr()?;
+        let condition = self.parse_head_expr()?;
         let then_block = self.parse_block()?;
 
         let else_block = if self.check(TokenKind::Else) {
@@ -581,6 +1012,96 @@ impl Parser {
         })
     }
 
+    fn parse_labelled_stmt(&mut self) -> ParseResult<Stmt> {
+        let label = self.parse_label()?;
+        self.expect(TokenKind::Colon)?;
+
+        match self.peek_kind() {
+            Some(TokenKind::While) => self.parse_while_stmt(Some(label)),
+            Some(TokenKind::For) => self.parse_for_stmt(Some(label)),
+            Some(TokenKind::Loop) => self.parse_loop_stmt(Some(label)),
+            _ => Err(self.error("loop after label")),
+        }
+    }
+
+    fn parse_while_stmt(&mut self, label: Option<Ident>) -> ParseResult<Stmt> {
+        let start = label.as_ref().map(|l| l.span).unwrap_or_else(|| self.current_span());
+        self.expect(TokenKind::While)?;
+        let condition = self.parse_head_expr()?;
+        let body = self.parse_block()?;
+        let span = self.span_from(start);
+        Ok(Stmt::While {
+            label,
+            condition,
+            body,
+            span,
+        })
+    }
+
+    fn parse_for_stmt(&mut self, label: Option<Ident>) -> ParseResult<Stmt> {
+        let start = label.as_ref().map(|l| l.span).unwrap_or_else(|| self.current_span());
+        self.expect(TokenKind::For)?;
+        let binding = self.parse_ident()?;
+        self.expect(TokenKind::In)?;
+        let iterable = self.parse_head_expr()?;
+        let body = self.parse_block()?;
+        let span = self.span_from(start);
+        Ok(Stmt::For {
+            label,
+            binding,
+            iterable,
+            body,
+            span,
+        })
+    }
+
+    fn parse_loop_stmt(&mut self, label: Option<Ident>) -> ParseResult<Stmt> {
+        let expr = self.parse_loop_expr(label)?;
+
+        // Semicolon is optional after a loop body
+        if self.check(TokenKind::Semicolon) {
+            self.advance();
+        }
+
+        Ok(Stmt::Expr(expr))
+    }
+
+    fn parse_break_stmt(&mut self) -> ParseResult<Stmt> {
+        let start = self.current_span();
+        self.expect(TokenKind::Break)?;
+
+        let label = if self.check(TokenKind::Label) {
+            Some(self.parse_label()?)
+        } else {
+            None
+        };
+
+        let value = if !self.check(TokenKind::Semicolon) {
+            Some(self.parse_expr()?)
+        } else {
+            None
+        };
+
+        self.expect(TokenKind::Semicolon)?;
+        let span = self.span_from(start);
+        Ok(Stmt::Break { label, value, span })
+    }
+
+    fn parse_continue_stmt(&mut self) -> ParseResult<Stmt> {
+        let start = self.current_span();
+        self.expect(TokenKind::Continue)?;
+
+        let label = if self.check(TokenKind::Label) {
+            Some(self.parse_label()?)
+        } else {
+            None
+        };
+
+        self.expect(TokenKind::Semicolon)?;
+        let span = self.span_from(start);
+        Ok(Stmt::Continue { label, span })
+    }
+
     fn parse_go_stmt(&mut self) -> ParseResult<Stmt> {
         let start = self.current_span();
         self.expect(TokenKind::Go)?;
@@ -688,7 +1209,58 @@ impl Parser {
     // ============================================
 
     fn parse_expr(&mut self) -> ParseResult<Expr> {
-        self.parse_or_expr()
+        self.parse_assign_expr()
+    }
+
+    fn parse_assign_expr(&mut self) -> ParseResult<Expr> {
+        let left = self.parse_or_expr()?;
+
+        if let Some((op, tokens)) = self.match_assign_op() {
+            let start = self.current_span();
+            for _ in 0..tokens {
+                self.advance();
+            }
+            // Assignment is right-associative: `a = b = c` is `a = (b = c)`
+            let right = self.parse_assign_expr()?;
+            let span = self.span_from(start);
+            return Ok(Expr::Assign {
+                target: Box::new(left),
+                op,
+                value: Box::new(right),
+                span,
+            });
+        }
+
+        Ok(left)
+    }
+
+    /// `=` or a compound assignment operator, with the number of tokens it
+    /// takes
+    fn match_assign_op(&self) -> Option<(Option<BinaryOp>, usize)> {
+        let op = match self.peek_kind()? {
+            TokenKind::Eq => None,
+            TokenKind::PlusEq => Some(BinaryOp::Add),
+            TokenKind::MinusEq => Some(BinaryOp::Sub),
+            TokenKind::StarEq => Some(BinaryOp::Mul),
+            TokenKind::SlashEq => Some(BinaryOp::Div),
+            TokenKind::PercentEq => Some(BinaryOp::Rem),
+            TokenKind::AmpersandEq => Some(BinaryOp::BitAnd),
+            TokenKind::PipeEq => Some(BinaryOp::BitOr),
+            TokenKind::CaretEq => Some(BinaryOp::BitXor),
+            TokenKind::LtLtEq => Some(BinaryOp::Shl),
+            TokenKind::Gt if self.joined_with_next(TokenKind::GtEq) => return Some((Some(BinaryOp::Shr), 2)),
+            _ => return None,
+        };
+        Some((op, 1))
+    }
+
+    /// Whether the next token is `kind` and touches the current one, as the
+    /// `>`s of `>>` do
+    fn joined_with_next(&self, kind: TokenKind) -> bool {
+        match (self.tokens.get(self.pos), self.tokens.get(self.pos + 1)) {
+            (Some(current), Some(next)) => next.kind == kind && current.span.end == next.span.start,
+            _ => false,
+        }
     }
 
     fn parse_or_expr(&mut self) -> ParseResult<Expr> {
@@ -757,12 +1329,12 @@ impl Parser {
     }
 
     fn parse_comparison_expr(&mut self) -> ParseResult<Expr> {
-        let mut left = self.parse_additive_expr()?;
+        let mut left = self.parse_bit_or_expr()?;
 
         while let Some(op) = self.match_comparison_op() {
             let start = self.current_span();
             self.advance();
-            let right = self.parse_additive_expr()?;
+            let right = self.parse_bit_or_expr()?;
             let span = self.span_from(start);
             left = Expr::Binary {
                 left: Box::new(left),
@@ -778,13 +1350,102 @@ impl Parser {
     fn match_comparison_op(&self) -> Option<BinaryOp> {
         match self.peek_kind() {
             Some(TokenKind::Lt) => Some(BinaryOp::Lt),
-            Some(TokenKind::Gt) => Some(BinaryOp::Gt),
+            Some(TokenKind::Gt) if !self.joined_with_next(TokenKind::GtEq) => Some(BinaryOp::Gt),
             Some(TokenKind::LtEq) => Some(BinaryOp::Le),
             Some(TokenKind::GtEq) => Some(BinaryOp::Ge),
             _ => None,
         }
     }
 
+    // `|`, then `^`, then `&` bind tighter than comparisons, and shifts
+    // tighter still, as in Rust
+    fn parse_bit_or_expr(&mut self) -> ParseResult<Expr> {
+        let mut left = self.parse_bit_xor_expr()?;
+
+        while self.check(TokenKind::Pipe) {
+            let start = self.current_span();
+            self.advance();
+            let right = self.parse_bit_xor_expr()?;
+            let span = self.span_from(start);
+            left = Expr::Binary {
+                left: Box::new(left),
+                op: BinaryOp::BitOr,
+                right: Box::new(right),
+                span,
+            };
+        }
+
+        Ok(left)
+    }
+
+    fn parse_bit_xor_expr(&mut self) -> ParseResult<Expr> {
+        let mut left = self.parse_bit_and_expr()?;
+
+        while self.check(TokenKind::Caret) {
+            let start = self.current_span();
+            self.advance();
+            let right = self.parse_bit_and_expr()?;
+            let span = self.span_from(start);
+            left = Expr::Binary {
+                left: Box::new(left),
+                op: BinaryOp::BitXor,
+                right: Box::new(right),
+                span,
+            };
+        }
+
+        Ok(left)
+    }
+
+    fn parse_bit_and_expr(&mut self) -> ParseResult<Expr> {
+        let mut left = self.parse_shift_expr()?;
+
+        while self.check(TokenKind::Ampersand) {
+            let start = self.current_span();
+            self.advance();
+            let right = self.parse_shift_expr()?;
+            let span = self.span_from(start);
+            left = Expr::Binary {
+                left: Box::new(left),
+                op: BinaryOp::BitAnd,
+                right: Box::new(right),
+                span,
+            };
+        }
+
+        Ok(left)
+    }
+
+    fn parse_shift_expr(&mut self) -> ParseResult<Expr> {
+        let mut left = self.parse_additive_expr()?;
+
+        while let Some((op, tokens)) = self.match_shift_op() {
+            let start = self.current_span();
+            for _ in 0..tokens {
+                self.advance();
+            }
+            let right = self.parse_additive_expr()?;
+            let span = self.span_from(start);
+            left = Expr::Binary {
+                left: Box::new(left),
+                op,
+                right: Box::new(right),
+                span,
+            };
+        }
+
+        Ok(left)
+    }
+
+    /// `<<`, or `>>` as two touching `>`, with the number of tokens it takes
+    fn match_shift_op(&self) -> Option<(BinaryOp, usize)> {
+        match self.peek_kind() {
+            Some(TokenKind::LtLt) => Some((BinaryOp::Shl, 1)),
+            Some(TokenKind::Gt) if self.joined_with_next(TokenKind::Gt) => Some((BinaryOp::Shr, 2)),
+            _ => None,
+        }
+    }
+
     fn parse_additive_expr(&mut self) -> ParseResult<Expr> {
         let mut left = self.parse_multiplicative_expr()?;
 
@@ -835,6 +1496,7 @@ impl Parser {
         match self.peek_kind() {
             Some(TokenKind::Star) => Some(BinaryOp::Mul),
             Some(TokenKind::Slash) => Some(BinaryOp::Div),
+            Some(TokenKind::Percent) => Some(BinaryOp::Rem),
             _ => None,
         }
     }
@@ -844,6 +1506,16 @@ impl Parser {
             Some(TokenKind::Minus) => {
                 let start = self.current_span();
                 self.advance();
+                // A minus before a bare integer literal is part of it, as Rust's is,
+                // so that `-9223372036854775808` is in range
+                let postfix = matches!(
+                    self.tokens.get(self.pos + 1).map(|t| &t.kind),
+                    Some(TokenKind::LParen | TokenKind::Dot | TokenKind::LBracket)
+                );
+                if self.check(TokenKind::IntLit) && !postfix {
+                    let value = self.parse_negated_int_literal()?;
+                    return Ok(Expr::Literal(Literal::Int(value, self.span_from(start))));
+                }
                 let operand = self.parse_unary_expr()?;
                 let span = self.span_from(start);
                 Ok(Expr::Unary {
@@ -884,9 +1556,16 @@ impl Parser {
                 let start = self.current_span();
                 self.advance();
                 let operand = self.parse_unary_expr()?;
+                let propagate = if self.check(TokenKind::Question) {
+                    self.advance();
+                    true
+                } else {
+                    false
+                };
                 let span = self.span_from(start);
                 Ok(Expr::Try {
                     operand: Box::new(operand),
+                    propagate,
                     span,
                 })
             }
@@ -924,7 +1603,38 @@ impl Parser {
                 Some(TokenKind::Dot) => {
                     let start = self.current_span();
                     self.advance();
+
+                    // Tuple fields: `t.0`; `t.0.1` lexes as a float, so split it
+                    if matches!(self.peek_kind(), Some(TokenKind::IntLit) | Some(TokenKind::FloatLit)) {
+                        let token = self.advance().ok_or_else(|| self.eof())?;
+                        for index in token.literal.split('.') {
+                            if index.is_empty() || !index.bytes().all(|b| b.is_ascii_digit()) {
+                                return Err(ParseError::InvalidLiteral { literal: token.literal.clone(), span: token.span });
+                            }
+                            let span = self.span_from(start);
+                            expr = Expr::Field {
+                                object: Box::new(expr),
+                                field: Ident::new(index, token.span),
+                                span,
+                            };
+                        }
+                        continue;
+                    }
+
                     let field = self.parse_ident()?;
+                    if self.check(TokenKind::LParen) {
+                        self.advance();
+                        let args = self.parse_expr_list()?;
+                        self.expect(TokenKind::RParen)?;
+                        let span = self.span_from(start);
+                        expr = Expr::MethodCall {
+                            receiver: Box::new(expr),
+                            method: field,
+                            args,
+                            span,
+                        };
+                        continue;
+                    }
                     let span = self.span_from(start);
                     expr = Expr::Field {
                         object: Box::new(expr),
@@ -932,6 +1642,42 @@ impl Parser {
                         span,
                     };
                 }
+                Some(TokenKind::LBracket) => {
+                    let start = self.current_span();
+                    self.advance();
+
+                    let lower = if self.check(TokenKind::DotDot) {
+                        None
+                    } else {
+                        Some(Box::new(self.parse_expr()?))
+                    };
+
+                    if self.check(TokenKind::DotDot) {
+                        self.advance();
+                        let upper = if self.check(TokenKind::RBracket) {
+                            None
+                        } else {
+                            Some(Box::new(self.parse_expr()?))
+                        };
+                        self.expect(TokenKind::RBracket)?;
+                        let span = self.span_from(start);
+                        expr = Expr::Slice {
+                            object: Box::new(expr),
+                            start: lower,
+                            end: upper,
+                            span,
+                        };
+                    } else {
+                        self.expect(TokenKind::RBracket)?;
+                        let span = self.span_from(start);
+                        let index = lower.ok_or_else(|| self.error("index expression"))?;
+                        expr = Expr::Index {
+                            object: Box::new(expr),
+                            index,
+                            span,
+                        };
+                    }
+                }
                 Some(TokenKind::Bang) => {
                     // Check if this is a prompt invocation (ident!)
                     if let Expr::Ident(ident) = &expr {
@@ -939,7 +1685,7 @@ impl Parser {
                         self.advance();
                         let args = if self.check(TokenKind::LParen) {
                             self.advance();
-                            let args = self.parse_expr_list()?;
+                            let args = self.parse_prompt_args()?;
                             self.expect(TokenKind::RParen)?;
                             args
                         } else {
@@ -974,6 +1720,15 @@ impl Parser {
             Some(TokenKind::LBracket) => self.parse_array_expr(),
             Some(TokenKind::Pipe) => self.parse_lambda_expr(),
             Some(TokenKind::Match) => self.parse_match_expr(),
+            Some(TokenKind::Loop) => self.parse_loop_expr(None),
+            Some(TokenKind::Label) => {
+                let label = self.parse_label()?;
+                self.expect(TokenKind::Colon)?;
+                self.parse_loop_expr(Some(label))
+            }
+            Some(TokenKind::Perform) => self.parse_perform_expr(),
+            Some(TokenKind::Handle) => self.parse_handle_expr(),
+            Some(TokenKind::Resume) => self.parse_resume_expr(),
             Some(TokenKind::Ai) => self.parse_ai_expr(),
             Some(TokenKind::AiBang) => self.parse_ai_quick_expr(),
             _ => Err(self.error("expression")),
@@ -981,44 +1736,167 @@ impl Parser {
     }
 
     fn parse_int_literal(&mut self) -> ParseResult<Expr> {
-        let token = self.advance().ok_or(ParseError::UnexpectedEof)?;
-        let value: i64 = token.literal.parse()
-            .map_err(|_| ParseError::InvalidLiteral(token.literal.clone()))?;
+        let token = self.advance().ok_or_else(|| self.eof())?;
+        let value = int_value(&token.literal)
+            .ok_or_else(|| ParseError::InvalidLiteral { literal: token.literal.clone(), span: token.span })?;
         Ok(Expr::Literal(Literal::Int(value, token.span)))
     }
 
+    /// The value of an integer literal after a minus, which has been consumed
+    fn parse_negated_int_literal(&mut self) -> ParseResult<i64> {
+        let token = self.advance().ok_or_else(|| self.eof())?;
+        negated_int_value(&token.literal)
+            .ok_or_else(|| ParseError::InvalidLiteral { literal: format!("-{}", token.literal), span: token.span })
+    }
+
     fn parse_float_literal(&mut self) -> ParseResult<Expr> {
-        let token = self.advance().ok_or(ParseError::UnexpectedEof)?;
-        let value: f64 = token.literal.parse()
-            .map_err(|_| ParseError::InvalidLiteral(token.literal.clone()))?;
+        let token = self.advance().ok_or_else(|| self.eof())?;
+        let value = float_value(&token.literal)
+            .ok_or_else(|| ParseError::InvalidLiteral { literal: token.literal.clone(), span: token.span })?;
         Ok(Expr::Literal(Literal::Float(value, token.span)))
     }
 
+    /// A string literal in an expression, which is interpolated if it
+    /// embeds `{expr}`
     fn parse_string_literal(&mut self) -> ParseResult<Expr> {
-        let token = self.advance().ok_or(ParseError::UnexpectedEof)?;
-        Ok(Expr::Literal(Literal::String(token.literal.clone(), token.span)))
+        let token = self.advance().ok_or_else(|| self.eof())?;
+        let contents = string_contents(&token.literal);
+        if contents.raw {
+            return Ok(Expr::Literal(Literal::String(contents.text.to_string(), token.span)));
+        }
+        let (text, offset) = (contents.text, contents.offset);
+        let mut parts = Vec::new();
+        let (mut i, mut text_start) = (0, 0);
+        while let Some(ch) = text[i..].chars().next() {
+            match ch {
+                '\\' => {
+                    let rest = &text[i + 1..];
+                    i += 1 + match rest.strip_prefix("u{") {
+                        Some(hex) => hex.find('}').map_or(hex.len(), |end| end + 1) + 2,
+                        None => rest.chars().next().map_or(0, char::len_utf8),
+                    };
+                }
+                '{' => {
+                    let close = matching_brace(&text[i..]).map(|end| i + end).ok_or_else(|| {
+                        string_error(&token, offset + i, "unclosed `{` in string; write `\\{` for a brace")
+                    })?;
+                    if text_start < i {
+                        parts.push(StringPart::Text(string_text(&token, offset + text_start, &text[text_start..i])?));
+                    }
+                    parts.push(StringPart::Expr(parse_hole(&token, offset + i + 1, &text[i + 1..close])?));
+                    i = close + 1;
+                    text_start = i;
+                }
+                _ => i += ch.len_utf8(),
+            }
+        }
+        if parts.is_empty() {
+            return Ok(Expr::Literal(Literal::String(string_text(&token, offset, text)?, token.span)));
+        }
+        if text_start < text.len() {
+            parts.push(StringPart::Text(string_text(&token, offset + text_start, &text[text_start..])?));
+        }
+        Ok(Expr::Interpolation { parts, span: token.span })
     }
 
     fn parse_bool_literal(&mut self) -> ParseResult<Expr> {
-        let token = self.advance().ok_or(ParseError::UnexpectedEof)?;
+        let token = self.advance().ok_or_else(|| self.eof())?;
         let value = token.kind == TokenKind::True;
         Ok(Expr::Literal(Literal::Bool(value, token.span)))
     }
 
     fn parse_ident_expr(&mut self) -> ParseResult<Expr> {
         let ident = self.parse_ident()?;
+
+        if self.is_struct_lit_following(&ident) {
+            return self.parse_struct_lit(ident);
+        }
+
+        if self.check(TokenKind::ColonColon) {
+            self.advance();
+            let name = self.parse_ident()?;
+            let span = self.span_from(ident.span);
+            return Ok(Expr::Path { ty: ident, name, span });
+        }
+
         Ok(Expr::Ident(ident))
     }
 
+    /// `Name { field: ...` and `Name {}` start a struct literal. Only capitalised names qualify
+    /// so that `if x { y: ... }`-style ambiguities with blocks cannot arise, and `Name {}` is
+    /// left to be a body in the head of an `if`, `while`, `for` or `match`.
+    fn is_struct_lit_following(&self, name: &Ident) -> bool {
+        let kind = |offset: usize| self.tokens.get(self.pos + offset).map(|t| &t.kind);
+        name.name.starts_with(|c: char| c.is_ascii_uppercase())
+            && self.check(TokenKind::LBrace)
+            && match kind(1) {
+                Some(TokenKind::Ident) => kind(2) == Some(&TokenKind::Colon),
+                Some(TokenKind::RBrace) => !self.in_head,
+                _ => false,
+            }
+    }
+
+    fn parse_struct_lit(&mut self, name: Ident) -> ParseResult<Expr> {
+        let start = name.span;
+        self.expect(TokenKind::LBrace)?;
+
+        let mut fields = Vec::new();
+        while !self.check(TokenKind::RBrace) && !self.is_at_end() {
+            let field = self.parse_ident()?;
+            self.expect(TokenKind::Colon)?;
+            let value = self.parse_expr()?;
+            fields.push(RecordField { name: field, value });
+            if self.check(TokenKind::Comma) {
+                self.advance();
+            } else {
+                break;
+            }
+        }
+        self.expect(TokenKind::RBrace)?;
+
+        let span = self.span_from(start);
+        Ok(Expr::StructLit { name, fields, span })
+    }
+
+    /// A parenthesized expression or a tuple: `(e)`, `()`, `(e,)`, `(a, b)`
     fn parse_paren_expr(&mut self) -> ParseResult<Expr> {
+        // Parentheses close off any body a head is followed by
+        let outer = std::mem::replace(&mut self.in_head, false);
+        let expr = self.parse_paren_contents();
+        self.in_head = outer;
+        expr
+    }
+
+    fn parse_paren_contents(&mut self) -> ParseResult<Expr> {
+        let start = self.current_span();
         self.expect(TokenKind::LParen)?;
-        let expr = self.parse_expr()?;
+        if self.check(TokenKind::RParen) {
+            self.advance();
+            let span = self.span_from(start);
+            return Ok(Expr::Tuple { elements: vec![], span });
+        }
+
+        let first = self.parse_expr()?;
+        if !self.check(TokenKind::Comma) {
+            self.expect(TokenKind::RParen)?;
+            return Ok(first);
+        }
+
+        let mut elements = vec![first];
+        while self.check(TokenKind::Comma) {
+            self.advance();
+            if self.check(TokenKind::RParen) {
+                break;
+            }
+            elements.push(self.parse_expr()?);
+        }
         self.expect(TokenKind::RParen)?;
-        Ok(expr)
+        let span = self.span_from(start);
+        Ok(Expr::Tuple { elements, span })
     }
 
     fn parse_block_or_record_expr(&mut self) -> ParseResult<Expr> {
-        let start = self.current_span();
+        let (start, first) = (self.current_span(), self.pos);
         self.expect(TokenKind::LBrace)?;
 
         // Check if this is a record literal (starts with ident:)
@@ -1053,11 +1931,9 @@ impl Parser {
         }
 
         // Parse as block
-        let mut stmts = Vec::new();
-        while !self.check(TokenKind::RBrace) && !self.is_at_end() {
-            stmts.push(self.parse_stmt()?);
-        }
+        let stmts = self.parse_stmts();
         self.expect(TokenKind::RBrace)?;
+        self.node(SyntaxKind::Block, first);
         let span = self.span_from(start);
 
         Ok(Expr::Block(Block { stmts, span }))
@@ -1076,11 +1952,14 @@ impl Parser {
         let start = self.current_span();
         self.expect(TokenKind::Pipe)?;
 
-        let params = if !self.check(TokenKind::Pipe) {
-            self.parse_param_list()?
-        } else {
-            vec![]
-        };
+        let mut params = Vec::new();
+        if !self.check(TokenKind::Pipe) {
+            params.push(self.parse_lambda_param()?);
+            while self.check(TokenKind::Comma) {
+                self.advance();
+                params.push(self.parse_lambda_param()?);
+            }
+        }
 
         self.expect(TokenKind::Pipe)?;
 
@@ -1095,11 +1974,96 @@ impl Parser {
         Ok(Expr::Lambda { params, body, span })
     }
 
-    fn parse_match_expr(&mut self) -> ParseResult<Expr> {
+    /// Lambda parameter, whose type annotation may be omitted: `x` or `x: Int`
+    fn parse_lambda_param(&mut self) -> ParseResult<Param> {
         let start = self.current_span();
-        self.expect(TokenKind::Match)?;
-        let scrutinee = self.parse_expr()?;
-        self.expect(TokenKind::LBrace)?;
+        let name = self.parse_ident()?;
+        let ty = if self.check(TokenKind::Colon) {
+            self.advance();
+            self.parse_type()?
+        } else {
+            Type::Infer(name.span)
+        };
+        let span = self.span_from(start);
+        Ok(Param { name, ty, mutable: false, span })
+    }
+
+    fn parse_loop_expr(&mut self, label: Option<Ident>) -> ParseResult<Expr> {
+        let start = label.as_ref().map(|l| l.span).unwrap_or_else(|| self.current_span());
+        self.expect(TokenKind::Loop)?;
+        let body = self.parse_block()?;
+        let span = self.span_from(start);
+        Ok(Expr::Loop { label, body, span })
+    }
+
+    fn parse_perform_expr(&mut self) -> ParseResult<Expr> {
+        self.expect(TokenKind::Perform)?;
+        let effect = self.parse_ident()?;
+        self.expect(TokenKind::Dot)?;
+        let op = self.parse_ident()?;
+        self.expect(TokenKind::LParen)?;
+        let args = self.parse_expr_list()?;
+        self.expect(TokenKind::RParen)?;
+        Ok(Expr::Perform { effect, op, args })
+    }
+
+    fn parse_handle_expr(&mut self) -> ParseResult<Expr> {
+        let start = self.current_span();
+        self.expect(TokenKind::Handle)?;
+        let body = self.parse_block()?;
+        self.expect(TokenKind::With)?;
+        let effect = self.parse_ident()?;
+        self.expect(TokenKind::LBrace)?;
+
+        let mut clauses = Vec::new();
+        while !self.check(TokenKind::RBrace) && !self.is_at_end() {
+            clauses.push(self.parse_handler_clause()?);
+        }
+
+        self.expect(TokenKind::RBrace)?;
+        let span = self.span_from(start);
+        Ok(Expr::Handle { body: Box::new(body), effect, clauses, span })
+    }
+
+    fn parse_handler_clause(&mut self) -> ParseResult<HandlerClause> {
+        let start = self.current_span();
+        let op = self.parse_ident()?;
+        self.expect(TokenKind::LParen)?;
+        let mut params = Vec::new();
+        while !self.check(TokenKind::RParen) && !self.is_at_end() {
+            params.push(self.parse_ident()?);
+            if !self.check(TokenKind::RParen) {
+                self.expect(TokenKind::Comma)?;
+            }
+        }
+        self.expect(TokenKind::RParen)?;
+        self.expect(TokenKind::FatArrow)?;
+        let body = self.parse_expr()?;
+
+        // Optional trailing comma
+        if self.check(TokenKind::Comma) {
+            self.advance();
+        }
+
+        let span = self.span_from(start);
+        Ok(HandlerClause { op, params, body, span })
+    }
+
+    fn parse_resume_expr(&mut self) -> ParseResult<Expr> {
+        let start = self.current_span();
+        self.expect(TokenKind::Resume)?;
+        self.expect(TokenKind::LParen)?;
+        let value = self.parse_expr()?;
+        self.expect(TokenKind::RParen)?;
+        let span = self.span_from(start);
+        Ok(Expr::Resume { value: Box::new(value), span })
+    }
+
+    fn parse_match_expr(&mut self) -> ParseResult<Expr> {
+        let start = self.current_span();
+        self.expect(TokenKind::Match)?;
+        let scrutinee = self.parse_head_expr()?;
+        self.expect(TokenKind::LBrace)?;
 
         let mut arms = Vec::new();
         while !self.check(TokenKind::RBrace) && !self.is_at_end() {
@@ -1119,6 +2083,12 @@ impl Parser {
     fn parse_match_arm(&mut self) -> ParseResult<MatchArm> {
         let start = self.current_span();
         let pattern = self.parse_pattern()?;
+        let guard = if self.check(TokenKind::If) {
+            self.advance();
+            Some(self.parse_expr()?)
+        } else {
+            None
+        };
         self.expect(TokenKind::FatArrow)?;
         let body = self.parse_expr()?;
 
@@ -1128,34 +2098,76 @@ impl Parser {
         }
 
         let span = self.span_from(start);
-        Ok(MatchArm { pattern, body, span })
+        Ok(MatchArm { pattern, guard, body, span })
     }
 
+    /// A pattern with optional `|` alternatives
     fn parse_pattern(&mut self) -> ParseResult<Pattern> {
+        let start = self.current_span();
+        let first = self.parse_single_pattern()?;
+        if !self.check(TokenKind::Pipe) {
+            return Ok(first);
+        }
+
+        let mut alternatives = vec![first];
+        while self.check(TokenKind::Pipe) {
+            self.advance();
+            alternatives.push(self.parse_single_pattern()?);
+        }
+        let span = self.span_from(start);
+        Ok(Pattern::Or(alternatives, span))
+    }
+
+    fn parse_single_pattern(&mut self) -> ParseResult<Pattern> {
         match self.peek_kind() {
-            Some(TokenKind::IntLit) => {
-                let expr = self.parse_int_literal()?;
-                if let Expr::Literal(lit) = expr {
-                    Ok(Pattern::Literal(lit))
-                } else {
-                    unreachable!()
+            Some(TokenKind::IntLit) | Some(TokenKind::FloatLit) | Some(TokenKind::Minus) => {
+                let start = self.current_span();
+                let lit = self.parse_pattern_literal()?;
+                let inclusive = match self.peek_kind() {
+                    Some(TokenKind::DotDotEq) => true,
+                    Some(TokenKind::DotDot) => false,
+                    _ => return Ok(Pattern::Literal(lit)),
+                };
+                self.advance();
+                if !matches!(self.peek_kind(), Some(TokenKind::IntLit) | Some(TokenKind::FloatLit) | Some(TokenKind::Minus)) {
+                    return Err(self.error("range end"));
                 }
+                let end = self.parse_pattern_literal()?;
+                let span = self.span_from(start);
+                Ok(Pattern::Range { start: lit, end, inclusive, span })
             }
-            Some(TokenKind::StringLit) => {
-                let expr = self.parse_string_literal()?;
-                if let Expr::Literal(lit) = expr {
-                    Ok(Pattern::Literal(lit))
-                } else {
-                    unreachable!()
-                }
+            Some(TokenKind::StringLit) | Some(TokenKind::True) | Some(TokenKind::False) => {
+                Ok(Pattern::Literal(self.parse_pattern_literal()?))
             }
-            Some(TokenKind::True) | Some(TokenKind::False) => {
-                let expr = self.parse_bool_literal()?;
-                if let Expr::Literal(lit) = expr {
-                    Ok(Pattern::Literal(lit))
-                } else {
-                    unreachable!()
+            Some(TokenKind::LBrace) => {
+                let start = self.current_span();
+                self.advance();
+                let (fields, rest) = self.parse_field_patterns()?;
+                let span = self.span_from(start);
+                Ok(Pattern::Record { fields, rest, span })
+            }
+            Some(TokenKind::LBracket) => self.parse_slice_pattern(),
+            Some(TokenKind::LParen) => {
+                let start = self.current_span();
+                self.advance();
+                let mut elements = Vec::new();
+                let mut trailing_comma = false;
+                while !self.check(TokenKind::RParen) && !self.is_at_end() {
+                    elements.push(self.parse_pattern()?);
+                    trailing_comma = self.check(TokenKind::Comma);
+                    if !trailing_comma {
+                        break;
+                    }
+                    self.advance();
                 }
+                self.expect(TokenKind::RParen)?;
+
+                // `(p)` only groups; `(p,)` is a one-element tuple
+                if elements.len() == 1 && !trailing_comma {
+                    return Ok(elements.remove(0));
+                }
+                let span = self.span_from(start);
+                Ok(Pattern::Tuple(elements, span))
             }
             Some(TokenKind::Ident) => {
                 let ident = self.parse_ident()?;
@@ -1163,7 +2175,17 @@ impl Parser {
                     return Ok(Pattern::Wildcard(ident.span));
                 }
 
-                if self.check(TokenKind::LParen) {
+                if self.check(TokenKind::At) {
+                    let start = ident.span;
+                    self.advance();
+                    let pattern = self.parse_single_pattern()?;
+                    let span = self.span_from(start);
+                    Ok(Pattern::Binding {
+                        name: ident,
+                        pattern: Box::new(pattern),
+                        span,
+                    })
+                } else if self.check(TokenKind::LParen) {
                     let start = ident.span;
                     self.advance();
                     let args = self.parse_pattern_list()?;
@@ -1174,6 +2196,17 @@ impl Parser {
                         args,
                         span,
                     })
+                } else if self.check(TokenKind::LBrace) {
+                    let start = ident.span;
+                    self.advance();
+                    // Unlisted fields are ignored, so a trailing `..` changes nothing
+                    let (fields, _) = self.parse_field_patterns()?;
+                    let span = self.span_from(start);
+                    Ok(Pattern::Struct {
+                        name: ident,
+                        fields,
+                        span,
+                    })
                 } else {
                     Ok(Pattern::Ident(ident))
                 }
@@ -1182,6 +2215,103 @@ impl Parser {
         }
     }
 
+    /// A literal in pattern position; numbers may be negated
+    fn parse_pattern_literal(&mut self) -> ParseResult<Literal> {
+        let start = self.current_span();
+        let negate = self.check(TokenKind::Minus);
+        if negate {
+            self.advance();
+        }
+        let expr = match self.peek_kind() {
+            Some(TokenKind::IntLit) if negate => {
+                let value = self.parse_negated_int_literal()?;
+                return Ok(Literal::Int(value, self.span_from(start)));
+            }
+            Some(TokenKind::IntLit) => self.parse_int_literal()?,
+            Some(TokenKind::FloatLit) => self.parse_float_literal()?,
+            Some(TokenKind::StringLit) if !negate => {
+                let token = self.advance().ok_or_else(|| self.eof())?;
+                Expr::Literal(Literal::String(string_value(&token)?, token.span))
+            }
+            Some(TokenKind::True) | Some(TokenKind::False) if !negate => self.parse_bool_literal()?,
+            _ => return Err(self.error("literal")),
+        };
+        let Expr::Literal(lit) = expr else {
+            unreachable!()
+        };
+        let span = self.span_from(start);
+        Ok(match lit {
+            Literal::Float(f, _) if negate => Literal::Float(-f, span),
+            lit => lit,
+        })
+    }
+
+    /// Field patterns after the opening `{`, through the closing `}`.
+    /// Returns whether the list ended with `..`.
+    fn parse_field_patterns(&mut self) -> ParseResult<(Vec<(Ident, Pattern)>, bool)> {
+        let mut fields = Vec::new();
+        let mut rest = false;
+        while !self.check(TokenKind::RBrace) && !self.is_at_end() {
+            if self.check(TokenKind::DotDot) {
+                self.advance();
+                rest = true;
+                break;
+            }
+            let field = self.parse_ident()?;
+            // `{ radius }` is shorthand for `{ radius: radius }`
+            let pattern = if self.check(TokenKind::Colon) {
+                self.advance();
+                self.parse_pattern()?
+            } else {
+                Pattern::Ident(field.clone())
+            };
+            fields.push((field, pattern));
+            if self.check(TokenKind::Comma) {
+                self.advance();
+            } else {
+                break;
+            }
+        }
+        self.expect(TokenKind::RBrace)?;
+        Ok((fields, rest))
+    }
+
+    /// `[a, b]`, `[first, ..rest]` or `[.., last]`
+    fn parse_slice_pattern(&mut self) -> ParseResult<Pattern> {
+        let start = self.current_span();
+        self.expect(TokenKind::LBracket)?;
+        let mut prefix = Vec::new();
+        let mut rest = None;
+        let mut suffix = Vec::new();
+        while !self.check(TokenKind::RBracket) && !self.is_at_end() {
+            if self.check(TokenKind::DotDot) {
+                if rest.is_some() {
+                    return Err(self.error("at most one '..' in an array pattern"));
+                }
+                let dots = self.current_span();
+                self.advance();
+                let binding = if self.check(TokenKind::Ident) {
+                    Pattern::Ident(self.parse_ident()?)
+                } else {
+                    Pattern::Wildcard(dots)
+                };
+                rest = Some(Box::new(binding));
+            } else if rest.is_some() {
+                suffix.push(self.parse_pattern()?);
+            } else {
+                prefix.push(self.parse_pattern()?);
+            }
+            if self.check(TokenKind::Comma) {
+                self.advance();
+            } else {
+                break;
+            }
+        }
+        self.expect(TokenKind::RBracket)?;
+        let span = self.span_from(start);
+        Ok(Pattern::Slice { prefix, rest, suffix, span })
+    }
+
     fn parse_pattern_list(&mut self) -> ParseResult<Vec<Pattern>> {
         let mut patterns = Vec::new();
         if !self.check(TokenKind::RParen) {
@@ -1247,10 +2377,30 @@ impl Parser {
             let name = self.parse_ident()?;
             self.expect(TokenKind::Colon)?;
             let value = self.parse_expr()?;
-            Ok(AiBodyItem::Field { name, value })
+            Ok(AiBodyItem::Field { name, value: Box::new(value) })
         }
     }
 
+    /// Arguments of a prompt invocation, each optionally named: `text: body`
+    fn parse_prompt_args(&mut self) -> ParseResult<Vec<PromptArg>> {
+        let mut args = Vec::new();
+        while !self.check(TokenKind::RParen) {
+            let name = if self.check(TokenKind::Ident) && self.next_is(TokenKind::Colon) {
+                let name = self.parse_ident()?;
+                self.advance();
+                Some(name)
+            } else {
+                None
+            };
+            args.push(PromptArg { name, value: self.parse_expr()? });
+            if !self.check(TokenKind::Comma) {
+                break;
+            }
+            self.advance();
+        }
+        Ok(args)
+    }
+
     fn parse_expr_list(&mut self) -> ParseResult<Vec<Expr>> {
         let mut exprs = Vec::new();
         if !self.check(TokenKind::RParen) && !self.check(TokenKind::RBracket) {
@@ -1357,10 +2507,37 @@ impl Parser {
                     let start = ident.span;
                     self.advance();
                     let inner = self.parse_type()?;
+                    let mut effects = Vec::new();
+                    while self.check(TokenKind::Comma) {
+                        self.advance();
+                        // `AI` is a keyword but names the built-in AI effect here
+                        if self.check(TokenKind::AI) {
+                            let token = self.advance().ok_or_else(|| self.eof())?;
+                            effects.push(Ident::new(token.literal, token.span));
+                        } else {
+                            effects.push(self.parse_ident()?);
+                        }
+                    }
                     self.expect(TokenKind::Gt)?;
                     let span = self.span_from(start);
                     Ok(Type::Effect {
                         inner: Box::new(inner),
+                        effects,
+                        span,
+                    })
+                } else if self.check(TokenKind::Lt) {
+                    let start = ident.span;
+                    self.advance();
+                    let mut args = vec![self.parse_type()?];
+                    while self.check(TokenKind::Comma) {
+                        self.advance();
+                        args.push(self.parse_type()?);
+                    }
+                    self.expect(TokenKind::Gt)?;
+                    let span = self.span_from(start);
+                    Ok(Type::Generic {
+                        name: ident,
+                        args,
                         span,
                     })
                 } else {
@@ -1406,6 +2583,11 @@ impl Parser {
             Some(TokenKind::LParen) => {
                 let start = self.current_span();
                 self.advance();
+                if self.check(TokenKind::RParen) {
+                    self.advance();
+                    let span = self.span_from(start);
+                    return Ok(Type::Tuple { elements: vec![], span });
+                }
                 let mut elements = vec![self.parse_type()?];
                 while self.check(TokenKind::Comma) {
                     self.advance();
@@ -1439,7 +2621,14 @@ impl Parser {
 
     fn parse_ai_constraints(&mut self) -> ParseResult<Vec<AiConstraint>> {
         let mut constraints = vec![self.parse_ai_constraint()?];
-        while self.check(TokenKind::Comma) {
+        // After a comma, only a constraint keyword continues the list; anything
+        // else is the next struct field or parameter
+        while self.check(TokenKind::Comma)
+            && matches!(
+                self.tokens.get(self.pos + 1).map(|t| &t.kind),
+                Some(TokenKind::AiCheck | TokenKind::AiValid | TokenKind::AiFormat | TokenKind::AiInfer)
+            )
+        {
             self.advance();
             constraints.push(self.parse_ai_constraint()?);
         }
@@ -1471,7 +2660,7 @@ impl Parser {
                 let name = self.parse_ident()?;
                 self.expect(TokenKind::Colon)?;
                 let value = self.parse_expr()?;
-                Ok(AiConstraint::Custom { name, value })
+                Ok(AiConstraint::Custom { name, value: Box::new(value) })
             }
             _ => Err(self.error("AI constraint")),
         }
@@ -1535,13 +2724,18 @@ impl Parser {
     fn parse_ident(&mut self) -> ParseResult<Ident> {
         // Allow certain keywords to be used as identifiers
         if self.is_keyword_as_ident() || self.check(TokenKind::Ident) {
-            let token = self.advance().ok_or(ParseError::UnexpectedEof)?;
+            let token = self.advance().ok_or_else(|| self.eof())?;
             Ok(Ident::new(token.literal, token.span))
         } else {
             Err(self.error("identifier"))
         }
     }
 
+    fn parse_label(&mut self) -> ParseResult<Ident> {
+        let token = self.expect(TokenKind::Label)?;
+        Ok(Ident::new(token.literal.trim_start_matches('\''), token.span))
+    }
+
     fn is_keyword_as_ident(&self) -> bool {
         matches!(
             self.peek_kind(),
@@ -1575,17 +2769,17 @@ impl Parser {
         if !self.check(TokenKind::StringLit) {
             return Err(self.error("string literal"));
         }
-        let token = self.advance().ok_or(ParseError::UnexpectedEof)?;
-        Ok(token.literal)
+        let token = self.advance().ok_or_else(|| self.eof())?;
+        string_value(&token)
     }
 
     fn parse_float_lit(&mut self) -> ParseResult<f64> {
         if !self.check(TokenKind::FloatLit) && !self.check(TokenKind::IntLit) {
             return Err(self.error("number"));
         }
-        let token = self.advance().ok_or(ParseError::UnexpectedEof)?;
-        token.literal.parse()
-            .map_err(|_| ParseError::InvalidLiteral(token.literal))
+        let token = self.advance().ok_or_else(|| self.eof())?;
+        float_value(&token.literal)
+            .ok_or(ParseError::InvalidLiteral { literal: token.literal, span: token.span })
     }
 
     fn parse_bool_lit(&mut self) -> ParseResult<bool> {
@@ -1606,7 +2800,11 @@ impl Parser {
         if !self.check(kind.clone()) {
             return Err(self.error(&kind.to_string()));
         }
-        self.advance().ok_or(ParseError::UnexpectedEof)
+        self.advance().ok_or_else(|| self.eof())
+    }
+
+    fn next_is(&self, kind: TokenKind) -> bool {
+        self.tokens.get(self.pos + 1).map(|t| &t.kind) == Some(&kind)
     }
 
     fn check(&self, kind: TokenKind) -> bool {
@@ -1648,23 +2846,96 @@ impl Parser {
         } else {
             start.end
         };
-        Span::new(start.start, end, start.line, start.column)
+        Span { end, ..start }
     }
 
     fn error(&self, expected: &str) -> ParseError {
-        let (found, line, column) = if let Some(token) = self.peek() {
-            (token.kind.to_string(), token.span.line, token.span.column)
+        let (found, span) = if let Some(token) = self.peek() {
+            (token.kind.to_string(), token.span)
         } else {
-            ("end of input".to_string(), 0, 0)
+            ("end of input".to_string(), Span::default())
         };
 
         ParseError::UnexpectedToken {
             expected: expected.to_string(),
             found,
-            line,
-            column,
+            span,
+        }
+    }
+
+    fn eof(&self) -> ParseError {
+        ParseError::UnexpectedEof { span: self.current_span() }
+    }
+}
+
+/// The value of a string literal without interpolation, as used by prompt
+/// templates, attributes and patterns, which keep `{name}` as written
+fn string_value(token: &Token) -> ParseResult<String> {
+    let contents = string_contents(&token.literal);
+    if contents.raw {
+        return Ok(contents.text.to_string());
+    }
+    string_text(token, contents.offset, contents.text)
+}
+
+/// Decode the escapes of `text`, found `at` bytes into the string token
+fn string_text(token: &Token, at: usize, text: &str) -> ParseResult<String> {
+    unescape(text).map_err(|(i, message)| string_error(token, at + i, &message))
+}
+
+/// Parse the expression of an interpolation `{...}`, whose source starts `at`
+/// bytes into the string token
+fn parse_hole(token: &Token, at: usize, source: &str) -> ParseResult<Expr> {
+    if source.trim().is_empty() {
+        return Err(string_error(token, at - 1, "empty `{}` in string; write `\\{` for a brace"));
+    }
+    let base = position_in(token, at);
+    let mut tokens = Lexer::new(source).tokenize();
+    for t in &mut tokens {
+        if t.span.line == 1 {
+            t.span.column += base.column - 1;
+        }
+        t.span.line += base.line - 1;
+        t.span.start += base.start;
+        t.span.end += base.start;
+        t.span.file = base.file;
+    }
+    let mut parser = Parser::new(tokens);
+    let expr = parser.parse_expr()?;
+    if !parser.is_at_end() {
+        return Err(parser.error("`}`"));
+    }
+    Ok(expr)
+}
+
+fn string_error(token: &Token, at: usize, message: &str) -> ParseError {
+    ParseError::InvalidString { message: message.to_string(), span: position_in(token, at) }
+}
+
+/// Where the byte `at` of a token's literal is in the source
+fn position_in(token: &Token, at: usize) -> Span {
+    let before = &token.literal[..at];
+    let line = token.span.line + before.matches('\n').count();
+    let column = match before.rfind('\n') {
+        Some(newline) => before[newline + 1..].chars().count() + 1,
+        None => token.span.column + before.chars().count(),
+    };
+    let start = token.span.start + at;
+    Span { start, end: start, line, column, file: token.span.file }
+}
+
+/// The index of the `}` closing the `{` that `text` starts with
+fn matching_brace(text: &str) -> Option<usize> {
+    let mut depth = 0;
+    for (i, ch) in text.char_indices() {
+        match ch {
+            '{' => depth += 1,
+            '}' if depth == 1 => return Some(i),
+            '}' => depth -= 1,
+            _ => {}
         }
     }
+    None
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -1803,7 +3074,7 @@ mod tests {
         let program = parse(input).unwrap();
         if let TopLevel::Function(f) = &program.items[0] {
             assert_eq!(f.body.stmts.len(), 1);
-            if let Stmt::Let { name, .. } = &f.body.stmts[0] {
+            if let Stmt::Let { pattern: Pattern::Ident(name), .. } = &f.body.stmts[0] {
                 assert_eq!(name.name, "x");
             } else {
                 panic!("Expected let stmt");
@@ -1883,11 +3154,12 @@ mod tests {
 
     #[test]
     fn test_type_constraint() {
-        let input = r#"fn check(email: String where ai_valid: "email") { }"#;
+        let input = r#"fn check(email: String where ai_valid: "email", name: String) { }"#;
         let program = parse(input).unwrap();
         if let TopLevel::Function(f) = &program.items[0] {
             if let Type::Constrained { constraints, .. } = &f.params[0].ty {
                 assert_eq!(constraints.len(), 1);
+                assert_eq!(f.params.len(), 2);
             } else {
                 panic!("Expected constrained type");
             }
@@ -1895,4 +3167,613 @@ mod tests {
             panic!("Expected function");
         }
     }
+
+    #[test]
+    fn test_while_loop() {
+        let input = "fn main() { let mut i = 0; while i < 10 { i = i + 1; } }";
+        let program = parse(input).unwrap();
+        if let TopLevel::Function(f) = &program.items[0] {
+            if let Stmt::While { label, body, .. } = &f.body.stmts[1] {
+                assert!(label.is_none());
+                assert!(matches!(
+                    &body.stmts[0],
+                    Stmt::Expr(Expr::Assign { .. })
+                ));
+            } else {
+                panic!("Expected while loop");
+            }
+        } else {
+            panic!("Expected function");
+        }
+    }
+
+    #[test]
+    fn test_for_loop() {
+        let input = "fn main() { for x in [1, 2, 3] { println(x); } }";
+        let program = parse(input).unwrap();
+        if let TopLevel::Function(f) = &program.items[0] {
+            if let Stmt::For { binding, iterable, .. } = &f.body.stmts[0] {
+                assert_eq!(binding.name, "x");
+                assert!(matches!(iterable, Expr::Array { .. }));
+            } else {
+                panic!("Expected for loop");
+            }
+        } else {
+            panic!("Expected function");
+        }
+    }
+
+    #[test]
+    fn test_labelled_loops() {
+        let input = r#"
+            fn main() {
+                'outer: for i in xs {
+                    loop {
+                        if i > 2 { break 'outer; }
+                        continue 'outer;
+                    }
+                }
+            }
+        "#;
+        let program = parse(input).unwrap();
+        if let TopLevel::Function(f) = &program.items[0] {
+            if let Stmt::For { label, body, .. } = &f.body.stmts[0] {
+                assert_eq!(label.as_ref().unwrap().name, "outer");
+                if let Stmt::Expr(Expr::Loop { body, .. }) = &body.stmts[0] {
+                    assert!(matches!(
+                        &body.stmts[1],
+                        Stmt::Continue { label: Some(l), .. } if l.name == "outer"
+                    ));
+                } else {
+                    panic!("Expected loop");
+                }
+            } else {
+                panic!("Expected for loop");
+            }
+        } else {
+            panic!("Expected function");
+        }
+    }
+
+    #[test]
+    fn test_loop_break_value() {
+        let input = "fn main() { let x = loop { break 42; }; }";
+        let program = parse(input).unwrap();
+        if let TopLevel::Function(f) = &program.items[0] {
+            if let Stmt::Let { value: Expr::Loop { body, .. }, .. } = &f.body.stmts[0] {
+                assert!(matches!(&body.stmts[0], Stmt::Break { value: Some(_), .. }));
+            } else {
+                panic!("Expected loop expression");
+            }
+        } else {
+            panic!("Expected function");
+        }
+    }
+
+    #[test]
+    fn test_index_and_slice() {
+        let input = r#"fn main() { xs[0] = rec["key"]; let s = xs[1..n]; let t = xs[..]; }"#;
+        let program = parse(input).unwrap();
+        if let TopLevel::Function(f) = &program.items[0] {
+            if let Stmt::Expr(Expr::Assign { target, value, .. }) = &f.body.stmts[0] {
+                assert!(matches!(target.as_ref(), Expr::Index { .. }));
+                assert!(matches!(value.as_ref(), Expr::Index { .. }));
+            } else {
+                panic!("Expected index assignment");
+            }
+            assert!(matches!(
+                &f.body.stmts[1],
+                Stmt::Let { value: Expr::Slice { start: Some(_), end: Some(_), .. }, .. }
+            ));
+            assert!(matches!(
+                &f.body.stmts[2],
+                Stmt::Let { value: Expr::Slice { start: None, end: None, .. }, .. }
+            ));
+        } else {
+            panic!("Expected function");
+        }
+    }
+
+    #[test]
+    fn test_enum_decl() {
+        let input = r#"
+            #[derive(Debug)]
+            enum Shape<T> {
+                Empty,
+                Rect(T, T),
+                Circle { radius: T },
+            }
+        "#;
+        let program = parse(input).unwrap();
+        if let TopLevel::Enum(e) = &program.items[0] {
+            assert_eq!(e.name.name, "Shape");
+            assert_eq!(e.type_params.len(), 1);
+            assert_eq!(e.modifiers.len(), 1);
+            assert!(matches!(e.variants[0].kind, VariantKind::Unit));
+            assert!(matches!(&e.variants[1].kind, VariantKind::Tuple(tys) if tys.len() == 2));
+            assert!(matches!(&e.variants[2].kind, VariantKind::Struct(fields) if fields.len() == 1));
+        } else {
+            panic!("Expected enum");
+        }
+    }
+
+    #[test]
+    fn test_variant_construction_and_patterns() {
+        let input = r#"
+            fn area(s: Shape<Float>) -> Float {
+                return match s {
+                    Empty => 0.0,
+                    Rect(w, h) => w * h,
+                    Circle { radius: r } => r * r,
+                };
+            }
+            fn main() { let c: Shape<Float> = Circle { radius: 1.0 }; }
+        "#;
+        let program = parse(input).unwrap();
+        if let TopLevel::Function(f) = &program.items[0] {
+            assert!(matches!(
+                &f.params[0].ty,
+                Type::Generic { name, args, .. } if name.name == "Shape" && args.len() == 1
+            ));
+            if let Stmt::Return { value: Some(Expr::Match { arms, .. }), .. } = &f.body.stmts[0] {
+                assert!(matches!(&arms[1].pattern, Pattern::Constructor { args, .. } if args.len() == 2));
+                assert!(matches!(&arms[2].pattern, Pattern::Struct { fields, .. } if fields.len() == 1));
+            } else {
+                panic!("Expected match");
+            }
+        } else {
+            panic!("Expected function");
+        }
+        if let TopLevel::Function(f) = &program.items[1] {
+            assert!(matches!(&f.body.stmts[0], Stmt::Let { value: Expr::StructLit { .. }, .. }));
+        } else {
+            panic!("Expected function");
+        }
+    }
+
+    #[test]
+    fn test_empty_struct_lit() {
+        let input = r#"
+            fn main() {
+                let s = S {};
+                if s == (S {}) {}
+                while done == Done {}
+            }
+        "#;
+        let program = parse(input).unwrap();
+        if let TopLevel::Function(f) = &program.items[0] {
+            assert!(matches!(&f.body.stmts[0], Stmt::Let { value: Expr::StructLit { fields, .. }, .. } if fields.is_empty()));
+            assert!(matches!(
+                &f.body.stmts[1],
+                Stmt::If { condition: Expr::Binary { right, .. }, then_block, .. }
+                    if matches!(**right, Expr::StructLit { .. }) && then_block.stmts.is_empty()
+            ));
+            assert!(matches!(
+                &f.body.stmts[2],
+                Stmt::While { condition: Expr::Binary { right, .. }, body, .. }
+                    if matches!(**right, Expr::Ident(_)) && body.stmts.is_empty()
+            ));
+        } else {
+            panic!("Expected function");
+        }
+    }
+
+    #[test]
+    fn test_try_propagate() {
+        let input = "fn f(s: String) -> Result<Int, String> { let n = try to_int(s)?; try check(n)?; return Ok(n); }";
+        let program = parse(input).unwrap();
+        if let TopLevel::Function(f) = &program.items[0] {
+            assert!(matches!(&f.body.stmts[0], Stmt::Let { value: Expr::Try { propagate: true, .. }, .. }));
+            assert!(matches!(&f.body.stmts[1], Stmt::Try { propagate: true, .. }));
+        } else {
+            panic!("Expected function");
+        }
+    }
+
+    #[test]
+    fn test_generic_fn_decl() {
+        let input = "fn max<T: Ord + Show, U>(a: T, b: T) -> T { return a; }";
+        let program = parse(input).unwrap();
+        if let TopLevel::Function(f) = &program.items[0] {
+            assert_eq!(f.type_params.len(), 2);
+            let bounds: Vec<&str> = f.type_params[0].bounds.iter().map(|b| b.name.as_str()).collect();
+            assert_eq!(bounds, vec!["Ord", "Show"]);
+            assert!(f.type_params[1].bounds.is_empty());
+        } else {
+            panic!("Expected function");
+        }
+    }
+
+    #[test]
+    fn test_lambda_untyped_params() {
+        let input = r#"
+            fn main() {
+                |x, y: Int| => x + y;
+            }
+        "#;
+        let program = parse(input).unwrap();
+        if let TopLevel::Function(f) = &program.items[0] {
+            if let Stmt::Expr(Expr::Lambda { params, .. }) = &f.body.stmts[0] {
+                assert!(matches!(params[0].ty, Type::Infer(_)));
+                assert!(matches!(params[1].ty, Type::Primitive(PrimitiveType::Int)));
+            } else {
+                panic!("Expected lambda");
+            }
+        } else {
+            panic!("Expected function");
+        }
+    }
+
+    #[test]
+    fn test_trait_and_impl_decl() {
+        let input = r#"
+            trait Shape {
+                fn area(self) -> Float;
+                fn name(self) -> String { return "shape"; }
+            }
+
+            impl Shape for Circle {
+                fn area(&self) -> Float { return 3.14 * self.r * self.r; }
+            }
+
+            impl<T> Boxed<T> {
+                fn new(value: T) -> Self { return Boxed { value: value }; }
+            }
+        "#;
+        let program = parse(input).unwrap();
+        if let TopLevel::Trait(t) = &program.items[0] {
+            assert_eq!(t.name.name, "Shape");
+            assert!(t.methods[0].default.is_none());
+            assert!(t.methods[1].default.is_some());
+            assert!(t.methods[0].params[0].is_self());
+        } else {
+            panic!("Expected trait");
+        }
+        if let TopLevel::Impl(i) = &program.items[1] {
+            assert_eq!(i.trait_name.as_ref().map(|t| t.name.as_str()), Some("Shape"));
+            assert!(matches!(&i.self_ty, Type::Named(n) if n.name == "Circle"));
+            assert!(i.methods[0].params[0].is_self());
+        } else {
+            panic!("Expected impl");
+        }
+        if let TopLevel::Impl(i) = &program.items[2] {
+            assert!(i.trait_name.is_none());
+            assert_eq!(i.type_params.len(), 1);
+            assert!(matches!(&i.self_ty, Type::Generic { name, .. } if name.name == "Boxed"));
+        } else {
+            panic!("Expected impl");
+        }
+    }
+
+    #[test]
+    fn test_method_call_and_path() {
+        let input = r#"
+            fn main() {
+                let p = Point::new(1, 2);
+                p.shift(1).norm();
+                p.on_click(1);
+            }
+        "#;
+        let program = parse(input).unwrap();
+        if let TopLevel::Function(f) = &program.items[0] {
+            if let Stmt::Let { value: Expr::Call { callee, .. }, .. } = &f.body.stmts[0] {
+                assert!(matches!(callee.as_ref(), Expr::Path { ty, name, .. } if ty.name == "Point" && name.name == "new"));
+            } else {
+                panic!("Expected path call");
+            }
+            if let Stmt::Expr(Expr::MethodCall { receiver, method, args, .. }) = &f.body.stmts[1] {
+                assert_eq!(method.name, "norm");
+                assert!(args.is_empty());
+                assert!(matches!(receiver.as_ref(), Expr::MethodCall { method, .. } if method.name == "shift"));
+            } else {
+                panic!("Expected method call");
+            }
+            assert!(matches!(&f.body.stmts[2], Stmt::Expr(Expr::MethodCall { .. })));
+        } else {
+            panic!("Expected function");
+        }
+    }
+
+    #[test]
+    fn test_pattern_forms() {
+        let input = r#"
+            fn main() {
+                match x {
+                    0 | 1 => a,
+                    -5..=-1 => b,
+                    2..10 => c,
+                    n @ Some(_) if n != None => d,
+                    {name, age: 30, ..} => e,
+                    [first, ..rest] => f,
+                    [.., last] => g,
+                    [] => h,
+                };
+            }
+        "#;
+        let program = parse(input).unwrap();
+        let TopLevel::Function(f) = &program.items[0] else {
+            panic!("Expected function");
+        };
+        let Stmt::Expr(Expr::Match { arms, .. }) = &f.body.stmts[0] else {
+            panic!("Expected match");
+        };
+        assert!(matches!(&arms[0].pattern, Pattern::Or(alts, _) if alts.len() == 2));
+        assert!(matches!(&arms[1].pattern, Pattern::Range {
+            start: Literal::Int(-5, _), end: Literal::Int(-1, _), inclusive: true, ..
+        }));
+        assert!(matches!(&arms[2].pattern, Pattern::Range { inclusive: false, .. }));
+        assert!(matches!(&arms[3].pattern, Pattern::Binding { name, pattern, .. }
+            if name.name == "n" && matches!(pattern.as_ref(), Pattern::Constructor { .. })));
+        assert!(arms[3].guard.is_some());
+        assert!(arms[4].guard.is_none());
+        assert!(matches!(&arms[4].pattern, Pattern::Record { fields, rest: true, .. } if fields.len() == 2));
+        assert!(matches!(&arms[5].pattern, Pattern::Slice { prefix, rest: Some(rest), suffix, .. }
+            if prefix.len() == 1 && suffix.is_empty() && matches!(rest.as_ref(), Pattern::Ident(i) if i.name == "rest")));
+        assert!(matches!(&arms[6].pattern, Pattern::Slice { prefix, rest: Some(rest), suffix, .. }
+            if prefix.is_empty() && suffix.len() == 1 && matches!(rest.as_ref(), Pattern::Wildcard(_))));
+        assert!(matches!(&arms[7].pattern, Pattern::Slice { prefix, rest: None, .. } if prefix.is_empty()));
+    }
+
+    #[test]
+    fn test_slice_pattern_single_rest() {
+        let input = "fn main() { match xs { [a, .., ..b] => a, }; }";
+        assert!(parse(input).is_err());
+    }
+
+    #[test]
+    fn test_tuples_and_let_patterns() {
+        let input = r#"
+            fn main() {
+                let (x, {y, ..}) = (1, {y: 2, z: 3});
+                let t = (x,);
+                let u = ();
+                let v = (x);
+                return t.0.1;
+            }
+        "#;
+        let program = parse(input).unwrap();
+        let TopLevel::Function(f) = &program.items[0] else {
+            panic!("Expected function");
+        };
+        let Stmt::Let { pattern: Pattern::Tuple(elements, _), value: Expr::Tuple { elements: values, .. }, .. } =
+            &f.body.stmts[0]
+        else {
+            panic!("Expected tuple let, got {:?}", f.body.stmts[0]);
+        };
+        assert!(matches!(&elements[..], [Pattern::Ident(x), Pattern::Record { rest: true, .. }] if x.name == "x"));
+        assert!(matches!(&values[1], Expr::Record { .. }));
+        assert!(matches!(&f.body.stmts[1], Stmt::Let { value: Expr::Tuple { elements, .. }, .. } if elements.len() == 1));
+        assert!(matches!(&f.body.stmts[2], Stmt::Let { value: Expr::Tuple { elements, .. }, .. } if elements.is_empty()));
+        assert!(matches!(&f.body.stmts[3], Stmt::Let { value: Expr::Ident(_), .. }));
+        let Stmt::Return { value: Some(Expr::Field { object, field, .. }), .. } = &f.body.stmts[4] else {
+            panic!("Expected field access");
+        };
+        assert_eq!(field.name, "1");
+        assert!(matches!(object.as_ref(), Expr::Field { field, .. } if field.name == "0"));
+    }
+
+    #[test]
+    fn test_assignment_targets_and_mut_params() {
+        let input = r#"
+            impl Point {
+                fn shift(&mut self, mut dx: Int, dy: Int) {
+                    dx = dx * 2;
+                    self.x = self.x + dx;
+                    self.pos.0 = dy;
+                }
+            }
+        "#;
+        let program = parse(input).unwrap();
+        let TopLevel::Impl(i) = &program.items[0] else {
+            panic!("Expected impl");
+        };
+        let m = &i.methods[0];
+        let mutable: Vec<bool> = m.params.iter().map(|p| p.mutable).collect();
+        assert_eq!(mutable, [true, true, false]);
+        assert!(matches!(&m.body.stmts[0], Stmt::Expr(Expr::Assign { target, .. }) if matches!(target.as_ref(), Expr::Ident(_))));
+        assert!(matches!(&m.body.stmts[1], Stmt::Expr(Expr::Assign { target, value, .. })
+            if matches!(target.as_ref(), Expr::Field { .. }) && matches!(value.as_ref(), Expr::Binary { .. })));
+        assert!(matches!(&m.body.stmts[2], Stmt::Expr(Expr::Assign { target, .. })
+            if matches!(target.as_ref(), Expr::Field { object, .. } if matches!(object.as_ref(), Expr::Field { .. }))));
+    }
+
+    #[test]
+    fn test_struct_invariant_and_clause_spans() {
+        let input = r#"
+struct Account where invariant: balance >= 0 {
+    balance: Int
+}
+
+fn withdraw(a: Account, amount: Int) -> Int
+    where pre: amount > 0, post: result >= 0
+{ return a.balance - amount; }
+        "#;
+        let program = parse(input).unwrap();
+        let TopLevel::Struct(s) = &program.items[0] else {
+            panic!("Expected struct");
+        };
+        let clauses = &s.contract.as_ref().unwrap().clauses;
+        assert!(matches!(&clauses[..], [ContractClause::Invariant(Expr::Binary { .. }, _)]));
+        assert_eq!((clauses[0].span().line, clauses[0].span().column), (2, 22));
+        assert_eq!(s.fields.len(), 1);
+
+        let TopLevel::Function(f) = &program.items[1] else {
+            panic!("Expected function");
+        };
+        let clauses = &f.contract.as_ref().unwrap().clauses;
+        assert!(matches!(&clauses[..], [ContractClause::Pre(..), ContractClause::Post(..)]));
+        assert_eq!((clauses[1].span().line, clauses[1].span().column), (7, 28));
+    }
+
+    #[test]
+    fn test_effect_handlers() {
+        let input = r#"
+effect State {
+    op get: () -> Int
+    op put: Int
+}
+
+fn run() -> Effect<Int, State> {
+    return handle { perform State.put(perform State.get() + 1); } with State {
+        get() => resume(0),
+        put(n) => { println(n); resume(()); },
+    };
+}
+        "#;
+        let program = parse(input).unwrap();
+        let TopLevel::Function(f) = &program.items[1] else {
+            panic!("Expected function");
+        };
+        let Some(Type::Effect { effects, .. }) = &f.return_type else {
+            panic!("Expected effect type, got {:?}", f.return_type);
+        };
+        assert_eq!(effects[0].name, "State");
+
+        let Stmt::Return { value: Some(Expr::Handle { body, effect, clauses, .. }), .. } = &f.body.stmts[0] else {
+            panic!("Expected handle, got {:?}", f.body.stmts[0]);
+        };
+        assert_eq!(effect.name, "State");
+        assert!(matches!(&body.stmts[0], Stmt::Expr(Expr::Perform { op, args, .. })
+            if op.name == "put" && matches!(&args[..], [Expr::Binary { left, .. }] if matches!(**left, Expr::Perform { .. }))));
+        assert_eq!(clauses.len(), 2);
+        assert!(clauses[0].params.is_empty() && matches!(clauses[0].body, Expr::Resume { .. }));
+        assert_eq!(clauses[1].params[0].name, "n");
+        assert!(matches!(clauses[1].body, Expr::Block(_)));
+    }
+
+    #[test]
+    fn test_prompt_placeholders_and_named_args() {
+        let program = parse(r#"
+            prompt translate { "Translate from {source} to {target}: {text} ({source})" }
+            fn main() { let x = translate!("es", text: body, target: "en"); }
+        "#).unwrap();
+        let TopLevel::Prompt(p) = &program.items[0] else { panic!("expected prompt") };
+        assert_eq!(p.placeholders, vec!["source", "target", "text"]);
+        let TopLevel::Function(f) = &program.items[1] else { panic!("expected function") };
+        let Stmt::Let { value: Expr::Ai(AiExpr::PromptInvocation { args, .. }), .. } = &f.body.stmts[0] else {
+            panic!("expected prompt invocation")
+        };
+        let names: Vec<Option<&str>> = args.iter().map(|a| a.name.as_ref().map(|n| n.name.as_str())).collect();
+        assert_eq!(names, vec![None, Some("text"), Some("target")]);
+    }
+
+    #[test]
+    fn test_pub_declarations_and_file_ids() {
+        let tokens = Lexer::new("use a::b::{c, d};\n#[ai_generate]\npub struct Point { x: Int }\nfn helper() { }").with_file(3).tokenize();
+        let program = Parser::new(tokens).parse_program().unwrap();
+        let TopLevel::Import(import) = &program.items[0] else { panic!("expected import") };
+        assert_eq!(import.items.as_ref().map(Vec::len), Some(2));
+        assert_eq!(import.span.file, 3);
+        assert!(program.items[1].is_public());
+        assert!(!program.items[2].is_public());
+        let TopLevel::Struct(s) = &program.items[1] else { panic!("expected struct") };
+        assert_eq!((s.span.file, s.span.line), (3, 3));
+        assert!(parse("pub impl Point { }").is_err());
+    }
+
+    #[test]
+    fn test_error_recovery() {
+        let source = "fn main() {\n    let x = ;\n    let y = 2;\n    foo(1 2);\n    if y { bar(; }\n}\nstruct { }\nfn ok() -> Int { return 1; }\nfn open() {\n    let z = 1\nfn last() { }";
+        let tokens = Lexer::new(source).tokenize();
+        let (program, errors) = Parser::new(tokens).parse_with_errors();
+        let at: Vec<(usize, usize)> = errors.iter().map(|e| (e.span().line, e.span().column)).collect();
+        assert_eq!(at, vec![(2, 13), (4, 11), (5, 16), (7, 8), (11, 1)]);
+        assert_eq!(errors[0].to_string(), "unexpected token: expected expression, found ; at line 2, column 13");
+
+        let names: Vec<Option<&str>> = program.items.iter().map(|i| i.name().map(|n| n.name.as_str())).collect();
+        assert_eq!(names, vec![Some("main"), None, Some("ok"), None, Some("last")]);
+        assert!(matches!(program.items[1], TopLevel::Error(span) if span.line == 7));
+        let TopLevel::Function(main) = &program.items[0] else { panic!("expected function") };
+        assert!(matches!(main.body.stmts[..], [Stmt::Error(_), Stmt::Let { .. }, Stmt::Error(_), Stmt::If { .. }]));
+
+        // Without recovery the first error is reported
+        let err = parse(source).unwrap_err();
+        assert_eq!(err.to_string(), errors[0].to_string());
+    }
+
+    #[test]
+    fn test_string_interpolation() {
+        let source = "fn greet(name: String, age: Int) -> String {\n    return \"Hello {name}, you are {age + 1}\\n\";\n}";
+        let program = parse(source).unwrap();
+        let TopLevel::Function(f) = &program.items[0] else { panic!("expected function") };
+        let Stmt::Return { value: Some(Expr::Interpolation { parts, .. }), .. } = &f.body.stmts[0] else {
+            panic!("expected an interpolated string");
+        };
+        assert_eq!(parts.len(), 5);
+        assert_eq!(parts[0], StringPart::Text("Hello ".to_string()));
+        let Some(StringPart::Expr(Expr::Ident(name))) = parts.get(1) else { panic!("expected {{name}}") };
+        assert_eq!((name.span.line, name.span.column, name.span.start), (2, 20, source.find("name}").unwrap()));
+        assert_eq!(parts[2], StringPart::Text(", you are ".to_string()));
+        assert!(matches!(parts[3], StringPart::Expr(Expr::Binary { op: BinaryOp::Add, .. })));
+        assert_eq!(parts[4], StringPart::Text("\n".to_string()));
+
+        // Escaped braces, raw strings and strings without holes are plain literals
+        for (literal, value) in [(r#""\{x}""#, "{x}"), (r##"r"{x}\n""##, r"{x}\n"), (r#""a\u{62}""#, "ab")] {
+            let program = parse(&format!("fn f() -> String {{ return {}; }}", literal)).unwrap();
+            let TopLevel::Function(f) = &program.items[0] else { panic!("expected function") };
+            let Stmt::Return { value: Some(Expr::Literal(Literal::String(s, _))), .. } = &f.body.stmts[0] else {
+                panic!("expected a string literal");
+            };
+            assert_eq!(s, value);
+        }
+
+        let errors: Vec<String> = ["\"a {x\"", "\"{}\"", "\"{a b}\"", "\"\\q\""]
+            .iter()
+            .map(|s| parse(&format!("fn f() {{\n    let s = {};\n}}", s)).unwrap_err().to_string())
+            .collect();
+        assert_eq!(errors, vec![
+            "unclosed `{` in string; write `\\{` for a brace at line 2, column 16",
+            "empty `{}` in string; write `\\{` for a brace at line 2, column 14",
+            "unexpected token: expected `}`, found identifier at line 2, column 17",
+            "unknown escape `\\q` at line 2, column 14",
+        ]);
+    }
+
+    #[test]
+    fn test_numeric_operators() {
+        let source = "fn f(a: Int, xs: Vec<Vec<Int>>) -> Int {\n    let mut y = a >> 2 + 1 | a & 3 == 0x3;\n    y >>= 1;\n    y %= 2;\n    return y;\n}";
+        let program = parse(source).unwrap();
+        let TopLevel::Function(f) = &program.items[0] else { panic!("expected function") };
+        let Stmt::Let { value: Expr::Binary { op: BinaryOp::Eq, left, .. }, .. } = &f.body.stmts[0] else {
+            panic!("expected `==` at the top");
+        };
+        let Expr::Binary { op: BinaryOp::BitOr, left: shift, right: and, .. } = left.as_ref() else {
+            panic!("expected `|` under `==`");
+        };
+        assert!(matches!(shift.as_ref(), Expr::Binary { op: BinaryOp::Shr, right, .. }
+            if matches!(right.as_ref(), Expr::Binary { op: BinaryOp::Add, .. })));
+        assert!(matches!(and.as_ref(), Expr::Binary { op: BinaryOp::BitAnd, .. }));
+        assert!(matches!(&f.body.stmts[1], Stmt::Expr(Expr::Assign { op: Some(BinaryOp::Shr), .. })));
+        assert!(matches!(&f.body.stmts[2], Stmt::Expr(Expr::Assign { op: Some(BinaryOp::Rem), .. })));
+
+        // `> >` with a space is not a shift
+        assert!(parse("fn f(a: Int) -> Bool { return a > > 1; }").is_err());
+        let err = parse("fn f() -> Int { return 0b12; }").unwrap_err();
+        assert_eq!(err.to_string(), "invalid literal: 0b12");
+    }
+
+    #[test]
+    fn test_negative_literals() {
+        let source = r#"
+            fn f(n: Int) -> Int {
+                let min = -9223372036854775808;
+                let hex = -0x8000_0000_0000_0000;
+                let call = -1.abs();
+                return match n { -9223372036854775808 => 0, _ => n };
+            }
+        "#;
+        let program = parse(source).unwrap();
+        let TopLevel::Function(f) = &program.items[0] else { panic!("expected function") };
+        for stmt in &f.body.stmts[..2] {
+            assert!(matches!(stmt, Stmt::Let { value: Expr::Literal(Literal::Int(i64::MIN, _)), .. }));
+        }
+        assert!(matches!(&f.body.stmts[2], Stmt::Let { value: Expr::Unary { op: UnaryOp::Neg, .. }, .. }));
+        let Stmt::Return { value: Some(Expr::Match { arms, .. }), .. } = &f.body.stmts[3] else {
+            panic!("expected match");
+        };
+        assert!(matches!(arms[0].pattern, Pattern::Literal(Literal::Int(i64::MIN, _))));
+
+        let err = parse("fn f() -> Int { return -9223372036854775809; }").unwrap_err();
+        assert_eq!(err.to_string(), "invalid literal: -9223372036854775809");
+    }
 }
@@ -23,6 +23,10 @@ pub enum SymbolKind {
     Function,
     Parameter,
     Struct,
+    Enum,
+    Variant,
+    Trait,
+    TypeParam,
     Effect,
     AiModel,
     Prompt,
@@ -108,6 +112,11 @@ impl SymbolTable {
         self.scopes[self.current].symbols.get(name)
     }
 
+    /// Look up a symbol only in the current scope, to update it in place
+    pub fn lookup_current_mut(&mut self, name: &str) -> Option<&mut Symbol> {
+        self.scopes[self.current].symbols.get_mut(name)
+    }
+
     /// Check if a name is defined in any accessible scope
     pub fn is_defined(&self, name: &str) -> bool {
         self.lookup(name).is_some()
@@ -137,6 +146,14 @@ impl SymbolTable {
 pub struct TypeEnv {
     /// Struct definitions: name -> (fields, type_params)
     structs: HashMap<String, StructDef>,
+    /// Enum definitions: name -> (variants, type_params)
+    enums: HashMap<String, EnumDef>,
+    /// Variant name -> owning enum name
+    variants: HashMap<String, String>,
+    /// Trait definitions: name -> method signatures
+    traits: HashMap<String, TraitDef>,
+    /// Impl blocks by the implementing type's key (see `Ty::impl_key`)
+    impls: HashMap<String, Vec<ImplDef>>,
     /// Effect definitions: name -> operations
     effects: HashMap<String, EffectDef>,
     /// AI model definitions
@@ -150,6 +167,77 @@ pub struct StructDef {
     pub name: String,
     pub fields: Vec<(String, Ty)>,
     pub type_params: Vec<String>,
+    /// Marked `#[ai_generate]`, so `ai generate` may produce it
+    pub ai_generate: bool,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub struct EnumDef {
+    pub name: String,
+    pub variants: Vec<VariantDef>,
+    pub type_params: Vec<String>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub struct VariantDef {
+    pub name: String,
+    pub shape: VariantShape,
+    /// Payload fields; tuple variants use positional names "0", "1", ...
+    pub fields: Vec<(String, Ty)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VariantShape {
+    Unit,
+    Tuple,
+    Struct,
+}
+
+impl EnumDef {
+    /// The enum type with its own type parameters as arguments
+    pub fn self_ty(&self) -> Ty {
+        if self.type_params.is_empty() {
+            Ty::Named(self.name.clone())
+        } else {
+            Ty::Generic {
+                name: self.name.clone(),
+                args: self.type_params.iter().map(|p| Ty::Named(p.clone())).collect(),
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TraitDef {
+    pub name: String,
+    pub methods: Vec<MethodDef>,
+    pub span: Span,
+}
+
+/// Signature of a trait or impl method
+#[derive(Debug, Clone)]
+pub struct MethodDef {
+    pub name: String,
+    /// Function type with the receiver, if any, as the first parameter. In traits the
+    /// implementing type is `Self`; generic methods are wrapped in `Ty::Forall`.
+    pub ty: Ty,
+    /// Takes `self`, so it can be called with method syntax
+    pub has_self: bool,
+    /// Trait methods only: has a default body
+    pub has_default: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct ImplDef {
+    /// `None` for inherent impls
+    pub trait_name: Option<String>,
+    /// Type parameters of the impl with their trait bounds
+    pub type_params: Vec<(String, Vec<String>)>,
+    pub self_ty: Ty,
+    /// Methods with `Self` replaced by `self_ty`, including inherited trait defaults
+    pub methods: Vec<MethodDef>,
     pub span: Span,
 }
 
@@ -172,6 +260,7 @@ pub struct AiModelDef {
 pub struct PromptDef {
     pub name: String,
     pub template: String,
+    pub placeholders: Vec<String>,
     pub span: Span,
 }
 
@@ -188,6 +277,45 @@ impl TypeEnv {
         Ok(())
     }
 
+    pub fn define_enum(&mut self, def: EnumDef) -> Result<(), String> {
+        if self.enums.contains_key(&def.name) || self.structs.contains_key(&def.name) {
+            return Err(format!("Type '{}' is already defined", def.name));
+        }
+        if let Some(v) = def.variants.iter().find(|v| self.variants.contains_key(&v.name)) {
+            return Err(format!("Variant '{}' is already defined", v.name));
+        }
+        for v in &def.variants {
+            self.variants.insert(v.name.clone(), def.name.clone());
+        }
+        self.enums.insert(def.name.clone(), def);
+        Ok(())
+    }
+
+    pub fn define_trait(&mut self, def: TraitDef) -> Result<(), String> {
+        if self.traits.contains_key(&def.name) {
+            return Err(format!("Trait '{}' is already defined", def.name));
+        }
+        self.traits.insert(def.name.clone(), def);
+        Ok(())
+    }
+
+    /// Register an impl block for the type with the given key
+    pub fn add_impl(&mut self, key: &str, def: ImplDef) -> Result<(), String> {
+        let impls = self.impls.entry(key.to_string()).or_default();
+        if let Some(trait_name) = &def.trait_name {
+            if impls.iter().any(|i| i.trait_name.as_ref() == Some(trait_name)) {
+                return Err(format!("Trait '{}' is already implemented for '{}'", trait_name, key));
+            }
+        } else {
+            let mut inherent = impls.iter().filter(|i| i.trait_name.is_none()).flat_map(|i| &i.methods);
+            if let Some(m) = inherent.find(|m| def.methods.iter().any(|d| d.name == m.name)) {
+                return Err(format!("Method '{}' is already defined for '{}'", m.name, key));
+            }
+        }
+        impls.push(def);
+        Ok(())
+    }
+
     pub fn define_effect(&mut self, def: EffectDef) -> Result<(), String> {
         if self.effects.contains_key(&def.name) {
             return Err(format!("Effect '{}' is already defined", def.name));
@@ -216,6 +344,40 @@ impl TypeEnv {
         self.structs.get(name)
     }
 
+    pub fn get_enum(&self, name: &str) -> Option<&EnumDef> {
+        self.enums.get(name)
+    }
+
+    /// Look up a variant by name, together with the enum that declares it
+    pub fn get_variant(&self, name: &str) -> Option<(&EnumDef, &VariantDef)> {
+        let enum_def = self.enums.get(self.variants.get(name)?)?;
+        let variant = enum_def.variants.iter().find(|v| v.name == name)?;
+        Some((enum_def, variant))
+    }
+
+    pub fn get_trait(&self, name: &str) -> Option<&TraitDef> {
+        self.traits.get(name)
+    }
+
+    /// The impl of `trait_name` for the type with the given key
+    pub fn get_impl(&self, key: &str, trait_name: &str) -> Option<&ImplDef> {
+        self.impls
+            .get(key)?
+            .iter()
+            .find(|i| i.trait_name.as_deref() == Some(trait_name))
+    }
+
+    /// Find a method of the type with the given key; inherent methods take precedence
+    /// over trait methods
+    pub fn find_method(&self, key: &str, name: &str) -> Option<(&ImplDef, &MethodDef)> {
+        let impls = self.impls.get(key)?;
+        let mut ordered: Vec<&ImplDef> = impls.iter().filter(|i| i.trait_name.is_none()).collect();
+        ordered.extend(impls.iter().filter(|i| i.trait_name.is_some()));
+        ordered
+            .into_iter()
+            .find_map(|i| i.methods.iter().find(|m| m.name == name).map(|m| (i, m)))
+    }
+
     pub fn get_effect(&self, name: &str) -> Option<&EffectDef> {
         self.effects.get(name)
     }
@@ -3,9 +3,79 @@
 //! This module provides built-in functions and types that are automatically
 //! available in every program.
 
+use crate::ast::{StructModifier, TopLevel, TypeParam};
 use crate::interpreter::{NativeFunction, RuntimeError, Value};
 use std::collections::HashMap;
 
+/// Types and traits declared in every program
+pub const PRELUDE_SOURCE: &str = "
+#[derive(Debug, Clone)]
+enum Option<T> { Some(T), None }
+#[derive(Debug, Clone)]
+enum Result<T, E> { Ok(T), Err(E) }
+
+trait Debug { fn debug(self) -> String; }
+trait Clone { fn clone(self) -> Self; }
+
+impl Debug for Int { fn debug(self) -> String { return to_string(self); } }
+impl Debug for Float { fn debug(self) -> String { return to_string(self); } }
+impl Debug for String { fn debug(self) -> String { return self; } }
+impl Debug for Bool { fn debug(self) -> String { return to_string(self); } }
+impl Clone for Int { fn clone(self) -> Int { return self; } }
+impl Clone for Float { fn clone(self) -> Float { return self; } }
+impl Clone for String { fn clone(self) -> String { return self; } }
+impl Clone for Bool { fn clone(self) -> Bool { return self; } }
+";
+
+/// Traits that `#[derive(..)]` can implement
+pub const DERIVABLE_TRAITS: &[&str] = &["Debug", "Clone"];
+
+/// Parse the prelude declarations, including the impls derived for them
+pub fn prelude() -> Vec<TopLevel> {
+    let mut items = crate::parse(PRELUDE_SOURCE).expect("prelude must parse").items;
+    items.extend(derived_impls(&items));
+    items
+}
+
+/// Generate the impl blocks requested by `#[derive(..)]` on structs and enums.
+/// Unknown trait names are skipped; the checker reports them.
+pub fn derived_impls(items: &[TopLevel]) -> Vec<TopLevel> {
+    let mut source = String::new();
+    for item in items {
+        let (name, type_params, modifiers) = match item {
+            TopLevel::Struct(s) => (&s.name.name, &s.type_params, &s.modifiers),
+            TopLevel::Enum(e) => (&e.name.name, &e.type_params, &e.modifiers),
+            _ => continue,
+        };
+        let (generics, self_ty) = derive_target(name, type_params);
+
+        let derives = modifiers.iter().flat_map(|m| match m {
+            StructModifier::Derive(traits) => traits.as_slice(),
+            _ => &[],
+        });
+        for derive in derives {
+            let body = match derive.name.as_str() {
+                "Debug" => "fn debug(self) -> String { return to_string(self); }",
+                // Arguments are passed by value, so the receiver already is a copy
+                "Clone" => "fn clone(self) -> Self { return self; }",
+                _ => continue,
+            };
+            source.push_str(&format!("impl{} {} for {} {{ {} }}\n", generics, derive.name, self_ty, body));
+        }
+    }
+    crate::parse(&source).expect("derived impls must parse").items
+}
+
+/// `<T, U>` and `Name<T, U>` for a derived impl, or `""` and `Name` when not generic
+fn derive_target(name: &str, type_params: &[TypeParam]) -> (String, String) {
+    if type_params.is_empty() {
+        return (String::new(), name.to_string());
+    }
+    let params: Vec<&str> = type_params.iter().map(|p| p.name.name.as_str()).collect();
+    let generics = format!("<{}>", params.join(", "));
+    (generics.clone(), format!("{}{}", name, generics))
+}
+
 /// Register all standard library functions into an environment
 pub fn register_stdlib(define: &mut impl FnMut(String, Value)) {
     // I/O Functions
@@ -27,6 +97,16 @@ pub fn register_stdlib(define: &mut impl FnMut(String, Value)) {
     register_utility_functions(define);
 }
 
+/// The built-in effect a native function performs, if any (see `effects::BUILTIN_EFFECTS`)
+pub fn native_effect(name: &str) -> Option<&'static str> {
+    match name {
+        "print" | "println" | "debug" | "input" | "input_prompt" | "env" => Some("IO"),
+        "random" | "random_int" => Some("Random"),
+        "time" | "sleep" => Some("Time"),
+        _ => None,
+    }
+}
+
 // ============================================================================
 // I/O FUNCTIONS
 // ============================================================================
@@ -377,7 +457,9 @@ fn register_math_functions(define: &mut impl FnMut(String, Value)) {
             name: "abs".to_string(),
             arity: 1,
             func: |args| match &args[0] {
-                Value::Int(n) => Ok(Value::Int(n.abs())),
+                Value::Int(n) => {
+                    n.checked_abs().map(Value::Int).ok_or_else(|| RuntimeError::Overflow(format!("abs({})", n)))
+                }
                 Value::Float(f) => Ok(Value::Float(f.abs())),
                 _ => Err(RuntimeError::TypeError {
                     expected: "number".to_string(),
@@ -502,7 +584,11 @@ fn register_math_functions(define: &mut impl FnMut(String, Value)) {
             func: |args| match (&args[0], &args[1]) {
                 (Value::Int(base), Value::Int(exp)) => {
                     if *exp >= 0 {
-                        Ok(Value::Int(base.pow(*exp as u32)))
+                        u32::try_from(*exp)
+                            .ok()
+                            .and_then(|exp| base.checked_pow(exp))
+                            .map(Value::Int)
+                            .ok_or_else(|| RuntimeError::Overflow(format!("pow({}, {})", base, exp)))
                     } else {
                         Ok(Value::Float((*base as f64).powi(*exp as i32)))
                     }
@@ -645,12 +731,44 @@ fn register_math_functions(define: &mut impl FnMut(String, Value)) {
         }),
     );
 
+    // wrapping_add(a, b), wrapping_sub, wrapping_mul - Integer arithmetic
+    // that wraps around at the bounds of Int instead of overflowing;
+    // saturating_add(a, b), saturating_sub, saturating_mul - Integer
+    // arithmetic that stops at the bounds
+    let integer_ops: [(&str, NativeFn); 6] = [
+        ("wrapping_add", |args| int_op(&args, i64::wrapping_add)),
+        ("wrapping_sub", |args| int_op(&args, i64::wrapping_sub)),
+        ("wrapping_mul", |args| int_op(&args, i64::wrapping_mul)),
+        ("saturating_add", |args| int_op(&args, i64::saturating_add)),
+        ("saturating_sub", |args| int_op(&args, i64::saturating_sub)),
+        ("saturating_mul", |args| int_op(&args, i64::saturating_mul)),
+    ];
+    for (name, func) in integer_ops {
+        define(
+            name.to_string(),
+            Value::NativeFunction(NativeFunction { name: name.to_string(), arity: 2, func }),
+        );
+    }
+
     // Constants
     define("PI".to_string(), Value::Float(std::f64::consts::PI));
     define("E".to_string(), Value::Float(std::f64::consts::E));
     define("TAU".to_string(), Value::Float(std::f64::consts::TAU));
 }
 
+type NativeFn = fn(Vec<Value>) -> Result<Value, RuntimeError>;
+
+/// Apply an operator on two `Int` arguments
+fn int_op(args: &[Value], op: fn(i64, i64) -> i64) -> Result<Value, RuntimeError> {
+    match (&args[0], &args[1]) {
+        (Value::Int(a), Value::Int(b)) => Ok(Value::Int(op(*a, *b))),
+        _ => Err(RuntimeError::TypeError {
+            expected: "int, int".to_string(),
+            got: format!("{:?}, {:?}", args[0], args[1]),
+        }),
+    }
+}
+
 // ============================================================================
 // ARRAY FUNCTIONS
 // ============================================================================
@@ -914,19 +1032,7 @@ fn register_type_functions(define: &mut impl FnMut(String, Value)) {
             name: "type_of".to_string(),
             arity: 1,
             func: |args| {
-                let type_name = match &args[0] {
-                    Value::Int(_) => "Int",
-                    Value::Float(_) => "Float",
-                    Value::String(_) => "String",
-                    Value::Bool(_) => "Bool",
-                    Value::Unit => "Unit",
-                    Value::Array(_) => "Array",
-                    Value::Record(_) => "Record",
-                    Value::Function(_) => "Function",
-                    Value::NativeFunction(_) => "NativeFunction",
-                    Value::AiResult(_) => "AiResult",
-                };
-                Ok(Value::String(type_name.to_string()))
+                Ok(Value::String(args[0].type_name().to_string()))
             },
         }),
     );
@@ -941,22 +1047,20 @@ fn register_type_functions(define: &mut impl FnMut(String, Value)) {
         }),
     );
 
-    // to_int(value) - Convert to int
+    // to_int(value) - Convert to int: Result<Int, String>
     define(
         "to_int".to_string(),
         Value::NativeFunction(NativeFunction {
             name: "to_int".to_string(),
             arity: 1,
             func: |args| match &args[0] {
-                Value::Int(n) => Ok(Value::Int(*n)),
-                Value::Float(f) => Ok(Value::Int(*f as i64)),
-                Value::String(s) => s.parse::<i64>().map(Value::Int).map_err(|_| {
-                    RuntimeError::TypeError {
-                        expected: "integer string".to_string(),
-                        got: s.clone(),
-                    }
+                Value::Int(n) => Ok(Value::ok(Value::Int(*n))),
+                Value::Float(f) => Ok(Value::ok(Value::Int(*f as i64))),
+                Value::String(s) => Ok(match s.trim().parse::<i64>() {
+                    Ok(n) => Value::ok(Value::Int(n)),
+                    Err(e) => Value::err(Value::String(format!("invalid integer '{}': {}", s, e))),
                 }),
-                Value::Bool(b) => Ok(Value::Int(if *b { 1 } else { 0 })),
+                Value::Bool(b) => Ok(Value::ok(Value::Int(if *b { 1 } else { 0 }))),
                 _ => Err(RuntimeError::TypeError {
                     expected: "convertible to int".to_string(),
                     got: format!("{:?}", args[0]),
@@ -965,20 +1069,18 @@ fn register_type_functions(define: &mut impl FnMut(String, Value)) {
         }),
     );
 
-    // to_float(value) - Convert to float
+    // to_float(value) - Convert to float: Result<Float, String>
     define(
         "to_float".to_string(),
         Value::NativeFunction(NativeFunction {
             name: "to_float".to_string(),
             arity: 1,
             func: |args| match &args[0] {
-                Value::Int(n) => Ok(Value::Float(*n as f64)),
-                Value::Float(f) => Ok(Value::Float(*f)),
-                Value::String(s) => s.parse::<f64>().map(Value::Float).map_err(|_| {
-                    RuntimeError::TypeError {
-                        expected: "float string".to_string(),
-                        got: s.clone(),
-                    }
+                Value::Int(n) => Ok(Value::ok(Value::Float(*n as f64))),
+                Value::Float(f) => Ok(Value::ok(Value::Float(*f))),
+                Value::String(s) => Ok(match s.trim().parse::<f64>() {
+                    Ok(f) => Value::ok(Value::Float(f)),
+                    Err(e) => Value::err(Value::String(format!("invalid float '{}': {}", s, e))),
                 }),
                 _ => Err(RuntimeError::TypeError {
                     expected: "convertible to float".to_string(),
@@ -1332,6 +1434,12 @@ pub fn stdlib_functions() -> Vec<&'static str> {
         "log",
         "log10",
         "exp",
+        "wrapping_add",
+        "wrapping_sub",
+        "wrapping_mul",
+        "saturating_add",
+        "saturating_sub",
+        "saturating_mul",
         // Math constants
         "PI",
         "E",
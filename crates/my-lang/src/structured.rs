@@ -0,0 +1,195 @@
+//! Structured output for `ai generate`
+//!
+//! `let a: Article = ai generate { ... }` asks the model for an `Article`
+//! rather than for text. The JSON schema of the struct is built from its
+//! fields with the tool schema builder and appended to the prompt; the reply
+//! is then parsed and checked against the struct, field by field, into a
+//! `Value::Record`. Nested structs become nested records, `Option` fields may
+//! be missing or null, and enums whose variants are all units are answered by
+//! variant name. A `#[ai_validate("...")]` string on a field is passed to the
+//! model as the field's description.
+
+use crate::ast::*;
+use crate::interpreter::{Value, VariantValue};
+use crate::library::mylang::tools::{ToolDef, ToolParamType, ToolParameter};
+use std::collections::HashMap;
+
+/// The structs and enums of a program, which struct fields may name
+pub struct Shapes<'a> {
+    pub structs: &'a HashMap<String, StructDecl>,
+    pub enums: &'a HashMap<String, EnumDecl>,
+}
+
+impl Shapes<'_> {
+    /// The JSON schema of the objects describing a `decl`
+    pub fn schema(&self, decl: &StructDecl) -> String {
+        let mut tool = ToolDef::new(&decl.name.name, "");
+        for field in &decl.fields {
+            let (ty, required) = match option_payload(&field.ty) {
+                Some(payload) => (payload, false),
+                None => (&field.ty, true),
+            };
+            let mut param = ToolParameter::new(&field.name.name, self.param_type(ty, &mut vec![&decl.name.name]));
+            for modifier in &field.modifiers {
+                if let FieldModifier::AiValidate(description) = modifier {
+                    param = param.with_description(description);
+                }
+            }
+            tool = tool.param(if required { param } else { param.optional() });
+        }
+        let schema: serde_json::Value =
+            serde_json::from_str(&tool.to_json_schema()).expect("tool schemas are valid JSON");
+        schema["parameters"].to_string()
+    }
+
+    /// `visiting` holds the structs being described, so recursive structs end
+    /// in an object without properties
+    fn param_type<'a>(&'a self, ty: &'a Type, visiting: &mut Vec<&'a str>) -> ToolParamType {
+        match ty {
+            Type::Primitive(PrimitiveType::Int) => ToolParamType::Integer,
+            Type::Primitive(PrimitiveType::Float) => ToolParamType::Float,
+            Type::Primitive(PrimitiveType::String) => ToolParamType::String,
+            Type::Primitive(PrimitiveType::Bool) => ToolParamType::Boolean,
+            Type::Array { element, .. } => ToolParamType::Array(Box::new(self.param_type(element, visiting))),
+            Type::Constrained { base, .. } => self.param_type(base, visiting),
+            Type::Generic { .. } => match option_payload(ty) {
+                Some(payload) => self.param_type(payload, visiting),
+                None => ToolParamType::Any,
+            },
+            Type::Record { fields, .. } => ToolParamType::Object(
+                fields.iter().map(|f| (f.name.name.clone(), self.param_type(&f.ty, visiting))).collect(),
+            ),
+            Type::Named(name) => {
+                if let Some(variants) = self.unit_variants(&name.name) {
+                    return ToolParamType::Enum(variants);
+                }
+                let Some(decl) = self.structs.get(&name.name) else { return ToolParamType::Any };
+                if visiting.contains(&name.name.as_str()) {
+                    return ToolParamType::Object(HashMap::new());
+                }
+                visiting.push(&name.name);
+                let fields =
+                    decl.fields.iter().map(|f| (f.name.name.clone(), self.param_type(&f.ty, visiting))).collect();
+                visiting.pop();
+                ToolParamType::Object(fields)
+            }
+            _ => ToolParamType::Any,
+        }
+    }
+
+    /// The variant names of the enum `name`, if all its variants are units
+    fn unit_variants(&self, name: &str) -> Option<Vec<String>> {
+        let decl = self.enums.get(name)?;
+        decl.variants
+            .iter()
+            .map(|v| matches!(v.kind, VariantKind::Unit).then(|| v.name.name.clone()))
+            .collect()
+    }
+
+    /// Parse a model's reply into a record of `decl`. The JSON object may be
+    /// surrounded by other text, such as a Markdown code fence. The error says
+    /// what is wrong with the reply, for the model to correct.
+    pub fn parse_reply(&self, reply: &str, decl: &StructDecl) -> Result<Value, String> {
+        let (Some(start), Some(end)) = (reply.find('{'), reply.rfind('}')) else {
+            return Err("the reply does not contain a JSON object".to_string());
+        };
+        let json: serde_json::Value = serde_json::from_str(&reply[start..=end.max(start)])
+            .map_err(|e| format!("the reply is not valid JSON: {}", e))?;
+        self.record(&json, &decl.fields, "")
+    }
+
+    fn record(&self, json: &serde_json::Value, fields: &[StructField], path: &str) -> Result<Value, String> {
+        let object = json.as_object().ok_or_else(|| mismatch(path, "an object", json))?;
+        let mut record = HashMap::new();
+        for field in fields {
+            let name = &field.name.name;
+            let path = if path.is_empty() { name.clone() } else { format!("{}.{}", path, name) };
+            let value = match object.get(name) {
+                Some(value) => self.value(value, &field.ty, &path)?,
+                None if option_payload(&field.ty).is_some() => Value::none(),
+                None => return Err(format!("field '{}' is missing", path)),
+            };
+            record.insert(name.clone(), value);
+        }
+        Ok(Value::Record(record))
+    }
+
+    fn value(&self, json: &serde_json::Value, ty: &Type, path: &str) -> Result<Value, String> {
+        use serde_json::Value as Json;
+        match (ty, json) {
+            (Type::Primitive(PrimitiveType::Int), Json::Number(n)) if n.is_i64() => {
+                Ok(Value::Int(n.as_i64().unwrap_or_default()))
+            }
+            (Type::Primitive(PrimitiveType::Int), _) => Err(mismatch(path, "an integer", json)),
+            (Type::Primitive(PrimitiveType::Float), Json::Number(n)) => Ok(Value::Float(n.as_f64().unwrap_or_default())),
+            (Type::Primitive(PrimitiveType::Float), _) => Err(mismatch(path, "a number", json)),
+            (Type::Primitive(PrimitiveType::String), Json::String(s)) => Ok(Value::String(s.clone())),
+            (Type::Primitive(PrimitiveType::String), _) => Err(mismatch(path, "a string", json)),
+            (Type::Primitive(PrimitiveType::Bool), Json::Bool(b)) => Ok(Value::Bool(*b)),
+            (Type::Primitive(PrimitiveType::Bool), _) => Err(mismatch(path, "a boolean", json)),
+            (Type::Array { element, .. }, Json::Array(items)) => items
+                .iter()
+                .enumerate()
+                .map(|(i, item)| self.value(item, element, &format!("{}[{}]", path, i)))
+                .collect::<Result<_, _>>()
+                .map(Value::Array),
+            (Type::Array { .. }, _) => Err(mismatch(path, "an array", json)),
+            (Type::Constrained { base, .. }, _) => self.value(json, base, path),
+            (Type::Record { fields, .. }, _) => {
+                let fields: Vec<StructField> = fields
+                    .iter()
+                    .map(|f| StructField { modifiers: Vec::new(), name: f.name.clone(), ty: f.ty.clone(), span: f.name.span })
+                    .collect();
+                self.record(json, &fields, path)
+            }
+            (Type::Generic { .. }, _) => match option_payload(ty) {
+                Some(_) if json.is_null() => Ok(Value::none()),
+                Some(payload) => self.value(json, payload, path).map(Value::some),
+                None => Ok(untyped(json)),
+            },
+            (Type::Named(name), _) => {
+                if let Some(variants) = self.unit_variants(&name.name) {
+                    return match json.as_str().filter(|s| variants.iter().any(|v| v == s)) {
+                        Some(variant) => Ok(Value::Variant(VariantValue {
+                            enum_name: name.name.clone(),
+                            variant: variant.to_string(),
+                            fields: Vec::new(),
+                        })),
+                        None => Err(mismatch(path, &format!("one of {}", variants.join(", ")), json)),
+                    };
+                }
+                match self.structs.get(&name.name) {
+                    Some(decl) => self.record(json, &decl.fields, path),
+                    None => Ok(untyped(json)),
+                }
+            }
+            _ => Ok(untyped(json)),
+        }
+    }
+}
+
+/// `T` if `ty` is `Option<T>`
+fn option_payload(ty: &Type) -> Option<&Type> {
+    match ty {
+        Type::Generic { name, args, .. } if name.name == "Option" && args.len() == 1 => Some(&args[0]),
+        _ => None,
+    }
+}
+
+fn mismatch(path: &str, expected: &str, found: &serde_json::Value) -> String {
+    let place = if path.is_empty() { "the reply".to_string() } else { format!("field '{}'", path) };
+    format!("{} should be {}, not {}", place, expected, found)
+}
+
+/// A JSON value for a field whose type has no schema
+fn untyped(json: &serde_json::Value) -> Value {
+    use serde_json::Value as Json;
+    match json {
+        Json::Null => Value::Unit,
+        Json::Bool(b) => Value::Bool(*b),
+        Json::Number(n) => n.as_i64().map_or_else(|| Value::Float(n.as_f64().unwrap_or_default()), Value::Int),
+        Json::String(s) => Value::String(s.clone()),
+        Json::Array(items) => Value::Array(items.iter().map(untyped).collect()),
+        Json::Object(fields) => Value::Record(fields.iter().map(|(k, v)| (k.clone(), untyped(v))).collect()),
+    }
+}
@@ -9,17 +9,22 @@ pub struct Token {
     pub literal: String,
 }
 
+/// Identifies a source file among those loaded together; `0` is the entry
+/// file, and the only file of a single-file program
+pub type FileId = u32;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub struct Span {
     pub start: usize,
     pub end: usize,
     pub line: usize,
     pub column: usize,
+    pub file: FileId,
 }
 
 impl Span {
     pub fn new(start: usize, end: usize, line: usize, column: usize) -> Self {
-        Self { start, end, line, column }
+        Self { start, end, line, column, file: 0 }
     }
 }
 
@@ -34,10 +39,14 @@ pub enum TokenKind {
 
     // Identifiers
     Ident,
+    Label,       // 'name
 
     // Keywords
     Fn,
     Struct,
+    Enum,
+    Trait,
+    Impl,
     Effect,
     Where,
     Pre,
@@ -55,7 +64,18 @@ pub enum TokenKind {
     Restrict,
     Match,
     Use,
+    Pub,
     Op,
+    While,
+    For,
+    In,
+    Loop,
+    Break,
+    Continue,
+    Perform,
+    Handle,
+    With,
+    Resume,
 
     // AI Keywords
     Ai,
@@ -90,6 +110,9 @@ pub enum TokenKind {
     Minus,
     Star,
     Slash,
+    Percent,
+    Caret,       // ^
+    LtLt,        // <<, while `>>` is two `Gt`, which may close type arguments
     Eq,
     EqEq,
     BangEq,
@@ -104,9 +127,22 @@ pub enum TokenKind {
     Arrow,       // ->
     FatArrow,    // =>
     ColonColon,  // ::
+    DotDot,      // ..
+    DotDotEq,    // ..=
     Ampersand,   // &
     Pipe,        // |
 
+    // Compound assignment; `>>=` is a `Gt` followed by a `GtEq`
+    PlusEq,
+    MinusEq,
+    StarEq,
+    SlashEq,
+    PercentEq,
+    AmpersandEq,
+    PipeEq,
+    CaretEq,
+    LtLtEq,
+
     // Delimiters
     LParen,
     RParen,
@@ -123,6 +159,11 @@ pub enum TokenKind {
     // Attributes
     HashBracket, // #[
 
+    // Trivia, kept only by `Lexer::with_trivia`
+    Whitespace,
+    LineComment,  // // ...
+    BlockComment, // /* ... */ or (* ... *)
+
     // Special
     Eof,
     Error,
@@ -137,8 +178,12 @@ impl fmt::Display for TokenKind {
             TokenKind::True => write!(f, "true"),
             TokenKind::False => write!(f, "false"),
             TokenKind::Ident => write!(f, "identifier"),
+            TokenKind::Label => write!(f, "label"),
             TokenKind::Fn => write!(f, "fn"),
             TokenKind::Struct => write!(f, "struct"),
+            TokenKind::Enum => write!(f, "enum"),
+            TokenKind::Trait => write!(f, "trait"),
+            TokenKind::Impl => write!(f, "impl"),
             TokenKind::Effect => write!(f, "effect"),
             TokenKind::Where => write!(f, "where"),
             TokenKind::Pre => write!(f, "pre"),
@@ -156,7 +201,18 @@ impl fmt::Display for TokenKind {
             TokenKind::Restrict => write!(f, "restrict"),
             TokenKind::Match => write!(f, "match"),
             TokenKind::Use => write!(f, "use"),
+            TokenKind::Pub => write!(f, "pub"),
             TokenKind::Op => write!(f, "op"),
+            TokenKind::While => write!(f, "while"),
+            TokenKind::For => write!(f, "for"),
+            TokenKind::In => write!(f, "in"),
+            TokenKind::Loop => write!(f, "loop"),
+            TokenKind::Break => write!(f, "break"),
+            TokenKind::Continue => write!(f, "continue"),
+            TokenKind::Perform => write!(f, "perform"),
+            TokenKind::Handle => write!(f, "handle"),
+            TokenKind::With => write!(f, "with"),
+            TokenKind::Resume => write!(f, "resume"),
             TokenKind::Ai => write!(f, "ai"),
             TokenKind::AiBang => write!(f, "ai!"),
             TokenKind::Query => write!(f, "query"),
@@ -185,6 +241,9 @@ impl fmt::Display for TokenKind {
             TokenKind::Minus => write!(f, "-"),
             TokenKind::Star => write!(f, "*"),
             TokenKind::Slash => write!(f, "/"),
+            TokenKind::Percent => write!(f, "%"),
+            TokenKind::Caret => write!(f, "^"),
+            TokenKind::LtLt => write!(f, "<<"),
             TokenKind::Eq => write!(f, "="),
             TokenKind::EqEq => write!(f, "=="),
             TokenKind::BangEq => write!(f, "!="),
@@ -199,8 +258,19 @@ impl fmt::Display for TokenKind {
             TokenKind::Arrow => write!(f, "->"),
             TokenKind::FatArrow => write!(f, "=>"),
             TokenKind::ColonColon => write!(f, "::"),
+            TokenKind::DotDot => write!(f, ".."),
+            TokenKind::DotDotEq => write!(f, "..="),
             TokenKind::Ampersand => write!(f, "&"),
             TokenKind::Pipe => write!(f, "|"),
+            TokenKind::PlusEq => write!(f, "+="),
+            TokenKind::MinusEq => write!(f, "-="),
+            TokenKind::StarEq => write!(f, "*="),
+            TokenKind::SlashEq => write!(f, "/="),
+            TokenKind::PercentEq => write!(f, "%="),
+            TokenKind::AmpersandEq => write!(f, "&="),
+            TokenKind::PipeEq => write!(f, "|="),
+            TokenKind::CaretEq => write!(f, "^="),
+            TokenKind::LtLtEq => write!(f, "<<="),
             TokenKind::LParen => write!(f, "("),
             TokenKind::RParen => write!(f, ")"),
             TokenKind::LBrace => write!(f, "{{"),
@@ -213,12 +283,25 @@ impl fmt::Display for TokenKind {
             TokenKind::Dot => write!(f, "."),
             TokenKind::At => write!(f, "@"),
             TokenKind::HashBracket => write!(f, "#["),
+            TokenKind::Whitespace => write!(f, "whitespace"),
+            TokenKind::LineComment | TokenKind::BlockComment => write!(f, "comment"),
             TokenKind::Eof => write!(f, "EOF"),
             TokenKind::Error => write!(f, "ERROR"),
         }
     }
 }
 
+impl TokenKind {
+    /// Whitespace or a comment
+    pub fn is_trivia(&self) -> bool {
+        matches!(self, TokenKind::Whitespace | TokenKind::LineComment | TokenKind::BlockComment)
+    }
+
+    pub fn is_comment(&self) -> bool {
+        matches!(self, TokenKind::LineComment | TokenKind::BlockComment)
+    }
+}
+
 impl Token {
     pub fn new(kind: TokenKind, span: Span, literal: impl Into<String>) -> Self {
         Self {
@@ -19,12 +19,24 @@ pub enum Ty {
     /// Named type (struct, effect, etc.)
     Named(String),
 
+    /// Generic type applied to arguments: `Option<Int>`
+    Generic {
+        name: String,
+        args: Vec<Ty>,
+    },
+
     /// Function type
     Function {
         params: Vec<Ty>,
         result: Box<Ty>,
     },
 
+    /// Polymorphic type quantified over named type parameters: `<T> T -> T`
+    Forall {
+        params: Vec<String>,
+        ty: Box<Ty>,
+    },
+
     /// Array type
     Array(Box<Ty>),
 
@@ -43,8 +55,9 @@ pub enum Ty {
     /// AI effect type
     AI(Box<Ty>),
 
-    /// Effect type
-    Effect(Box<Ty>),
+    /// Effect type: a value computed while performing the effects named in the
+    /// row, which is kept sorted
+    Effect(Box<Ty>, Vec<String>),
 
     /// Type variable (for inference)
     Var(usize),
@@ -72,6 +85,112 @@ impl Ty {
         matches!(self, Ty::Error | Ty::Unknown)
     }
 
+    /// Name of the nominal type (struct or enum) this type refers to, if any
+    pub fn nominal_name(&self) -> Option<&str> {
+        match self {
+            Ty::Named(name) | Ty::Generic { name, .. } => Some(name),
+            _ => None,
+        }
+    }
+
+    /// Key under which impl blocks for this type are registered: the nominal name,
+    /// the primitive name or `Array`
+    pub fn impl_key(&self) -> Option<String> {
+        match self {
+            Ty::Named(name) | Ty::Generic { name, .. } => Some(name.clone()),
+            Ty::Int | Ty::Float | Ty::String | Ty::Bool => Some(self.to_string()),
+            Ty::Array(_) => Some("Array".to_string()),
+            _ => None,
+        }
+    }
+
+    /// Replace the type parameters `params` with `args`
+    pub fn substitute(&self, params: &[String], args: &[Ty]) -> Ty {
+        let sub = |t: &Ty| t.substitute(params, args);
+        match self {
+            Ty::Named(name) => match params.iter().position(|p| p == name) {
+                Some(i) => args.get(i).cloned().unwrap_or(Ty::Unknown),
+                None => self.clone(),
+            },
+            Ty::Generic { name, args: inner } => Ty::Generic {
+                name: name.clone(),
+                args: inner.iter().map(sub).collect(),
+            },
+            Ty::Function { params: ps, result } => Ty::Function {
+                params: ps.iter().map(sub).collect(),
+                result: Box::new(sub(result)),
+            },
+            Ty::Array(inner) => Ty::Array(Box::new(sub(inner))),
+            Ty::Ref { mutable, inner } => Ty::Ref {
+                mutable: *mutable,
+                inner: Box::new(sub(inner)),
+            },
+            Ty::Tuple(types) => Ty::Tuple(types.iter().map(sub).collect()),
+            Ty::Record(fields) => Ty::Record(fields.iter().map(|(n, t)| (n.clone(), sub(t))).collect()),
+            Ty::AI(inner) => Ty::AI(Box::new(sub(inner))),
+            Ty::Effect(inner, row) => Ty::Effect(Box::new(sub(inner)), row.clone()),
+            Ty::Forall { params: bound, ty } => {
+                // Inner quantifiers shadow the outer parameters of the same name
+                let (outer, outer_args): (Vec<String>, Vec<Ty>) = params
+                    .iter()
+                    .cloned()
+                    .zip(args.iter().cloned().chain(std::iter::repeat(Ty::Unknown)))
+                    .filter(|(p, _)| !bound.contains(p))
+                    .unzip();
+                Ty::Forall {
+                    params: bound.clone(),
+                    ty: Box::new(ty.substitute(&outer, &outer_args)),
+                }
+            }
+            _ => self.clone(),
+        }
+    }
+
+    /// Rebuild the type bottom-up, letting `f` replace any subterm before it is visited
+    pub fn map(&self, f: &mut impl FnMut(&Ty) -> Option<Ty>) -> Ty {
+        if let Some(ty) = f(self) {
+            return ty;
+        }
+        match self {
+            Ty::Generic { name, args } => Ty::Generic {
+                name: name.clone(),
+                args: args.iter().map(|t| t.map(f)).collect(),
+            },
+            Ty::Function { params, result } => Ty::Function {
+                params: params.iter().map(|t| t.map(f)).collect(),
+                result: Box::new(result.map(f)),
+            },
+            Ty::Forall { params, ty } => Ty::Forall {
+                params: params.clone(),
+                ty: Box::new(ty.map(f)),
+            },
+            Ty::Array(inner) => Ty::Array(Box::new(inner.map(f))),
+            Ty::Ref { mutable, inner } => Ty::Ref {
+                mutable: *mutable,
+                inner: Box::new(inner.map(f)),
+            },
+            Ty::Tuple(types) => Ty::Tuple(types.iter().map(|t| t.map(f)).collect()),
+            Ty::Record(fields) => Ty::Record(fields.iter().map(|(n, t)| (n.clone(), t.map(f))).collect()),
+            Ty::AI(inner) => Ty::AI(Box::new(inner.map(f))),
+            Ty::Effect(inner, row) => Ty::Effect(Box::new(inner.map(f)), row.clone()),
+            _ => self.clone(),
+        }
+    }
+
+    /// Type variables occurring in this type, in order of first occurrence
+    pub fn vars(&self) -> Vec<usize> {
+        let mut vars = Vec::new();
+        self.map(&mut |t| {
+            if let Ty::Var(v) = t {
+                if !vars.contains(v) {
+                    vars.push(*v);
+                }
+            }
+            None
+        });
+        vars
+    }
+
     /// Check if two types are compatible for assignment
     pub fn is_assignable_from(&self, other: &Ty) -> bool {
         if self == other {
@@ -83,18 +202,29 @@ impl Ty {
             return true;
         }
 
-        // AI<T> is assignable from T
-        if let Ty::AI(inner) = self {
+        // AI<T> and Effect<T> are assignable from T
+        if let Ty::AI(inner) | Ty::Effect(inner, _) = self {
             if inner.as_ref() == other {
                 return true;
             }
         }
 
         match (self, other) {
+            // A polymorphic value can be used at any instantiation
+            (_, Ty::Forall { params, ty }) => self.is_assignable_from(&ty.substitute(params, &[])),
+            (Ty::Generic { name: n1, args: a1 }, Ty::Generic { name: n2, args: a2 }) => {
+                n1 == n2
+                    && a1.len() == a2.len()
+                    && a1.iter().zip(a2.iter()).all(|(x, y)| x.is_assignable_from(y))
+            }
+            // A bare generic name stands for any instantiation
+            (Ty::Generic { name, .. }, Ty::Named(n)) | (Ty::Named(n), Ty::Generic { name, .. }) => name == n,
             (Ty::Array(a), Ty::Array(b)) => a.is_assignable_from(b),
             (Ty::Ref { inner: a, .. }, Ty::Ref { inner: b, .. }) => a.is_assignable_from(b),
             (Ty::AI(a), Ty::AI(b)) => a.is_assignable_from(b),
-            (Ty::Effect(a), Ty::Effect(b)) => a.is_assignable_from(b),
+            (Ty::Effect(a, ra), Ty::Effect(b, rb)) => a.is_assignable_from(b) && rb.iter().all(|e| ra.contains(e)),
+            // An AI<T> is a T computed with the AI effect
+            (Ty::Effect(a, ra), Ty::AI(b)) => a.is_assignable_from(b) && ra.iter().any(|e| e == "AI"),
             (Ty::Tuple(a), Ty::Tuple(b)) if a.len() == b.len() => {
                 a.iter().zip(b.iter()).all(|(x, y)| x.is_assignable_from(y))
             }
@@ -117,6 +247,9 @@ impl fmt::Display for Ty {
             Ty::Bool => write!(f, "Bool"),
             Ty::Unit => write!(f, "()"),
             Ty::Named(name) => write!(f, "{}", name),
+            Ty::Generic { name, args } => {
+                write!(f, "{}<{}>", name, args.iter().map(|a| a.to_string()).collect::<Vec<_>>().join(", "))
+            }
             Ty::Function { params, result } => {
                 if params.len() == 1 {
                     write!(f, "{} -> {}", params[0], result)
@@ -126,6 +259,7 @@ impl fmt::Display for Ty {
                         result)
                 }
             }
+            Ty::Forall { params, ty } => write!(f, "<{}> {}", params.join(", "), ty),
             Ty::Array(inner) => write!(f, "[{}]", inner),
             Ty::Ref { mutable, inner } => {
                 if *mutable {
@@ -142,7 +276,8 @@ impl fmt::Display for Ty {
                     fields.iter().map(|(n, t)| format!("{}: {}", n, t)).collect::<Vec<_>>().join(", "))
             }
             Ty::AI(inner) => write!(f, "AI<{}>", inner),
-            Ty::Effect(inner) => write!(f, "Effect<{}>", inner),
+            Ty::Effect(inner, row) if row.is_empty() => write!(f, "Effect<{}>", inner),
+            Ty::Effect(inner, row) => write!(f, "Effect<{}, {}>", inner, row.join(", ")),
             Ty::Var(id) => write!(f, "?{}", id),
             Ty::Error => write!(f, "<error>"),
             Ty::Unknown => write!(f, "<unknown>"),
@@ -150,6 +285,185 @@ impl fmt::Display for Ty {
     }
 }
 
+/// Why two types could not be unified
+#[derive(Debug, Clone, PartialEq)]
+pub enum UnifyError {
+    /// The types have different shapes
+    Mismatch,
+    /// Binding the variable to the type would make it infinite: `?0 = ?0 -> Int`
+    Occurs(usize, Ty),
+}
+
+/// Bindings of inference variables, solved by unification
+///
+/// Every variable records the `let` nesting level it was created at. Binding a variable
+/// lowers the levels of the variables it is bound to, so after checking a `let` value the
+/// variables still above the enclosing level are exactly those that can be generalized.
+#[derive(Debug, Default)]
+pub struct Substitution {
+    bindings: Vec<Option<Ty>>,
+    levels: Vec<usize>,
+}
+
+impl Substitution {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A new unbound variable created at `level`
+    pub fn fresh(&mut self, level: usize) -> Ty {
+        self.bindings.push(None);
+        self.levels.push(level);
+        Ty::Var(self.bindings.len() - 1)
+    }
+
+    /// Follow the bindings of a variable at the root of `ty`
+    fn shallow(&self, ty: &Ty) -> Ty {
+        match ty {
+            Ty::Var(v) => match &self.bindings[*v] {
+                Some(bound) => self.shallow(bound),
+                None => ty.clone(),
+            },
+            _ => ty.clone(),
+        }
+    }
+
+    /// Apply the substitution throughout `ty`, leaving only unbound variables
+    pub fn resolve(&self, ty: &Ty) -> Ty {
+        ty.map(&mut |t| match t {
+            Ty::Var(v) => Some(match &self.bindings[*v] {
+                Some(bound) => self.resolve(bound),
+                None => t.clone(),
+            }),
+            _ => None,
+        })
+    }
+
+    /// Make `actual` and `expected` equal by binding variables in either.
+    ///
+    /// Where no variables are involved this is as permissive as `is_assignable_from`:
+    /// unknown types unify with anything and `AI<T>` accepts a plain `T`.
+    pub fn unify(&mut self, expected: &Ty, actual: &Ty) -> Result<(), UnifyError> {
+        let expected = self.shallow(expected);
+        let actual = self.shallow(actual);
+        match (&expected, &actual) {
+            (Ty::Var(a), Ty::Var(b)) if a == b => Ok(()),
+            (Ty::Var(v), ty) | (ty, Ty::Var(v)) => self.bind(*v, ty),
+            (a, b) if a.is_error_or_unknown() || b.is_error_or_unknown() => Ok(()),
+            (Ty::Function { params: p1, result: r1 }, Ty::Function { params: p2, result: r2 }) => {
+                if p1.len() != p2.len() {
+                    return Err(UnifyError::Mismatch);
+                }
+                self.unify_all(p1, p2)?;
+                self.unify(r1, r2)
+            }
+            (Ty::Array(a), Ty::Array(b))
+            | (Ty::AI(a), Ty::AI(b))
+            | (Ty::Ref { inner: a, .. }, Ty::Ref { inner: b, .. }) => self.unify(a, b),
+            (Ty::Generic { name: n1, args: a1 }, Ty::Generic { name: n2, args: a2 })
+                if n1 == n2 && a1.len() == a2.len() =>
+            {
+                self.unify_all(a1, a2)
+            }
+            (Ty::Tuple(a), Ty::Tuple(b)) if a.len() == b.len() => self.unify_all(a, b),
+            (Ty::Record(a), Ty::Record(b)) if a.len() == b.len() => {
+                for (name, ty) in a {
+                    let (_, other) = b.iter().find(|(n, _)| n == name).ok_or(UnifyError::Mismatch)?;
+                    self.unify(ty, other)?;
+                }
+                Ok(())
+            }
+            (Ty::Effect(a, ra), Ty::Effect(b, rb)) => {
+                if !rb.iter().all(|e| ra.contains(e)) {
+                    return Err(UnifyError::Mismatch);
+                }
+                self.unify(a, b)
+            }
+            (Ty::Effect(a, ra), Ty::AI(b)) if ra.iter().any(|e| e == "AI") => self.unify(a, b),
+            (Ty::AI(inner), _) | (Ty::Effect(inner, _), _) => self.unify(inner, &actual),
+            _ if expected.is_assignable_from(&actual) => Ok(()),
+            _ => Err(UnifyError::Mismatch),
+        }
+    }
+
+    fn unify_all(&mut self, expected: &[Ty], actual: &[Ty]) -> Result<(), UnifyError> {
+        for (e, a) in expected.iter().zip(actual) {
+            self.unify(e, a)?;
+        }
+        Ok(())
+    }
+
+    /// Bind the unbound variable `var` to `ty`, with an occurs check
+    fn bind(&mut self, var: usize, ty: &Ty) -> Result<(), UnifyError> {
+        let ty = self.resolve(ty);
+        let vars = ty.vars();
+        if vars.contains(&var) {
+            return Err(UnifyError::Occurs(var, ty));
+        }
+        let level = self.levels[var];
+        for v in vars {
+            self.levels[v] = self.levels[v].min(level);
+        }
+        self.bindings[var] = Some(ty);
+        Ok(())
+    }
+
+    /// Quantify `ty` over its unbound variables created deeper than `level`
+    pub fn generalize(&self, ty: &Ty, level: usize) -> Ty {
+        let ty = self.resolve(ty);
+        let vars: Vec<usize> = ty.vars().into_iter().filter(|v| self.levels[*v] > level).collect();
+        if vars.is_empty() {
+            return ty;
+        }
+        let params: Vec<String> = (0..vars.len()).map(param_name).collect();
+        let body = ty.map(&mut |t| match t {
+            Ty::Var(v) => vars.iter().position(|g| g == v).map(|i| Ty::Named(params[i].clone())),
+            _ => None,
+        });
+        Ty::Forall { params, ty: Box::new(body) }
+    }
+
+    /// Keep the unbound variables of `ty` from being generalized above `level`
+    pub fn pin(&mut self, ty: &Ty, level: usize) {
+        for v in self.resolve(ty).vars() {
+            self.levels[v] = self.levels[v].min(level);
+        }
+    }
+
+    /// Replace the quantified parameters of a polymorphic type with fresh variables
+    pub fn instantiate(&mut self, params: &[String], ty: &Ty, level: usize) -> Ty {
+        let args: Vec<Ty> = params.iter().map(|_| self.fresh(level)).collect();
+        ty.substitute(params, &args)
+    }
+}
+
+/// Name of the `i`th generalized type parameter: `'a`, `'b`, ..., `'z`, `'a1`, ...
+fn param_name(i: usize) -> String {
+    let letter = (b'a' + (i % 26) as u8) as char;
+    match i / 26 {
+        0 => format!("'{}", letter),
+        n => format!("'{}{}", letter, n),
+    }
+}
+
+/// Parameter and result types of an effect operation declared as `op name: ty`.
+///
+/// `A -> R` takes an `A` and resumes with an `R`; any other type is taken as the
+/// parameter of an operation resuming with unit. A tuple parameter is spread
+/// over several arguments, and a unit parameter means there are none.
+pub fn operation_signature(ty: &Ty) -> (Vec<Ty>, Ty) {
+    let (param, result) = match ty {
+        Ty::Function { params, result } if params.len() == 1 => (&params[0], result.as_ref().clone()),
+        _ => (ty, Ty::Unit),
+    };
+    let params = match param {
+        Ty::Unit => vec![],
+        Ty::Tuple(elements) => elements.clone(),
+        other => vec![other.clone()],
+    };
+    (params, result)
+}
+
 /// Convert AST type to internal type representation
 pub fn ast_type_to_ty(ty: &crate::ast::Type) -> Ty {
     use crate::ast::{Type, PrimitiveType};
@@ -162,11 +476,20 @@ pub fn ast_type_to_ty(ty: &crate::ast::Type) -> Ty {
             PrimitiveType::Bool => Ty::Bool,
         },
         Type::Named(ident) => Ty::Named(ident.name.clone()),
+        Type::Generic { name, args, .. } => Ty::Generic {
+            name: name.name.clone(),
+            args: args.iter().map(ast_type_to_ty).collect(),
+        },
         Type::Function { param, result, .. } => Ty::Function {
             params: vec![ast_type_to_ty(param)],
             result: Box::new(ast_type_to_ty(result)),
         },
-        Type::Effect { inner, .. } => Ty::Effect(Box::new(ast_type_to_ty(inner))),
+        Type::Effect { inner, effects, .. } => {
+            let mut row: Vec<String> = effects.iter().map(|e| e.name.clone()).collect();
+            row.sort();
+            row.dedup();
+            Ty::Effect(Box::new(ast_type_to_ty(inner)), row)
+        }
         Type::Ai { inner, .. } => Ty::AI(Box::new(ast_type_to_ty(inner))),
         Type::Reference { mutable, inner, .. } => Ty::Ref {
             mutable: *mutable,
@@ -176,9 +499,11 @@ pub fn ast_type_to_ty(ty: &crate::ast::Type) -> Ty {
         Type::Record { fields, .. } => Ty::Record(
             fields.iter().map(|f| (f.name.name.clone(), ast_type_to_ty(&f.ty))).collect()
         ),
+        Type::Tuple { elements, .. } if elements.is_empty() => Ty::Unit,
         Type::Tuple { elements, .. } => Ty::Tuple(
             elements.iter().map(ast_type_to_ty).collect()
         ),
         Type::Constrained { base, .. } => ast_type_to_ty(base),
+        Type::Infer(_) => Ty::Unknown,
     }
 }
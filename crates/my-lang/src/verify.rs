@@ -0,0 +1,1685 @@
+//! Static verification of function contracts
+//!
+//! `verify` tries to prove the `pre`, `post` and `invariant` clauses of every
+//! top-level function before the program runs. A bounded symbolic executor
+//! walks each body with its `Int` and `Bool` parameters as symbolic inputs:
+//! `if` forks the path, `while` loops are unrolled a fixed number of times and
+//! then the variables they assign are widened to arbitrary values, and calls
+//! are summarised by the callee's own contract, so that every function is
+//! verified on its own.
+//!
+//! A `post` or function `invariant` clause must hold on every path that
+//! returns, assuming the `pre` and `invariant` clauses on entry. A `pre`
+//! clause must hold at every call site, under the condition of the path that
+//! reaches it.
+//!
+//! Each obligation goes to a small decision procedure for linear integer
+//! arithmetic: the formula is put in disjunctive normal form, equalities are
+//! solved away and the remaining inequalities go through Fourier-Motzkin
+//! elimination, tightened to integers. When no rational solution of the path
+//! and the negated clause exists the clause is proved; when back-substitution
+//! finds integer inputs violating it, those inputs are a counterexample.
+//! Everything else, such as a product of two variables, a loop past its bound
+//! or a call that the analysis does not follow, makes the verdict unknown
+//! rather than wrong. `Int` is treated as unbounded, so overflow is out of
+//! scope.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+
+use crate::ast::{
+    AiBodyItem, AiExpr, AiStmtBody, BinaryOp, Block, ContractClause, Expr, FnDecl, Ident,
+    LambdaBody, Literal, Pattern, PrimitiveType, Program, Stmt, StringPart, TopLevel, Type, UnaryOp,
+};
+use crate::interpreter::Value;
+use crate::modules::ModuleGraph;
+use crate::token::Span;
+
+/// Iterations of a `while` loop executed before widening
+const LOOP_UNROLL: usize = 8;
+/// Paths kept alive through a block before giving up on a function
+const MAX_PATHS: usize = 256;
+/// Why a `pre` clause of a function that is never called is unknown
+const NO_CALL_SITES: &str = "no call sites";
+/// Conjunctions a formula may expand to in disjunctive normal form
+const MAX_DISJUNCTS: usize = 256;
+/// Inequalities kept after eliminating one variable
+const MAX_CONSTRAINTS: usize = 512;
+/// Nesting of callee contracts evaluated at a call site
+const MAX_CALL_DEPTH: usize = 4;
+
+// ============================================================================
+// Reports
+// ============================================================================
+
+/// The kind of contract clause a report is about
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClauseKind {
+    Pre,
+    Post,
+    Invariant,
+}
+
+impl fmt::Display for ClauseKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClauseKind::Pre => write!(f, "pre"),
+            ClauseKind::Post => write!(f, "post"),
+            ClauseKind::Invariant => write!(f, "invariant"),
+        }
+    }
+}
+
+/// Inputs under which a clause fails
+#[derive(Debug, Clone, PartialEq)]
+pub struct Counterexample {
+    /// Whose inputs these are: the verified function for `post` and
+    /// `invariant`, the caller for `pre`
+    pub function: String,
+    pub inputs: Vec<(String, Value)>,
+    /// The call that violates a `pre` clause
+    pub call: Option<Span>,
+}
+
+impl fmt::Display for Counterexample {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let inputs = self
+            .inputs
+            .iter()
+            .map(|(name, value)| format!("{} = {}", name, value))
+            .collect::<Vec<_>>()
+            .join(", ");
+        match self.call {
+            Some(span) => {
+                write!(f, "the call in '{}' at {}:{}", self.function, span.line, span.column)?;
+                if !inputs.is_empty() {
+                    write!(f, " with {}", inputs)?;
+                }
+                Ok(())
+            }
+            None if inputs.is_empty() => write!(f, "every input"),
+            None => write!(f, "{}", inputs),
+        }
+    }
+}
+
+/// The outcome of verifying one clause
+#[derive(Debug, Clone, PartialEq)]
+pub enum Verdict {
+    Proved,
+    Refuted(Counterexample),
+    /// Neither proved nor refuted, with the reason
+    Unknown(String),
+}
+
+impl fmt::Display for Verdict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Verdict::Proved => write!(f, "proved"),
+            Verdict::Refuted(cex) => write!(f, "refuted by {}", cex),
+            Verdict::Unknown(why) => write!(f, "unknown: {}", why),
+        }
+    }
+}
+
+/// The verdict on one clause of a function's contract
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClauseReport {
+    pub function: String,
+    pub kind: ClauseKind,
+    pub span: Span,
+    pub verdict: Verdict,
+}
+
+impl fmt::Display for ClauseReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}: {} of '{}' {}",
+            self.span.line, self.span.column, self.kind, self.function, self.verdict
+        )
+    }
+}
+
+/// Verify the `pre`, `post` and `invariant` clauses of the program's
+/// top-level functions, reporting on each in source order
+pub fn verify(program: &Program) -> Vec<ClauseReport> {
+    let functions: Vec<&FnDecl> = program
+        .items
+        .iter()
+        .filter_map(|item| match item {
+            TopLevel::Function(f) => Some(&**f),
+            _ => None,
+        })
+        .collect();
+
+    let mut verifier = Verifier::new(&functions);
+    // Every body runs before any report, so that all call sites are known
+    let runs: Vec<Run> = functions.iter().map(|f| verifier.run(f)).collect();
+    verifier.recording = false;
+
+    let mut reports = Vec::new();
+    for (f, run) in functions.iter().zip(&runs) {
+        for (idx, clause) in clauses(f).iter().enumerate() {
+            let (kind, verdict) = match clause {
+                ContractClause::Pre(..) => (ClauseKind::Pre, verifier.check_calls(f, idx)),
+                ContractClause::Post(expr, _) => (ClauseKind::Post, verifier.check_returns(f, run, expr)),
+                ContractClause::Invariant(expr, _) => {
+                    (ClauseKind::Invariant, verifier.check_returns(f, run, expr))
+                }
+                ContractClause::AiCheck(..) | ContractClause::AiEnsure(..) => continue,
+            };
+            reports.push(ClauseReport {
+                function: f.name.name.clone(),
+                kind,
+                span: clause.span(),
+                verdict,
+            });
+        }
+    }
+    reports
+}
+
+/// Verify the contracts of every module of a multi-file program. Each module
+/// is verified with the functions it imports, so that a `pre` clause is
+/// checked at the call sites of every module that calls the function; it is
+/// reported once, with the weakest verdict of those modules. Other clauses
+/// are verified in their own module.
+pub fn verify_modules(graph: &ModuleGraph) -> Vec<ClauseReport> {
+    let strength = |verdict: &Verdict| match verdict {
+        Verdict::Unknown(why) if why == NO_CALL_SITES => 0,
+        Verdict::Proved => 1,
+        Verdict::Unknown(_) => 2,
+        Verdict::Refuted(_) => 3,
+    };
+    let mut reports: Vec<ClauseReport> = Vec::new();
+    for &file in &graph.order {
+        let mut program = graph.module(file).program.clone();
+        program.items.extend(graph.imported_items(file));
+        for report in verify(&program) {
+            if report.kind != ClauseKind::Pre && report.span.file != file {
+                continue;
+            }
+            match reports.iter_mut().find(|r| r.span == report.span) {
+                Some(seen) if strength(&report.verdict) > strength(&seen.verdict) => *seen = report,
+                Some(_) => {}
+                None => reports.push(report),
+            }
+        }
+    }
+    reports
+}
+
+fn clauses(f: &FnDecl) -> &[ContractClause] {
+    f.contract.as_ref().map_or(&[], |c| &c.clauses)
+}
+
+// ============================================================================
+// Linear arithmetic
+// ============================================================================
+
+/// Values of the variables in a solution; missing variables are zero
+type Model = BTreeMap<usize, i128>;
+
+/// An integer combination of variables plus a constant
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+struct Linear {
+    terms: BTreeMap<usize, i128>,
+    constant: i128,
+}
+
+impl Linear {
+    fn constant(constant: i128) -> Self {
+        Linear { terms: BTreeMap::new(), constant }
+    }
+
+    /// `coeff * var + constant`
+    fn term(var: usize, coeff: i128, constant: i128) -> Self {
+        Linear { terms: BTreeMap::from([(var, coeff)]), constant }
+    }
+
+    fn as_constant(&self) -> Option<i128> {
+        self.terms.is_empty().then_some(self.constant)
+    }
+
+    fn coeff(&self, var: usize) -> i128 {
+        self.terms.get(&var).copied().unwrap_or(0)
+    }
+
+    fn add(&self, other: &Linear) -> Option<Linear> {
+        let mut sum = self.clone();
+        sum.constant = sum.constant.checked_add(other.constant)?;
+        for (&var, &c) in &other.terms {
+            let c = sum.coeff(var).checked_add(c)?;
+            if c == 0 {
+                sum.terms.remove(&var);
+            } else {
+                sum.terms.insert(var, c);
+            }
+        }
+        Some(sum)
+    }
+
+    fn sub(&self, other: &Linear) -> Option<Linear> {
+        self.add(&other.scale(-1)?)
+    }
+
+    fn offset(&self, c: i128) -> Option<Linear> {
+        self.add(&Linear::constant(c))
+    }
+
+    fn scale(&self, k: i128) -> Option<Linear> {
+        if k == 0 {
+            return Some(Linear::default());
+        }
+        let mut terms = BTreeMap::new();
+        for (&var, &c) in &self.terms {
+            terms.insert(var, c.checked_mul(k)?);
+        }
+        Some(Linear { terms, constant: self.constant.checked_mul(k)? })
+    }
+
+    /// Replace `var` with `def`
+    fn substitute(&self, var: usize, def: &Linear) -> Option<Linear> {
+        let c = self.coeff(var);
+        if c == 0 {
+            return Some(self.clone());
+        }
+        let mut rest = self.clone();
+        rest.terms.remove(&var);
+        rest.add(&def.scale(c)?)
+    }
+
+    fn eval(&self, model: &Model) -> Option<i128> {
+        self.terms.iter().try_fold(self.constant, |acc, (var, c)| {
+            acc.checked_add(c.checked_mul(model.get(var).copied().unwrap_or(0))?)
+        })
+    }
+}
+
+/// A constraint on a linear combination
+#[derive(Debug, Clone, PartialEq)]
+enum Atom {
+    /// `e <= 0`
+    Le(Linear),
+    /// `e == 0`
+    Eq(Linear),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Formula {
+    Const(bool),
+    Atom(Atom),
+    Not(Box<Formula>),
+    And(Vec<Formula>),
+    Or(Vec<Formula>),
+}
+
+impl Formula {
+    fn not(self) -> Formula {
+        match self {
+            Formula::Const(b) => Formula::Const(!b),
+            Formula::Not(f) => *f,
+            f => Formula::Not(Box::new(f)),
+        }
+    }
+
+    fn iff(a: Formula, b: Formula) -> Formula {
+        Formula::Or(vec![
+            Formula::And(vec![a.clone(), b.clone()]),
+            Formula::And(vec![a.not(), b.not()]),
+        ])
+    }
+}
+
+/// Disjunctive normal form of `f`, or of its negation, as a list of
+/// conjunctions; `None` when it grows too large. Over the integers
+/// `!(e <= 0)` is `1 - e <= 0` and `e != 0` splits into `e < 0` or `e > 0`.
+fn dnf(f: &Formula, negate: bool) -> Option<Vec<Vec<Atom>>> {
+    match (f, negate) {
+        (Formula::Const(b), _) => Some(if *b != negate { vec![vec![]] } else { vec![] }),
+        (Formula::Atom(atom), false) => Some(vec![vec![atom.clone()]]),
+        (Formula::Atom(Atom::Le(e)), true) => Some(vec![vec![Atom::Le(e.scale(-1)?.offset(1)?)]]),
+        (Formula::Atom(Atom::Eq(e)), true) => Some(vec![
+            vec![Atom::Le(e.offset(1)?)],
+            vec![Atom::Le(e.scale(-1)?.offset(1)?)],
+        ]),
+        (Formula::Not(g), _) => dnf(g, !negate),
+        (Formula::And(fs), false) | (Formula::Or(fs), true) => {
+            let mut product = vec![vec![]];
+            for g in fs {
+                let part = dnf(g, negate)?;
+                let mut next = Vec::new();
+                for left in &product {
+                    for right in &part {
+                        next.push(left.iter().chain(right).cloned().collect());
+                    }
+                }
+                if next.len() > MAX_DISJUNCTS {
+                    return None;
+                }
+                product = next;
+            }
+            Some(product)
+        }
+        (Formula::And(fs), true) | (Formula::Or(fs), false) => {
+            let mut sum = Vec::new();
+            for g in fs {
+                sum.extend(dnf(g, negate)?);
+                if sum.len() > MAX_DISJUNCTS {
+                    return None;
+                }
+            }
+            Some(sum)
+        }
+    }
+}
+
+enum Sat {
+    Unsat,
+    /// An integer solution, checked against the original constraints
+    Model(Model),
+    Unknown,
+}
+
+/// Decide the conjunction of `formulas` over the integers
+fn satisfiable(formulas: &[Formula]) -> Sat {
+    let Some(disjuncts) = dnf(&Formula::And(formulas.to_vec()), false) else {
+        return Sat::Unknown;
+    };
+    let mut unknown = false;
+    for atoms in disjuncts {
+        match solve(&atoms) {
+            Some(Sat::Unsat) => {}
+            Some(Sat::Model(model)) => return Sat::Model(model),
+            Some(Sat::Unknown) | None => unknown = true,
+        }
+    }
+    if unknown {
+        Sat::Unknown
+    } else {
+        Sat::Unsat
+    }
+}
+
+fn gcd(a: i128, b: i128) -> i128 {
+    let (mut a, mut b) = (a.abs(), b.abs());
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+enum Tightened {
+    Trivial,
+    Infeasible,
+    Constraint(Linear),
+}
+
+/// Normalise `e <= 0` by dividing through by the gcd of its coefficients and
+/// rounding the constant up, which loses no integer solutions
+fn tighten(mut e: Linear) -> Tightened {
+    let g = e.terms.values().fold(0, |g, &c| gcd(g, c));
+    if g == 0 {
+        return if e.constant <= 0 { Tightened::Trivial } else { Tightened::Infeasible };
+    }
+    for c in e.terms.values_mut() {
+        *c /= g;
+    }
+    e.constant = -(-e.constant).div_euclid(g);
+    Tightened::Constraint(e)
+}
+
+/// The variable whose elimination creates the fewest new constraints
+fn pick(constraints: &HashSet<Linear>) -> Option<usize> {
+    let mut counts: BTreeMap<usize, (usize, usize)> = BTreeMap::new();
+    for e in constraints {
+        for (&var, &c) in &e.terms {
+            let (lower, upper) = counts.entry(var).or_default();
+            if c < 0 {
+                *lower += 1;
+            } else {
+                *upper += 1;
+            }
+        }
+    }
+    counts.into_iter().min_by_key(|(_, (lower, upper))| lower * upper).map(|(var, _)| var)
+}
+
+/// Decide a conjunction of atoms, or `None` on overflow or blow-up
+fn solve(atoms: &[Atom]) -> Option<Sat> {
+    let mut eqs = Vec::new();
+    let mut les = Vec::new();
+    for atom in atoms {
+        match atom {
+            Atom::Eq(e) => eqs.push(e.clone()),
+            Atom::Le(e) => les.push(e.clone()),
+        }
+    }
+
+    // Solve each equality for a variable with a unit coefficient, if it has
+    // one, and substitute it everywhere else
+    let mut defs: Vec<(usize, Linear)> = Vec::new();
+    while let Some(eq) = eqs.pop() {
+        let g = eq.terms.values().fold(0, |g, &c| gcd(g, c));
+        if g == 0 || eq.constant % g != 0 {
+            if eq.constant != 0 {
+                return Some(Sat::Unsat);
+            }
+            continue;
+        }
+        let Some((&var, &c)) = eq.terms.iter().find(|(_, c)| c.abs() == 1) else {
+            les.push(eq.scale(-1)?);
+            les.push(eq);
+            continue;
+        };
+        let mut rest = eq.clone();
+        rest.terms.remove(&var);
+        let def = rest.scale(-c)?;
+        for e in eqs.iter_mut().chain(les.iter_mut()) {
+            *e = e.substitute(var, &def)?;
+        }
+        defs.push((var, def));
+    }
+
+    let mut constraints = HashSet::new();
+    for e in les {
+        match tighten(e) {
+            Tightened::Trivial => {}
+            Tightened::Infeasible => return Some(Sat::Unsat),
+            Tightened::Constraint(e) => {
+                constraints.insert(e);
+            }
+        }
+    }
+
+    // Fourier-Motzkin: combine every lower bound on a variable with every
+    // upper bound, keeping the bounds for back-substitution
+    let mut stages: Vec<(usize, Vec<Linear>)> = Vec::new();
+    while let Some(var) = pick(&constraints) {
+        let (bounds, rest): (Vec<_>, Vec<_>) = constraints.into_iter().partition(|e| e.coeff(var) != 0);
+        let mut next: HashSet<Linear> = rest.into_iter().collect();
+        for lower in bounds.iter().filter(|e| e.coeff(var) < 0) {
+            for upper in bounds.iter().filter(|e| e.coeff(var) > 0) {
+                let combined = lower.scale(upper.coeff(var))?.add(&upper.scale(-lower.coeff(var))?)?;
+                match tighten(combined) {
+                    Tightened::Trivial => {}
+                    Tightened::Infeasible => return Some(Sat::Unsat),
+                    Tightened::Constraint(e) => {
+                        next.insert(e);
+                    }
+                }
+            }
+        }
+        if next.len() > MAX_CONSTRAINTS {
+            return None;
+        }
+        stages.push((var, bounds));
+        constraints = next;
+    }
+
+    // Pick each variable as close to zero as its bounds allow, in reverse
+    // order of elimination. Between two bounds there may be no integer, in
+    // which case the answer is unknown.
+    let mut model = Model::new();
+    for (var, bounds) in stages.iter().rev() {
+        let (mut lo, mut hi) = (i128::MIN, i128::MAX);
+        for e in bounds {
+            let c = e.coeff(*var);
+            let mut rest = e.clone();
+            rest.terms.remove(var);
+            let r = rest.eval(&model)?;
+            if c > 0 {
+                hi = hi.min((-r).div_euclid(c));
+            } else {
+                lo = lo.max(-(-r).div_euclid(-c));
+            }
+        }
+        if lo > hi {
+            return Some(Sat::Unknown);
+        }
+        model.insert(*var, 0.clamp(lo, hi));
+    }
+    for (var, def) in defs.iter().rev() {
+        let value = def.eval(&model)?;
+        model.insert(*var, value);
+    }
+
+    let holds = atoms.iter().all(|atom| match atom {
+        Atom::Le(e) => e.eval(&model).is_some_and(|v| v <= 0),
+        Atom::Eq(e) => e.eval(&model) == Some(0),
+    });
+    Some(if holds { Sat::Model(model) } else { Sat::Unknown })
+}
+
+// ============================================================================
+// Symbolic execution
+// ============================================================================
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Sort {
+    Int,
+    Bool,
+}
+
+fn sort_of(ty: &Type) -> Option<Sort> {
+    match ty {
+        Type::Primitive(PrimitiveType::Int) => Some(Sort::Int),
+        Type::Primitive(PrimitiveType::Bool) => Some(Sort::Bool),
+        _ => None,
+    }
+}
+
+/// A symbolic value: `Bool` variables are integers that are 0 or 1
+#[derive(Debug, Clone)]
+enum Sym {
+    Int(Linear),
+    Bool(Formula),
+    /// Anything outside linear `Int`/`Bool` arithmetic
+    Unknown,
+}
+
+/// One path through a function body
+#[derive(Debug, Clone)]
+struct State {
+    scopes: Vec<HashMap<String, Sym>>,
+    /// Conditions under which the path is taken
+    path: Vec<Formula>,
+    /// Why the path over-approximates the program, if it does: it may take a
+    /// branch no real run takes. A solution of an approximate path need not
+    /// be a real run, so it cannot refute.
+    approx: Option<String>,
+}
+
+impl State {
+    fn new() -> Self {
+        State { scopes: vec![HashMap::new()], path: Vec::new(), approx: None }
+    }
+
+    fn lookup(&self, name: &str) -> Option<&Sym> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(name))
+    }
+
+    fn define(&mut self, name: &str, sym: Sym) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.to_string(), sym);
+        }
+    }
+
+    fn assign(&mut self, name: &str, sym: Sym) {
+        if let Some(scope) = self.scopes.iter_mut().rev().find(|s| s.contains_key(name)) {
+            scope.insert(name.to_string(), sym);
+        }
+    }
+
+    fn approximate(&mut self, why: impl Into<String>) {
+        if self.approx.is_none() {
+            self.approx = Some(why.into());
+        }
+    }
+
+}
+
+/// How a statement finished on one path
+enum Flow {
+    Normal(Sym),
+    Return(Sym),
+    Break(Option<String>),
+    Continue(Option<String>),
+}
+
+/// Does a `break` or `continue` with `target` leave the loop labelled `label`?
+fn targets(target: &Option<String>, label: Option<&str>) -> bool {
+    target.as_deref().is_none_or(|t| Some(t) == label)
+}
+
+/// The paths that finished running a function body
+struct Run {
+    /// Final states with the returned value
+    paths: Vec<(State, Sym)>,
+    /// Why some paths were not followed to the end
+    incomplete: Option<String>,
+    inputs: Vec<(String, usize, Sort)>,
+}
+
+/// A `pre` clause to prove at a call site
+struct Obligation {
+    caller: String,
+    inputs: Vec<(String, usize, Sort)>,
+    path: Vec<Formula>,
+    approx: Option<String>,
+    goal: Option<Formula>,
+    call: Span,
+}
+
+struct Verifier<'a> {
+    functions: HashMap<&'a str, &'a FnDecl>,
+    next_var: usize,
+    /// The function being executed and its symbolic inputs
+    current: String,
+    inputs: Vec<(String, usize, Sort)>,
+    /// Why the current function's paths could not all be followed
+    incomplete: Option<String>,
+    /// Whether calls record obligations; off while evaluating clauses
+    recording: bool,
+    depth: usize,
+    /// Variables standing for values the analysis cannot compute, with the
+    /// reason. What is assumed about them holds, but a solution that depends
+    /// on their value need not be a real run.
+    opaque: HashMap<usize, String>,
+    /// Call sites for each `pre` clause, by callee and clause index
+    obligations: HashMap<(String, usize), Vec<Obligation>>,
+    /// Functions called from code the executor did not follow, whose `pre`
+    /// clauses therefore cannot be proved
+    unchecked: HashSet<String>,
+}
+
+impl<'a> Verifier<'a> {
+    fn new(functions: &[&'a FnDecl]) -> Self {
+        let mut unchecked = HashSet::new();
+        for f in functions {
+            for clause in clauses(f) {
+                if let ContractClause::Pre(e, _) | ContractClause::Post(e, _) | ContractClause::Invariant(e, _) =
+                    clause
+                {
+                    let mut effects = Effects::default();
+                    scan_expr(e, &mut effects);
+                    unchecked.extend(effects.calls);
+                }
+            }
+        }
+        Verifier {
+            functions: functions.iter().map(|f| (f.name.name.as_str(), *f)).collect(),
+            next_var: 0,
+            current: String::new(),
+            inputs: Vec::new(),
+            incomplete: None,
+            recording: true,
+            depth: 0,
+            opaque: HashMap::new(),
+            obligations: HashMap::new(),
+            unchecked,
+        }
+    }
+
+    fn fresh(&mut self, sort: Sort, st: &mut State) -> (usize, Sym) {
+        let var = self.next_var;
+        self.next_var += 1;
+        let sym = match sort {
+            Sort::Int => Sym::Int(Linear::term(var, 1, 0)),
+            Sort::Bool => {
+                st.path.push(Formula::Atom(Atom::Le(Linear::term(var, -1, 0))));
+                st.path.push(Formula::Atom(Atom::Le(Linear::term(var, 1, -1))));
+                Sym::Bool(Formula::Atom(Atom::Eq(Linear::term(var, 1, -1))))
+            }
+        };
+        (var, sym)
+    }
+
+    /// A fresh variable standing for a value the analysis cannot compute
+    fn fresh_opaque(&mut self, sort: Sort, st: &mut State, why: impl Into<String>) -> Sym {
+        let (var, sym) = self.fresh(sort, st);
+        self.opaque.insert(var, why.into());
+        sym
+    }
+
+    fn opaque(&mut self, st: &mut State, why: &str) -> Sym {
+        self.fresh_opaque(Sort::Int, st, why)
+    }
+
+    /// Why `f` depends on an opaque variable, if it does
+    fn opaque_in(&self, f: &Formula) -> Option<&String> {
+        match f {
+            Formula::Const(_) => None,
+            Formula::Atom(Atom::Le(e) | Atom::Eq(e)) => e.terms.keys().find_map(|var| self.opaque.get(var)),
+            Formula::Not(g) => self.opaque_in(g),
+            Formula::And(fs) | Formula::Or(fs) => fs.iter().find_map(|g| self.opaque_in(g)),
+        }
+    }
+
+    /// The path continuing into one side of a condition, unless that side
+    /// is infeasible
+    fn branch(&self, st: &State, cond: &Sym, taken: bool) -> Option<State> {
+        let mut st = st.clone();
+        match cond {
+            Sym::Bool(c) => {
+                if let Some(why) = self.opaque_in(c) {
+                    st.approximate(why.clone());
+                }
+                st.path.push(if taken { c.clone() } else { c.clone().not() });
+                if matches!(satisfiable(&st.path), Sat::Unsat) {
+                    return None;
+                }
+            }
+            _ => st.approximate("a condition outside linear arithmetic"),
+        }
+        Some(st)
+    }
+
+    fn linear(&mut self, e: Option<Linear>, st: &mut State) -> Sym {
+        match e {
+            Some(e) => Sym::Int(e),
+            None => self.opaque(st, "arithmetic overflow"),
+        }
+    }
+
+    fn give_up(&mut self, why: &str) {
+        if self.incomplete.is_none() {
+            self.incomplete = Some(why.to_string());
+        }
+    }
+
+    /// Over-approximate code the executor does not step through: every
+    /// variable it may assign becomes arbitrary
+    fn widen(&mut self, effects: &Effects, st: &mut State, why: &str) {
+        if effects.escapes {
+            self.give_up("an early exit the verifier does not follow");
+        }
+        self.unchecked.extend(effects.calls.iter().cloned());
+        for name in &effects.assigned {
+            self.forget(name, st, why);
+        }
+    }
+
+    fn forget(&mut self, name: &str, st: &mut State, why: &str) {
+        let sort = match st.lookup(name) {
+            Some(Sym::Int(_)) => Sort::Int,
+            Some(Sym::Bool(_)) => Sort::Bool,
+            _ => return,
+        };
+        let sym = self.fresh_opaque(sort, st, why);
+        st.assign(name, sym);
+    }
+
+    fn run(&mut self, f: &FnDecl) -> Run {
+        self.current = f.name.name.clone();
+        self.incomplete = None;
+
+        let mut st = State::new();
+        let mut inputs = Vec::new();
+        for param in &f.params {
+            let sym = match sort_of(&param.ty) {
+                Some(sort) => {
+                    let (var, sym) = self.fresh(sort, &mut st);
+                    inputs.push((param.name.name.clone(), var, sort));
+                    sym
+                }
+                None => Sym::Unknown,
+            };
+            st.define(&param.name.name, sym);
+        }
+        self.inputs = inputs.clone();
+
+        for clause in clauses(f) {
+            if let ContractClause::Pre(e, _) | ContractClause::Invariant(e, _) = clause {
+                match self.eval_clause(e, &mut st) {
+                    Sym::Bool(c) => st.path.push(c),
+                    _ => st.approximate("a precondition outside linear arithmetic"),
+                }
+            }
+        }
+
+        let mut paths = Vec::new();
+        for (st, flow) in self.exec_block(&f.body, st) {
+            match flow {
+                Flow::Normal(value) | Flow::Return(value) => paths.push((st, value)),
+                // Rejected by the checker
+                Flow::Break(_) | Flow::Continue(_) => {}
+            }
+        }
+
+        if self.incomplete.is_some() {
+            // Call sites may hide on the paths that were dropped
+            let mut effects = Effects::default();
+            scan_block(&f.body, &mut effects);
+            self.unchecked.extend(effects.calls);
+        }
+        Run { paths, incomplete: self.incomplete.take(), inputs }
+    }
+
+    /// Evaluate a contract clause without recording the calls in it
+    fn eval_clause(&mut self, expr: &Expr, st: &mut State) -> Sym {
+        let recording = std::mem::replace(&mut self.recording, false);
+        let sym = self.eval(expr, st);
+        self.recording = recording;
+        sym
+    }
+
+    /// Prove a `post` or `invariant` clause on every path that returns
+    fn check_returns(&mut self, f: &FnDecl, run: &Run, expr: &Expr) -> Verdict {
+        if let Some(why) = &run.incomplete {
+            return Verdict::Unknown(why.clone());
+        }
+        let mut verdict = Verdict::Proved;
+        for (end, value) in &run.paths {
+            // The clause sees the parameters and `result`
+            let mut st = State::new();
+            for param in &f.params {
+                let sym = end.lookup(&param.name.name).cloned().unwrap_or(Sym::Unknown);
+                st.define(&param.name.name, sym);
+            }
+            st.define("result", value.clone());
+            st.path = end.path.clone();
+            st.approx = end.approx.clone();
+
+            let outcome = match self.eval_clause(expr, &mut st) {
+                Sym::Bool(goal) => decide(&st.path, st.approx.as_ref().or(self.opaque_in(&goal)), &goal, |model| {
+                    counterexample(&f.name.name, &run.inputs, model, None)
+                }),
+                _ => Verdict::Unknown("the clause is outside linear arithmetic".to_string()),
+            };
+            verdict = combine(verdict, outcome);
+        }
+        verdict
+    }
+
+    /// Prove a `pre` clause at every call site
+    fn check_calls(&self, f: &FnDecl, idx: usize) -> Verdict {
+        let name = &f.name.name;
+        let obligations = self.obligations.get(&(name.clone(), idx)).map_or(&[][..], Vec::as_slice);
+        let mut verdict = if self.unchecked.contains(name) {
+            Verdict::Unknown("called from code the verifier does not follow".to_string())
+        } else if obligations.is_empty() {
+            Verdict::Unknown(NO_CALL_SITES.to_string())
+        } else {
+            Verdict::Proved
+        };
+        for ob in obligations {
+            let outcome = match &ob.goal {
+                Some(goal) => decide(&ob.path, ob.approx.as_ref(), goal, |model| {
+                    counterexample(&ob.caller, &ob.inputs, model, Some(ob.call))
+                }),
+                None => Verdict::Unknown("the clause is outside linear arithmetic".to_string()),
+            };
+            verdict = combine(verdict, outcome);
+        }
+        verdict
+    }
+
+    fn exec_block(&mut self, block: &Block, mut st: State) -> Vec<(State, Flow)> {
+        st.scopes.push(HashMap::new());
+        let mut live = vec![(st, Sym::Unknown)];
+        let mut done = Vec::new();
+        for stmt in &block.stmts {
+            let mut next = Vec::new();
+            for (st, _) in live {
+                for (st, flow) in self.exec_stmt(stmt, st) {
+                    match flow {
+                        Flow::Normal(value) => next.push((st, value)),
+                        flow => done.push((st, flow)),
+                    }
+                }
+            }
+            if next.len() > MAX_PATHS {
+                self.give_up("too many paths");
+                next.truncate(MAX_PATHS);
+            }
+            live = next;
+        }
+        done.extend(live.into_iter().map(|(st, value)| (st, Flow::Normal(value))));
+        for (st, _) in &mut done {
+            st.scopes.pop();
+        }
+        done
+    }
+
+    fn exec_stmt(&mut self, stmt: &Stmt, mut st: State) -> Vec<(State, Flow)> {
+        match stmt {
+            Stmt::Expr(expr) => {
+                let value = self.eval(expr, &mut st);
+                vec![(st, Flow::Normal(value))]
+            }
+            Stmt::Let { pattern, value, .. } => {
+                let value = self.eval(value, &mut st);
+                if let Pattern::Ident(name) = pattern {
+                    st.define(&name.name, value);
+                } else {
+                    let mut names = Vec::new();
+                    pattern_names(pattern, &mut names);
+                    for name in names {
+                        st.define(name, Sym::Unknown);
+                    }
+                }
+                vec![(st, Flow::Normal(Sym::Unknown))]
+            }
+            Stmt::If { condition, then_block, else_block, .. } => {
+                let cond = self.eval(condition, &mut st);
+                let mut out = Vec::new();
+                if let Some(st) = self.branch(&st, &cond, true) {
+                    out.extend(self.exec_block(then_block, st));
+                }
+                if let Some(st) = self.branch(&st, &cond, false) {
+                    match else_block {
+                        Some(block) => out.extend(self.exec_block(block, st)),
+                        None => out.push((st, Flow::Normal(Sym::Unknown))),
+                    }
+                }
+                out
+            }
+            Stmt::While { label, condition, body, .. } => self.exec_while(label.as_ref(), condition, body, st),
+            Stmt::For { iterable, body, .. } => {
+                self.eval(iterable, &mut st);
+                let mut effects = Effects::default();
+                scan_block(body, &mut effects);
+                self.widen(&effects, &mut st, "a for loop");
+                vec![(st, Flow::Normal(Sym::Unknown))]
+            }
+            Stmt::Break { label, value, .. } => {
+                if let Some(value) = value {
+                    self.eval(value, &mut st);
+                }
+                vec![(st, Flow::Break(label.as_ref().map(|l| l.name.clone())))]
+            }
+            Stmt::Continue { label, .. } => vec![(st, Flow::Continue(label.as_ref().map(|l| l.name.clone())))],
+            Stmt::Return { value, .. } => {
+                let value = value.as_ref().map_or(Sym::Unknown, |v| self.eval(v, &mut st));
+                vec![(st, Flow::Return(value))]
+            }
+            Stmt::Await { value, .. } | Stmt::Try { value, propagate: false, .. } => {
+                self.eval(value, &mut st);
+                vec![(st, Flow::Normal(Sym::Unknown))]
+            }
+            Stmt::Try { .. } | Stmt::Go { .. } | Stmt::Comptime { .. } | Stmt::Ai(_) | Stmt::Error(_) => {
+                let mut effects = Effects::default();
+                scan_stmt(stmt, &mut effects);
+                self.widen(&effects, &mut st, "code the verifier does not step through");
+                vec![(st, Flow::Normal(Sym::Unknown))]
+            }
+        }
+    }
+
+    fn exec_while(&mut self, label: Option<&Ident>, condition: &Expr, body: &Block, st: State) -> Vec<(State, Flow)> {
+        let label = label.map(|l| l.name.as_str());
+        let mut out = Vec::new();
+        let mut looping = vec![st];
+        for _ in 0..LOOP_UNROLL {
+            let mut next = Vec::new();
+            for mut st in looping {
+                let cond = self.eval(condition, &mut st);
+                if let Some(st) = self.branch(&st, &cond, false) {
+                    out.push((st, Flow::Normal(Sym::Unknown)));
+                }
+                let Some(st) = self.branch(&st, &cond, true) else { continue };
+                for (st, flow) in self.exec_block(body, st) {
+                    match flow {
+                        Flow::Normal(_) => next.push(st),
+                        Flow::Continue(target) if targets(&target, label) => next.push(st),
+                        Flow::Break(target) if targets(&target, label) => out.push((st, Flow::Normal(Sym::Unknown))),
+                        flow => out.push((st, flow)),
+                    }
+                }
+            }
+            if next.len() > MAX_PATHS {
+                self.give_up("too many paths");
+                next.truncate(MAX_PATHS);
+            }
+            looping = next;
+        }
+
+        // Paths still looping: forget what the loop assigns, and leave once
+        // the condition fails
+        let mut effects = Effects::default();
+        scan_expr(condition, &mut effects);
+        scan_block(body, &mut effects);
+        for mut st in looping {
+            self.widen(&effects, &mut st, "a loop past its unrolling bound");
+            st.approximate("a loop past its unrolling bound");
+            let cond = self.eval(condition, &mut st);
+            let exit = if effects.breaks { Some(st) } else { self.branch(&st, &cond, false) };
+            out.extend(exit.map(|st| (st, Flow::Normal(Sym::Unknown))));
+        }
+        out
+    }
+
+    fn eval(&mut self, expr: &Expr, st: &mut State) -> Sym {
+        match expr {
+            Expr::Literal(Literal::Int(n, _)) => Sym::Int(Linear::constant(i128::from(*n))),
+            Expr::Literal(Literal::Bool(b, _)) => Sym::Bool(Formula::Const(*b)),
+            Expr::Literal(_) | Expr::Path { .. } => Sym::Unknown,
+            Expr::Ident(name) => st.lookup(&name.name).cloned().unwrap_or(Sym::Unknown),
+            Expr::Binary { left, op, right, .. } => self.eval_binary(left, *op, right, st),
+            Expr::Unary { op: UnaryOp::Neg, operand, .. } => match self.eval(operand, st) {
+                Sym::Int(e) => {
+                    let e = e.scale(-1);
+                    self.linear(e, st)
+                }
+                _ => Sym::Unknown,
+            },
+            Expr::Unary { op: UnaryOp::Not, operand, .. } => match self.eval(operand, st) {
+                Sym::Bool(f) => Sym::Bool(f.not()),
+                _ => Sym::Unknown,
+            },
+            Expr::Unary { op: UnaryOp::RefMut, operand, .. } => {
+                self.eval(operand, st);
+                if let Some(name) = root(operand) {
+                    self.forget(name, st, "a mutable reference");
+                }
+                Sym::Unknown
+            }
+            Expr::Assign { target, op, value, .. } => {
+                let value = match op {
+                    Some(op) => self.eval_binary(target, *op, value, st),
+                    None => self.eval(value, st),
+                };
+                match &**target {
+                    Expr::Ident(name) => {
+                        st.assign(&name.name, value.clone());
+                        value
+                    }
+                    target => {
+                        self.eval(target, st);
+                        Sym::Unknown
+                    }
+                }
+            }
+            Expr::Call { callee, args, .. } => self.eval_call(callee, args, st),
+            Expr::Unary { operand: inner, .. }
+            | Expr::Field { object: inner, .. }
+            | Expr::Restrict { operand: inner, .. }
+            | Expr::Try { operand: inner, propagate: false, .. } => {
+                self.eval(inner, st);
+                Sym::Unknown
+            }
+            Expr::Index { object, index, .. } => {
+                self.eval(object, st);
+                self.eval(index, st);
+                Sym::Unknown
+            }
+            Expr::Slice { object, start, end, .. } => {
+                self.eval(object, st);
+                for bound in [start, end].into_iter().flatten() {
+                    self.eval(bound, st);
+                }
+                Sym::Unknown
+            }
+            Expr::MethodCall { receiver, args, .. } => {
+                self.eval(receiver, st);
+                for arg in args {
+                    self.eval(arg, st);
+                }
+                Sym::Unknown
+            }
+            // A handler may not resume, but then the function does not return either
+            Expr::Array { elements, .. } | Expr::Tuple { elements, .. } | Expr::Perform { args: elements, .. } => {
+                for element in elements {
+                    self.eval(element, st);
+                }
+                Sym::Unknown
+            }
+            Expr::Interpolation { parts, .. } => {
+                for expr in parts.iter().filter_map(StringPart::expr) {
+                    self.eval(expr, st);
+                }
+                Sym::Unknown
+            }
+            Expr::StructLit { fields, .. } | Expr::Record { fields, .. } => {
+                for field in fields {
+                    self.eval(&field.value, st);
+                }
+                Sym::Unknown
+            }
+            Expr::Try { .. }
+            | Expr::Block(_)
+            | Expr::Ai(_)
+            | Expr::Lambda { .. }
+            | Expr::Match { .. }
+            | Expr::Loop { .. }
+            | Expr::Handle { .. }
+            | Expr::Resume { .. } => {
+                let mut effects = Effects::default();
+                scan_expr(expr, &mut effects);
+                self.widen(&effects, st, "code the verifier does not step through");
+                Sym::Unknown
+            }
+        }
+    }
+
+    fn eval_binary(&mut self, left: &Expr, op: BinaryOp, right: &Expr, st: &mut State) -> Sym {
+        if let BinaryOp::And | BinaryOp::Or = op {
+            let l = self.eval(left, st);
+            let r = match &l {
+                Sym::Bool(c) => {
+                    // The right operand only runs when the left one does not
+                    // decide the result, so what it assumes is conditional
+                    let guard = if op == BinaryOp::And { c.clone() } else { c.clone().not() };
+                    let mark = st.path.len();
+                    st.path.push(guard.clone());
+                    let r = self.eval_operand(right, st);
+                    let assumed = st.path.split_off(mark + 1);
+                    st.path.pop();
+                    if !assumed.is_empty() {
+                        st.path.push(Formula::Or(vec![guard.not(), Formula::And(assumed)]));
+                    }
+                    r
+                }
+                _ => self.eval_operand(right, st),
+            };
+            return match (l, r) {
+                (Sym::Bool(a), Sym::Bool(b)) if op == BinaryOp::And => Sym::Bool(Formula::And(vec![a, b])),
+                (Sym::Bool(a), Sym::Bool(b)) => Sym::Bool(Formula::Or(vec![a, b])),
+                _ => Sym::Unknown,
+            };
+        }
+
+        let l = self.eval(left, st);
+        let r = self.eval(right, st);
+        let le = |e: Option<Linear>| e.map_or(Sym::Unknown, |e| Sym::Bool(Formula::Atom(Atom::Le(e))));
+        match (op, l, r) {
+            (BinaryOp::Add, Sym::Int(a), Sym::Int(b)) => self.linear(a.add(&b), st),
+            (BinaryOp::Sub, Sym::Int(a), Sym::Int(b)) => self.linear(a.sub(&b), st),
+            (BinaryOp::Mul, Sym::Int(a), Sym::Int(b)) => match (a.as_constant(), b.as_constant()) {
+                (Some(k), _) => self.linear(b.scale(k), st),
+                (_, Some(k)) => self.linear(a.scale(k), st),
+                _ => self.opaque(st, "non-linear arithmetic"),
+            },
+            (BinaryOp::Div, Sym::Int(a), Sym::Int(b)) => match (a.as_constant(), b.as_constant()) {
+                (Some(x), Some(y)) if y != 0 => Sym::Int(Linear::constant(x / y)),
+                _ => self.opaque(st, "division"),
+            },
+            (BinaryOp::Eq, Sym::Int(a), Sym::Int(b)) => {
+                a.sub(&b).map_or(Sym::Unknown, |e| Sym::Bool(Formula::Atom(Atom::Eq(e))))
+            }
+            (BinaryOp::Ne, Sym::Int(a), Sym::Int(b)) => {
+                a.sub(&b).map_or(Sym::Unknown, |e| Sym::Bool(Formula::Atom(Atom::Eq(e)).not()))
+            }
+            (BinaryOp::Lt, Sym::Int(a), Sym::Int(b)) => le(a.sub(&b).and_then(|e| e.offset(1))),
+            (BinaryOp::Le, Sym::Int(a), Sym::Int(b)) => le(a.sub(&b)),
+            (BinaryOp::Gt, Sym::Int(a), Sym::Int(b)) => le(b.sub(&a).and_then(|e| e.offset(1))),
+            (BinaryOp::Ge, Sym::Int(a), Sym::Int(b)) => le(b.sub(&a)),
+            (BinaryOp::Eq, Sym::Bool(a), Sym::Bool(b)) => Sym::Bool(Formula::iff(a, b)),
+            (BinaryOp::Ne, Sym::Bool(a), Sym::Bool(b)) => Sym::Bool(Formula::iff(a, b).not()),
+            _ => Sym::Unknown,
+        }
+    }
+
+    /// The right operand of `&&` or `||`, which might not run
+    fn eval_operand(&mut self, expr: &Expr, st: &mut State) -> Sym {
+        let mut effects = Effects::default();
+        scan_expr(expr, &mut effects);
+        if effects.assigned.is_empty() {
+            self.eval(expr, st)
+        } else {
+            self.widen(&effects, st, "an assignment that might not run");
+            Sym::Unknown
+        }
+    }
+
+    /// A call to a top-level function records its `pre` clauses as
+    /// obligations and yields an arbitrary result satisfying its `post`
+    fn eval_call(&mut self, callee: &Expr, args: &[Expr], st: &mut State) -> Sym {
+        let f = match callee {
+            Expr::Ident(name) if st.lookup(&name.name).is_none() => self.functions.get(name.name.as_str()).copied(),
+            _ => None,
+        };
+        let args: Vec<Sym> = args.iter().map(|arg| self.eval(arg, st)).collect();
+        let (Some(f), Expr::Ident(name)) = (f, callee) else {
+            self.eval(callee, st);
+            return Sym::Unknown;
+        };
+        // The callee is verified against its own `post` clauses, which may
+        // not pin down the result
+        let result = match f.return_type.as_ref().and_then(sort_of) {
+            Some(sort) => self.fresh_opaque(sort, st, format!("the result of calling '{}'", f.name.name)),
+            None => Sym::Unknown,
+        };
+        if self.depth >= MAX_CALL_DEPTH {
+            return result;
+        }
+        self.depth += 1;
+
+        let mut callee_st = State::new();
+        for (param, arg) in f.params.iter().zip(args) {
+            callee_st.define(&param.name.name, arg);
+        }
+        for (idx, clause) in clauses(f).iter().enumerate() {
+            if let ContractClause::Pre(e, _) = clause {
+                let recording = self.recording;
+                let goal = match self.eval_clause(e, &mut callee_st) {
+                    Sym::Bool(goal) => Some(goal),
+                    _ => None,
+                };
+                if recording {
+                    let mut path = st.path.clone();
+                    path.append(&mut callee_st.path);
+                    let approx = st.approx.clone().or(callee_st.approx.take()).or_else(|| {
+                        goal.as_ref().and_then(|g| self.opaque_in(g)).cloned()
+                    });
+                    self.obligations.entry((f.name.name.clone(), idx)).or_default().push(Obligation {
+                        caller: self.current.clone(),
+                        inputs: self.inputs.clone(),
+                        path,
+                        approx,
+                        goal,
+                        call: name.span,
+                    });
+                }
+            }
+        }
+
+        callee_st.define("result", result.clone());
+        callee_st.path.clear();
+        for clause in clauses(f) {
+            if let ContractClause::Post(e, _) = clause {
+                if let Sym::Bool(c) = self.eval_clause(e, &mut callee_st) {
+                    st.path.push(c);
+                }
+            }
+        }
+        st.path.append(&mut callee_st.path);
+        self.depth -= 1;
+        result
+    }
+}
+
+/// Try to prove `goal` on a path. A solution of the path and the negated
+/// goal refutes it, unless the path or the goal is approximate.
+fn decide(
+    path: &[Formula],
+    approx: Option<&String>,
+    goal: &Formula,
+    counterexample: impl FnOnce(&Model) -> Option<Counterexample>,
+) -> Verdict {
+    let mut query = path.to_vec();
+    query.push(goal.clone().not());
+    match satisfiable(&query) {
+        Sat::Unsat => Verdict::Proved,
+        Sat::Unknown => Verdict::Unknown("the arithmetic is beyond the decision procedure".to_string()),
+        Sat::Model(model) => match approx {
+            Some(why) => Verdict::Unknown(format!("no proof on a path involving {}", why)),
+            None => counterexample(&model).map_or_else(
+                || Verdict::Unknown("the counterexample does not fit in Int".to_string()),
+                Verdict::Refuted,
+            ),
+        },
+    }
+}
+
+fn counterexample(
+    function: &str,
+    inputs: &[(String, usize, Sort)],
+    model: &Model,
+    call: Option<Span>,
+) -> Option<Counterexample> {
+    let inputs = inputs
+        .iter()
+        .map(|(name, var, sort)| {
+            let v = model.get(var).copied().unwrap_or(0);
+            let value = match sort {
+                Sort::Int => Value::Int(i64::try_from(v).ok()?),
+                Sort::Bool => Value::Bool(v != 0),
+            };
+            Some((name.clone(), value))
+        })
+        .collect::<Option<Vec<_>>>()?;
+    Some(Counterexample { function: function.to_string(), inputs, call })
+}
+
+/// The verdict on a clause over several paths or call sites: the first
+/// refutation, else the first unknown
+fn combine(a: Verdict, b: Verdict) -> Verdict {
+    match (&a, &b) {
+        (Verdict::Refuted(_), _) => a,
+        (_, Verdict::Refuted(_)) => b,
+        (Verdict::Unknown(_), _) => a,
+        _ => b,
+    }
+}
+
+// ============================================================================
+// Effects of code the executor skips
+// ============================================================================
+
+#[derive(Default)]
+struct Effects {
+    /// Variables that may be assigned or mutably borrowed
+    assigned: HashSet<String>,
+    /// Top-level functions that may be called
+    calls: HashSet<String>,
+    breaks: bool,
+    /// A `return`, `?` or labelled `break`/`continue` that may leave the code
+    escapes: bool,
+}
+
+/// The variable a place expression such as `a.b[i]` writes to
+fn root(expr: &Expr) -> Option<&str> {
+    match expr {
+        Expr::Ident(name) => Some(&name.name),
+        Expr::Field { object, .. } | Expr::Index { object, .. } => root(object),
+        _ => None,
+    }
+}
+
+fn pattern_names<'p>(pattern: &'p Pattern, names: &mut Vec<&'p str>) {
+    match pattern {
+        Pattern::Ident(name) => names.push(&name.name),
+        Pattern::Binding { name, pattern, .. } => {
+            names.push(&name.name);
+            pattern_names(pattern, names);
+        }
+        Pattern::Constructor { args: patterns, .. } | Pattern::Tuple(patterns, _) => {
+            patterns.iter().for_each(|p| pattern_names(p, names));
+        }
+        // Every alternative binds the same names
+        Pattern::Or(alternatives, _) => {
+            if let Some(first) = alternatives.first() {
+                pattern_names(first, names);
+            }
+        }
+        Pattern::Struct { fields, .. } | Pattern::Record { fields, .. } => {
+            fields.iter().for_each(|(_, p)| pattern_names(p, names));
+        }
+        Pattern::Slice { prefix, rest, suffix, .. } => {
+            prefix.iter().chain(rest.as_deref()).chain(suffix).for_each(|p| pattern_names(p, names));
+        }
+        Pattern::Literal(_) | Pattern::Wildcard(_) | Pattern::Range { .. } => {}
+    }
+}
+
+fn scan_block(block: &Block, effects: &mut Effects) {
+    for stmt in &block.stmts {
+        scan_stmt(stmt, effects);
+    }
+}
+
+fn scan_stmt(stmt: &Stmt, effects: &mut Effects) {
+    match stmt {
+        Stmt::Expr(e) | Stmt::Let { value: e, .. } | Stmt::Await { value: e, .. } => scan_expr(e, effects),
+        Stmt::If { condition, then_block, else_block, .. } => {
+            scan_expr(condition, effects);
+            scan_block(then_block, effects);
+            if let Some(block) = else_block {
+                scan_block(block, effects);
+            }
+        }
+        Stmt::While { condition: e, body, .. } | Stmt::For { iterable: e, body, .. } => {
+            scan_expr(e, effects);
+            scan_block(body, effects);
+        }
+        Stmt::Break { label, value, .. } => {
+            effects.breaks = true;
+            effects.escapes |= label.is_some();
+            if let Some(value) = value {
+                scan_expr(value, effects);
+            }
+        }
+        Stmt::Continue { label, .. } => effects.escapes |= label.is_some(),
+        Stmt::Error(_) => {}
+        Stmt::Go { block, .. } | Stmt::Comptime { block, .. } => scan_block(block, effects),
+        Stmt::Return { value, .. } => {
+            effects.escapes = true;
+            if let Some(value) = value {
+                scan_expr(value, effects);
+            }
+        }
+        Stmt::Try { value, propagate, .. } => {
+            effects.escapes |= *propagate;
+            scan_expr(value, effects);
+        }
+        Stmt::Ai(ai) => match &ai.body {
+            AiStmtBody::Block(block) => scan_block(block, effects),
+            AiStmtBody::Expr(e) => scan_expr(e, effects),
+        },
+    }
+}
+
+fn scan_expr(expr: &Expr, effects: &mut Effects) {
+    match expr {
+        Expr::Literal(_) | Expr::Ident(_) | Expr::Path { .. } => {}
+        Expr::Call { callee, args, .. } => {
+            if let Expr::Ident(name) = &**callee {
+                effects.calls.insert(name.name.clone());
+            }
+            scan_expr(callee, effects);
+            args.iter().for_each(|arg| scan_expr(arg, effects));
+        }
+        Expr::MethodCall { receiver, args, .. } => {
+            scan_expr(receiver, effects);
+            args.iter().for_each(|arg| scan_expr(arg, effects));
+        }
+        Expr::Field { object: e, .. } | Expr::Restrict { operand: e, .. } => scan_expr(e, effects),
+        Expr::Unary { op, operand, .. } => {
+            if let (UnaryOp::RefMut, Some(name)) = (op, root(operand)) {
+                effects.assigned.insert(name.to_string());
+            }
+            scan_expr(operand, effects);
+        }
+        Expr::Try { operand, propagate, .. } => {
+            effects.escapes |= *propagate;
+            scan_expr(operand, effects);
+        }
+        Expr::Index { object: a, index: b, .. } | Expr::Binary { left: a, right: b, .. } => {
+            scan_expr(a, effects);
+            scan_expr(b, effects);
+        }
+        Expr::Slice { object, start, end, .. } => {
+            scan_expr(object, effects);
+            for bound in [start, end].into_iter().flatten() {
+                scan_expr(bound, effects);
+            }
+        }
+        Expr::Assign { target, value, .. } => {
+            if let Some(name) = root(target) {
+                effects.assigned.insert(name.to_string());
+            }
+            scan_expr(target, effects);
+            scan_expr(value, effects);
+        }
+        Expr::StructLit { fields, .. } | Expr::Record { fields, .. } => {
+            fields.iter().for_each(|field| scan_expr(&field.value, effects));
+        }
+        Expr::Array { elements, .. } | Expr::Tuple { elements, .. } | Expr::Perform { args: elements, .. } => {
+            elements.iter().for_each(|e| scan_expr(e, effects));
+        }
+        Expr::Interpolation { parts, .. } => {
+            parts.iter().filter_map(StringPart::expr).for_each(|e| scan_expr(e, effects));
+        }
+        Expr::Block(block) | Expr::Loop { body: block, .. } => scan_block(block, effects),
+        Expr::Handle { body, clauses, .. } => {
+            scan_block(body, effects);
+            clauses.iter().for_each(|clause| scan_expr(&clause.body, effects));
+        }
+        Expr::Resume { value, .. } => scan_expr(value, effects),
+        Expr::Match { scrutinee, arms, .. } => {
+            scan_expr(scrutinee, effects);
+            for arm in arms {
+                if let Some(guard) = &arm.guard {
+                    scan_expr(guard, effects);
+                }
+                scan_expr(&arm.body, effects);
+            }
+        }
+        Expr::Lambda { body, .. } => {
+            // A `return` in a lambda only leaves the lambda
+            let mut inner = Effects::default();
+            match body {
+                LambdaBody::Expr(e) => scan_expr(e, &mut inner),
+                LambdaBody::Block(block) => scan_block(block, &mut inner),
+            }
+            effects.assigned.extend(inner.assigned);
+            effects.calls.extend(inner.calls);
+        }
+        Expr::Ai(ai) => match ai {
+            AiExpr::Block { body, .. } => {
+                for item in body {
+                    if let AiBodyItem::Field { value, .. } = item {
+                        scan_expr(value, effects);
+                    }
+                }
+            }
+            AiExpr::Call { args, .. } => args.iter().for_each(|arg| scan_expr(arg, effects)),
+            AiExpr::PromptInvocation { args, .. } => args.iter().for_each(|arg| scan_expr(&arg.value, effects)),
+            AiExpr::Quick { .. } => {}
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+
+    fn verify_source(source: &str) -> Vec<ClauseReport> {
+        verify(&parse(source).expect("parse error"))
+    }
+
+    fn verdicts(reports: &[ClauseReport]) -> Vec<(&str, ClauseKind, &Verdict)> {
+        reports.iter().map(|r| (r.function.as_str(), r.kind, &r.verdict)).collect()
+    }
+
+    #[test]
+    fn test_proves_linear_postconditions() {
+        let reports = verify_source(
+            r#"
+            fn abs(x: Int) -> Int where post: result >= 0 && (result == x || result == -x) {
+                if x < 0 {
+                    return -x;
+                }
+                return x;
+            }
+
+            fn clamp(x: Int, lo: Int, hi: Int) -> Int
+                where pre: lo <= hi, post: lo <= result && result <= hi
+            {
+                if x < lo { return lo; }
+                if x > hi { return hi; }
+                x;
+            }
+
+            fn inc(x: Int) -> Int where post: result == x + 1 { return x + 1; }
+
+            fn add_two(x: Int) -> Int where post: result == x + 2 { return inc(inc(x)); }
+
+            fn sum_to_three() -> Int where post: result == 6 {
+                let mut i = 0;
+                let mut sum = 0;
+                while i < 3 {
+                    i = i + 1;
+                    sum = sum + i;
+                }
+                sum;
+            }
+        "#,
+        );
+        for (name, kind, verdict) in verdicts(&reports) {
+            if kind == ClauseKind::Pre {
+                assert_eq!(verdict, &Verdict::Unknown("no call sites".to_string()), "{}", name);
+            } else {
+                assert_eq!(verdict, &Verdict::Proved, "{} {}", kind, name);
+            }
+        }
+        assert_eq!(reports.len(), 6);
+    }
+
+    #[test]
+    fn test_refutes_postcondition_with_counterexample() {
+        let reports = verify_source(
+            r#"
+            fn pred(n: Int, strict: Bool) -> Int where post: result > 0 || !strict {
+                return n - 1;
+            }
+        "#,
+        );
+        let Verdict::Refuted(cex) = &reports[0].verdict else {
+            panic!("{}", reports[0]);
+        };
+        assert_eq!(cex.function, "pred");
+        assert!(cex.call.is_none());
+        // Any n <= 1 with strict set violates the clause
+        let [(n, Value::Int(value)), (strict, Value::Bool(true))] = &cex.inputs[..] else {
+            panic!("{}", cex);
+        };
+        assert_eq!((n.as_str(), strict.as_str()), ("n", "strict"));
+        assert!(*value <= 1);
+        assert_eq!(reports[0].span.line, 2);
+    }
+
+    #[test]
+    fn test_preconditions_checked_at_call_sites() {
+        let program = r#"
+            fn div(a: Int, b: Int) -> Int where pre: b != 0 { return a / b; }
+
+            fn safe(x: Int) -> Int {
+                if x > 0 {
+                    return div(10, x);
+                }
+                0;
+            }
+        "#;
+        assert_eq!(verify_source(program)[0].verdict, Verdict::Proved);
+
+        let reports = verify_source(&format!(
+            "{}\nfn shifted(x: Int) -> Int {{ let y = div(x, 1); div(y, 2 * x - 6); }}",
+            program
+        ));
+        let Verdict::Refuted(cex) = &reports[0].verdict else {
+            panic!("{}", reports[0]);
+        };
+        assert_eq!(cex.function, "shifted");
+        assert_eq!(cex.inputs, vec![("x".to_string(), Value::Int(3))]);
+        assert_eq!(cex.call.map(|span| span.line), Some(11));
+    }
+
+    #[test]
+    fn test_preconditions_checked_across_modules() {
+        let dir = std::env::temp_dir().join(format!("my-lang-verify-modules-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("math.ml"), "pub fn div(a: Int, b: Int) -> Int where pre: b != 0 { return a / b; }").unwrap();
+        std::fs::write(dir.join("main.ml"), "use math::{div};\nfn main() -> Int { return div(1, 0); }").unwrap();
+        let graph = crate::modules::Loader::new().load(&dir.join("main.ml"));
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let reports = verify_modules(&graph.unwrap());
+        assert_eq!(reports.len(), 1);
+        let Verdict::Refuted(cex) = &reports[0].verdict else {
+            panic!("{}", reports[0]);
+        };
+        assert_eq!(cex.function, "main");
+    }
+
+    #[test]
+    fn test_unknown_outside_linear_arithmetic() {
+        let reports = verify_source(
+            r#"
+            fn square(x: Int) -> Int where post: result >= 0 { return x * x; }
+
+            fn count(n: Int) -> Int where post: result >= 0 {
+                let mut i = 0;
+                while i < n {
+                    i = i + 1;
+                }
+                i;
+            }
+
+            fn count_exact(n: Int) -> Int where post: result == n || n < 0 {
+                let mut i = 0;
+                while i < n {
+                    i = i + 1;
+                }
+                i;
+            }
+        "#,
+        );
+        assert!(matches!(&reports[0].verdict, Verdict::Unknown(why) if why.contains("non-linear")));
+        // Past the unrolling bound `n > 7` and, after widening, `i >= n`:
+        // enough for `i >= 0` but not for `i == n`
+        assert_eq!(reports[1].verdict, Verdict::Proved);
+        assert!(matches!(&reports[2].verdict, Verdict::Unknown(why) if why.contains("unrolling")));
+    }
+
+    #[test]
+    fn test_integer_reasoning() {
+        let x = 0;
+        let atom = |coeff, constant| Formula::Atom(Atom::Le(Linear::term(x, coeff, constant)));
+        // 2x - 1 <= 0 and 1 - 2x <= 0 has the rational solution x = 1/2 only
+        assert!(matches!(satisfiable(&[atom(2, -1), atom(-2, 1)]), Sat::Unsat));
+        // 2x == 1
+        let odd = Formula::Atom(Atom::Eq(Linear::term(x, 2, -1)));
+        assert!(matches!(satisfiable(&[odd]), Sat::Unsat));
+        // 3 <= x != 3
+        let ne = Formula::Atom(Atom::Eq(Linear::term(x, 1, -3))).not();
+        let Sat::Model(model) = satisfiable(&[atom(-1, 3), ne]) else {
+            panic!("expected a model");
+        };
+        assert_eq!(model.get(&x), Some(&4));
+    }
+}
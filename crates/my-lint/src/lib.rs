@@ -8,9 +8,12 @@
 //! - Contract violations
 //! - Style recommendations
 
-use my_lang::{parse, Program, TopLevel, FnDecl, Stmt, Expr, AiModelDecl, AiModelAttr};
+use my_lang::effects;
+use my_lang::types::ast_type_to_ty;
+use my_lang::verify::{verify, Verdict};
+use my_lang::{parse, Program, TopLevel, FnDecl, AiModelAttr, Type};
 use serde::{Deserialize, Serialize};
-use std::collections::HashSet;
+use std::collections::{BTreeSet, HashSet};
 use thiserror::Error;
 
 /// Lint errors
@@ -73,7 +76,7 @@ impl LintRule for UnusedVariable {
         for item in &program.items {
             if let TopLevel::Function(f) = item {
                 let mut declared: HashSet<String> = HashSet::new();
-                let mut used: HashSet<String> = HashSet::new();
+                let used: HashSet<String> = HashSet::new();
 
                 // Collect declarations from parameters
                 for param in &f.params {
@@ -118,9 +121,57 @@ impl LintRule for MissingEffectAnnotation {
         Severity::Warning
     }
 
-    fn check(&self, _program: &Program) -> Vec<Diagnostic> {
-        // TODO: Implement effect analysis
-        Vec::new()
+    fn check(&self, program: &Program) -> Vec<Diagnostic> {
+        let rows = effects::infer(program);
+        let mut functions: Vec<(String, &FnDecl)> = Vec::new();
+        for item in &program.items {
+            match item {
+                TopLevel::Function(f) if f.name.name != "main" => functions.push((f.name.name.clone(), f)),
+                TopLevel::Impl(i) => {
+                    let Some(ty) = ast_type_to_ty(&i.self_ty).impl_key() else { continue };
+                    functions.extend(i.methods.iter().map(|m| (format!("{}::{}", ty, m.name.name), m)));
+                }
+                _ => {}
+            }
+        }
+
+        let mut diagnostics = Vec::new();
+        for (key, f) in functions {
+            // What the signature declares: `AI<T>` declares AI, `Effect<T, ...>` its row
+            let (result, mut declared) = match &f.return_type {
+                Some(Type::Ai { inner, .. }) => (ast_type_to_ty(inner).to_string(), BTreeSet::from(["AI".to_string()])),
+                Some(Type::Effect { inner, effects, .. }) => (
+                    ast_type_to_ty(inner).to_string(),
+                    effects.iter().map(|e| e.name.clone()).collect(),
+                ),
+                Some(ty) => (ast_type_to_ty(ty).to_string(), BTreeSet::new()),
+                None => ("()".to_string(), BTreeSet::new()),
+            };
+            let missing: Vec<String> = rows
+                .get(&key)
+                .into_iter()
+                .flat_map(|row| row.keys())
+                .filter(|effect| !declared.contains(*effect))
+                .cloned()
+                .collect();
+            if missing.is_empty() {
+                continue;
+            }
+            declared.extend(missing.iter().cloned());
+            diagnostics.push(Diagnostic {
+                rule: self.name().to_string(),
+                message: format!("'{}' performs {} without declaring it", key, missing.join(", ")),
+                severity: self.severity(),
+                line: f.name.span.line,
+                column: f.name.span.column,
+                suggestion: Some(format!(
+                    "declare the effects in the return type: Effect<{}, {}>",
+                    result,
+                    declared.into_iter().collect::<Vec<_>>().join(", ")
+                )),
+            });
+        }
+        diagnostics
     }
 }
 
@@ -141,7 +192,7 @@ impl LintRule for DeprecatedAIModel {
     }
 
     fn check(&self, program: &Program) -> Vec<Diagnostic> {
-        let deprecated_models = vec![
+        let deprecated_models = [
             "gpt-3.5-turbo",
             "claude-2",
             "claude-instant",
@@ -190,9 +241,27 @@ impl LintRule for ContractViolation {
         Severity::Error
     }
 
-    fn check(&self, _program: &Program) -> Vec<Diagnostic> {
-        // TODO: Implement contract analysis
-        Vec::new()
+    fn check(&self, program: &Program) -> Vec<Diagnostic> {
+        // Only refuted clauses are reported: unknown ones are not violations
+        verify(program)
+            .into_iter()
+            .filter_map(|report| {
+                let Verdict::Refuted(cex) = &report.verdict else {
+                    return None;
+                };
+                Some(Diagnostic {
+                    rule: self.name().to_string(),
+                    message: format!(
+                        "{} clause of '{}' can be violated by {}",
+                        report.kind, report.function, cex
+                    ),
+                    severity: self.severity(),
+                    line: report.span.line,
+                    column: report.span.column,
+                    suggestion: None,
+                })
+            })
+            .collect()
     }
 }
 
@@ -265,4 +334,56 @@ mod tests {
         let linter = Linter::default();
         assert!(!linter.rules.is_empty());
     }
+
+    #[test]
+    fn test_contract_violation() {
+        let source = r#"
+            fn half(n: Int) -> Int where post: result * 2 == n { return n / 2; }
+            fn dec(n: Int) -> Int where pre: n > 0, post: result >= 0 { return n - 2; }
+        "#;
+        let diagnostics: Vec<_> = Linter::default()
+            .lint(source)
+            .unwrap()
+            .into_iter()
+            .filter(|d| d.rule == "contract-violation")
+            .collect();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].line, 3);
+        assert_eq!(diagnostics[0].message, "post clause of 'dec' can be violated by n = 1");
+    }
+
+    #[test]
+    fn test_missing_effect_annotation() {
+        let source = r#"
+            effect Logger { op log: String }
+
+            fn roll() -> Int { return random_int(1, 6); }
+            fn greet(name: String) { println(name); perform Logger.log(name); }
+            fn ask(q: String) -> AI<String> { return ai! { "hello" }; }
+            fn noisy() -> Effect<Int, Logger> { perform Logger.log("x"); println("y"); return 1; }
+            fn pure(x: Int) -> Int { return x + 1; }
+            fn main() { greet("me"); }
+        "#;
+        let diagnostics: Vec<_> = Linter::default()
+            .lint(source)
+            .unwrap()
+            .into_iter()
+            .filter(|d| d.rule == "missing-effect-annotation")
+            .collect();
+        let messages: Vec<_> = diagnostics.iter().map(|d| d.message.as_str()).collect();
+        assert_eq!(messages, [
+            "'roll' performs Random without declaring it",
+            "'greet' performs IO, Logger without declaring it",
+            "'noisy' performs IO without declaring it",
+        ]);
+        assert_eq!((diagnostics[0].line, diagnostics[0].column), (4, 16));
+        assert_eq!(
+            diagnostics[1].suggestion.as_deref(),
+            Some("declare the effects in the return type: Effect<(), IO, Logger>")
+        );
+        assert_eq!(
+            diagnostics[2].suggestion.as_deref(),
+            Some("declare the effects in the return type: Effect<Int, IO, Logger>")
+        );
+    }
 }
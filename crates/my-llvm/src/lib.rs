@@ -203,6 +203,12 @@ impl<'ctx> Codegen<'ctx> {
                     fields.iter().map(|f| self.lower_type(f)).collect();
                 self.context.struct_type(&field_types, false).into()
             }
+            // Unions are represented by their largest member
+            MirType::Union(members) => members
+                .iter()
+                .map(|m| self.lower_type(m))
+                .max_by_key(|t| t.size_of().map(|s| s.get_zero_extended_constant().unwrap_or(0)).unwrap_or(0))
+                .unwrap_or_else(|| self.context.i8_type().into()),
             MirType::Unit => self.context.i8_type().into(), // Unit as i8
             MirType::Never => self.context.i8_type().into(),
             MirType::Function(_, _) => self
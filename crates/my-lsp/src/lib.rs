@@ -12,7 +12,7 @@
 //! - Formatting
 //! - Signature help
 
-use my_lang::{parse, check, Program, CheckError};
+use my_lang::{parse_with_errors, Checker, Program, CheckError, ParseError};
 use std::collections::HashMap;
 use std::sync::Arc;
 use tokio::sync::RwLock;
@@ -52,39 +52,50 @@ impl Document {
     fn analyze(&mut self) {
         self.diagnostics.clear();
 
-        match parse(&self.text) {
-            Ok(program) => {
-                // Run type checker
-                match check(&program) {
-                    Ok(()) => {
-                        self.program = Some(program);
-                    }
-                    Err(errors) => {
-                        for error in errors {
-                            self.diagnostics.push(check_error_to_diagnostic(&error));
-                        }
-                        self.program = Some(program);
-                    }
+        let (program, parse_errors) = parse_with_errors(&self.text);
+        if parse_errors.is_empty() {
+            // Run type checker
+            let mut checker = Checker::new();
+            if let Err(errors) = checker.check_program(&program) {
+                for error in errors {
+                    self.diagnostics.push(check_error_to_diagnostic(&error, DiagnosticSeverity::ERROR));
                 }
             }
-            Err(parse_error) => {
-                self.diagnostics.push(Diagnostic {
-                    range: Range {
-                        start: Position { line: 0, character: 0 },
-                        end: Position { line: 0, character: 0 },
-                    },
-                    severity: Some(DiagnosticSeverity::ERROR),
-                    source: Some("my-lang".to_string()),
-                    message: format!("{}", parse_error),
-                    ..Default::default()
-                });
+            for warning in checker.warnings() {
+                self.diagnostics.push(check_error_to_diagnostic(warning, DiagnosticSeverity::WARNING));
+            }
+        } else {
+            // Type errors in a partial program are mostly noise from what
+            // failed to parse, so only the syntax errors are reported
+            for error in &parse_errors {
+                self.diagnostics.push(parse_error_to_diagnostic(error));
             }
         }
+        self.program = Some(program);
+    }
+}
+
+/// Convert a ParseError to an LSP Diagnostic covering the offending token
+fn parse_error_to_diagnostic(error: &ParseError) -> Diagnostic {
+    let span = error.span();
+    let line = span.line.saturating_sub(1) as u32;
+    let character = span.column.saturating_sub(1) as u32;
+    let width = span.end.saturating_sub(span.start).max(1) as u32;
+
+    Diagnostic {
+        range: Range {
+            start: Position { line, character },
+            end: Position { line, character: character + width },
+        },
+        severity: Some(DiagnosticSeverity::ERROR),
+        source: Some("my-lang".to_string()),
+        message: format!("{}", error),
+        ..Default::default()
     }
 }
 
 /// Extract location from CheckError and convert to LSP Diagnostic
-fn check_error_to_diagnostic(error: &CheckError) -> Diagnostic {
+fn check_error_to_diagnostic(error: &CheckError, severity: DiagnosticSeverity) -> Diagnostic {
     let (line, column) = extract_error_location(error);
     // LSP uses 0-based line numbers
     let lsp_line = if line > 0 { line as u32 - 1 } else { 0 };
@@ -95,7 +106,7 @@ fn check_error_to_diagnostic(error: &CheckError) -> Diagnostic {
             start: Position { line: lsp_line, character: lsp_col },
             end: Position { line: lsp_line, character: lsp_col + 1 },
         },
-        severity: Some(DiagnosticSeverity::ERROR),
+        severity: Some(severity),
         source: Some("my-lang".to_string()),
         message: format!("{}", error),
         ..Default::default()
@@ -108,14 +119,27 @@ fn extract_error_location(error: &CheckError) -> (usize, usize) {
         CheckError::UndefinedVariable { line, column, .. } => (*line, *column),
         CheckError::UndefinedType { line, column, .. } => (*line, *column),
         CheckError::UndefinedFunction { line, column, .. } => (*line, *column),
+        CheckError::PrivateItem { line, column, .. } => (*line, *column),
         CheckError::UndefinedAiModel { line, column, .. } => (*line, *column),
         CheckError::UndefinedPrompt { line, column, .. } => (*line, *column),
         CheckError::TypeMismatch { line, column, .. } => (*line, *column),
         CheckError::DuplicateDefinition { line, column, .. } => (*line, *column),
         CheckError::ImmutableAssignment { line, column, .. } => (*line, *column),
+        CheckError::InvalidAssignmentTarget { line, column } => (*line, *column),
         CheckError::WrongArgCount { line, column, .. } => (*line, *column),
         CheckError::InvalidBinaryOp { line, column, .. } => (*line, *column),
         CheckError::NonBoolCondition { line, column, .. } => (*line, *column),
+        CheckError::OutsideLoop { line, column, .. } => (*line, *column),
+        CheckError::UndefinedLabel { line, column, .. } => (*line, *column),
+        CheckError::IncompatibleTry { line, column, .. } => (*line, *column),
+        CheckError::InfiniteType { line, column, .. } => (*line, *column),
+        CheckError::NonExhaustiveMatch { line, column, .. } => (*line, *column),
+        CheckError::UnreachablePattern { line, column } => (*line, *column),
+        CheckError::UndefinedMethod { line, column, .. } => (*line, *column),
+        CheckError::UnsatisfiedBound { line, column, .. } => (*line, *column),
+        CheckError::UnhandledEffect { line, column, .. } => (*line, *column),
+        CheckError::MissingEffectAnnotation { line, column, .. } => (*line, *column),
+        CheckError::PromptArgument { line, column, .. } => (*line, *column),
         CheckError::Other { line, column, .. } => (*line, *column),
     }
 }
@@ -221,8 +245,13 @@ impl LanguageServer for MyLanguageServer {
                 CompletionItem::new_simple("let".to_string(), "Variable binding".to_string()),
                 CompletionItem::new_simple("if".to_string(), "Conditional expression".to_string()),
                 CompletionItem::new_simple("match".to_string(), "Pattern matching".to_string()),
+                CompletionItem::new_simple("while".to_string(), "Conditional loop".to_string()),
+                CompletionItem::new_simple("for".to_string(), "Iterate over an array or string".to_string()),
+                CompletionItem::new_simple("loop".to_string(), "Infinite loop".to_string()),
                 CompletionItem::new_simple("struct".to_string(), "Struct definition".to_string()),
                 CompletionItem::new_simple("effect".to_string(), "Effect declaration".to_string()),
+                CompletionItem::new_simple("perform".to_string(), "Perform an effect operation".to_string()),
+                CompletionItem::new_simple("handle".to_string(), "Handle an effect".to_string()),
                 CompletionItem::new_simple("ai".to_string(), "AI expression".to_string()),
                 CompletionItem::new_simple("ai_model".to_string(), "AI model configuration".to_string()),
                 CompletionItem::new_simple("prompt".to_string(), "Prompt template".to_string()),
@@ -246,60 +275,78 @@ impl LanguageServer for MyLanguageServer {
                 // Look up in definitions
                 for item in &program.items {
                     match item {
-                        my_lang::TopLevel::Function(f) => {
-                            if f.name.name == word {
-                                let params_str: Vec<String> = f.params.iter()
-                                    .map(|p| format!("{}: {:?}", p.name.name, p.ty))
-                                    .collect();
-                                let ret_str = f.return_type.as_ref()
-                                    .map(|t| format!(" -> {:?}", t))
-                                    .unwrap_or_default();
-
-                                return Ok(Some(Hover {
-                                    contents: HoverContents::Markup(MarkupContent {
-                                        kind: MarkupKind::Markdown,
-                                        value: format!("```my\nfn {}({}){}\n```", f.name.name, params_str.join(", "), ret_str),
-                                    }),
-                                    range: None,
-                                }));
-                            }
+                        my_lang::TopLevel::Function(f) if f.name.name == word => {
+                            let params_str: Vec<String> = f.params.iter()
+                                .map(|p| format!("{}: {:?}", p.name.name, p.ty))
+                                .collect();
+                            let ret_str = f.return_type.as_ref()
+                                .map(|t| format!(" -> {:?}", t))
+                                .unwrap_or_default();
+
+                            return Ok(Some(Hover {
+                                contents: HoverContents::Markup(MarkupContent {
+                                    kind: MarkupKind::Markdown,
+                                    value: format!("```my\nfn {}({}){}\n```", f.name.name, params_str.join(", "), ret_str),
+                                }),
+                                range: None,
+                            }));
                         }
-                        my_lang::TopLevel::Struct(s) => {
-                            if s.name.name == word {
-                                let fields_str: Vec<String> = s.fields.iter()
-                                    .map(|f| format!("    {}: {:?}", f.name.name, f.ty))
-                                    .collect();
-
-                                return Ok(Some(Hover {
-                                    contents: HoverContents::Markup(MarkupContent {
-                                        kind: MarkupKind::Markdown,
-                                        value: format!("```my\nstruct {} {{\n{}\n}}\n```", s.name.name, fields_str.join(",\n")),
-                                    }),
-                                    range: None,
-                                }));
-                            }
+                        my_lang::TopLevel::Struct(s) if s.name.name == word => {
+                            let fields_str: Vec<String> = s.fields.iter()
+                                .map(|f| format!("    {}: {:?}", f.name.name, f.ty))
+                                .collect();
+
+                            return Ok(Some(Hover {
+                                contents: HoverContents::Markup(MarkupContent {
+                                    kind: MarkupKind::Markdown,
+                                    value: format!("```my\nstruct {} {{\n{}\n}}\n```", s.name.name, fields_str.join(",\n")),
+                                }),
+                                range: None,
+                            }));
                         }
-                        my_lang::TopLevel::Effect(e) => {
-                            if e.name.name == word {
-                                return Ok(Some(Hover {
-                                    contents: HoverContents::Markup(MarkupContent {
-                                        kind: MarkupKind::Markdown,
-                                        value: format!("```my\neffect {} {{ ... }}\n```", e.name.name),
-                                    }),
-                                    range: None,
-                                }));
-                            }
+                        my_lang::TopLevel::Enum(e) if e.name.name == word => {
+                            let variants_str: Vec<&str> = e.variants.iter()
+                                .map(|v| v.name.name.as_str())
+                                .collect();
+
+                            return Ok(Some(Hover {
+                                contents: HoverContents::Markup(MarkupContent {
+                                    kind: MarkupKind::Markdown,
+                                    value: format!("```my\nenum {} {{ {} }}\n```", e.name.name, variants_str.join(", ")),
+                                }),
+                                range: None,
+                            }));
                         }
-                        my_lang::TopLevel::AiModel(m) => {
-                            if m.name.name == word {
-                                return Ok(Some(Hover {
-                                    contents: HoverContents::Markup(MarkupContent {
-                                        kind: MarkupKind::Markdown,
-                                        value: format!("```my\nai_model {} {{ ... }}\n```\nAI model configuration", m.name.name),
-                                    }),
-                                    range: None,
-                                }));
-                            }
+                        my_lang::TopLevel::Trait(t) if t.name.name == word => {
+                            let methods_str: Vec<String> = t.methods.iter()
+                                .map(|m| format!("    fn {}(..);", m.name.name))
+                                .collect();
+
+                            return Ok(Some(Hover {
+                                contents: HoverContents::Markup(MarkupContent {
+                                    kind: MarkupKind::Markdown,
+                                    value: format!("```my\ntrait {} {{\n{}\n}}\n```", t.name.name, methods_str.join("\n")),
+                                }),
+                                range: None,
+                            }));
+                        }
+                        my_lang::TopLevel::Effect(e) if e.name.name == word => {
+                            return Ok(Some(Hover {
+                                contents: HoverContents::Markup(MarkupContent {
+                                    kind: MarkupKind::Markdown,
+                                    value: format!("```my\neffect {} {{ ... }}\n```", e.name.name),
+                                }),
+                                range: None,
+                            }));
+                        }
+                        my_lang::TopLevel::AiModel(m) if m.name.name == word => {
+                            return Ok(Some(Hover {
+                                contents: HoverContents::Markup(MarkupContent {
+                                    kind: MarkupKind::Markdown,
+                                    value: format!("```my\nai_model {} {{ ... }}\n```\nAI model configuration", m.name.name),
+                                }),
+                                range: None,
+                            }));
                         }
                         _ => {}
                     }
@@ -314,9 +361,17 @@ impl LanguageServer for MyLanguageServer {
                     "match" => Some("Pattern matching expression"),
                     "struct" => Some("Structure type definition"),
                     "effect" => Some("Effect type declaration"),
+                    "perform" => Some("Perform an effect operation - suspends until a handler resumes"),
+                    "handle" => Some("Run a block with a handler for an effect's operations"),
+                    "resume" => Some("Continue the suspended perform - must end the handler clause"),
                     "ai" => Some("AI expression - invoke AI capabilities"),
                     "ai_model" => Some("AI model configuration block"),
                     "return" => Some("Return from function"),
+                    "while" => Some("Loop while a condition holds"),
+                    "for" => Some("Iterate over the elements of an array or string"),
+                    "loop" => Some("Infinite loop - yields the value given to break"),
+                    "break" => Some("Exit the enclosing (or labelled) loop"),
+                    "continue" => Some("Skip to the next iteration of the enclosing (or labelled) loop"),
                     "go" => Some("Spawn concurrent task"),
                     "await" => Some("Wait for async result"),
                     "try" => Some("Error handling expression"),
@@ -356,6 +411,8 @@ impl LanguageServer for MyLanguageServer {
                     let (name, span) = match item {
                         my_lang::TopLevel::Function(f) => (&f.name.name, f.span),
                         my_lang::TopLevel::Struct(s) => (&s.name.name, s.span),
+                        my_lang::TopLevel::Enum(e) => (&e.name.name, e.span),
+                        my_lang::TopLevel::Trait(t) => (&t.name.name, t.span),
                         my_lang::TopLevel::Effect(e) => (&e.name.name, e.span),
                         my_lang::TopLevel::AiModel(m) => (&m.name.name, m.span),
                         _ => continue,
@@ -511,4 +568,27 @@ mod tests {
         );
         assert!(doc.program.is_some());
     }
+
+    #[test]
+    fn test_parse_errors_are_all_reported() {
+        let doc = Document::new(
+            Url::parse("file:///test.my").unwrap(),
+            "fn main() {\n    let x = ;\n    undefined_name;\n    foo(1 2);\n}".to_string(),
+            1,
+        );
+        let ranges: Vec<(u32, u32)> = doc.diagnostics.iter().map(|d| (d.range.start.line, d.range.start.character)).collect();
+        assert_eq!(ranges, vec![(1, 12), (3, 10)]);
+        assert!(doc.program.is_some());
+    }
+
+    #[test]
+    fn test_unreachable_arm_is_a_warning() {
+        let doc = Document::new(
+            Url::parse("file:///test.my").unwrap(),
+            "fn f(b: Bool) -> Int {\n    return match b { true => 1, _ => 0, false => 2 };\n}".to_string(),
+            1,
+        );
+        let severities: Vec<_> = doc.diagnostics.iter().map(|d| d.severity).collect();
+        assert_eq!(severities, vec![Some(DiagnosticSeverity::WARNING)]);
+    }
 }
@@ -15,6 +15,6 @@ async fn main() {
     let stdin = tokio::io::stdin();
     let stdout = tokio::io::stdout();
 
-    let (service, socket) = LspService::new(|client| MyLanguageServer::new(client));
+    let (service, socket) = LspService::new(MyLanguageServer::new);
     Server::new(stdin, stdout, socket).serve(service).await;
 }
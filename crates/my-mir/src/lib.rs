@@ -32,15 +32,55 @@ pub enum MirError {
 
     #[error("unreachable code")]
     UnreachableCode,
+
+    #[error("{0} outside of loop")]
+    OutsideLoop(String),
+
+    #[error("cannot resolve method: {0}")]
+    UnresolvedMethod(String),
+
+    #[error("not supported in MIR yet: {0}")]
+    Unsupported(String),
 }
 
 /// MIR Program - collection of functions
 #[derive(Debug)]
 pub struct MirProgram {
     pub functions: HashMap<String, MirFunction>,
+    pub enums: HashMap<String, MirEnumLayout>,
     pub entry: Option<String>,
 }
 
+/// Tagged-union layout of an enum: `{ tag: i64, payload: union { variant structs } }`
+#[derive(Debug, Clone)]
+pub struct MirEnumLayout {
+    pub name: String,
+    pub variants: Vec<MirVariantLayout>,
+}
+
+/// Layout of a single enum variant's payload
+#[derive(Debug, Clone)]
+pub struct MirVariantLayout {
+    pub name: String,
+    pub tag: i64,
+    pub fields: Vec<(String, MirType)>,
+}
+
+impl MirEnumLayout {
+    /// The in-memory type of a value of this enum
+    pub fn ty(&self) -> MirType {
+        let payloads = self
+            .variants
+            .iter()
+            .map(|v| MirType::Struct(
+                format!("{}::{}", self.name, v.name),
+                v.fields.iter().map(|(_, ty)| ty.clone()).collect(),
+            ))
+            .collect();
+        MirType::Struct(self.name.clone(), vec![MirType::I64, MirType::Union(payloads)])
+    }
+}
+
 /// MIR Function - CFG of basic blocks
 #[derive(Debug, Clone)]
 pub struct MirFunction {
@@ -75,6 +115,8 @@ pub enum MirType {
     Ptr(Box<MirType>),
     Array(Box<MirType>, usize),
     Struct(String, Vec<MirType>),
+    /// Untagged union; enums pair one with an `I64` tag
+    Union(Vec<MirType>),
     Function(Vec<MirType>, Box<MirType>),
     Unit,
     Never,
@@ -242,17 +284,90 @@ pub enum Terminator {
 /// Lower HIR to MIR
 pub fn lower(hir: &HirProgram) -> Result<MirProgram, MirError> {
     let mut functions = HashMap::new();
+    let mut enums = HashMap::new();
+
+    // Enum layouts are needed before any function can construct or match variants
+    for item in &hir.items {
+        if let my_hir::HirItem::Enum(e) = item {
+            let layout = lower_enum(e);
+            enums.insert(layout.name.clone(), layout);
+        }
+    }
+
+    // Generic functions are only emitted once instantiated from a call site
+    let generics: HashMap<String, &HirFunction> = hir
+        .items
+        .iter()
+        .filter_map(|item| match item {
+            my_hir::HirItem::Function(f) if !f.type_params.is_empty() => Some((f.name.clone(), f)),
+            _ => None,
+        })
+        .collect();
+    let signatures: HashMap<String, GenericSig> = generics
+        .iter()
+        .map(|(name, f)| (name.clone(), GenericSig {
+            type_params: f.type_params.clone(),
+            params: f.params.iter().map(|p| p.ty.clone()).collect(),
+            return_type: f.return_type.clone(),
+        }))
+        .collect();
 
+    let mut pending = Vec::new();
     for item in &hir.items {
         if let my_hir::HirItem::Function(f) = item {
-            let mir_func = lower_function(f)?;
-            functions.insert(mir_func.name.clone(), mir_func);
+            if f.type_params.is_empty() {
+                let mir_func = lower_function(f, &enums, &signatures, HashMap::new(), &mut pending)?;
+                functions.insert(mir_func.name.clone(), mir_func);
+            }
+        }
+    }
+
+    // Monomorphize: lower each requested instantiation once, which may request more
+    while let Some(instance) = pending.pop() {
+        if functions.contains_key(&instance.name) {
+            continue;
         }
+        let f = generics[&instance.generic];
+        let type_args = f.type_params.iter().cloned().zip(instance.type_args).collect();
+        let mut mir_func = lower_function(f, &enums, &signatures, type_args, &mut pending)?;
+        mir_func.name = instance.name.clone();
+        functions.insert(instance.name, mir_func);
     }
 
     let entry = functions.get("main").map(|_| "main".to_string());
 
-    Ok(MirProgram { functions, entry })
+    Ok(MirProgram { functions, enums, entry })
+}
+
+fn lower_enum(e: &my_hir::HirEnum) -> MirEnumLayout {
+    MirEnumLayout {
+        name: e.name.clone(),
+        variants: e
+            .variants
+            .iter()
+            .enumerate()
+            .map(|(tag, v)| MirVariantLayout {
+                name: v.name.clone(),
+                tag: tag as i64,
+                fields: v.fields.iter().map(|f| (f.name.clone(), lower_type(&f.ty))).collect(),
+            })
+            .collect(),
+    }
+}
+
+/// Signature of a generic function, used to infer type arguments at call sites
+#[derive(Debug, Clone)]
+struct GenericSig {
+    type_params: Vec<String>,
+    params: Vec<HirType>,
+    return_type: HirType,
+}
+
+/// A request to emit `generic` specialized to `type_args` under the mangled `name`
+struct Instance {
+    name: String,
+    generic: String,
+    type_args: Vec<MirType>,
 }
 
 /// MIR builder for constructing CFGs
@@ -264,10 +379,40 @@ struct MirBuilder {
     current_block: Option<NodeIndex>,
     current_instructions: Vec<Instruction>,
     var_map: HashMap<String, LocalId>,
+    /// Enclosing loops, innermost last
+    loops: Vec<LoopTarget>,
+    /// Enum variants by name, with the type of their enum
+    variants: HashMap<String, (MirType, MirVariantLayout)>,
+    /// Signatures of generic functions callable from this function
+    generics: HashMap<String, GenericSig>,
+    /// Concrete types of the type parameters of the function being lowered
+    type_args: HashMap<String, MirType>,
+    /// Instantiations requested by calls to generic functions
+    instances: Vec<Instance>,
+}
+
+/// Jump targets for `break`/`continue` inside a loop
+struct LoopTarget {
+    label: Option<String>,
+    continue_block: (BlockId, NodeIndex),
+    exit_block: (BlockId, NodeIndex),
+    /// Local receiving `break value` (only for `loop` expressions)
+    result: Option<LocalId>,
 }
 
 impl MirBuilder {
-    fn new() -> Self {
+    fn new(
+        enums: &HashMap<String, MirEnumLayout>,
+        generics: &HashMap<String, GenericSig>,
+        type_args: HashMap<String, MirType>,
+    ) -> Self {
+        let variants = enums
+            .values()
+            .flat_map(|e| {
+                let ty = e.ty();
+                e.variants.iter().map(move |v| (v.name.clone(), (ty.clone(), v.clone())))
+            })
+            .collect();
         MirBuilder {
             blocks: DiGraph::new(),
             locals: Vec::new(),
@@ -276,9 +421,23 @@ impl MirBuilder {
             current_block: None,
             current_instructions: Vec::new(),
             var_map: HashMap::new(),
+            loops: Vec::new(),
+            variants,
+            generics: generics.clone(),
+            type_args,
+            instances: Vec::new(),
         }
     }
 
+    /// Lower a type, replacing the current function's type parameters
+    fn lower_type(&self, ty: &HirType) -> MirType {
+        lower_type_with(ty, &self.type_args)
+    }
+
+    fn local_type(&self, id: LocalId) -> MirType {
+        self.locals.get(id.0).map(|l| l.ty.clone()).unwrap_or(MirType::Unit)
+    }
+
     fn new_local(&mut self, name: Option<String>, ty: MirType) -> LocalId {
         let id = LocalId(self.local_counter);
         self.local_counter += 1;
@@ -336,17 +495,72 @@ impl MirBuilder {
     fn lookup_var(&self, name: &str) -> Option<LocalId> {
         self.var_map.get(name).copied()
     }
+
+    /// Finish the current block with a jump to `target`, recording the CFG edge
+    fn goto(&mut self, target: (BlockId, NodeIndex)) {
+        let node = self.finish_block(Terminator::Goto(target.0));
+        self.blocks.add_edge(node, target.1, BranchKind::Unconditional);
+    }
+
+    /// Finish the current block with a conditional branch, recording both CFG edges
+    fn branch(&mut self, cond: LocalId, then: (BlockId, NodeIndex), otherwise: (BlockId, NodeIndex)) {
+        let node = self.finish_block(Terminator::If(cond, then.0, otherwise.0));
+        self.blocks.add_edge(node, then.1, BranchKind::True);
+        self.blocks.add_edge(node, otherwise.1, BranchKind::False);
+    }
+
+    /// Finish the current block with a switch, recording one CFG edge per case
+    fn switch(&mut self, value: LocalId, cases: &[(i64, (BlockId, NodeIndex))], default: (BlockId, NodeIndex)) {
+        let terminator = Terminator::Switch(value, cases.iter().map(|(v, b)| (*v, b.0)).collect(), default.0);
+        let node = self.finish_block(terminator);
+        for (v, b) in cases {
+            self.blocks.add_edge(node, b.1, BranchKind::SwitchCase(*v));
+        }
+        self.blocks.add_edge(node, default.1, BranchKind::SwitchDefault);
+    }
+
+    /// Id of the block currently being built
+    fn current_block_id(&self) -> BlockId {
+        self.current_block
+            .and_then(|node| self.blocks.node_weight(node))
+            .map(|b| b.id)
+            .unwrap_or(BlockId(self.block_counter))
+    }
+
+    fn const_i64(&mut self, value: i64) -> LocalId {
+        let dest = self.new_temp(MirType::I64);
+        self.emit(dest, InstructionKind::Const(MirConstant::I64(value)));
+        dest
+    }
+
+    /// Find the loop targeted by a `break`/`continue`
+    fn find_loop(&self, keyword: &str, label: &Option<String>) -> Result<&LoopTarget, MirError> {
+        let target = match label {
+            Some(l) => self.loops.iter().rev().find(|t| t.label.as_ref() == Some(l)),
+            None => self.loops.last(),
+        };
+        target.ok_or_else(|| match label {
+            Some(l) => MirError::OutsideLoop(format!("{} '{}", keyword, l)),
+            None => MirError::OutsideLoop(keyword.to_string()),
+        })
+    }
 }
 
-fn lower_function(f: &HirFunction) -> Result<MirFunction, MirError> {
-    let mut builder = MirBuilder::new();
+fn lower_function(
+    f: &HirFunction,
+    enums: &HashMap<String, MirEnumLayout>,
+    generics: &HashMap<String, GenericSig>,
+    type_args: HashMap<String, MirType>,
+    pending: &mut Vec<Instance>,
+) -> Result<MirFunction, MirError> {
+    let mut builder = MirBuilder::new(enums, generics, type_args);
 
     // Create locals for parameters
     let params: Vec<MirLocal> = f
         .params
         .iter()
         .map(|p| {
-            let ty = lower_type(&p.ty);
+            let ty = builder.lower_type(&p.ty);
             let id = builder.new_local(Some(p.name.clone()), ty.clone());
             MirLocal {
                 id,
@@ -370,11 +584,12 @@ fn lower_function(f: &HirFunction) -> Result<MirFunction, MirError> {
         Terminator::Return(None)
     };
     builder.finish_block(terminator);
+    pending.append(&mut builder.instances);
 
     Ok(MirFunction {
         name: f.name.clone(),
         params,
-        return_type: lower_type(&f.return_type),
+        return_type: builder.lower_type(&f.return_type),
         locals: builder.locals,
         blocks: builder.blocks,
         entry_block: entry_node,
@@ -398,7 +613,10 @@ fn lower_stmt(builder: &mut MirBuilder, stmt: &my_hir::HirStmt) -> Result<(), Mi
     match stmt {
         my_hir::HirStmt::Let { name, ty, value } => {
             let val_id = lower_expr(builder, value)?;
-            let mir_ty = ty.as_ref().map(lower_type).unwrap_or(MirType::Unit);
+            let mir_ty = match ty {
+                Some(t) => builder.lower_type(t),
+                None => builder.local_type(val_id),
+            };
             let local_id = builder.new_local(Some(name.clone()), mir_ty);
             builder.emit(local_id, InstructionKind::Copy(val_id));
             Ok(())
@@ -415,6 +633,98 @@ fn lower_stmt(builder: &mut MirBuilder, stmt: &my_hir::HirStmt) -> Result<(), Mi
             builder.set_current_block(node);
             Ok(())
         }
+        my_hir::HirStmt::While { label, condition, body } => {
+            let header = builder.new_block();
+            builder.goto(header);
+
+            // Header re-evaluates the condition on every iteration
+            builder.set_current_block(header.1);
+            let cond_id = lower_expr(builder, condition)?;
+            let body_block = builder.new_block();
+            let exit = builder.new_block();
+            builder.branch(cond_id, body_block, exit);
+
+            builder.loops.push(LoopTarget {
+                label: label.clone(),
+                continue_block: header,
+                exit_block: exit,
+                result: None,
+            });
+            builder.set_current_block(body_block.1);
+            lower_block(builder, body)?;
+            builder.goto(header); // back-edge
+            builder.loops.pop();
+
+            builder.set_current_block(exit.1);
+            Ok(())
+        }
+        my_hir::HirStmt::For { label, binding, iterable, body } => {
+            // Desugar to an index loop: `i = 0; while i < len(xs) { x = xs[i]; ...; i = i + 1 }`
+            let iter_id = lower_expr(builder, iterable)?;
+            let len = builder.new_temp(MirType::I64);
+            builder.emit(len, InstructionKind::Call("len".to_string(), vec![iter_id]));
+            let index = builder.new_temp(MirType::I64);
+            builder.emit(index, InstructionKind::Const(MirConstant::I64(0)));
+
+            let header = builder.new_block();
+            builder.goto(header);
+
+            builder.set_current_block(header.1);
+            let cond_id = builder.new_temp(MirType::Bool);
+            builder.emit(cond_id, InstructionKind::BinOp(BinOp::Lt, index, len));
+            let body_block = builder.new_block();
+            let latch = builder.new_block();
+            let exit = builder.new_block();
+            builder.branch(cond_id, body_block, exit);
+
+            builder.loops.push(LoopTarget {
+                label: label.clone(),
+                continue_block: latch,
+                exit_block: exit,
+                result: None,
+            });
+            builder.set_current_block(body_block.1);
+            let ptr = builder.new_temp(MirType::Ptr(Box::new(MirType::I64)));
+            builder.emit(ptr, InstructionKind::GetElementPtr(iter_id, vec![index]));
+            let elem = builder.new_local(Some(binding.clone()), MirType::I64);
+            builder.emit(elem, InstructionKind::Load(ptr));
+            lower_block(builder, body)?;
+            builder.goto(latch);
+            builder.loops.pop();
+
+            // Latch advances the index and jumps back to the header
+            builder.set_current_block(latch.1);
+            let one = builder.new_temp(MirType::I64);
+            builder.emit(one, InstructionKind::Const(MirConstant::I64(1)));
+            let next = builder.new_temp(MirType::I64);
+            builder.emit(next, InstructionKind::BinOp(BinOp::Add, index, one));
+            builder.emit(index, InstructionKind::Copy(next));
+            builder.goto(header); // back-edge
+
+            builder.set_current_block(exit.1);
+            Ok(())
+        }
+        my_hir::HirStmt::Break { label, value } => {
+            let value_id = value.as_ref().map(|v| lower_expr(builder, v)).transpose()?;
+            let target = builder.find_loop("break", label)?;
+            let (exit, result) = (target.exit_block, target.result);
+            if let (Some(result), Some(value_id)) = (result, value_id) {
+                builder.emit(result, InstructionKind::Copy(value_id));
+            }
+            builder.goto(exit);
+            // Start a new unreachable block
+            let (_, node) = builder.new_block();
+            builder.set_current_block(node);
+            Ok(())
+        }
+        my_hir::HirStmt::Continue { label } => {
+            let target = builder.find_loop("continue", label)?.continue_block;
+            builder.goto(target);
+            // Start a new unreachable block
+            let (_, node) = builder.new_block();
+            builder.set_current_block(node);
+            Ok(())
+        }
     }
 }
 
@@ -426,10 +736,23 @@ fn lower_expr(builder: &mut MirBuilder, expr: &my_hir::HirExpr) -> Result<LocalI
             builder.emit(dest, InstructionKind::Const(constant));
             Ok(dest)
         }
-        my_hir::HirExpr::Var(name) => {
-            builder.lookup_var(name).ok_or_else(|| MirError::UndefinedVariable(name.clone()))
-        }
+        my_hir::HirExpr::Var(name) => match builder.lookup_var(name) {
+            Some(id) => Ok(id),
+            None if builder.variants.contains_key(name) => lower_variant(builder, name, vec![]),
+            None => Err(MirError::UndefinedVariable(name.clone())),
+        },
         my_hir::HirExpr::Call(callee, args) => {
+            if let my_hir::HirExpr::Var(name) = callee.as_ref() {
+                if builder.lookup_var(name).is_none() && builder.variants.contains_key(name) {
+                    let values = args
+                        .iter()
+                        .enumerate()
+                        .map(|(i, a)| Ok((i.to_string(), lower_expr(builder, a)?)))
+                        .collect::<Result<_, MirError>>()?;
+                    return lower_variant(builder, name, values);
+                }
+            }
+
             let arg_ids: Vec<LocalId> = args
                 .iter()
                 .map(|a| lower_expr(builder, a))
@@ -437,6 +760,9 @@ fn lower_expr(builder: &mut MirBuilder, expr: &my_hir::HirExpr) -> Result<LocalI
 
             // Check if callee is a direct function name
             if let my_hir::HirExpr::Var(func_name) = callee.as_ref() {
+                if let Some(sig) = builder.generics.get(func_name).cloned() {
+                    return lower_generic_call(builder, func_name, &sig, arg_ids);
+                }
                 let dest = builder.new_temp(MirType::Unit); // TODO: Infer return type
                 builder.emit(dest, InstructionKind::Call(func_name.clone(), arg_ids));
                 Ok(dest)
@@ -447,6 +773,25 @@ fn lower_expr(builder: &mut MirBuilder, expr: &my_hir::HirExpr) -> Result<LocalI
                 Ok(dest)
             }
         }
+        my_hir::HirExpr::MethodCall(receiver, method, args) => {
+            // Methods are resolved statically from the receiver's type and
+            // called as `Type::method` with the receiver as first argument
+            let recv_id = lower_expr(builder, receiver)?;
+            let func_name = match impl_key(&builder.local_type(recv_id)) {
+                Some(key) => format!("{}::{}", key, method),
+                None => return Err(MirError::UnresolvedMethod(method.clone())),
+            };
+            let mut arg_ids = vec![recv_id];
+            for a in args {
+                arg_ids.push(lower_expr(builder, a)?);
+            }
+            if let Some(sig) = builder.generics.get(&func_name).cloned() {
+                return lower_generic_call(builder, &func_name, &sig, arg_ids);
+            }
+            let dest = builder.new_temp(MirType::Unit); // TODO: Infer return type
+            builder.emit(dest, InstructionKind::Call(func_name, arg_ids));
+            Ok(dest)
+        }
         my_hir::HirExpr::BinOp(left, op, right) => {
             let left_id = lower_expr(builder, left)?;
             let right_id = lower_expr(builder, right)?;
@@ -469,13 +814,12 @@ fn lower_expr(builder: &mut MirBuilder, expr: &my_hir::HirExpr) -> Result<LocalI
             let (merge_bid, merge_node) = builder.new_block();
 
             // Finish current block with conditional branch
-            builder.finish_block(Terminator::If(cond_id, then_bid, else_bid));
+            builder.branch(cond_id, (then_bid, then_node), (else_bid, else_node));
 
             // Lower then branch
             builder.set_current_block(then_node);
             let then_result = lower_block(builder, then_block)?;
-            builder.finish_block(Terminator::Goto(merge_bid));
-            builder.blocks.add_edge(then_node, merge_node, BranchKind::Unconditional);
+            builder.goto((merge_bid, merge_node));
 
             // Lower else branch
             builder.set_current_block(else_node);
@@ -484,8 +828,7 @@ fn lower_expr(builder: &mut MirBuilder, expr: &my_hir::HirExpr) -> Result<LocalI
             } else {
                 None
             };
-            builder.finish_block(Terminator::Goto(merge_bid));
-            builder.blocks.add_edge(else_node, merge_node, BranchKind::Unconditional);
+            builder.goto((merge_bid, merge_node));
 
             // Set merge block as current
             builder.set_current_block(merge_node);
@@ -504,6 +847,44 @@ fn lower_expr(builder: &mut MirBuilder, expr: &my_hir::HirExpr) -> Result<LocalI
                 Ok(dest)
             }
         }
+        my_hir::HirExpr::Loop(label, body) => {
+            let result = builder.new_temp(MirType::Unit);
+            builder.emit(result, InstructionKind::Const(MirConstant::Unit));
+
+            let body_block = builder.new_block();
+            let exit = builder.new_block();
+            builder.goto(body_block);
+
+            builder.loops.push(LoopTarget {
+                label: label.clone(),
+                continue_block: body_block,
+                exit_block: exit,
+                result: Some(result),
+            });
+            builder.set_current_block(body_block.1);
+            lower_block(builder, body)?;
+            builder.goto(body_block); // back-edge
+            builder.loops.pop();
+
+            builder.set_current_block(exit.1);
+            Ok(result)
+        }
+        my_hir::HirExpr::Assign(target, value) => {
+            let value_id = lower_expr(builder, value)?;
+            if let my_hir::HirExpr::Var(name) = target.as_ref() {
+                let local = builder
+                    .lookup_var(name)
+                    .ok_or_else(|| MirError::UndefinedVariable(name.clone()))?;
+                builder.emit(local, InstructionKind::Copy(value_id));
+                Ok(local)
+            } else {
+                // Place expressions lower to a pointer we can store through
+                let ptr = lower_place(builder, target)?;
+                let dest = builder.new_temp(MirType::Unit);
+                builder.emit(dest, InstructionKind::Store(ptr, value_id));
+                Ok(value_id)
+            }
+        }
         my_hir::HirExpr::Block(block) => {
             let result = lower_block(builder, block)?;
             if let Some(id) = result {
@@ -514,13 +895,45 @@ fn lower_expr(builder: &mut MirBuilder, expr: &my_hir::HirExpr) -> Result<LocalI
                 Ok(dest)
             }
         }
-        my_hir::HirExpr::Field(object, field) => {
+        my_hir::HirExpr::Field(..) | my_hir::HirExpr::Index(..) => {
+            let ptr = lower_place(builder, expr)?;
+            let elem_ty = match builder.local_type(ptr) {
+                MirType::Ptr(ty) => *ty,
+                _ => MirType::Unit,
+            };
+            let dest = builder.new_temp(elem_ty);
+            builder.emit(dest, InstructionKind::Load(ptr));
+            Ok(dest)
+        }
+        my_hir::HirExpr::Slice(object, start, end) => {
+            // A slice is a (pointer to first element, length) pair
             let obj_id = lower_expr(builder, object)?;
-            let dest = builder.new_temp(MirType::Unit);
-            // Field access becomes a GEP in MIR
-            let field_idx = builder.new_temp(MirType::I64);
-            builder.emit(field_idx, InstructionKind::Const(MirConstant::I64(0))); // TODO: Field index
-            builder.emit(dest, InstructionKind::GetElementPtr(obj_id, vec![field_idx]));
+            let start_id = match start {
+                Some(s) => lower_expr(builder, s)?,
+                None => {
+                    let zero = builder.new_temp(MirType::I64);
+                    builder.emit(zero, InstructionKind::Const(MirConstant::I64(0)));
+                    zero
+                }
+            };
+            let end_id = match end {
+                Some(e) => lower_expr(builder, e)?,
+                None => {
+                    let len = builder.new_temp(MirType::I64);
+                    builder.emit(len, InstructionKind::Call("len".to_string(), vec![obj_id]));
+                    len
+                }
+            };
+
+            let ptr = builder.new_temp(MirType::Ptr(Box::new(MirType::I64)));
+            builder.emit(ptr, InstructionKind::GetElementPtr(obj_id, vec![start_id]));
+            let len = builder.new_temp(MirType::I64);
+            builder.emit(len, InstructionKind::BinOp(BinOp::Sub, end_id, start_id));
+            let dest = builder.new_temp(MirType::Struct(
+                "slice".to_string(),
+                vec![MirType::Ptr(Box::new(MirType::I64)), MirType::I64],
+            ));
+            builder.emit(dest, InstructionKind::Call("slice".to_string(), vec![ptr, len]));
             Ok(dest)
         }
         my_hir::HirExpr::Array(elements) => {
@@ -529,15 +942,16 @@ fn lower_expr(builder: &mut MirBuilder, expr: &my_hir::HirExpr) -> Result<LocalI
                 .map(|e| lower_expr(builder, e))
                 .collect::<Result<_, _>>()?;
 
-            // Allocate array and store elements
-            let arr_ty = MirType::Array(Box::new(MirType::I64), elem_ids.len());
-            let arr = builder.new_temp(arr_ty);
-            builder.emit(arr, InstructionKind::Alloca(MirType::Array(Box::new(MirType::I64), elem_ids.len())));
+            // Allocate array and store elements; an empty array holds `I64`s
+            let elem_ty = elem_ids.first().map_or(MirType::I64, |id| builder.local_type(*id));
+            let arr_ty = MirType::Array(Box::new(elem_ty.clone()), elem_ids.len());
+            let arr = builder.new_temp(arr_ty.clone());
+            builder.emit(arr, InstructionKind::Alloca(arr_ty));
 
             for (i, elem_id) in elem_ids.iter().enumerate() {
                 let idx = builder.new_temp(MirType::I64);
                 builder.emit(idx, InstructionKind::Const(MirConstant::I64(i as i64)));
-                let ptr = builder.new_temp(MirType::Ptr(Box::new(MirType::I64)));
+                let ptr = builder.new_temp(MirType::Ptr(Box::new(elem_ty.clone())));
                 builder.emit(ptr, InstructionKind::GetElementPtr(arr, vec![idx]));
                 let store_dest = builder.new_temp(MirType::Unit);
                 builder.emit(store_dest, InstructionKind::Store(ptr, *elem_id));
@@ -545,6 +959,23 @@ fn lower_expr(builder: &mut MirBuilder, expr: &my_hir::HirExpr) -> Result<LocalI
 
             Ok(arr)
         }
+        my_hir::HirExpr::Tuple(elements) => {
+            let values = elements
+                .iter()
+                .map(|e| lower_expr(builder, e))
+                .collect::<Result<Vec<_>, _>>()?;
+            let ty = MirType::Struct("tuple".to_string(), values.iter().map(|v| builder.local_type(*v)).collect());
+            let dest = builder.new_temp(ty.clone());
+            builder.emit(dest, InstructionKind::Alloca(ty));
+            for (i, value) in values.iter().enumerate() {
+                let idx = builder.const_i64(i as i64);
+                let ptr = builder.new_temp(MirType::Ptr(Box::new(builder.local_type(*value))));
+                builder.emit(ptr, InstructionKind::GetElementPtr(dest, vec![idx]));
+                let store_dest = builder.new_temp(MirType::Unit);
+                builder.emit(store_dest, InstructionKind::Store(ptr, *value));
+            }
+            Ok(dest)
+        }
         my_hir::HirExpr::Record(fields) => {
             // Lower record as a struct allocation
             let dest = builder.new_temp(MirType::Unit);
@@ -556,7 +987,29 @@ fn lower_expr(builder: &mut MirBuilder, expr: &my_hir::HirExpr) -> Result<LocalI
 
             Ok(dest)
         }
-        my_hir::HirExpr::Lambda(params, body) => {
+        my_hir::HirExpr::Construct(name, fields) => {
+            let values = fields
+                .iter()
+                .map(|(field, value)| Ok((field.clone(), lower_expr(builder, value)?)))
+                .collect::<Result<Vec<_>, MirError>>()?;
+            if builder.variants.contains_key(name) {
+                return lower_variant(builder, name, values);
+            }
+
+            // Plain structs are laid out in literal order
+            let ty = MirType::Struct(name.clone(), values.iter().map(|_| MirType::I64).collect());
+            let dest = builder.new_temp(ty.clone());
+            builder.emit(dest, InstructionKind::Alloca(ty));
+            for (i, (_, value)) in values.iter().enumerate() {
+                let idx = builder.const_i64(i as i64);
+                let ptr = builder.new_temp(MirType::Ptr(Box::new(MirType::I64)));
+                builder.emit(ptr, InstructionKind::GetElementPtr(dest, vec![idx]));
+                let store_dest = builder.new_temp(MirType::Unit);
+                builder.emit(store_dest, InstructionKind::Store(ptr, *value));
+            }
+            Ok(dest)
+        }
+        my_hir::HirExpr::Lambda(..) => {
             // Lambdas are lowered to closures (function pointer + environment)
             let dest = builder.new_temp(MirType::Unit);
             builder.emit(dest, InstructionKind::Const(MirConstant::Unit));
@@ -566,6 +1019,10 @@ fn lower_expr(builder: &mut MirBuilder, expr: &my_hir::HirExpr) -> Result<LocalI
         my_hir::HirExpr::Match(scrutinee, arms) => {
             let scrut_id = lower_expr(builder, scrutinee)?;
 
+            if arms.iter().any(|arm| variant_pattern(builder, &arm.pattern).is_some()) {
+                return lower_variant_match(builder, scrut_id, arms);
+            }
+
             // Simple lowering: chain of if-else
             // TODO: Full match compilation with decision trees
             if arms.is_empty() {
@@ -581,6 +1038,287 @@ fn lower_expr(builder: &mut MirBuilder, expr: &my_hir::HirExpr) -> Result<LocalI
         my_hir::HirExpr::AI(ai_expr) => {
             lower_ai_expr(builder, ai_expr)
         }
+        // Effect handlers need captured continuations
+        my_hir::HirExpr::Perform(effect, op, _) => {
+            Err(MirError::Unsupported(format!("perform {}.{}", effect, op)))
+        }
+        my_hir::HirExpr::Handle(_, effect, _) => {
+            Err(MirError::Unsupported(format!("handlers of effect '{}'", effect)))
+        }
+        my_hir::HirExpr::Resume(_) => Err(MirError::Unsupported("resume".to_string())),
+    }
+}
+
+/// Call a generic function through the instantiation for its argument types
+fn lower_generic_call(
+    builder: &mut MirBuilder,
+    name: &str,
+    sig: &GenericSig,
+    arg_ids: Vec<LocalId>,
+) -> Result<LocalId, MirError> {
+    let mut bindings = HashMap::new();
+    for (param, arg) in sig.params.iter().zip(&arg_ids) {
+        bind_type_param(param, &builder.local_type(*arg), &mut bindings);
+    }
+    // Parameters not determined by the arguments are erased to unit
+    let type_args: Vec<MirType> = sig
+        .type_params
+        .iter()
+        .map(|p| bindings.get(p).cloned().unwrap_or(MirType::Unit))
+        .collect();
+
+    let instance = mangle(name, &type_args);
+    let subst: HashMap<String, MirType> = sig.type_params.iter().cloned().zip(type_args.iter().cloned()).collect();
+    let dest = builder.new_temp(lower_type_with(&sig.return_type, &subst));
+    builder.emit(dest, InstructionKind::Call(instance.clone(), arg_ids));
+    builder.instances.push(Instance {
+        name: instance,
+        generic: name.to_string(),
+        type_args,
+    });
+    Ok(dest)
+}
+
+/// Match a declared parameter type against an argument's type, binding type parameters
+fn bind_type_param(param: &HirType, arg: &MirType, bindings: &mut HashMap<String, MirType>) {
+    match (param, arg) {
+        (HirType::Named(name), _) => {
+            bindings.entry(name.clone()).or_insert_with(|| arg.clone());
+        }
+        (HirType::Array(p), MirType::Array(a, _)) => bind_type_param(p, a, bindings),
+        (HirType::Function(p, r), MirType::Function(ps, ret)) => {
+            if let Some(a) = ps.first() {
+                bind_type_param(p, a, bindings);
+            }
+            bind_type_param(r, ret, bindings);
+        }
+        _ => {}
+    }
+}
+
+/// Symbol name of a generic function or struct instantiated at `type_args`: `id<i64>`
+fn mangle(name: &str, type_args: &[MirType]) -> String {
+    fn type_name(ty: &MirType) -> String {
+        match ty {
+            MirType::I32 => "i32".to_string(),
+            MirType::I64 => "i64".to_string(),
+            MirType::F32 => "f32".to_string(),
+            MirType::F64 => "f64".to_string(),
+            MirType::Bool => "bool".to_string(),
+            MirType::Ptr(inner) => format!("*{}", type_name(inner)),
+            MirType::Array(inner, _) => format!("[{}]", type_name(inner)),
+            MirType::Struct(name, _) => name.clone(),
+            MirType::Union(_) => "union".to_string(),
+            MirType::Function(params, ret) => format!(
+                "fn({})->{}",
+                params.iter().map(type_name).collect::<Vec<_>>().join(","),
+                type_name(ret)
+            ),
+            MirType::Unit => "()".to_string(),
+            MirType::Never => "!".to_string(),
+        }
+    }
+    format!("{}<{}>", name, type_args.iter().map(type_name).collect::<Vec<_>>().join(","))
+}
+
+/// The name impl methods of a type are registered under, as in `Ty::impl_key`
+fn impl_key(ty: &MirType) -> Option<String> {
+    match ty {
+        MirType::I64 => Some("Int".to_string()),
+        MirType::F64 => Some("Float".to_string()),
+        MirType::Bool => Some("Bool".to_string()),
+        MirType::Ptr(_) => Some("String".to_string()),
+        MirType::Array(..) => Some("Array".to_string()),
+        // Generic instances are mangled as `Name<args>`
+        MirType::Struct(name, _) => Some(name.split('<').next().unwrap_or(name).to_string()),
+        _ => None,
+    }
+}
+
+/// Build a variant value: store the tag, then each payload field in layout order
+fn lower_variant(
+    builder: &mut MirBuilder,
+    name: &str,
+    values: Vec<(String, LocalId)>,
+) -> Result<LocalId, MirError> {
+    let (ty, layout) = builder
+        .variants
+        .get(name)
+        .cloned()
+        .ok_or_else(|| MirError::UndefinedVariable(name.to_string()))?;
+
+    let dest = builder.new_temp(ty.clone());
+    builder.emit(dest, InstructionKind::Alloca(ty));
+
+    let zero = builder.const_i64(0);
+    let tag_ptr = builder.new_temp(MirType::Ptr(Box::new(MirType::I64)));
+    builder.emit(tag_ptr, InstructionKind::GetElementPtr(dest, vec![zero]));
+    let tag = builder.const_i64(layout.tag);
+    let store_dest = builder.new_temp(MirType::Unit);
+    builder.emit(store_dest, InstructionKind::Store(tag_ptr, tag));
+
+    let one = builder.const_i64(1);
+    for (i, (field, field_ty)) in layout.fields.iter().enumerate() {
+        let Some((_, value)) = values.iter().find(|(n, _)| n == field) else {
+            continue;
+        };
+        let idx = builder.const_i64(i as i64);
+        let ptr = builder.new_temp(MirType::Ptr(Box::new(field_ty.clone())));
+        builder.emit(ptr, InstructionKind::GetElementPtr(dest, vec![one, idx]));
+        let store_dest = builder.new_temp(MirType::Unit);
+        builder.emit(store_dest, InstructionKind::Store(ptr, *value));
+    }
+
+    Ok(dest)
+}
+
+/// The variant a pattern tests for, with its sub-patterns keyed by field name
+fn variant_pattern<'p>(
+    builder: &MirBuilder,
+    pattern: &'p my_hir::HirPattern,
+) -> Option<(MirVariantLayout, Vec<(String, &'p my_hir::HirPattern)>)> {
+    let (name, fields) = match pattern {
+        my_hir::HirPattern::Var(name) => (name, vec![]),
+        my_hir::HirPattern::Constructor(name, args) => (
+            name,
+            args.iter().enumerate().map(|(i, p)| (i.to_string(), p)).collect(),
+        ),
+        my_hir::HirPattern::Struct(name, fields) => (
+            name,
+            fields.iter().map(|(f, p)| (f.clone(), p)).collect(),
+        ),
+        _ => return None,
+    };
+    builder.variants.get(name).map(|(_, layout)| (layout.clone(), fields))
+}
+
+/// Lower a match on an enum to a switch over its tag
+fn lower_variant_match(
+    builder: &mut MirBuilder,
+    scrut_id: LocalId,
+    arms: &[my_hir::HirArm],
+) -> Result<LocalId, MirError> {
+    // Only the tag is tested, so every arm must match all values with its tag
+    for arm in arms {
+        if arm.guard.is_some() {
+            return Err(MirError::Unsupported("guards in a match on an enum".to_string()));
+        }
+        let irrefutable = |p: &my_hir::HirPattern| matches!(p, my_hir::HirPattern::Var(_) | my_hir::HirPattern::Wildcard);
+        match variant_pattern(builder, &arm.pattern) {
+            Some((layout, fields)) if !fields.iter().all(|(_, p)| irrefutable(p)) => {
+                return Err(MirError::Unsupported(format!("nested patterns in a '{}' arm", layout.name)));
+            }
+            None if !irrefutable(&arm.pattern) => {
+                return Err(MirError::Unsupported("patterns other than variants in a match on an enum".to_string()));
+            }
+            _ => {}
+        }
+    }
+
+    let zero = builder.const_i64(0);
+    let tag_ptr = builder.new_temp(MirType::Ptr(Box::new(MirType::I64)));
+    builder.emit(tag_ptr, InstructionKind::GetElementPtr(scrut_id, vec![zero]));
+    let tag = builder.new_temp(MirType::I64);
+    builder.emit(tag, InstructionKind::Load(tag_ptr));
+
+    // Each tag goes to its first arm; the first non-variant arm is the default
+    let mut cases: Vec<(i64, (BlockId, NodeIndex))> = Vec::new();
+    let mut default = None;
+    let mut targets = Vec::new();
+    for arm in arms {
+        let layout = variant_pattern(builder, &arm.pattern).map(|(layout, _)| layout);
+        let reachable = match &layout {
+            Some(layout) => !cases.iter().any(|(t, _)| *t == layout.tag),
+            None => default.is_none(),
+        };
+        if !reachable {
+            continue;
+        }
+        let block = builder.new_block();
+        match layout {
+            Some(layout) => cases.push((layout.tag, block)),
+            None => default = Some(block),
+        }
+        targets.push((arm, block));
+    }
+
+    // Without a catch-all arm the default target is a fresh block left `Unreachable`
+    let default = match default {
+        Some(block) => block,
+        None => builder.new_block(),
+    };
+    let merge = builder.new_block();
+    builder.switch(tag, &cases, default);
+
+    let mut incoming = Vec::new();
+    for (arm, block) in targets {
+        builder.set_current_block(block.1);
+        match variant_pattern(builder, &arm.pattern) {
+            Some((layout, fields)) => {
+                let one = builder.const_i64(1);
+                for (field, pattern) in fields {
+                    let my_hir::HirPattern::Var(binding) = pattern else {
+                        continue;
+                    };
+                    let Some(i) = layout.fields.iter().position(|(n, _)| *n == field) else {
+                        continue;
+                    };
+                    let field_ty = layout.fields[i].1.clone();
+                    let idx = builder.const_i64(i as i64);
+                    let ptr = builder.new_temp(MirType::Ptr(Box::new(field_ty.clone())));
+                    builder.emit(ptr, InstructionKind::GetElementPtr(scrut_id, vec![one, idx]));
+                    let local = builder.new_local(Some(binding.clone()), field_ty);
+                    builder.emit(local, InstructionKind::Load(ptr));
+                }
+            }
+            None => {
+                if let my_hir::HirPattern::Var(binding) = &arm.pattern {
+                    let local = builder.new_local(Some(binding.clone()), MirType::Unit);
+                    builder.emit(local, InstructionKind::Copy(scrut_id));
+                }
+            }
+        }
+
+        let result = lower_expr(builder, &arm.body)?;
+        incoming.push((builder.current_block_id(), result));
+        builder.goto(merge);
+    }
+
+    builder.set_current_block(merge.1);
+    let dest = builder.new_temp(MirType::Unit);
+    builder.emit(dest, InstructionKind::Phi(incoming));
+    Ok(dest)
+}
+
+/// Lower a place expression to a pointer that can be loaded from or stored through
+fn lower_place(builder: &mut MirBuilder, expr: &my_hir::HirExpr) -> Result<LocalId, MirError> {
+    match expr {
+        my_hir::HirExpr::Index(object, index) => {
+            let obj_id = lower_expr(builder, object)?;
+            let idx_id = lower_expr(builder, index)?;
+            let elem_ty = match builder.local_type(obj_id) {
+                MirType::Array(ty, _) => *ty,
+                _ => MirType::Unit,
+            };
+            let ptr = builder.new_temp(MirType::Ptr(Box::new(elem_ty)));
+            builder.emit(ptr, InstructionKind::GetElementPtr(obj_id, vec![idx_id]));
+            Ok(ptr)
+        }
+        my_hir::HirExpr::Field(object, field) => {
+            // Field access becomes a GEP in MIR. Tuple fields are numbered;
+            // named fields are not resolved yet
+            let obj_id = lower_expr(builder, object)?;
+            let index: usize = field.parse().unwrap_or(0); // TODO: Field index
+            let field_ty = match builder.local_type(obj_id) {
+                MirType::Struct(_, fields) => fields.get(index).cloned().unwrap_or(MirType::Unit),
+                _ => MirType::Unit,
+            };
+            let field_idx = builder.const_i64(index as i64);
+            let ptr = builder.new_temp(MirType::Ptr(Box::new(field_ty)));
+            builder.emit(ptr, InstructionKind::GetElementPtr(obj_id, vec![field_idx]));
+            Ok(ptr)
+        }
+        _ => lower_expr(builder, expr),
     }
 }
 
@@ -607,7 +1345,7 @@ fn lower_ai_expr(builder: &mut MirBuilder, ai_expr: &my_hir::HirAIExpr) -> Resul
             builder.emit(dest, InstructionKind::AIStub(AIOperation::Embed, vec![input_id]));
             Ok(dest)
         }
-        my_hir::HirAIExpr::Generate { template, params } => {
+        my_hir::HirAIExpr::Generate { params, .. } => {
             let param_ids: Vec<LocalId> = params
                 .iter()
                 .map(|p| lower_expr(builder, p))
@@ -634,6 +1372,12 @@ fn lower_binop(op: my_hir::HirBinOp) -> BinOp {
         my_hir::HirBinOp::Sub => BinOp::Sub,
         my_hir::HirBinOp::Mul => BinOp::Mul,
         my_hir::HirBinOp::Div => BinOp::Div,
+        my_hir::HirBinOp::Rem => BinOp::Rem,
+        my_hir::HirBinOp::BitAnd => BinOp::And,
+        my_hir::HirBinOp::BitOr => BinOp::Or,
+        my_hir::HirBinOp::BitXor => BinOp::Xor,
+        my_hir::HirBinOp::Shl => BinOp::Shl,
+        my_hir::HirBinOp::Shr => BinOp::Shr,
         my_hir::HirBinOp::Eq => BinOp::Eq,
         my_hir::HirBinOp::Ne => BinOp::Ne,
         my_hir::HirBinOp::Lt => BinOp::Lt,
@@ -655,7 +1399,14 @@ fn lower_unop(op: my_hir::HirUnOp) -> UnOp {
 }
 
 fn lower_type(ty: &HirType) -> MirType {
+    lower_type_with(ty, &HashMap::new())
+}
+
+/// Lower a type, substituting the given concrete types for type parameters
+fn lower_type_with(ty: &HirType, type_args: &HashMap<String, MirType>) -> MirType {
+    let lower_type = |ty: &HirType| lower_type_with(ty, type_args);
     match ty {
+        HirType::Named(name) if type_args.contains_key(name) => type_args[name].clone(),
         HirType::Primitive(p) => match p {
             my_hir::HirPrimitive::Int => MirType::I64,
             my_hir::HirPrimitive::Float => MirType::F64,
@@ -668,6 +1419,11 @@ fn lower_type(ty: &HirType) -> MirType {
             MirType::Function(vec![lower_type(param)], Box::new(lower_type(ret)))
         }
         HirType::Unit => MirType::Unit,
+        HirType::Named(name) => MirType::Struct(name.clone(), vec![]),
+        // Each instantiation of a generic struct or enum is a distinct type
+        HirType::Generic(name, args) => {
+            MirType::Struct(mangle(name, &args.iter().map(lower_type).collect::<Vec<_>>()), vec![])
+        }
         _ => MirType::Unit, // TODO: Handle all types
     }
 }
@@ -735,6 +1491,9 @@ pub mod interpreter {
         #[error("division by zero")]
         DivisionByZero,
 
+        #[error("integer overflow in {0}")]
+        Overflow(String),
+
         #[error("AI operations require runtime (set ANTHROPIC_API_KEY or OPENAI_API_KEY)")]
         AINotSupported,
 
@@ -744,6 +1503,7 @@ pub mod interpreter {
 
     /// Call frame
     struct Frame {
+        #[allow(dead_code)]
         function: String,
         locals: HashMap<usize, Value>,
         block_idx: NodeIndex,
@@ -1064,27 +1824,38 @@ pub mod interpreter {
         fn eval_binop(&self, op: BinOp, left: Value, right: Value) -> Result<Value, InterpreterError> {
             match (left, right) {
                 (Value::I64(l), Value::I64(r)) => match op {
-                    BinOp::Add => Ok(Value::I64(l + r)),
-                    BinOp::Sub => Ok(Value::I64(l - r)),
-                    BinOp::Mul => Ok(Value::I64(l * r)),
-                    BinOp::Div => {
-                        if r == 0 { return Err(InterpreterError::DivisionByZero); }
-                        Ok(Value::I64(l / r))
+                    BinOp::Add | BinOp::Sub | BinOp::Mul | BinOp::Div | BinOp::Rem | BinOp::Shl | BinOp::Shr => {
+                        if r == 0 && matches!(op, BinOp::Div | BinOp::Rem) {
+                            return Err(InterpreterError::DivisionByZero);
+                        }
+                        let shift = u32::try_from(r).ok();
+                        let value = match op {
+                            BinOp::Add => l.checked_add(r),
+                            BinOp::Sub => l.checked_sub(r),
+                            BinOp::Mul => l.checked_mul(r),
+                            BinOp::Div => l.checked_div(r),
+                            BinOp::Rem => l.checked_rem(r),
+                            BinOp::Shl => shift.and_then(|s| l.checked_shl(s)),
+                            _ => shift.and_then(|s| l.checked_shr(s)),
+                        };
+                        value.map(Value::I64).ok_or_else(|| InterpreterError::Overflow(format!("{:?} of {} and {}", op, l, r)))
                     }
-                    BinOp::Rem => Ok(Value::I64(l % r)),
+                    BinOp::And => Ok(Value::I64(l & r)),
+                    BinOp::Or => Ok(Value::I64(l | r)),
+                    BinOp::Xor => Ok(Value::I64(l ^ r)),
                     BinOp::Eq => Ok(Value::Bool(l == r)),
                     BinOp::Ne => Ok(Value::Bool(l != r)),
                     BinOp::Lt => Ok(Value::Bool(l < r)),
                     BinOp::Le => Ok(Value::Bool(l <= r)),
                     BinOp::Gt => Ok(Value::Bool(l > r)),
                     BinOp::Ge => Ok(Value::Bool(l >= r)),
-                    _ => Err(InterpreterError::TypeError("invalid op for i64".to_string())),
                 },
                 (Value::F64(l), Value::F64(r)) => match op {
                     BinOp::Add => Ok(Value::F64(l + r)),
                     BinOp::Sub => Ok(Value::F64(l - r)),
                     BinOp::Mul => Ok(Value::F64(l * r)),
                     BinOp::Div => Ok(Value::F64(l / r)),
+                    BinOp::Rem => Ok(Value::F64(l % r)),
                     BinOp::Eq => Ok(Value::Bool(l == r)),
                     BinOp::Ne => Ok(Value::Bool(l != r)),
                     BinOp::Lt => Ok(Value::Bool(l < r)),
@@ -1096,6 +1867,7 @@ pub mod interpreter {
                 (Value::Bool(l), Value::Bool(r)) => match op {
                     BinOp::And => Ok(Value::Bool(l && r)),
                     BinOp::Or => Ok(Value::Bool(l || r)),
+                    BinOp::Xor => Ok(Value::Bool(l ^ r)),
                     BinOp::Eq => Ok(Value::Bool(l == r)),
                     BinOp::Ne => Ok(Value::Bool(l != r)),
                     _ => Err(InterpreterError::TypeError("invalid op for bool".to_string())),
@@ -1106,7 +1878,9 @@ pub mod interpreter {
 
         fn eval_unop(&self, op: UnOp, val: Value) -> Result<Value, InterpreterError> {
             match (op, val) {
-                (UnOp::Neg, Value::I64(v)) => Ok(Value::I64(-v)),
+                (UnOp::Neg, Value::I64(v)) => {
+                    v.checked_neg().map(Value::I64).ok_or_else(|| InterpreterError::Overflow(format!("Neg of {}", v)))
+                }
                 (UnOp::Neg, Value::F64(v)) => Ok(Value::F64(-v)),
                 (UnOp::Not, Value::Bool(v)) => Ok(Value::Bool(!v)),
                 _ => Err(InterpreterError::TypeError("invalid unary op".to_string())),
@@ -1261,4 +2035,278 @@ mod tests {
         let mir = lower(&hir).unwrap();
         assert!(mir.functions.is_empty());
     }
+
+    fn lower_source(source: &str) -> MirProgram {
+        let program = my_lang::parse(source).unwrap();
+        let hir = my_hir::lower(&program).unwrap();
+        lower(&hir).unwrap()
+    }
+
+    #[test]
+    fn test_loops_have_back_edges() {
+        let sources = [
+            "fn main() { let mut i = 0; while i < 10 { i = i + 1; } }",
+            "fn main() { for x in [1, 2, 3] { print(x); } }",
+            "fn main() { let mut n = 0; let x = loop { n = n + 1; if n > 3 { break n; } }; }",
+        ];
+        for source in sources {
+            let mir = lower_source(source);
+            let main = &mir.functions["main"];
+            assert!(petgraph::algo::is_cyclic_directed(&main.blocks), "{}", source);
+        }
+
+        let mir = lower_source("fn main() { if true { print(1); } }");
+        assert!(!petgraph::algo::is_cyclic_directed(&mir.functions["main"].blocks));
+    }
+
+    #[test]
+    fn test_while_loop_executes() {
+        let mir = lower_source(r#"
+            fn main() -> Int {
+                let mut i = 0;
+                while i < 10 {
+                    i = i + 1;
+                    if i == 5 { break; }
+                }
+                return i;
+            }
+        "#);
+        let mut interp = interpreter::Interpreter::new(mir);
+        assert!(matches!(interp.run(), Ok(interpreter::Value::I64(5))));
+    }
+
+    #[test]
+    fn test_index_lowers_to_gep() {
+        let mir = lower_source("fn main() { let mut xs = [1, 2]; xs[0] = xs[1]; }");
+        let main = &mir.functions["main"];
+        let instrs: Vec<&InstructionKind> = main
+            .blocks
+            .node_weights()
+            .flat_map(|b| b.instructions.iter().map(|i| &i.kind))
+            .collect();
+        assert!(instrs.iter().any(|k| matches!(k, InstructionKind::Load(_))));
+        // Two stores for the array literal, one for the assignment
+        let stores = instrs.iter().filter(|k| matches!(k, InstructionKind::Store(..))).count();
+        assert_eq!(stores, 3);
+    }
+
+    #[test]
+    fn test_index_loads_have_element_type() {
+        let mir = lower_source(r#"
+            fn first(xs: [Float]) -> Float { return xs[0]; }
+            fn main() { let flags = [true, false]; let f = flags[1]; }
+        "#);
+        let load_types = |name: &str| -> Vec<MirType> {
+            let function = &mir.functions[name];
+            function
+                .blocks
+                .node_weights()
+                .flat_map(|b| b.instructions.iter())
+                .filter(|i| matches!(i.kind, InstructionKind::Load(_)))
+                .map(|i| function.locals.iter().find(|l| l.id == i.dest).unwrap().ty.clone())
+                .collect()
+        };
+        assert_eq!(load_types("first"), vec![MirType::F64]);
+        assert_eq!(load_types("main"), vec![MirType::Bool]);
+    }
+
+    #[test]
+    fn test_enum_lowers_to_tagged_union() {
+        let mir = lower_source(r#"
+            enum Shape { Circle(Int), Rect { w: Int, h: Int }, Empty }
+            fn area(s: Shape) -> Int {
+                return match s {
+                    Circle(r) => r * r,
+                    Rect { w, h } => w * h,
+                    Empty => 0,
+                };
+            }
+            fn main() { area(Circle(2)); area(Rect { w: 1, h: 2 }); area(Empty); }
+        "#);
+
+        let layout = &mir.enums["Shape"];
+        assert_eq!(layout.variants.iter().map(|v| v.tag).collect::<Vec<_>>(), vec![0, 1, 2]);
+        assert!(matches!(&layout.ty(), MirType::Struct(_, fields)
+            if fields[0] == MirType::I64 && matches!(&fields[1], MirType::Union(u) if u.len() == 3)));
+
+        // Each tag goes to the arm of its variant, which binds that variant's fields
+        let area = &mir.functions["area"];
+        let block = |id: BlockId| area.blocks.node_weights().find(|b| b.id == id).unwrap();
+        let name = |id: LocalId| area.locals.iter().find(|l| l.id == id).and_then(|l| l.name.clone());
+        let (cases, default) = area
+            .blocks
+            .node_weights()
+            .find_map(|b| match &b.terminator {
+                Terminator::Switch(_, cases, default) => Some((cases.clone(), *default)),
+                _ => None,
+            })
+            .unwrap();
+        let arms: Vec<(i64, Vec<String>, Vec<String>)> = cases
+            .iter()
+            .map(|(tag, id)| {
+                let instructions = &block(*id).instructions;
+                let bound = instructions
+                    .iter()
+                    .filter(|i| matches!(i.kind, InstructionKind::Load(_)))
+                    .filter_map(|i| name(i.dest))
+                    .collect();
+                let product = instructions
+                    .iter()
+                    .filter_map(|i| match i.kind {
+                        InstructionKind::BinOp(BinOp::Mul, l, r) => Some([l, r]),
+                        _ => None,
+                    })
+                    .flatten()
+                    .filter_map(name)
+                    .collect();
+                (*tag, bound, product)
+            })
+            .collect();
+        let owned = |names: &[&str]| names.iter().map(|n| n.to_string()).collect::<Vec<_>>();
+        assert_eq!(arms, vec![
+            (0, owned(&["r"]), owned(&["r", "r"])),
+            (1, owned(&["w", "h"]), owned(&["w", "h"])),
+            (2, vec![], vec![]),
+        ]);
+        assert!(matches!(block(default).terminator, Terminator::Unreachable));
+
+        // Every construction allocates the enum and stores its tag
+        let main = &mir.functions["main"];
+        let allocas = main
+            .blocks
+            .node_weights()
+            .flat_map(|b| b.instructions.iter())
+            .filter(|i| matches!(&i.kind, InstructionKind::Alloca(MirType::Struct(name, _)) if name == "Shape"))
+            .count();
+        assert_eq!(allocas, 3);
+    }
+
+    #[test]
+    fn test_generic_functions_are_monomorphized() {
+        let mir = lower_source(r#"
+            fn id<T>(x: T) -> T { return x; }
+            fn unused<T>(x: T) -> T { return x; }
+            fn main() -> Int {
+                id(true);
+                return id(5);
+            }
+        "#);
+
+        let mut names: Vec<&str> = mir.functions.keys().map(|k| k.as_str()).collect();
+        names.sort();
+        assert_eq!(names, vec!["id<bool>", "id<i64>", "main"]);
+        assert_eq!(mir.functions["id<i64>"].params[0].ty, MirType::I64);
+        assert_eq!(mir.functions["id<bool>"].return_type, MirType::Bool);
+
+        let mut interp = interpreter::Interpreter::new(mir);
+        assert!(matches!(interp.run(), Ok(interpreter::Value::I64(5))));
+    }
+
+    #[test]
+    fn test_nested_variant_patterns_are_rejected() {
+        let program = my_lang::parse(r#"
+            enum C { C(Int), D }
+            fn f(c: C) -> Int { return match c { C(0) => 1, C(r) => r, D => 0 }; }
+        "#).unwrap();
+        let hir = my_hir::lower(&program).unwrap();
+        assert!(matches!(lower(&hir), Err(MirError::Unsupported(m)) if m == "nested patterns in a 'C' arm"));
+    }
+
+    #[test]
+    fn test_guarded_variant_arms_are_rejected() {
+        let program = my_lang::parse(r#"
+            enum C { C(Int), D }
+            fn f(c: C) -> Int { return match c { C(r) if r > 0 => r, C(r) => 0, D => 0 }; }
+        "#).unwrap();
+        let hir = my_hir::lower(&program).unwrap();
+        assert!(matches!(lower(&hir), Err(MirError::Unsupported(m)) if m == "guards in a match on an enum"));
+    }
+
+    #[test]
+    fn test_effects_are_rejected() {
+        let lower_effects = |body: &str| {
+            let source = format!("effect Logger {{ op log: String }}\nfn main() {{ {} }}", body);
+            let hir = my_hir::lower(&my_lang::parse(&source).unwrap()).unwrap();
+            lower(&hir).map(|_| ())
+        };
+        assert!(matches!(lower_effects(r#"perform Logger.log("hi");"#),
+            Err(MirError::Unsupported(m)) if m == "perform Logger.log"));
+        assert!(matches!(lower_effects("let n = handle { 1; } with Logger { log(m) => resume(()) };"),
+            Err(MirError::Unsupported(m)) if m == "handlers of effect 'Logger'"));
+    }
+
+    #[test]
+    fn test_break_outside_loop() {
+        let hir = my_hir::HirProgram {
+            items: vec![my_hir::HirItem::Function(my_hir::HirFunction {
+                name: "main".to_string(),
+                type_params: vec![],
+                params: vec![],
+                return_type: HirType::Unit,
+                effects: vec![],
+                body: my_hir::HirBlock {
+                    stmts: vec![my_hir::HirStmt::Continue { label: None }],
+                    expr: None,
+                },
+            })],
+        };
+        assert!(matches!(lower(&hir), Err(MirError::OutsideLoop(_))));
+    }
+
+    #[test]
+    fn test_methods_lower_to_direct_calls() {
+        let mir = lower_source(r#"
+            trait Double { fn double(self) -> Int; }
+            impl Double for Int {
+                fn double(self) -> Int { return self * 2; }
+            }
+            fn main() -> Int {
+                let n = 21;
+                return n.double();
+            }
+        "#);
+
+        assert_eq!(mir.functions["Int::double"].params[0].ty, MirType::I64);
+        let mut interp = interpreter::Interpreter::new(mir);
+        assert!(matches!(interp.run(), Ok(interpreter::Value::I64(42))));
+    }
+
+    #[test]
+    fn test_tuples_lower_to_aggregates() {
+        let mir = lower_source(r#"
+            fn main() -> Int {
+                let (a, b) = (20, true);
+                return a;
+            }
+        "#);
+
+        let main = &mir.functions["main"];
+        let instrs: Vec<&InstructionKind> =
+            main.blocks.node_weights().flat_map(|b| b.instructions.iter()).map(|i| &i.kind).collect();
+        assert!(instrs.iter().any(|k| matches!(k, InstructionKind::Alloca(MirType::Struct(name, fields))
+            if name == "tuple" && fields[..] == [MirType::I64, MirType::Bool])));
+
+        // Each destructured name loads its own element, with the element's type
+        let a = main.locals.iter().find(|l| l.name.as_deref() == Some("a")).unwrap();
+        let b = main.locals.iter().find(|l| l.name.as_deref() == Some("b")).unwrap();
+        assert_eq!((&a.ty, &b.ty), (&MirType::I64, &MirType::Bool));
+    }
+
+    #[test]
+    fn test_integer_operators_execute() {
+        let mir = lower_source(r#"
+            fn main() -> Int {
+                let mut x = 0b1100;
+                x |= 3;
+                x <<= 2;
+                return x % 7 + (x ^ 0xF0) + (x >> 1 & 0o7);
+            }
+        "#);
+        let mut interp = interpreter::Interpreter::new(mir);
+        assert!(matches!(interp.run(), Ok(interpreter::Value::I64(214))), "{:?}", interp.run());
+
+        let mir = lower_source("fn main() -> Int { let big = 9223372036854775807; return big + 1; }");
+        let mut interp = interpreter::Interpreter::new(mir);
+        assert!(matches!(interp.run(), Err(interpreter::InterpreterError::Overflow(_))));
+    }
 }
@@ -10,6 +10,7 @@
 use petgraph::graph::DiGraph;
 use semver::{Version, VersionReq};
 use serde::{Deserialize, Serialize};
+use my_lang::modules::Loader;
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 use thiserror::Error;
@@ -130,6 +131,7 @@ pub struct LockedPackage {
 
 /// Dependency resolver
 pub struct Resolver {
+    #[allow(dead_code)]
     registry: Registry,
 }
 
@@ -144,6 +146,7 @@ impl Resolver {
         let mut resolved = HashMap::new();
 
         // Add root package
+        #[allow(unused_variables)]
         let root_version = Version::parse(&manifest.package.version)
             .map_err(|e| PkgError::InvalidManifest(e.to_string()))?;
 
@@ -172,7 +175,7 @@ impl Resolver {
         &self,
         name: &str,
         dep: &Dependency,
-        graph: &mut DiGraph<(String, Version), ()>,
+        #[allow(unused_variables)] graph: &mut DiGraph<(String, Version), ()>,
         resolved: &mut HashMap<String, Version>,
     ) -> Result<(), PkgError> {
         let version_req = match dep {
@@ -305,12 +308,25 @@ impl PackageCache {
         }
     }
 
+    /// The path of the highest cached version of a package matching `req`
+    pub fn find(&self, name: &str, req: &VersionReq) -> Option<PathBuf> {
+        let entries = std::fs::read_dir(self.cache_dir.join("packages").join(name)).ok()?;
+        entries
+            .filter_map(|entry| {
+                let entry = entry.ok()?;
+                let version = Version::parse(entry.file_name().to_str()?).ok()?;
+                req.matches(&version).then(|| (version, entry.path()))
+            })
+            .max_by(|a, b| a.0.cmp(&b.0))
+            .map(|(_, path)| path)
+    }
+
     /// Store package in cache
     pub async fn store(
         &self,
         name: &str,
         version: &Version,
-        data: &[u8],
+        #[allow(unused_variables)] data: &[u8],
     ) -> Result<PathBuf, PkgError> {
         let path = self
             .cache_dir
@@ -340,6 +356,44 @@ pub fn save_manifest(manifest: &Manifest, path: &Path) -> Result<(), PkgError> {
     Ok(())
 }
 
+/// A module loader for the project in `project_dir`, resolving `use` paths
+/// that start with a dependency's name in that dependency's sources. Path
+/// dependencies are read in place and registry dependencies from `cache`;
+/// registry dependencies that are not cached yet are left out, so importing
+/// from them is an unresolved import. A dependency named `my-utils` is
+/// imported as `my_utils`.
+pub fn module_loader(project_dir: &Path, cache: &PackageCache) -> Result<Loader, PkgError> {
+    let manifest_path = project_dir.join("my.toml");
+    if !manifest_path.exists() {
+        return Err(PkgError::ManifestNotFound(manifest_path));
+    }
+    let manifest = load_manifest(&manifest_path)?;
+
+    let mut loader = Loader::new();
+    for (name, dependency) in &manifest.dependencies {
+        let root = match dependency {
+            Dependency::Detailed(DetailedDependency { path: Some(path), .. }) => {
+                let root = project_dir.join(path);
+                if !root.is_dir() {
+                    return Err(PkgError::PackageNotFound(name.clone()));
+                }
+                Some(root)
+            }
+            Dependency::Simple(version) | Dependency::Detailed(DetailedDependency { version: Some(version), .. }) => {
+                let req = VersionReq::parse(version).map_err(|e| PkgError::InvalidManifest(e.to_string()))?;
+                cache.find(name, &req)
+            }
+            Dependency::Detailed(_) => None,
+        };
+        if let Some(root) = root {
+            // Packages keep their sources in `src` when they have one
+            let src = root.join("src");
+            loader = loader.package(&name.replace('-', "_"), if src.is_dir() { src } else { root });
+        }
+    }
+    Ok(loader)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -358,4 +412,39 @@ std = "0.1"
         assert_eq!(manifest.package.name, "my-app");
         assert!(manifest.dependencies.contains_key("std"));
     }
+
+    #[test]
+    fn test_module_loader() {
+        let dir = std::env::temp_dir().join(format!("my-pkg-loader-{}", std::process::id()));
+        let write = |path: &str, content: &str| {
+            let path = dir.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        };
+        write("app/my.toml", r#"
+[package]
+name = "app"
+version = "0.1.0"
+
+[dependencies]
+geometry = { path = "../geometry" }
+my-utils = "1.2"
+missing = "2"
+"#);
+        write("app/main.ml", r#"
+use geometry::{area};
+use my_utils;
+fn main() -> Int { return my_utils::double(area(2, 3)); }
+"#);
+        write("geometry/src/lib.ml", "pub fn area(w: Int, h: Int) -> Int { return w * h; }");
+        write("cache/packages/my-utils/1.1.0/lib.ml", "pub fn double(x: Int) -> Int { return x; }");
+        write("cache/packages/my-utils/1.4.0/lib.ml", "pub fn double(x: Int) -> Int { return x * 2; }");
+
+        let cache = PackageCache::new(dir.join("cache"));
+        let loader = module_loader(&dir.join("app"), &cache).unwrap();
+        let graph = loader.load(&dir.join("app/main.ml")).unwrap();
+        let result = my_lang::Interpreter::new().run_modules(&graph);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(matches!(result, Ok(my_lang::Value::Int(12))), "{:?}", result);
+    }
 }
@@ -6,10 +6,12 @@
 //! - Parallel test execution
 //! - Coverage reporting
 //! - Benchmarking support
+//! - Recording AI calls to cassettes and replaying them offline
 
-use my_lang::{parse, eval, Program, TopLevel};
+use my_lang::ai::{AiBackend, MockBackend};
+use my_lang::cassette::{CassetteMode, RecordingBackend, ReplayBackend};
+use my_lang::{parse, eval_in, Interpreter, TopLevel};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
 use std::path::PathBuf;
 use std::time::{Duration, Instant};
 use thiserror::Error;
@@ -31,6 +33,9 @@ pub enum TestError {
 
     #[error("timeout after {0:?}")]
     Timeout(Duration),
+
+    #[error("cassette error: {0}")]
+    CassetteError(String),
 }
 
 /// Test case
@@ -101,6 +106,8 @@ pub struct TestConfig {
     pub bench: bool,
     /// Capture output
     pub capture: bool,
+    /// Record AI calls to, or replay them from, this cassette
+    pub cassette: Option<(CassetteMode, PathBuf)>,
 }
 
 impl Default for TestConfig {
@@ -112,6 +119,7 @@ impl Default for TestConfig {
             skip: None,
             bench: false,
             capture: true,
+            cassette: None,
         }
     }
 }
@@ -195,10 +203,11 @@ impl TestRunner {
             .filter(|t| self.should_run(t))
             .collect();
 
+        let mut ai_backend = self.ai_backend();
         let mut results = Vec::new();
 
         for test in filtered_tests {
-            let result = self.run_single(test).await;
+            let result = self.run_single(test, &mut ai_backend).await;
             results.push(result);
         }
 
@@ -221,12 +230,27 @@ impl TestRunner {
         true
     }
 
-    async fn run_single(&self, test: TestCase) -> TestResult {
+    /// The backend answering AI calls for the whole run, so that a cassette
+    /// collects or serves the interactions of every test in order
+    fn ai_backend(&self) -> Result<Box<dyn AiBackend>, String> {
+        let Some((mode, path)) = &self.config.cassette else {
+            return Ok(Box::new(MockBackend::new()));
+        };
+        Ok(match mode {
+            CassetteMode::Record => {
+                let live = my_ai::RuntimeBackend::from_env().map_err(|e| e.to_string())?;
+                Box::new(RecordingBackend::new(Box::new(live), path).map_err(|e| e.to_string())?)
+            }
+            CassetteMode::Replay => Box::new(ReplayBackend::open(path).map_err(|e| e.to_string())?),
+        })
+    }
+
+    async fn run_single(&self, test: TestCase, ai_backend: &mut Result<Box<dyn AiBackend>, String>) -> TestResult {
         let start = Instant::now();
         let timeout = test.timeout.unwrap_or(self.config.timeout);
 
         let result = tokio::time::timeout(timeout, async {
-            self.execute_test(&test)
+            self.execute_test(&test, ai_backend)
         })
         .await;
 
@@ -257,11 +281,17 @@ impl TestRunner {
         }
     }
 
-    fn execute_test(&self, test: &TestCase) -> Result<(), TestError> {
+    fn execute_test(&self, test: &TestCase, ai_backend: &mut Result<Box<dyn AiBackend>, String>) -> Result<(), TestError> {
         let source = std::fs::read_to_string(&test.file)?;
+        let ai_backend = ai_backend.as_mut().map_err(|e| TestError::CassetteError(e.clone()))?;
 
-        // Parse and evaluate
-        match eval(&source) {
+        // Parse and evaluate, lending the run's backend to the interpreter
+        let mut interpreter = Interpreter::new();
+        std::mem::swap(&mut interpreter.ai_backend, ai_backend);
+        let result = eval_in(&mut interpreter, &source);
+        std::mem::swap(&mut interpreter.ai_backend, ai_backend);
+
+        match result {
             Ok(_) => Ok(()),
             Err(e) => Err(TestError::RuntimeError(e.to_string())),
         }
@@ -380,4 +410,37 @@ mod tests {
         ]);
         assert!(!results.success());
     }
+
+    #[tokio::test]
+    async fn test_replay_cassette() {
+        let dir = std::env::temp_dir().join(format!("my-test-cassette-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("test_greeting.my");
+        std::fs::write(&file, r#"
+            fn test_greeting() { }
+            fn main() {
+                let reply = ai! { "Say hi" };
+                if reply != "hi there" { let boom = 1 / 0; }
+            }
+        "#).unwrap();
+        let cassette = dir.join("cassette.json");
+        std::fs::write(&cassette, r#"{ "interactions": [
+            { "operation": "query", "model": null, "temperature": null,
+              "messages": [{ "role": "user", "content": "Say hi" }], "result": "hi there" }
+        ] }"#).unwrap();
+
+        let tests = discover_tests(std::slice::from_ref(&file)).unwrap();
+        let config = TestConfig { cassette: Some((CassetteMode::Replay, cassette.clone())), ..TestConfig::default() };
+        let results = TestRunner::new(config).run(tests.clone()).await;
+        assert!(results.success(), "{:?}", results.results);
+
+        std::fs::write(&cassette, r#"{ "interactions": [] }"#).unwrap();
+        let config = TestConfig { cassette: Some((CassetteMode::Replay, cassette)), ..TestConfig::default() };
+        let results = TestRunner::new(config).run(tests).await;
+        assert_eq!(
+            results.results[0].error.as_deref(),
+            Some("runtime error: Runtime error: AI backend error: no recorded interaction matches query request: \"Say hi\"")
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
 }
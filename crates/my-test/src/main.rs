@@ -2,6 +2,7 @@
 //! My Language Test Runner CLI
 
 use clap::Parser;
+use my_lang::cassette::CassetteMode;
 use my_test::{discover_tests, TestRunner, TestConfig, TestError};
 use std::path::PathBuf;
 use std::time::Duration;
@@ -45,6 +46,14 @@ struct Args {
     /// Show verbose output
     #[arg(short, long)]
     verbose: bool,
+
+    /// Record provider replies to a fresh JSON cassette
+    #[arg(long, value_name = "FILE", conflicts_with = "replay")]
+    record: Option<PathBuf>,
+
+    /// Serve AI calls from a recorded cassette, failing on unrecorded ones
+    #[arg(long, value_name = "FILE")]
+    replay: Option<PathBuf>,
 }
 
 #[tokio::main]
@@ -58,6 +67,10 @@ async fn main() -> Result<(), TestError> {
         skip: args.skip,
         bench: args.bench,
         capture: !args.nocapture,
+        cassette: args
+            .record
+            .map(|path| (CassetteMode::Record, path))
+            .or(args.replay.map(|path| (CassetteMode::Replay, path))),
     };
 
     // Discover tests
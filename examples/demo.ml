@@ -106,11 +106,11 @@ fn fibonacci(n: Int) -> Int {
 // 6. AI Expressions
 // ============================================
 
-fn demonstrate_ai_expressions() {
+fn demonstrate_ai_expressions() -> Effect<(), AI> {
     // Define sample data for demonstration
-    let code_snippet = "fn main() {}";
-    let user_data = "{ \"name\": \"John\" }";
-    let template = "Generate a {type}";
+    let code_snippet = "fn main() \{}";
+    let user_data = "\{ \"name\": \"John\" }";
+    let template = "Generate a \{type}";
     let context = "programming";
     let document = "This is a document.";
     let text = "I love this product!";
@@ -175,7 +175,7 @@ fn control_flow_demo(x: Int) {
 // 8. Concurrency
 // ============================================
 
-fn concurrent_demo() {
+fn concurrent_demo() -> Effect<(), AI> {
     // Spawn concurrent task
     go {
         let result = ai query { prompt: "background task" };
@@ -215,9 +215,7 @@ fn main() {
     // Quick AI query
     let greeting = ai! { "Generate a friendly greeting" };
 
-    // Match on result
-    match analysis {
-        Ok(r) => "Analysis succeeded",
-        Err(e) => "Analysis failed",
-    };
+    // AI replies are used like the values they hold
+    println(analysis);
+    println(greeting);
 }